@@ -0,0 +1,131 @@
+use crate::config::AgendaCallConfig;
+use crate::formatter;
+use crate::ical::{CalendarEvent, IcalCalendar};
+use crate::state::State;
+use crate::template::Template;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+/// The state key kind used to remember which agenda calls were posted.
+pub const KIND: &str = "agenda-call";
+
+/// Default template for the call for agenda items.
+pub const AGENDA_CALL_TEMPLATE: &str = "📝 Call for agenda items: {#if summary}{summary}{else}Meeting{/if}\
+{#if start}\n🕒 {start}{/if}\
+\nReply with the topics you'd like to discuss!\
+{#if url}\n🔗 {url}{/if}";
+
+/// Whether the event is one of those selected for agenda calls.
+pub fn is_selected(config: &AgendaCallConfig, event: &CalendarEvent) -> bool {
+    event.summary_matches(&config.events)
+}
+
+/// The selected events starting within `days_before` days of `now` that have
+/// not had an agenda call posted yet.
+pub fn due_events<'a>(
+    calendar: &'a IcalCalendar,
+    config: &AgendaCallConfig,
+    state: &State,
+    now: DateTime<Utc>,
+) -> Vec<&'a CalendarEvent> {
+    let current_time = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let max_date = (now + Duration::days(config.days_before))
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string();
+
+    calendar
+        .get_upcoming_events_filtered(&current_time, Some(&max_date), None)
+        .into_iter()
+        .filter(|event| is_selected(config, event))
+        .filter(|event| !state.is_posted(&State::key(KIND, event)))
+        .collect()
+}
+
+/// The template the agenda call is rendered from: the configured one, or the default.
+pub fn template_source(config: &AgendaCallConfig) -> &str {
+    config.template.as_deref().unwrap_or(AGENDA_CALL_TEMPLATE)
+}
+
+/// Renders the agenda call for an event using the configured template.
+pub fn render(config: &AgendaCallConfig, event: &CalendarEvent) -> Result<String> {
+    let template = Template::parse(template_source(config))?;
+    Ok(template.render(&formatter::event_context(event)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::PostRecord;
+    use chrono::TimeZone;
+    use indoc::indoc;
+
+    fn calendar() -> IcalCalendar {
+        IcalCalendar::parse_ical_content(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:soon@example.com
+            DTSTART:20251205T190000Z
+            SUMMARY:Monthly Meeting
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:social@example.com
+            DTSTART:20251206T190000Z
+            SUMMARY:Social Hour
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:later@example.com
+            DTSTART:20251220T190000Z
+            SUMMARY:Monthly Meeting
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap()
+    }
+
+    fn config() -> AgendaCallConfig {
+        AgendaCallConfig {
+            days_before: 7,
+            events: vec!["monthly meeting".to_string()],
+            template: None,
+        }
+    }
+
+    #[test]
+    fn test_due_events() {
+        let calendar = calendar();
+        let now = Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap();
+        let mut state = State::default();
+
+        let due = due_events(&calendar, &config(), &state, now);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].uid, Some("soon@example.com".to_string()));
+
+        state.record(
+            State::key(KIND, due[0]),
+            PostRecord {
+                status_id: "1".to_string(),
+                url: None,
+                posted_at: now,
+                template_hash: None,
+                event_hash: None,
+                text_hash: None,
+                links: Default::default(),
+                details: None,
+            },
+        );
+        assert!(due_events(&calendar, &config(), &state, now).is_empty());
+    }
+
+    #[test]
+    fn test_render() {
+        let calendar = calendar();
+        let mut config = config();
+        config.template = Some("Agenda for {summary}?".to_string());
+        assert_eq!(
+            render(&config, &calendar.events[0]).unwrap(),
+            "Agenda for Monthly Meeting?"
+        );
+    }
+}
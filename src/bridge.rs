@@ -0,0 +1,237 @@
+use crate::config::{BridgeConfig, Config, UrlPolicyConfig};
+use crate::ical::{self, CalendarEvent};
+use crate::poster::{self, plain_text};
+use crate::quiet;
+use crate::timing::{self, Phase};
+use crate::url_policy;
+use anyhow::{Result, anyhow};
+use chrono::{NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use regex::Regex;
+
+/// The most statuses the Mastodon API gives in one page.
+const PAGE_SIZE: usize = 40;
+
+/// A public post of a bridged account.
+#[derive(Debug, Clone)]
+pub struct BridgedPost {
+    pub id: String,
+    pub url: Option<String>,
+    pub text: String,
+}
+
+/// The instance an account such as `venue@social.example` is on.
+fn instance(account: &str) -> Result<&str> {
+    account
+        .trim_start_matches('@')
+        .split_once('@')
+        .map(|(_, host)| host)
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| anyhow!("Bridged account {} isn't name@instance", account))
+}
+
+/// The account's latest public posts, up to `posts` of them, newest first,
+/// leaving out replies and boosts. No login is needed.
+pub async fn fetch(
+    bridge: &BridgeConfig,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<Vec<BridgedPost>> {
+    let base = format!("https://{}/api/v1", instance(&bridge.account)?);
+    let client = url_policy::client(policy, &base)?;
+    timing::time_async(Phase::Fetch, async {
+        let request = client
+            .get(format!("{}/accounts/lookup", base))
+            .query(&[("acct", bridge.account.trim_start_matches('@'))]);
+        let account = poster::send(request, &format!("looking up {}", bridge.account)).await?;
+        let id = account["id"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Account without an id in the response"))?;
+
+        let mut posts = Vec::new();
+        while posts.len() < bridge.posts {
+            let limit = (bridge.posts - posts.len()).min(PAGE_SIZE).to_string();
+            let mut query = vec![
+                ("limit", limit.as_str()),
+                ("exclude_replies", "true"),
+                ("exclude_reblogs", "true"),
+            ];
+            if let Some(last) = posts.last().map(|post: &BridgedPost| post.id.as_str()) {
+                query.push(("max_id", last));
+            }
+            let request = client
+                .get(format!("{}/accounts/{}/statuses", base, id))
+                .query(&query);
+            let page =
+                poster::send(request, &format!("fetching {}'s posts", bridge.account)).await?;
+            let page = page
+                .as_array()
+                .ok_or_else(|| anyhow!("Statuses aren't a list in the response"))?;
+            let fetched: Vec<_> = page
+                .iter()
+                .filter_map(|status| {
+                    Some(BridgedPost {
+                        id: status["id"].as_str()?.to_string(),
+                        url: status["url"].as_str().map(str::to_string),
+                        text: plain_text(status["content"].as_str().unwrap_or_default()),
+                    })
+                })
+                .collect();
+            if fetched.is_empty() {
+                break;
+            }
+            posts.extend(fetched);
+        }
+        posts.truncate(bridge.posts);
+        Ok(posts)
+    })
+    .await
+}
+
+/// `text` written as `format` in `timezone`, as an iCal time: in UTC, or a
+/// date when the format has no time.
+fn ical_time(text: &str, format: &str, timezone: Tz) -> Option<String> {
+    if let Ok(time) = NaiveDateTime::parse_from_str(text.trim(), format) {
+        let time = timezone.from_local_datetime(&time).earliest()?.to_utc();
+        return Some(time.format("%Y%m%dT%H%M%SZ").to_string());
+    }
+    NaiveDate::parse_from_str(text.trim(), format)
+        .ok()
+        .map(|date| date.format("%Y%m%d").to_string())
+}
+
+/// The events announced in `posts`: those whose text matches the bridge's
+/// pattern with a `start` that reads as a time. The summary is the first
+/// line of the post unless the pattern picks it out, and the post is the
+/// event's link.
+pub fn events(
+    bridge: &BridgeConfig,
+    timezone: Tz,
+    posts: &[BridgedPost],
+) -> Result<Vec<CalendarEvent>> {
+    let pattern = Regex::new(&bridge.pattern)
+        .map_err(|e| anyhow!("Invalid pattern for {}: {}", bridge.account, e))?;
+    if !pattern.capture_names().any(|name| name == Some("start")) {
+        return Err(anyhow!(
+            "The pattern for {} has no start group",
+            bridge.account
+        ));
+    }
+    let host = instance(&bridge.account)?;
+
+    let mut events = Vec::new();
+    for post in posts {
+        let Some(captures) = pattern.captures(&post.text) else {
+            continue;
+        };
+        let group = |name| captures.name(name).map(|found| found.as_str().trim());
+        let start = group("start").unwrap_or_default();
+        let Some(start_time) = ical_time(start, &bridge.time_format, timezone) else {
+            eprintln!(
+                "Warning: leaving out {}'s post {}, whose start {:?} isn't {:?}",
+                bridge.account, post.id, start, bridge.time_format
+            );
+            continue;
+        };
+        let summary = group("summary")
+            .or_else(|| {
+                post.text
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+            })
+            .unwrap_or_default();
+        events.push(CalendarEvent {
+            uid: Some(format!("bridge-{}@{}", post.id, host)),
+            summary: Some(ical::escape_text(summary)),
+            start_time: Some(start_time),
+            end_time: group("end").and_then(|end| ical_time(end, &bridge.time_format, timezone)),
+            location: group("location").map(ical::escape_text),
+            url: post.url.clone(),
+            ..CalendarEvent::default()
+        });
+    }
+    Ok(events)
+}
+
+/// The events of every `[[bridge]]` account, ready to merge into the
+/// calendar's. An account that can't be reached is warned about and left
+/// out, so it doesn't hold up the digest.
+pub async fn bridged_events(config: &Config) -> Result<Vec<CalendarEvent>> {
+    let timezone = quiet::timezone(config)?;
+    let display_timezone = quiet::display_timezone(config)?;
+    let mut bridged = Vec::new();
+    for bridge in &config.bridges {
+        // A mistake in the pattern shows before anything is fetched
+        events(bridge, timezone, &[])?;
+        let posts = match fetch(bridge, Some(&config.url_policy)).await {
+            Ok(posts) => posts,
+            Err(e) => {
+                eprintln!("Warning: could not fetch {}'s posts: {}", bridge.account, e);
+                continue;
+            }
+        };
+        bridged.extend(events(bridge, timezone, &posts)?);
+    }
+    for event in &mut bridged {
+        event.display_timezone = display_timezone;
+        event.week_start = Some(config.week_start);
+        event.add_hashtags(config.templates.hashtags.iter().map(String::as_str));
+    }
+    Ok(bridged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(id: &str, text: &str) -> BridgedPost {
+        BridgedPost {
+            id: id.to_string(),
+            url: Some(format!("https://venue.example/@hall/{}", id)),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_bridged_events() {
+        let bridge = BridgeConfig {
+            account: "hall@venue.example".to_string(),
+            pattern: r"(?m)^📅 (?P<start>\d{4}-\d{2}-\d{2}( \d{2}:\d{2})?)(, (?P<location>.+))?$"
+                .to_string(),
+            time_format: "%Y-%m-%d %H:%M".to_string(),
+            posts: 40,
+        };
+        let posts = [
+            post("3", "Open mic, all welcome\n📅 2025-12-10 19:30, Main Hall"),
+            post("2", "Thanks to everyone who came last night!"),
+            post("1", "Craft fair\n📅 2025-12-13"),
+        ];
+
+        let bridged = events(&bridge, chrono_tz::America::New_York, &posts).unwrap();
+        let [open_mic] = &bridged[..] else {
+            panic!("expected one event, got {:?}", bridged);
+        };
+        assert_eq!(open_mic.uid.as_deref(), Some("bridge-3@venue.example"));
+        assert_eq!(open_mic.summary.as_deref(), Some("Open mic\\, all welcome"));
+        assert_eq!(open_mic.start_time.as_deref(), Some("20251211T003000Z"));
+        assert_eq!(open_mic.location.as_deref(), Some("Main Hall"));
+        assert_eq!(
+            open_mic.url.as_deref(),
+            Some("https://venue.example/@hall/3")
+        );
+
+        // A format without a time gives all-day events
+        let bridge = BridgeConfig {
+            time_format: "%Y-%m-%d".to_string(),
+            ..bridge
+        };
+        let bridged = events(&bridge, chrono_tz::America::New_York, &posts[2..]).unwrap();
+        assert_eq!(bridged[0].start_time.as_deref(), Some("20251213"));
+
+        let bridge = BridgeConfig {
+            pattern: "📅 .+".to_string(),
+            ..bridge
+        };
+        assert!(events(&bridge, chrono_tz::UTC, &posts).is_err());
+    }
+}
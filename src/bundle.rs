@@ -0,0 +1,293 @@
+use crate::config::Config;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// The layout of bundles written by this version.
+pub const VERSION: u32 = 1;
+
+/// Keys of the configuration holding secrets, left out of a bundle unless
+/// secrets are asked for.
+const SECRET_KEYS: &[&str] = &["client_secret", "password", "token"];
+
+/// What a bundled file is to the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    Config,
+    State,
+    Cache,
+    /// An account's access token, written back for its owner only.
+    Token,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundledFile {
+    /// Where the file goes, as the configuration names it.
+    pub path: String,
+    pub kind: FileKind,
+    pub content: String,
+}
+
+/// Everything a bot needs to carry on on another host, in one file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bundle {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<BundledFile>,
+}
+
+/// The configuration's TOML without the values of [`SECRET_KEYS`], and the
+/// names of those left out. Without secrets it is kept as written.
+fn without_secrets(source: &str) -> Result<(String, Vec<String>)> {
+    fn strip(table: &mut toml::Table, at: &str, removed: &mut Vec<String>) {
+        for key in SECRET_KEYS {
+            if table.remove(*key).is_some() {
+                removed.push(format!("{}{}", at, key));
+            }
+        }
+        for (name, value) in table.iter_mut() {
+            match value {
+                toml::Value::Table(table) => strip(table, &format!("{}{}.", at, name), removed),
+                toml::Value::Array(values) => {
+                    for value in values {
+                        if let toml::Value::Table(table) = value {
+                            strip(table, &format!("{}{}.", at, name), removed);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut table: toml::Table = toml::from_str(source)?;
+    let mut removed = Vec::new();
+    strip(&mut table, "", &mut removed);
+    match removed.is_empty() {
+        true => Ok((source.to_string(), removed)),
+        false => Ok((toml::to_string(&table)?, removed)),
+    }
+}
+
+/// Every file in the cache directory, if there is one.
+fn cache_files(dir: &str) -> Result<Vec<String>> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(Vec::new());
+    };
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            paths.push(Path::new(dir).join(entry.file_name()).display().to_string());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Bundles the configuration at `config_path`, the state files and calendar
+/// caches of the main calendar and every feed, and with `secrets` the
+/// configuration's secrets and every account's token file. Returns the
+/// bundle and the secrets left out of the configuration.
+pub fn collect(
+    config_path: &str,
+    config: &Config,
+    secrets: bool,
+    now: DateTime<Utc>,
+) -> Result<(Bundle, Vec<String>)> {
+    let source = std::fs::read_to_string(config_path)
+        .map_err(|e| anyhow!("Could not read {}: {}", config_path, e))?;
+    let (source, left_out) = match secrets {
+        true => (source, Vec::new()),
+        false => without_secrets(&source)?,
+    };
+    let mut files = vec![BundledFile {
+        path: config_path.to_string(),
+        kind: FileKind::Config,
+        content: source,
+    }];
+
+    let configs: Vec<Config> = std::iter::once(config.clone())
+        .chain(config.feeds.iter().map(|feed| config.for_feed(feed)))
+        .collect();
+    let mut wanted = Vec::new();
+    for config in &configs {
+        wanted.push((config.state_file.clone(), FileKind::State));
+        if let Some(cache) = config.calendar_cache() {
+            wanted.extend(
+                cache_files(&cache.dir)?
+                    .into_iter()
+                    .map(|path| (path, FileKind::Cache)),
+            );
+        }
+        if secrets {
+            wanted.push((config.token_file.clone(), FileKind::Token));
+            wanted.extend(
+                config
+                    .backup
+                    .iter()
+                    .map(|backup| (backup.token_file.clone(), FileKind::Token)),
+            );
+            wanted.extend(
+                config
+                    .language_accounts
+                    .iter()
+                    .map(|account| (account.token_file.clone(), FileKind::Token)),
+            );
+        }
+    }
+
+    let mut seen = BTreeSet::new();
+    for (path, kind) in wanted {
+        if !seen.insert(path.clone()) || !Path::new(&path).exists() {
+            continue;
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Could not read {}: {}", path, e))?;
+        files.push(BundledFile {
+            path,
+            kind,
+            content,
+        });
+    }
+    let bundle = Bundle {
+        version: VERSION,
+        created_at: now,
+        files,
+    };
+    Ok((bundle, left_out))
+}
+
+/// [Collects](collect) a bundle and writes it to `output`, for its owner
+/// only when it holds secrets.
+pub fn write(
+    config_path: &str,
+    config: &Config,
+    output: &str,
+    secrets: bool,
+    now: DateTime<Utc>,
+) -> Result<(Bundle, Vec<String>)> {
+    let (bundle, left_out) = collect(config_path, config, secrets, now)?;
+    let json = serde_json::to_string_pretty(&bundle)?;
+    match secrets {
+        true => crate::secrets::write(Path::new(output), &json)?,
+        false => std::fs::write(output, json)?,
+    }
+    Ok((bundle, left_out))
+}
+
+/// Reads a bundle [written](write) to `path`.
+pub fn read(path: &str) -> Result<Bundle> {
+    let content = std::fs::read_to_string(path)?;
+    serde_json::from_str(&content)
+        .map_err(|e| anyhow!("{} isn't a bundle written by backup: {}", path, e))
+}
+
+/// Writes the bundle's files where they were, refusing to replace any that
+/// exist unless `force`. Returns the paths written.
+pub fn restore(bundle: &Bundle, force: bool) -> Result<Vec<String>> {
+    if bundle.version > VERSION {
+        return Err(anyhow!(
+            "The bundle is of version {}; this version of ical-to-masto reads up to {}",
+            bundle.version,
+            VERSION
+        ));
+    }
+    let existing: Vec<&str> = bundle
+        .files
+        .iter()
+        .map(|file| file.path.as_str())
+        .filter(|path| Path::new(path).exists())
+        .collect();
+    if !force && !existing.is_empty() {
+        return Err(anyhow!(
+            "Not replacing {}; restore with --force to replace them",
+            existing.join(", ")
+        ));
+    }
+
+    let mut written = Vec::new();
+    for file in &bundle.files {
+        let path = Path::new(&file.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match file.kind {
+            FileKind::Token => crate::secrets::write(path, &file.content)?,
+            FileKind::Config | FileKind::State | FileKind::Cache => {
+                std::fs::write(path, &file.content)?
+            }
+        }
+        written.push(file.path.clone());
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_and_restore() {
+        let old = std::env::temp_dir().join(format!("ical-to-masto-bundle-{}", std::process::id()));
+        let at = |name: &str| old.join(name).display().to_string();
+        std::fs::create_dir_all(old.join("cache")).unwrap();
+        let source = format!(
+            "instance = \"https://mastodon.example\"\n\
+             webcal = \"https://example.com/calendar.ics\"\n\
+             state_file = \"{}\"\n\
+             token_file = \"{}\"\n\
+             [cache]\n\
+             dir = \"{}\"\n\
+             [shortener]\n\
+             api_url = \"https://s.example/api\"\n\
+             token = \"hunter2\"\n",
+            at("state.json"),
+            at("token.json"),
+            at("cache")
+        );
+        std::fs::write(at("bot.toml"), &source).unwrap();
+        std::fs::write(at("state.json"), "{\"posts\": {}}").unwrap();
+        std::fs::write(at("token.json"), "{\"token\": \"secret\"}").unwrap();
+        std::fs::write(at("cache/feed.ics"), "BEGIN:VCALENDAR").unwrap();
+        let config: Config = toml::from_str(&source).unwrap();
+
+        let (bundle, left_out) = collect(&at("bot.toml"), &config, false, Utc::now()).unwrap();
+        assert_eq!(left_out, ["shortener.token"]);
+        let kinds: Vec<_> = bundle.files.iter().map(|file| file.kind).collect();
+        assert_eq!(kinds, [FileKind::Config, FileKind::State, FileKind::Cache]);
+        assert!(!bundle.files[0].content.contains("hunter2"));
+        assert!(bundle.files[0].content.contains("api_url"));
+
+        // Existing files are only replaced when asked to
+        assert!(restore(&bundle, false).is_err());
+        std::fs::remove_dir_all(&old).unwrap();
+        let written = restore(&bundle, false).unwrap();
+        assert_eq!(written.len(), 3);
+        assert_eq!(
+            std::fs::read_to_string(at("state.json")).unwrap(),
+            "{\"posts\": {}}"
+        );
+        assert!(!Path::new(&at("token.json")).exists());
+
+        std::fs::write(at("bot.toml"), &source).unwrap();
+        std::fs::write(at("token.json"), "{\"token\": \"secret\"}").unwrap();
+        let (bundle, left_out) = write(
+            &at("bot.toml"),
+            &config,
+            &at("bundle.json"),
+            true,
+            Utc::now(),
+        )
+        .unwrap();
+        assert!(left_out.is_empty());
+        assert_eq!(bundle.files[0].content, source);
+        assert!(bundle.files.iter().any(|file| file.kind == FileKind::Token));
+        assert_eq!(read(&at("bundle.json")).unwrap(), bundle);
+        assert!(read(&at("bot.toml")).is_err());
+        std::fs::remove_dir_all(&old).unwrap();
+    }
+}
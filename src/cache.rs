@@ -0,0 +1,177 @@
+use crate::config::CacheConfig;
+use crate::fetch::{self, Fetched};
+use crate::state::fingerprint;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A calendar as it was last fetched, with what the server identified that
+/// version by.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedFeed {
+    pub final_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+    /// Kept in a file of its own, next to the rest.
+    #[serde(skip)]
+    pub body: String,
+}
+
+/// Fetched calendars on disk, one `.ics` file and one `.json` file of what
+/// is known about it for each calendar URL.
+pub struct FeedCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl FeedCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        FeedCache {
+            dir: PathBuf::from(&config.dir),
+            ttl: i64::try_from(config.ttl_seconds)
+                .ok()
+                .and_then(Duration::try_seconds)
+                .unwrap_or(Duration::MAX),
+        }
+    }
+
+    fn paths(&self, url: &str) -> (PathBuf, PathBuf) {
+        let name = fingerprint(&fetch::normalize_url(url));
+        (
+            self.dir.join(format!("{}.json", name)),
+            self.dir.join(format!("{}.ics", name)),
+        )
+    }
+
+    /// The calendar last fetched from `url`, if it's in the cache.
+    pub fn load(&self, url: &str) -> Option<CachedFeed> {
+        let (meta, body) = self.paths(url);
+        let mut cached: CachedFeed = serde_json::from_str(&read(&meta)?).ok()?;
+        cached.body = read(&body)?;
+        Some(cached)
+    }
+
+    /// Remembers what was fetched from `url` at `now`.
+    pub fn store(&self, url: &str, fetched: &Fetched, now: DateTime<Utc>) -> Result<()> {
+        let (meta, body) = self.paths(url);
+        let cached = CachedFeed {
+            final_url: fetched.final_url.clone(),
+            etag: fetched.etag.clone(),
+            last_modified: fetched.last_modified.clone(),
+            fetched_at: now,
+            body: String::new(),
+        };
+        std::fs::create_dir_all(&self.dir)?;
+        // The body goes first, so metadata never describes a body that isn't there
+        std::fs::write(body, &fetched.body)?;
+        std::fs::write(meta, serde_json::to_string_pretty(&cached)?)?;
+        Ok(())
+    }
+
+    /// Whether a cached calendar is recent enough to use without asking the
+    /// server.
+    pub fn is_fresh(&self, cached: &CachedFeed, now: DateTime<Utc>) -> bool {
+        cached
+            .fetched_at
+            .checked_add_signed(self.ttl)
+            .is_none_or(|until| now < until)
+    }
+
+    /// Removes the calendars last fetched before `before`, and files of
+    /// calendars whose metadata can't be read, returning how many calendars
+    /// went. With `dry_run`, they are only counted.
+    pub fn trim(&self, before: DateTime<Utc>, dry_run: bool) -> Result<usize> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names: Vec<String> = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str())
+                && matches!(
+                    path.extension().and_then(|ext| ext.to_str()),
+                    Some("json" | "ics")
+                )
+                && !names.iter().any(|known| known == name)
+            {
+                names.push(name.to_string());
+            }
+        }
+
+        let mut trimmed = 0;
+        for name in names {
+            let meta = self.dir.join(format!("{}.json", name));
+            let body = self.dir.join(format!("{}.ics", name));
+            let is_stale = read(&meta)
+                .and_then(|json| serde_json::from_str::<CachedFeed>(&json).ok())
+                .is_none_or(|cached| cached.fetched_at < before);
+            if !is_stale {
+                continue;
+            }
+            trimmed += 1;
+            if !dry_run {
+                for path in [meta, body] {
+                    if path.exists() {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+            }
+        }
+        Ok(trimmed)
+    }
+}
+
+fn read(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim() {
+        let dir = std::env::temp_dir().join(format!("ical-to-masto-trim-{}", std::process::id()));
+        let cache = FeedCache::new(&CacheConfig {
+            dir: dir.display().to_string(),
+            ttl_seconds: 0,
+        });
+        let now = "2025-12-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let fetched = |url: &str| Fetched {
+            final_url: url.to_string(),
+            etag: None,
+            last_modified: None,
+            body: "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n".to_string(),
+        };
+        let recent = "https://recent.example/calendar.ics";
+        let old = "https://old.example/calendar.ics";
+        cache.store(recent, &fetched(recent), now).unwrap();
+        cache
+            .store(old, &fetched(old), now - Duration::days(200))
+            .unwrap();
+        // A body left without the metadata written after it
+        let (_, orphan) = cache.paths("https://orphan.example/calendar.ics");
+        std::fs::write(&orphan, "BEGIN:VCALENDAR").unwrap();
+        let before = now - Duration::days(180);
+
+        assert_eq!(cache.trim(before, true).unwrap(), 2);
+        assert!(cache.load(old).is_some());
+        assert!(orphan.exists());
+
+        assert_eq!(cache.trim(before, false).unwrap(), 2);
+        assert!(cache.load(recent).is_some());
+        assert!(cache.load(old).is_none());
+        assert!(!cache.paths(old).1.exists());
+        assert!(!orphan.exists());
+        assert_eq!(cache.trim(before, false).unwrap(), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(cache.trim(before, false).unwrap(), 0);
+    }
+}
@@ -0,0 +1,420 @@
+use crate::config::Config;
+use crate::ical::{self, CalendarEvent, TimeForm};
+use crate::quiet;
+use crate::reminders;
+use anyhow::Result;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+
+/// When posts that aren't tied to a meeting's hour go out: in the morning.
+const MORNING: NaiveTime = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+
+/// The most slots suggestions are made for; further ones are one-offs.
+const MAX_SLOTS: usize = 4;
+
+/// A weekday and time of day meetings keep starting at, in the configured
+/// timezone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slot {
+    pub weekday: Weekday,
+    pub time: NaiveTime,
+    /// How long the meetings usually last.
+    pub duration: Duration,
+    /// How many meetings started then.
+    pub meetings: usize,
+}
+
+/// A command to run on some weekdays at a time of day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    /// The command with its flags, like `post-next --count 3`.
+    pub command: String,
+    /// Why it runs then, for people.
+    pub note: String,
+    pub weekdays: Vec<Weekday>,
+    pub time: NaiveTime,
+}
+
+impl Suggestion {
+    /// The schedule as crontab fields, like `0 9 * * Mon,Thu`.
+    pub fn crontab(&self) -> String {
+        format!(
+            "{} {} * * {}",
+            self.time.format("%-M"),
+            self.time.format("%-H"),
+            days(&self.weekdays)
+        )
+    }
+
+    /// The schedule as a systemd timer's `OnCalendar=` value.
+    pub fn on_calendar(&self, timezone: Tz) -> String {
+        format!(
+            "{} *-*-* {} {}",
+            days(&self.weekdays),
+            self.time.format("%H:%M:%S"),
+            timezone
+        )
+    }
+}
+
+fn days(weekdays: &[Weekday]) -> String {
+    weekdays
+        .iter()
+        .map(|weekday| weekday.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// What `schedule-suggest` found in the calendar.
+#[derive(Debug)]
+pub struct Suggestions {
+    pub timezone: Tz,
+    /// The meetings the slots were found in.
+    pub meetings: usize,
+    pub slots: Vec<Slot>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+/// Suggests when to run the posting commands, from the slots the meetings
+/// in `events` keep starting at: the announcement (or, with several meetings
+/// a week, a weekly digest), and the agenda calls, reminders and minutes
+/// follow-ups that are configured.
+pub fn suggest(config: &Config, events: &[CalendarEvent]) -> Result<Suggestions> {
+    let timezone = quiet::timezone(config)?;
+    let meetings = meetings(events, timezone, &[]);
+    let slots = slots(&meetings);
+    let mut suggestions = Vec::new();
+
+    if slots.len() > 1 {
+        let count = slots.len();
+        // The morning before the week's first meeting
+        let (weekday, _) = at(digest_slot(&slots), -Duration::days(1));
+        suggestions.push(Suggestion {
+            command: format!("post-next --count {}", count),
+            note: format!("Post a digest of the week's {} meetings", count),
+            weekdays: vec![weekday],
+            time: MORNING,
+        });
+    } else if let Some(slot) = slots.first() {
+        let lead = (typical_gap(&meetings).num_days() / 2).clamp(1, 7);
+        let (weekday, time) = before(slot, Duration::days(lead), MORNING);
+        suggestions.push(Suggestion {
+            command: "post-next".to_string(),
+            note: format!("Announce the next meeting {} ahead", plural(lead, "day")),
+            weekdays: vec![weekday],
+            time,
+        });
+    }
+
+    if let Some(agenda_call) = &config.agenda_call {
+        let days_before = agenda_call.days_before;
+        for slot in slots_of(events, timezone, &agenda_call.events) {
+            let (weekday, time) = before(&slot, Duration::days(days_before), MORNING);
+            suggestions.push(Suggestion {
+                command: "post-agenda-call".to_string(),
+                note: format!("Ask for agenda items {} ahead", plural(days_before, "day")),
+                weekdays: vec![weekday],
+                time,
+            });
+        }
+    }
+
+    if let Some(reminder_config) = &config.reminders {
+        let lead_times = reminders::lead_times(reminder_config)?;
+        for slot in slots_of(events, timezone, &reminder_config.events) {
+            for lead in &lead_times {
+                let (weekday, time) = at(&slot, -lead.duration());
+                suggestions.push(Suggestion {
+                    command: "post-reminders".to_string(),
+                    note: format!("Remind of meetings {} ahead", lead.text()),
+                    weekdays: vec![weekday],
+                    time,
+                });
+            }
+        }
+    }
+
+    if let Some(minutes) = &config.minutes {
+        for slot in slots_of(events, timezone, &minutes.events) {
+            let (weekday, time) = at(&slot, slot.duration + Duration::hours(minutes.delay_hours));
+            suggestions.push(Suggestion {
+                command: "post-minutes".to_string(),
+                note: format!(
+                    "Follow up {} after meetings end",
+                    plural(minutes.delay_hours, "hour")
+                ),
+                weekdays: vec![weekday],
+                time,
+            });
+        }
+    }
+
+    Ok(Suggestions {
+        timezone,
+        meetings: meetings.len(),
+        slots,
+        suggestions: merge(suggestions),
+    })
+}
+
+/// The start and duration of the timed meetings matching `patterns`, in
+/// `timezone`.
+fn meetings(
+    events: &[CalendarEvent],
+    timezone: Tz,
+    patterns: &[String],
+) -> Vec<(NaiveDateTime, Duration)> {
+    let local = |value: &str| {
+        let (time, form) = ical::parse_ical_time(value)?;
+        match form {
+            TimeForm::Utc => Some(timezone.from_utc_datetime(&time).naive_local()),
+            TimeForm::Floating => Some(time),
+            TimeForm::Date => None,
+        }
+    };
+    let mut meetings: Vec<_> = events
+        .iter()
+        .filter(|event| !event.is_cancelled() && event.summary_matches(patterns))
+        .filter_map(|event| {
+            let start = local(event.start_time.as_deref()?)?;
+            let end = event.end_time.as_deref().and_then(local).unwrap_or(start);
+            Some((start, end - start))
+        })
+        .collect();
+    meetings.sort_by_key(|(start, _)| *start);
+    meetings
+}
+
+/// The slots of the meetings matching `patterns`.
+fn slots_of(events: &[CalendarEvent], timezone: Tz, patterns: &[String]) -> Vec<Slot> {
+    slots(&meetings(events, timezone, patterns))
+}
+
+/// The weekdays and times meetings started at more than once, most used
+/// first; every one of them when none repeats.
+fn slots(meetings: &[(NaiveDateTime, Duration)]) -> Vec<Slot> {
+    let mut starts: BTreeMap<(u32, NaiveTime), (Weekday, Vec<Duration>)> = BTreeMap::new();
+    for (start, duration) in meetings {
+        starts
+            .entry((start.weekday().num_days_from_monday(), start.time()))
+            .or_insert_with(|| (start.weekday(), Vec::new()))
+            .1
+            .push(*duration);
+    }
+    let repeats = starts.values().any(|(_, durations)| durations.len() > 1);
+    let mut slots: Vec<_> = starts
+        .into_iter()
+        .filter(|(_, (_, durations))| !repeats || durations.len() > 1)
+        .map(|((_, time), (weekday, mut durations))| {
+            durations.sort();
+            Slot {
+                weekday,
+                time,
+                duration: durations[durations.len() / 2],
+                meetings: durations.len(),
+            }
+        })
+        .collect();
+    // Stable, so slots used equally often stay in weekly order
+    slots.sort_by_key(|slot| std::cmp::Reverse(slot.meetings));
+    slots.truncate(MAX_SLOTS);
+    slots
+}
+
+/// The usual time between one meeting and the next.
+fn typical_gap(meetings: &[(NaiveDateTime, Duration)]) -> Duration {
+    let mut gaps: Vec<_> = meetings
+        .windows(2)
+        .map(|pair| pair[1].0 - pair[0].0)
+        .filter(|gap| *gap > Duration::zero())
+        .collect();
+    gaps.sort();
+    gaps.get(gaps.len() / 2)
+        .copied()
+        .unwrap_or(Duration::days(7))
+}
+
+/// The slot starting a week's meetings: the one after the longest break.
+fn digest_slot(slots: &[Slot]) -> &Slot {
+    let mut weekly: Vec<_> = slots.iter().collect();
+    weekly.sort_by_key(|slot| (slot.weekday.num_days_from_monday(), slot.time));
+    let minutes = |slot: &Slot| {
+        slot.weekday.num_days_from_monday() as i64 * 24 * 60
+            + (slot.time - NaiveTime::MIN).num_minutes()
+    };
+    (0..weekly.len())
+        .max_by_key(|&index| {
+            let previous = weekly[(index + weekly.len() - 1) % weekly.len()];
+            (minutes(weekly[index]) - minutes(previous)).rem_euclid(7 * 24 * 60)
+        })
+        .map(|index| weekly[index])
+        .unwrap_or(&slots[0])
+}
+
+/// The weekday and time `offset` from a slot's start.
+fn at(slot: &Slot, offset: Duration) -> (Weekday, NaiveTime) {
+    let time = start(slot) + offset;
+    (time.weekday(), time.time())
+}
+
+/// The first `time` of day at least `lead` before a slot's start, so the
+/// post is due by then.
+fn before(slot: &Slot, lead: Duration, time: NaiveTime) -> (Weekday, NaiveTime) {
+    let due = start(slot) - lead;
+    let mut run = due.date().and_time(time);
+    if run < due {
+        run += Duration::days(1);
+    }
+    (run.weekday(), run.time())
+}
+
+/// A start in the week of the slot, to count from.
+fn start(slot: &Slot) -> NaiveDateTime {
+    // 2024-01-01 was a Monday
+    let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap_or_default();
+    (monday + Duration::days(slot.weekday.num_days_from_monday() as i64)).and_time(slot.time)
+}
+
+/// Suggestions running the same command at the same time, on different
+/// weekdays, as one.
+fn merge(suggestions: Vec<Suggestion>) -> Vec<Suggestion> {
+    let mut merged: Vec<Suggestion> = Vec::new();
+    for suggestion in suggestions {
+        match merged.iter_mut().find(|other| {
+            other.command == suggestion.command
+                && other.note == suggestion.note
+                && other.time == suggestion.time
+        }) {
+            Some(other) => {
+                other.weekdays.extend(suggestion.weekdays);
+                other
+                    .weekdays
+                    .sort_by_key(|weekday| weekday.num_days_from_monday());
+                other.weekdays.dedup();
+            }
+            None => merged.push(suggestion),
+        }
+    }
+    merged
+}
+
+fn plural(count: i64, unit: &str) -> String {
+    match count {
+        1 => format!("1 {}", unit),
+        count => format!("{} {}s", count, unit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(summary: &str, start: &str, end: &str) -> CalendarEvent {
+        CalendarEvent {
+            summary: Some(summary.to_string()),
+            start_time: Some(start.to_string()),
+            end_time: Some(end.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn config(extra: &str) -> Config {
+        toml::from_str(&format!(
+            "{}\n{}",
+            indoc::indoc! {r#"
+                instance = "https://mastodon.example"
+                webcal = "https://example.com/calendar.ics"
+                timezone = "America/New_York"
+            "#},
+            extra
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_monthly_meetings() {
+        // First Wednesdays at 19:00 in New York, for two hours
+        let events = [
+            event("Monthly Meeting", "20251204T000000Z", "20251204T020000Z"),
+            event("Monthly Meeting", "20260108T000000Z", "20260108T020000Z"),
+            event("Monthly Meeting", "20260205T000000Z", "20260205T020000Z"),
+            event("Install Fest", "20251213", "20251214"),
+        ];
+        let config = config(indoc::indoc! {r#"
+            [agenda_call]
+            days_before = 2
+
+            [reminders]
+            lead_times = ["24h", "30m"]
+
+            [minutes]
+            delay_hours = 1
+        "#});
+        let found = suggest(&config, &events).unwrap();
+        assert_eq!(found.meetings, 3);
+        assert_eq!(found.slots.len(), 1);
+        assert_eq!(found.slots[0].weekday, Weekday::Wed);
+        assert_eq!(found.slots[0].duration, Duration::hours(2));
+
+        let lines: Vec<_> = found
+            .suggestions
+            .iter()
+            .map(|suggestion| format!("{}  {}", suggestion.crontab(), suggestion.command))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "0 9 * * Thu  post-next",
+                "0 9 * * Tue  post-agenda-call",
+                "0 19 * * Tue  post-reminders",
+                "30 18 * * Wed  post-reminders",
+                "0 22 * * Wed  post-minutes",
+            ]
+        );
+        assert_eq!(
+            found.suggestions[0].on_calendar(found.timezone),
+            "Thu *-*-* 09:00:00 America/New_York"
+        );
+        assert_eq!(
+            found.suggestions[0].note,
+            "Announce the next meeting 7 days ahead"
+        );
+    }
+
+    #[test]
+    fn test_weekly_digest() {
+        // Tuesdays and Thursdays at 18:30, and a one-off on a Saturday
+        let events = [
+            event("Hack Night", "20251202T183000", "20251202T203000"),
+            event("Study Group", "20251204T183000", "20251204T193000"),
+            event("Hack Night", "20251209T183000", "20251209T203000"),
+            event("Study Group", "20251211T183000", "20251211T193000"),
+            event("Picnic", "20251213T120000", "20251213T160000"),
+        ];
+        let config = config(indoc::indoc! {r#"
+            [reminders]
+            lead_times = ["1h"]
+            events = ["hack"]
+        "#});
+        let found = suggest(&config, &events).unwrap();
+        assert_eq!(found.slots.len(), 2);
+        assert_eq!(
+            found.suggestions,
+            [
+                Suggestion {
+                    command: "post-next --count 2".to_string(),
+                    note: "Post a digest of the week's 2 meetings".to_string(),
+                    weekdays: vec![Weekday::Mon],
+                    time: MORNING,
+                },
+                Suggestion {
+                    command: "post-reminders".to_string(),
+                    note: "Remind of meetings 1 hour ahead".to_string(),
+                    weekdays: vec![Weekday::Tue],
+                    time: NaiveTime::from_hms_opt(17, 30, 0).unwrap(),
+                },
+            ]
+        );
+    }
+}
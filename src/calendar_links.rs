@@ -0,0 +1,153 @@
+use crate::ical::{self, CalendarEvent, TimeForm};
+use chrono::{Duration, NaiveDateTime};
+
+/// How long an event without an end is taken to last.
+const DEFAULT_DURATION: Duration = Duration::hours(1);
+
+/// The event's start and end, with how they are given. An event without an
+/// end lasts an hour, or a day when it is all-day.
+fn times(event: &CalendarEvent) -> Option<(NaiveDateTime, NaiveDateTime, TimeForm)> {
+    let (start, form) = event
+        .start_time
+        .as_deref()
+        .and_then(ical::parse_ical_time)?;
+    let end = event
+        .end_time
+        .as_deref()
+        .and_then(ical::parse_ical_time)
+        .map(|(end, _)| end)
+        .filter(|end| *end > start)
+        .unwrap_or(match form {
+            TimeForm::Date => start + Duration::days(1),
+            TimeForm::Utc | TimeForm::Floating => start + DEFAULT_DURATION,
+        });
+    Some((start, end, form))
+}
+
+fn text(value: &Option<String>) -> String {
+    value
+        .as_deref()
+        .map(ical::unescape_text)
+        .unwrap_or_default()
+}
+
+/// What to put in the event's notes: its description and its link.
+fn details(event: &CalendarEvent) -> String {
+    [
+        text(&event.description),
+        event.url.clone().unwrap_or_default(),
+    ]
+    .into_iter()
+    .filter(|part| !part.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n\n")
+}
+
+fn link(base: &str, query: &[(&str, String)]) -> String {
+    let mut url = reqwest::Url::parse(base).expect("valid base URL");
+    url.query_pairs_mut()
+        .extend_pairs(query.iter().filter(|(_, value)| !value.is_empty()));
+    url.to_string()
+}
+
+/// A link that opens Google Calendar with the event filled in, ready to
+/// save; `None` for an event without a start.
+pub fn google(event: &CalendarEvent) -> Option<String> {
+    let (start, end, form) = times(event)?;
+    let format = match form {
+        TimeForm::Utc => "%Y%m%dT%H%M%SZ",
+        TimeForm::Floating => "%Y%m%dT%H%M%S",
+        TimeForm::Date => "%Y%m%d",
+    };
+    Some(link(
+        "https://calendar.google.com/calendar/render",
+        &[
+            ("action", "TEMPLATE".to_string()),
+            ("text", text(&event.summary)),
+            (
+                "dates",
+                format!("{}/{}", start.format(format), end.format(format)),
+            ),
+            ("details", details(event)),
+            ("location", text(&event.location)),
+        ],
+    ))
+}
+
+/// A link that opens Outlook on the web with the event filled in, ready to
+/// save; `None` for an event without a start.
+pub fn outlook(event: &CalendarEvent) -> Option<String> {
+    let (start, end, form) = times(event)?;
+    let format = match form {
+        TimeForm::Utc => "%Y-%m-%dT%H:%M:%SZ",
+        TimeForm::Floating => "%Y-%m-%dT%H:%M:%S",
+        TimeForm::Date => "%Y-%m-%d",
+    };
+    Some(link(
+        "https://outlook.live.com/calendar/0/action/compose",
+        &[
+            ("rru", "addevent".to_string()),
+            ("subject", text(&event.summary)),
+            ("startdt", start.format(format).to_string()),
+            ("enddt", end.format(format).to_string()),
+            ("allday", (form == TimeForm::Date).to_string()),
+            ("body", details(event)),
+            ("location", text(&event.location)),
+        ],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_calendar_links() {
+        let calendar = ical::IcalCalendar::parse_ical_content(indoc! {r"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:meeting@example.com
+            DTSTART:20251203T190000Z
+            DTEND:20251203T210000Z
+            SUMMARY:Monthly Meeting\, December
+            DESCRIPTION:New officers
+            LOCATION:Library
+            URL:https://example.com/meeting
+            END:VEVENT
+            BEGIN:VEVENT
+            DTSTART;VALUE=DATE:20251213
+            SUMMARY:Install Fest
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+        let [meeting, fest] = &calendar.events[..] else {
+            panic!("expected two events");
+        };
+
+        assert_eq!(
+            google(meeting).unwrap(),
+            "https://calendar.google.com/calendar/render?action=TEMPLATE\
+             &text=Monthly+Meeting%2C+December&dates=20251203T190000Z%2F20251203T210000Z\
+             &details=New+officers%0A%0Ahttps%3A%2F%2Fexample.com%2Fmeeting&location=Library"
+        );
+        assert_eq!(
+            outlook(meeting).unwrap(),
+            "https://outlook.live.com/calendar/0/action/compose?rru=addevent\
+             &subject=Monthly+Meeting%2C+December&startdt=2025-12-03T19%3A00%3A00Z\
+             &enddt=2025-12-03T21%3A00%3A00Z&allday=false\
+             &body=New+officers%0A%0Ahttps%3A%2F%2Fexample.com%2Fmeeting&location=Library"
+        );
+
+        // An all-day event without an end takes up its day
+        assert!(google(fest).unwrap().contains("&dates=20251213%2F20251214"));
+        assert!(
+            outlook(fest)
+                .unwrap()
+                .contains("&startdt=2025-12-13&enddt=2025-12-14&allday=true")
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use crate::config::EventCardConfig;
+use crate::ical::CalendarEvent;
+use crate::media::Media;
+use anyhow::{Result, anyhow};
+
+/// Size of a card, in pixels: the 1.91:1 that link previews have.
+pub const WIDTH: usize = 800;
+pub const HEIGHT: usize = 418;
+
+const MARGIN: usize = 48;
+const ACCENT_WIDTH: usize = 16;
+const TITLE_SCALE: usize = 6;
+const TIME_SCALE: usize = 3;
+const LOCATION_SCALE: usize = 3;
+const TITLE_LINES: usize = 3;
+
+/// Palette indices of the colors a card is drawn in.
+const BACKGROUND: u8 = 0;
+const ACCENT: u8 = 1;
+const TEXT: u8 = 2;
+const MUTED: u8 = 3;
+
+/// Columns of the printable ASCII characters in a 5×8 pixel font, the lowest
+/// bit the top row.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5F, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x56, 0x20, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00],
+    [0x2A, 0x1C, 0x7F, 0x1C, 0x2A],
+    [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E],
+    [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3C, 0x4A, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1E],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E],
+    [0x7F, 0x49, 0x49, 0x49, 0x36],
+    [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C],
+    [0x7F, 0x49, 0x49, 0x49, 0x41],
+    [0x7F, 0x09, 0x09, 0x09, 0x01],
+    [0x3E, 0x41, 0x49, 0x49, 0x7A],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F],
+    [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01],
+    [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F],
+    [0x7F, 0x04, 0x08, 0x10, 0x7F],
+    [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06],
+    [0x3E, 0x41, 0x51, 0x21, 0x5E],
+    [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01],
+    [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F],
+    [0x3F, 0x40, 0x38, 0x40, 0x3F],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x7F, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00],
+    [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7E, 0x09, 0x01, 0x02],
+    [0x18, 0xA4, 0xA4, 0xA4, 0x7C],
+    [0x7F, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7D, 0x40, 0x00],
+    [0x40, 0x80, 0x84, 0x7D, 0x00],
+    [0x7F, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7F, 0x40, 0x00],
+    [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0xFC, 0x24, 0x24, 0x24, 0x18],
+    [0x18, 0x24, 0x24, 0x18, 0xFC],
+    [0x7C, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20],
+    [0x3C, 0x40, 0x40, 0x20, 0x7C],
+    [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x1C, 0xA0, 0xA0, 0xA0, 0x7C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+/// Letters and punctuation outside ASCII, each with what the font draws for it.
+const FOLDS: &[(&str, &str)] = &[
+    ("ÀÁÂÃÄÅĀĂĄ", "A"),
+    ("àáâãäåāăą", "a"),
+    ("ÇĆČ", "C"),
+    ("çćč", "c"),
+    ("ĎĐ", "D"),
+    ("ďđ", "d"),
+    ("ÈÉÊËĒĖĘĚ", "E"),
+    ("èéêëēėęě", "e"),
+    ("ĞĢ", "G"),
+    ("ğģ", "g"),
+    ("ÌÍÎÏĪİ", "I"),
+    ("ìíîïīı", "i"),
+    ("ĹĽŁ", "L"),
+    ("ĺľł", "l"),
+    ("ÑŃŅŇ", "N"),
+    ("ñńņň", "n"),
+    ("ÒÓÔÕÖØŌŐ", "O"),
+    ("òóôõöøōő", "o"),
+    ("ŔŘ", "R"),
+    ("ŕř", "r"),
+    ("ŚŞŠ", "S"),
+    ("śşš", "s"),
+    ("ŢŤ", "T"),
+    ("ţť", "t"),
+    ("ÙÚÛÜŪŮŰ", "U"),
+    ("ùúûüūůű", "u"),
+    ("ÝŸ", "Y"),
+    ("ýÿ", "y"),
+    ("ŹŻŽ", "Z"),
+    ("źżž", "z"),
+    ("Æ", "AE"),
+    ("æ", "ae"),
+    ("Œ", "OE"),
+    ("œ", "oe"),
+    ("ß", "ss"),
+    ("‐‑‒–—", "-"),
+    ("‘’‚", "'"),
+    ("“”„", "\""),
+    ("…", "..."),
+    ("·•", "*"),
+];
+
+/// The colors of a card, from the configuration.
+struct Palette {
+    colors: [[u8; 3]; 4],
+}
+
+impl Palette {
+    fn new(config: &EventCardConfig) -> Result<Self> {
+        let background = parse_color(&config.background)?;
+        let text = parse_color(&config.text)?;
+        // Halfway between the text and the background
+        let mut muted = [0; 3];
+        for channel in 0..3 {
+            muted[channel] =
+                ((u16::from(background[channel]) + u16::from(text[channel])) / 2) as u8;
+        }
+        Ok(Palette {
+            colors: [background, parse_color(&config.accent)?, text, muted],
+        })
+    }
+}
+
+/// Parses a color written like `#1d3557`.
+fn parse_color(color: &str) -> Result<[u8; 3]> {
+    let invalid = || anyhow!("Invalid color {:?}, expected e.g. \"#1d3557\"", color);
+    let hex = color.strip_prefix('#').ok_or_else(invalid)?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let channel =
+        |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| invalid());
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// The text of `text` the font can draw: letters with accents lose them,
+/// typographic punctuation becomes its ASCII look-alike, other letters are
+/// drawn as `?` and symbols such as emoji are left out.
+fn drawable(text: &str) -> String {
+    let mut drawn = String::new();
+    for c in text.chars() {
+        if c.is_ascii_graphic() || c == ' ' {
+            drawn.push(c);
+        } else if c.is_whitespace() {
+            drawn.push(' ');
+        } else if let Some((_, fold)) = FOLDS.iter().find(|(from, _)| from.contains(c)) {
+            drawn.push_str(fold);
+        } else if c.is_alphanumeric() {
+            drawn.push('?');
+        }
+    }
+    drawn.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Breaks `text` into at most `max_lines` lines of at most `width`
+/// characters, between words where it can, ending the last line with `...`
+/// when the text doesn't fit.
+fn wrap(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let mut word = word;
+        while !word.is_empty() {
+            let room = match line.is_empty() {
+                true => width,
+                false => width.saturating_sub(line.len() + 1),
+            };
+            if word.len() <= room {
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(word);
+                word = "";
+            } else if line.is_empty() {
+                // A word longer than a line is broken where the line ends
+                line.push_str(&word[..width]);
+                word = &word[width..];
+            } else {
+                lines.push(std::mem::take(&mut line));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let last = &mut lines[max_lines - 1];
+        last.truncate(width.saturating_sub(3));
+        last.truncate(last.trim_end().len());
+        last.push_str("...");
+    }
+    lines
+}
+
+/// A picture being drawn, one palette index per pixel.
+struct Canvas {
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new() -> Self {
+        Canvas {
+            pixels: vec![BACKGROUND; WIDTH * HEIGHT],
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: u8) {
+        for row in y..(y + height).min(HEIGHT) {
+            for column in x..(x + width).min(WIDTH) {
+                self.pixels[row * WIDTH + column] = color;
+            }
+        }
+    }
+
+    /// Draws a line of ASCII text with its top left corner at `x`, `y`, each
+    /// pixel of the font `scale` pixels wide.
+    fn text(&mut self, x: usize, y: usize, scale: usize, color: u8, text: &str) {
+        for (index, c) in text.bytes().enumerate() {
+            let glyph = &FONT[usize::from(c.clamp(b' ', b'~') - b' ')];
+            let left = x + index * 6 * scale;
+            for (column, bits) in glyph.iter().enumerate() {
+                for row in 0..8 {
+                    if bits >> row & 1 == 1 {
+                        self.fill(left + column * scale, y + row * scale, scale, scale, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How many characters of the font fit in a line `scale` times its size.
+fn line_width(scale: usize) -> usize {
+    (WIDTH - ACCENT_WIDTH - 2 * MARGIN) / (6 * scale)
+}
+
+/// The alt text of an event's card, saying all it shows.
+pub fn alt_text(event: &CalendarEvent) -> String {
+    let mut parts = vec![
+        event
+            .summary
+            .clone()
+            .unwrap_or_else(|| "Meeting".to_string()),
+    ];
+    parts.extend(event.start_time_formatted());
+    parts.extend(place(event));
+    format!("Event card: {}", parts.join(", "))
+}
+
+/// Where the event takes place, as the card shows it.
+fn place(event: &CalendarEvent) -> Option<String> {
+    match (&event.location, event.is_online()) {
+        (Some(location), _) => Some(location.clone()),
+        (None, true) => Some("Online".to_string()),
+        (None, false) => None,
+    }
+}
+
+/// Renders the card of an event: its summary, when it starts and where,
+/// as a PNG image.
+pub fn render(config: &EventCardConfig, event: &CalendarEvent) -> Result<Media> {
+    let palette = Palette::new(config)?;
+    let mut canvas = Canvas::new();
+    canvas.fill(0, 0, ACCENT_WIDTH, HEIGHT, ACCENT);
+
+    let left = ACCENT_WIDTH + MARGIN;
+    let mut y = MARGIN;
+    let summary = drawable(event.summary.as_deref().unwrap_or("Meeting"));
+    for line in wrap(&summary, line_width(TITLE_SCALE), TITLE_LINES) {
+        canvas.text(left, y, TITLE_SCALE, TEXT, &line);
+        y += 10 * TITLE_SCALE;
+    }
+
+    // The time and place sit at the bottom, however long the title is
+    let mut details = Vec::new();
+    if let Some(start) = event.start_time_formatted() {
+        details.push((TIME_SCALE, TEXT, start));
+    }
+    if let Some(place) = place(event) {
+        details.push((LOCATION_SCALE, MUTED, place));
+    }
+    let mut y = HEIGHT - MARGIN;
+    for (scale, color, text) in details.into_iter().rev() {
+        y -= 8 * scale;
+        let line = wrap(&drawable(&text), line_width(scale), 1);
+        canvas.text(
+            left,
+            y,
+            scale,
+            color,
+            line.first().map_or("", String::as_str),
+        );
+        y -= 4 * scale;
+    }
+
+    Ok(Media {
+        data: png(&canvas, &palette),
+        file_name: "event.png".to_string(),
+        description: alt_text(event),
+    })
+}
+
+/// Encodes a canvas as a PNG with two bits per pixel, left uncompressed as
+/// so few colors keep it small anyway.
+fn png(canvas: &Canvas, palette: &Palette) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend((WIDTH as u32).to_be_bytes());
+    header.extend((HEIGHT as u32).to_be_bytes());
+    // Bit depth 2, indexed colors, no interlacing
+    header.extend([2, 3, 0, 0, 0]);
+
+    let mut rows = Vec::with_capacity(HEIGHT * (1 + WIDTH / 4));
+    for row in canvas.pixels.chunks(WIDTH) {
+        // Filter type none
+        rows.push(0);
+        rows.extend(row.chunks(4).map(|pixels| {
+            pixels
+                .iter()
+                .chain(std::iter::repeat(&0))
+                .take(4)
+                .fold(0, |byte, pixel| byte << 2 | pixel)
+        }));
+    }
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"PLTE", palette.colors.as_flattened());
+    chunk(&mut png, b"IDAT", &zlib_stored(&rows));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(u16::MAX as usize).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        stream.push(u8::from(index == blocks.len() - 1));
+        let length = block.len() as u16;
+        stream.extend(length.to_le_bytes());
+        stream.extend((!length).to_le_bytes());
+        stream.extend(*block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + u32::from(*byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    stream.extend((b << 16 | a).to_be_bytes());
+    stream
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => crc >> 1 ^ 0xEDB88320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            drawable("Café ☕ “Meetup” — Zürich"),
+            "Cafe \"Meetup\" - Zurich"
+        );
+        assert_eq!(drawable("会議"), "??");
+        assert_eq!(
+            wrap("Weekly community meeting of the docs team", 16, 3),
+            ["Weekly community", "meeting of the", "docs team"]
+        );
+        assert_eq!(
+            wrap("Weekly community meeting of the docs team", 16, 2),
+            ["Weekly community", "meeting of th..."]
+        );
+        assert_eq!(
+            wrap("Supercalifragilistic", 8, 3),
+            ["Supercal", "ifragili", "stic"]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let config: EventCardConfig = toml::from_str("").unwrap();
+        let event = CalendarEvent {
+            summary: Some("Monthly Meeting".to_string()),
+            location: Some("Room 4".to_string()),
+            start_time: Some("20251203T190000Z".to_string()),
+            ..Default::default()
+        };
+        let card = render(&config, &event).unwrap();
+        assert!(card.data.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(&card.data[16..24], [0, 0, 3, 32, 0, 0, 1, 162]);
+        assert!(card.data.ends_with(&[0xAE, 0x42, 0x60, 0x82]));
+        assert!(
+            card.description
+                .starts_with("Event card: Monthly Meeting, ")
+        );
+        assert!(card.description.ends_with(", Room 4"));
+
+        assert!(parse_color("1d3557").is_err());
+        assert!(parse_color("#1d35zz").is_err());
+    }
+}
@@ -0,0 +1,15 @@
+use chrono::{DateTime, Utc};
+
+/// Where the bot gets the current time from, so schedules can be simulated.
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real wall clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
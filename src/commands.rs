@@ -0,0 +1,4451 @@
+use crate::agenda;
+use crate::bridge;
+use crate::cache::FeedCache;
+use crate::card;
+use crate::clock::Clock;
+use crate::config::{
+    self, CancelledEvents, ChangedEvents, Config, LongDigests, MissedReminders, Network,
+    TentativeEvents,
+};
+use crate::error::{self, Error, Result};
+use crate::formatter::{self, BUILTIN_TEMPLATES, NEXT_MEETING_KIND, NEXT_MEETINGS_KIND, Templates};
+use crate::gc;
+use crate::ical::{self, CalendarEvent, EventFilter, IcalCalendar, TimeForm};
+use crate::ics_upload;
+use crate::media::{self, Media};
+use crate::minutes;
+use crate::outbox;
+use crate::overrides::Overrides;
+use crate::poster::{
+    self, FailoverPublisher, MIN_SCHEDULE_AHEAD, NewPoll, Posted, ProfileField, Publisher,
+};
+use crate::probe;
+use crate::profile;
+use crate::quiet;
+use crate::recap::{self, Period};
+use crate::recurrence::{self, Window};
+use crate::remind_me;
+use crate::reminders::{self, LeadTime};
+use crate::scheduling;
+use crate::shortener;
+use crate::skips::{self, SkipReason};
+use crate::source::CalendarSource;
+use crate::state::{
+    AnnouncedDetails, DeferredPost, Mute, PendingCatchUp, PinnedPost, PollRecord, PostLink,
+    PostRecord, ScheduledPost, State, fingerprint,
+};
+use crate::subscription;
+use crate::timing::{self, Phase};
+use crate::uid_map::UidMap;
+use crate::write_back;
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use mastodon_async::{NewStatus, Visibility};
+use std::collections::BTreeMap;
+
+/// How far back recurring events are expanded, for posts about past meetings.
+pub const LOOKBACK_DAYS: i64 = 7;
+
+/// The window recurring events are expanded over for a run at the clock's time.
+pub fn expansion_window(config: &Config, clock: &impl Clock) -> Window {
+    let mut lookback = Duration::days(LOOKBACK_DAYS);
+    if let Some(minutes) = &config.minutes {
+        lookback = lookback.max(Duration::hours(minutes.delay_hours + minutes.window_hours));
+    }
+    Window::around(clock.now(), lookback, Duration::days(config.lookahead_days))
+}
+
+/// Fetches the calendar and expands its recurring events, leaving out those
+/// the `[filter]` rules don't allow and those muted.
+pub async fn load_calendar(
+    source: &impl CalendarSource,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<IcalCalendar> {
+    load_filtered_calendar_over(
+        source,
+        clock,
+        config,
+        &expansion_window(config, clock),
+        state,
+    )
+    .await
+}
+
+/// Like [`load_calendar`], with recurring events expanded over `window`.
+async fn load_filtered_calendar_over(
+    source: &impl CalendarSource,
+    clock: &impl Clock,
+    config: &Config,
+    window: &Window,
+    state: &mut State,
+) -> Result<IcalCalendar> {
+    let mut calendar = load_calendar_over(source, config, window, state).await?;
+    leave_out(clock, config, state, &mut calendar.events)?;
+    Ok(calendar)
+}
+
+/// Drops the muted events and those the `[filter]` rules don't allow.
+fn leave_out(
+    clock: &impl Clock,
+    config: &Config,
+    state: &State,
+    events: &mut Vec<CalendarEvent>,
+) -> Result<()> {
+    events.retain(|event| {
+        let muted = state.muting(event, clock.now()).is_some();
+        if muted {
+            skips::record(event, SkipReason::Muted);
+        }
+        !muted
+    });
+    if let Some(filter) = event_filter(config, clock.now())? {
+        events.retain(|event| {
+            let allowed = filter.allows(event);
+            if !allowed {
+                skips::record(event, SkipReason::Filtered);
+            }
+            allowed
+        });
+    }
+    Ok(())
+}
+
+/// The `[filter]` rules as of `now`, if there are any.
+pub fn event_filter(config: &Config, now: DateTime<Utc>) -> Result<Option<EventFilter>> {
+    config
+        .filter
+        .as_ref()
+        .map(|filter| EventFilter::new(filter, now))
+        .transpose()
+        .map_err(error::invalid)
+}
+
+async fn load_calendar_over(
+    source: &impl CalendarSource,
+    config: &Config,
+    window: &Window,
+    state: &mut State,
+) -> Result<IcalCalendar> {
+    let display_timezone = quiet::display_timezone(config).map_err(error::invalid)?;
+    let mut calendar = source.fetch().await?;
+    if let Some(path) = &config.uid_map {
+        let remapped = state.remap_uids(&UidMap::load(path).map_err(Error::Parse)?);
+        for key in &remapped.dropped {
+            eprintln!(
+                "Warning: dropped what was remembered under {}; its new UID already has a record",
+                key
+            );
+        }
+        if remapped.changed() {
+            state.save()?;
+        }
+    }
+    let changed = timing::time(Phase::Expand, || {
+        recurrence::expand_calendar(&mut calendar, window, state)
+    });
+    if changed {
+        state.save()?;
+    }
+    if let Some(path) = &config.overrides {
+        Overrides::load(path).map_err(Error::Parse)?.apply(
+            &mut calendar.events,
+            quiet::timezone(config).map_err(error::invalid)?,
+        );
+    }
+    for event in &mut calendar.events {
+        event.display_timezone = display_timezone;
+        event.week_start = Some(config.week_start);
+        event.add_hashtags(config.templates.hashtags.iter().map(String::as_str));
+    }
+    Ok(calendar)
+}
+
+/// The calendar's events with recurring ones expanded, earliest first.
+/// `upcoming` leaves out events that already started and `days` those
+/// starting more than that many days from now; recurring events are expanded
+/// at least that far, even past `lookahead_days`.
+pub async fn list_events(
+    source: &impl CalendarSource,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    upcoming: bool,
+    days: Option<i64>,
+) -> Result<Vec<CalendarEvent>> {
+    let now = clock.now();
+    let mut window = expansion_window(config, clock);
+    if let Some(days) = days {
+        window.end = window.end.max(now + Duration::days(days));
+    }
+    let calendar = load_calendar_over(source, config, &window, state).await?;
+
+    let current_time = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let max_date = days.map(|days| {
+        (now + Duration::days(days))
+            .format("%Y%m%dT%H%M%SZ")
+            .to_string()
+    });
+    let mut events: Vec<_> = calendar
+        .events
+        .into_iter()
+        .filter(|event| match event.start_time.as_deref() {
+            Some(start) => {
+                (!upcoming || start > current_time.as_str())
+                    && max_date.as_deref().is_none_or(|max| start <= max)
+            }
+            None => !upcoming && days.is_none(),
+        })
+        .collect();
+    events.sort_by(|a, b| {
+        (a.start_time.is_none(), &a.start_time).cmp(&(b.start_time.is_none(), &b.start_time))
+    });
+    Ok(events)
+}
+
+/// Why `post-next` and `post-all` leave an upcoming event out for the
+/// account being posted to, if they do.
+pub fn skip_reason(config: &Config, event: &CalendarEvent) -> Option<SkipReason> {
+    if event.skip {
+        Some(SkipReason::Overridden)
+    } else if event.is_cancelled() {
+        Some(SkipReason::Cancelled)
+    } else if event.is_private() {
+        Some(SkipReason::Private)
+    } else if config.tentative_events == TentativeEvents::Exclude && event.is_tentative() {
+        Some(SkipReason::Tentative)
+    } else if language_account(config, event) != config.audience {
+        Some(SkipReason::OtherAccount)
+    } else {
+        None
+    }
+}
+
+/// Why `post-next` would leave an event out: as for [`skip_reason`], and
+/// for being muted, not passing `filter`, having started or being announced
+/// already.
+pub fn explain(
+    config: &Config,
+    state: &State,
+    filter: Option<&EventFilter>,
+    event: &CalendarEvent,
+    now: DateTime<Utc>,
+) -> Option<SkipReason> {
+    if state.muting(event, now).is_some() {
+        return Some(SkipReason::Muted);
+    }
+    if filter.is_some_and(|filter| !filter.allows(event)) {
+        return Some(SkipReason::Filtered);
+    }
+    let current_time = now.format("%Y%m%dT%H%M%SZ").to_string();
+    match event.start_time.as_deref() {
+        None => return Some(SkipReason::NoStart),
+        Some(start) if start <= current_time.as_str() => return Some(SkipReason::Past),
+        Some(_) => {}
+    }
+    skip_reason(config, event).or_else(|| {
+        let record = state.posts.get(&State::key(NEXT_MEETING_KIND, event))?;
+        (record.event_hash.as_deref() == Some(details_fingerprint(event).as_str()))
+            .then_some(SkipReason::AlreadyAnnounced)
+    })
+}
+
+/// The upcoming events `post-next` and `post-all` announce for the account
+/// being posted to, earliest first. Why the others are left out is recorded
+/// as they are reached.
+fn announced_events<'a>(
+    config: &'a Config,
+    calendar: &'a IcalCalendar,
+    current_time: &str,
+) -> impl Iterator<Item = &'a CalendarEvent> {
+    for event in &calendar.events {
+        match event.start_time.as_deref() {
+            None => skips::record(event, SkipReason::NoStart),
+            Some(start) if start <= current_time => skips::record(event, SkipReason::Past),
+            Some(_) => {}
+        }
+    }
+    calendar
+        .get_upcoming_events(current_time)
+        .into_iter()
+        .filter(move |event| match skip_reason(config, event) {
+            Some(reason) => {
+                skips::record(event, reason);
+                false
+            }
+            None => {
+                outbox::select(event);
+                true
+            }
+        })
+}
+
+/// Index of the language account that announces an event: the one in the
+/// event's language or, for an event without a LANGUAGE, one listing its
+/// summary. `None` leaves it to the main account.
+pub fn language_account(config: &Config, event: &CalendarEvent) -> Option<usize> {
+    let accounts = &config.language_accounts;
+    match &event.language {
+        Some(tag) => {
+            let primary = tag.split('-').next().unwrap_or(tag);
+            let language = config::parse_language(&primary.to_lowercase()).ok()?;
+            accounts
+                .iter()
+                .position(|account| account.language == language)
+        }
+        None => accounts.iter().position(|account| {
+            !account.events.is_empty() && event.summary_matches(&account.events)
+        }),
+    }
+}
+
+/// The templates for the account being posted to: its own, then those of
+/// `[templates]`, then the built-in ones.
+fn templates(config: &Config) -> Templates<'_> {
+    let account = config.language_account();
+    let custom = &config.templates;
+    Templates {
+        next_meeting: account
+            .and_then(|account| account.next_template.as_deref())
+            .or(custom.next.as_deref())
+            .unwrap_or(BUILTIN_TEMPLATES.next_meeting),
+        next_meetings: account
+            .and_then(|account| account.next_meetings_template.as_deref())
+            .or(custom.next_meetings.as_deref())
+            .unwrap_or(BUILTIN_TEMPLATES.next_meetings),
+        upcoming_meetings: account
+            .and_then(|account| account.upcoming_template.as_deref())
+            .or(custom.upcoming.as_deref())
+            .unwrap_or(BUILTIN_TEMPLATES.upcoming_meetings),
+        no_meetings: account
+            .and_then(|account| account.no_meetings_template.as_deref())
+            .or(custom.no_meetings.as_deref())
+            .unwrap_or(BUILTIN_TEMPLATES.no_meetings),
+    }
+}
+
+/// Copies of the events with the URLs to post in place of their own.
+async fn with_posted_urls(
+    config: &Config,
+    state: &mut State,
+    events: Vec<&CalendarEvent>,
+) -> Result<Vec<CalendarEvent>> {
+    let mut prepared = Vec::new();
+    for event in events {
+        prepared.push(
+            shortener::with_posted_url(config, state, event)
+                .await
+                .map_err(Error::Fetch)?,
+        );
+    }
+    Ok(prepared)
+}
+
+/// The language code deferred posts for the account being posted to are
+/// tagged with.
+fn deferred_account(config: &Config) -> Option<String> {
+    config
+        .language_account()
+        .map(|account| account.language.to_639_3().to_string())
+}
+
+/// Posts the next upcoming meeting, or a note that there are none.
+///
+/// Each occurrence is announced once. When its details have changed since,
+/// `changed_events` says whether to post it again, edit or replace the
+/// earlier status, or leave it; otherwise `None` is returned. During quiet
+/// hours a first announcement is held back instead, without its image, and
+/// `None` is returned too.
+pub async fn post_next_meeting(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Option<Posted>> {
+    let calendar = load_calendar(source, clock, config, state).await?;
+    unpin_past(publisher, clock, state).await?;
+
+    // Get current time in iCal format
+    let current_time = clock.now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    let next_event = announced_events(config, &calendar, &current_time).next();
+
+    let key = next_event.map(|event| State::key(NEXT_MEETING_KIND, event));
+    let event_hash = next_event.map(details_fingerprint);
+    if let (Some(key), Some(event_hash)) = (&key, &event_hash)
+        && !needs_announcing(config, state, key, event_hash)?
+    {
+        if let Some(event) = next_event {
+            skips::record(event, SkipReason::AlreadyAnnounced);
+        }
+        return Ok(None);
+    }
+    let previous = key
+        .as_ref()
+        .and_then(|key| state.posts.get(key))
+        .map(|record| record.status_id.clone());
+
+    // A failed upload shouldn't hold up the announcement itself, and a dry
+    // run doesn't upload at all
+    let mut ics_url = None;
+    if let (Some(upload_config), Some(event), false) =
+        (&config.ics_upload, next_event, config.dry_run)
+    {
+        let policy = Some(&config.url_policy);
+        match ics_upload::upload(upload_config, event, clock.now(), policy).await {
+            Ok(url) => ics_url = Some(url),
+            Err(e) => eprintln!("Warning: could not upload the meeting as .ics: {}", e),
+        }
+    }
+
+    let shown = match next_event {
+        Some(event) => Some(
+            shortener::with_posted_url(config, state, event)
+                .await
+                .map_err(Error::Fetch)?,
+        ),
+        None => None,
+    };
+    let mut status = templates(config)
+        .next_meeting(shown.as_ref(), ics_url.as_deref())
+        .map_err(Error::Parse)?;
+    if config.changed_events == ChangedEvents::Edit
+        && let (Some(key), Some(event)) = (&key, next_event)
+    {
+        status = with_change_note(config, state, key, event, status);
+    }
+    // Templates shaped for a longer limit are cut to fit the network's
+    if let Some(max_chars) = config.max_chars
+        && formatter::status_length(&status) > max_chars
+    {
+        status = formatter::truncate(&status, max_chars);
+    }
+    if previous.is_none()
+        && let Some(key) = &key
+        && let Some(duplicate) = find_duplicate(publisher, config, state, &status).await
+    {
+        eprintln!(
+            "Not announcing the meeting again, as the account already has it: {}",
+            duplicate.url.as_deref().unwrap_or(&duplicate.id)
+        );
+        if let Some(event) = next_event {
+            skips::record(event, SkipReason::AlreadyAnnounced);
+        }
+        record_post(clock, state, key.clone(), &duplicate, None, event_hash);
+        note_text(state, key, &config.post.status(status));
+        if let Some(event) = next_event {
+            note_details(state, key, event);
+        }
+        state.save()?;
+        return Ok(None);
+    }
+    // Updates to an announcement go out right away
+    if previous.is_none()
+        && let Some(at) = schedule_time(clock, config, next_event)?
+    {
+        let image = event_image(config, next_event).await;
+        let posted = publisher
+            .schedule(config.post.status(status), image, at)
+            .await?;
+        if let Some(key) = key {
+            state.scheduled.push(ScheduledPost {
+                id: posted.id.clone(),
+                scheduled_at: at,
+                target: posted.target.clone(),
+                post_key: key,
+            });
+            state.save()?;
+        }
+        return Ok(Some(posted));
+    }
+    if config.changed_events == ChangedEvents::Edit
+        && let Some(key) = &key
+        && shows(state, key, &config.post.status(status.clone()))
+    {
+        if let Some(record) = state.posts.get_mut(key) {
+            record.event_hash = event_hash;
+        }
+        if let Some(event) = next_event {
+            note_details(state, key, event);
+        }
+        state.save()?;
+        return Ok(None);
+    }
+    if holding_back(clock, config, state)? {
+        // An update waits for a run after quiet hours rather than piling up
+        if previous.is_none() {
+            defer(clock, config, state, status, key, None, event_hash)?;
+        }
+        return Ok(None);
+    }
+
+    let text = status.clone();
+    let status = config.post.status(status);
+    let posted_status = status.clone();
+    let posted = match (&previous, config.changed_events) {
+        (Some(id), ChangedEvents::Edit) => publisher.edit(id, status).await?,
+        (previous, changed_events) => {
+            if let (Some(id), ChangedEvents::Replace) = (previous, changed_events) {
+                match publisher.delete(id).await {
+                    Ok(()) => state.pinned.retain(|pin| &pin.status_id != id),
+                    Err(e) => {
+                        eprintln!("Warning: could not delete the earlier announcement: {}", e)
+                    }
+                }
+            }
+
+            let published = match event_image(config, next_event).await {
+                Some(image) => publisher.publish_with_media(status, image).await,
+                None => publisher.publish(status).await,
+            };
+            match published {
+                // Like during quiet hours, only a first announcement waits
+                Err(e) if note_unavailable(clock, config, state, &e) => {
+                    if previous.is_none() {
+                        defer(clock, config, state, text, key, None, event_hash)?;
+                    } else {
+                        state.save()?;
+                    }
+                    return Ok(None);
+                }
+                published => published?,
+            }
+        }
+    };
+
+    if let Some(key) = key {
+        record_post(clock, state, key.clone(), &posted, None, event_hash);
+        note_text(state, &key, &posted_status);
+        if let Some(event) = next_event {
+            note_details(state, &key, event);
+            pin_announcement(publisher, config, state, event, &posted).await?;
+            write_back_link(config, event, &posted).await;
+        }
+        state.save()?;
+    }
+    Ok(Some(posted))
+}
+
+/// Most statuses Mastodon lists at once, which the duplicate check looks
+/// through at most.
+const MAX_RECENT_STATUSES: usize = 40;
+
+/// One of the account's latest statuses showing `text`, when the
+/// `duplicate_check` applies: it may have gone out before the state that
+/// would remember it was lost. Statuses that can't be fetched are taken to
+/// not be there.
+async fn find_duplicate(
+    publisher: &impl Publisher,
+    config: &Config,
+    state: &State,
+    text: &str,
+) -> Option<Posted> {
+    let check = config.duplicate_check.as_ref()?;
+    if !check.always && !state.posts.is_empty() {
+        return None;
+    }
+    let recent = publisher
+        .recent_statuses(check.statuses.min(MAX_RECENT_STATUSES))
+        .await
+        .inspect_err(|e| {
+            eprintln!(
+                "Warning: could not look for the status on the account: {}",
+                e
+            )
+        })
+        .ok()?;
+    let wanted = comparable(text);
+    recent
+        .into_iter()
+        .find(|status| comparable(&status.text) == wanted)
+        .map(|status| status.posted)
+}
+
+/// A status' text as it is compared for duplicates: without links, which
+/// instances may shorten or reword, and regardless of case and spacing.
+fn comparable(text: &str) -> String {
+    text.split_whitespace()
+        .filter(|word| !word.starts_with("http://") && !word.starts_with("https://"))
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Writes the link of the event's announcement back into the calendar, when
+/// configured. Failing to is only worth a warning, as the meeting was
+/// announced all the same.
+async fn write_back_link(config: &Config, event: &CalendarEvent, posted: &Posted) {
+    let Some(url) = posted.url.as_deref().filter(|_| !config.dry_run) else {
+        return;
+    };
+    if let Err(e) = write_back::write(config, event, url).await {
+        eprintln!(
+            "Warning: could not write the announcement's link back to the calendar: {}",
+            e
+        );
+    }
+}
+
+/// The image to attach to the announcement of an event: its own if it has
+/// one that can be fetched, or else its card if cards are configured.
+async fn event_image(config: &Config, event: Option<&CalendarEvent>) -> Option<Media> {
+    let event = event?;
+    let image = media::event_image(event, config.max_image_bytes, Some(&config.url_policy))
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Warning: leaving out the meeting's image: {}", e);
+            None
+        });
+    match (image, &config.event_card) {
+        (Some(image), _) => Some(image),
+        (None, Some(card_config)) => card::render(card_config, event)
+            .inspect_err(|e| eprintln!("Warning: leaving out the meeting's card: {}", e))
+            .ok(),
+        (None, None) => None,
+    }
+}
+
+/// When a status goes out if the instance is to post it later: at the
+/// `--schedule-at` time, or `schedule_before` the start of the meeting it
+/// announces. `None` posts it now, as does a meeting too close for that.
+fn schedule_time(
+    clock: &impl Clock,
+    config: &Config,
+    event: Option<&CalendarEvent>,
+) -> Result<Option<DateTime<Utc>>> {
+    let earliest = clock.now() + MIN_SCHEDULE_AHEAD;
+    if let Some(at) = config.post.schedule_at {
+        if at < earliest {
+            return Err(error::invalid(format!(
+                "Statuses can only be scheduled at least {} minutes ahead",
+                MIN_SCHEDULE_AHEAD.num_minutes()
+            )));
+        }
+        return Ok(Some(at));
+    }
+
+    let (Some(before), Some(event)) = (&config.post.schedule_before, event) else {
+        return Ok(None);
+    };
+    let before = LeadTime::parse(before).map_err(error::invalid)?;
+    let Some(start) = event_time(config, event.start_time.as_deref())? else {
+        return Ok(None);
+    };
+    let at = start - before.duration();
+    Ok((at >= earliest).then_some(at))
+}
+
+/// An iCal time of an event as a point in time. Floating times and dates are
+/// taken to be in the configured timezone.
+fn event_time(config: &Config, value: Option<&str>) -> Result<Option<DateTime<Utc>>> {
+    let Some((time, form)) = value.and_then(ical::parse_ical_time) else {
+        return Ok(None);
+    };
+    Ok(match form {
+        TimeForm::Utc => Some(time.and_utc()),
+        TimeForm::Floating | TimeForm::Date => quiet::timezone(config)
+            .map_err(error::invalid)?
+            .from_local_datetime(&time)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc)),
+    })
+}
+
+/// Pins the announcement of an important meeting to the profile until the
+/// meeting is over, when `pin_important` says to. Failing to isn't fatal.
+async fn pin_announcement(
+    publisher: &impl Publisher,
+    config: &Config,
+    state: &mut State,
+    event: &CalendarEvent,
+    posted: &Posted,
+) -> Result<()> {
+    if !config.pin_important
+        || !event.is_important()
+        || posted.scheduled_at.is_some()
+        || state.pinned.iter().any(|pin| pin.status_id == posted.id)
+    {
+        return Ok(());
+    }
+    let end = event.end_time.as_deref().or(event.start_time.as_deref());
+    let Some(until) = event_time(config, end)? else {
+        return Ok(());
+    };
+    match publisher.set_pinned(&posted.id, true).await {
+        Ok(()) => state.pinned.push(PinnedPost {
+            status_id: posted.id.clone(),
+            until,
+        }),
+        Err(e) => eprintln!("Warning: could not pin the announcement: {}", e),
+    }
+    Ok(())
+}
+
+/// Unpins the announcements of meetings that are over. One that can't be
+/// unpinned, such as a deleted one, is forgotten all the same.
+async fn unpin_past(
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    state: &mut State,
+) -> Result<()> {
+    let now = clock.now();
+    let (past, pinned) = std::mem::take(&mut state.pinned)
+        .into_iter()
+        .partition::<Vec<_>, _>(|pin| pin.until <= now);
+    state.pinned = pinned;
+    if past.is_empty() {
+        return Ok(());
+    }
+    for pin in past {
+        if let Err(e) = publisher.set_pinned(&pin.status_id, false).await {
+            eprintln!("Warning: could not unpin status {}: {}", pin.status_id, e);
+        }
+    }
+    state.save()
+}
+
+/// Whether the next meeting has to be announced under `key`: it wasn't
+/// yet, or its details have changed and `changed_events` doesn't say to
+/// leave it.
+///
+/// Announcements recorded before details were tracked are assumed to be
+/// current.
+fn needs_announcing(
+    config: &Config,
+    state: &mut State,
+    key: &str,
+    event_hash: &str,
+) -> Result<bool> {
+    let Some(record) = state.posts.get_mut(key) else {
+        // It may still be waiting for quiet hours to end
+        return Ok(!state.is_posted(key));
+    };
+    match &record.event_hash {
+        Some(hash) => Ok(hash != event_hash && config.changed_events != ChangedEvents::Skip),
+        None => {
+            record.event_hash = Some(event_hash.to_string());
+            state.save()?;
+            Ok(false)
+        }
+    }
+}
+
+/// Fingerprint of what a status about `event` is rendered from: the
+/// variables `template` is given for it, the template itself, and the
+/// settings that change how its times and link are shown.
+fn render_inputs(config: &Config, event: &CalendarEvent, template: &str) -> String {
+    let context = formatter::event_context(event);
+    let mut names: Vec<&str> = context.names().collect();
+    names.sort_unstable();
+    let variables: Vec<String> = names
+        .into_iter()
+        .map(|name| format!("{}={:?}", name, context.get(name)))
+        .collect();
+    // The shortener's token is left out so it isn't kept in the state file
+    let shortener = config.shortener.as_ref().map(|shortener| {
+        (
+            &shortener.api_url,
+            &shortener.response_field,
+            shortener.min_length,
+        )
+    });
+    let link_check = config.link_check.as_ref().map(|check| check.dead_links);
+    fingerprint(&format!(
+        "{}\n{}\n{}\n{:?}\n{:?}\n{:?}\n{:?}",
+        variables.join("\n"),
+        template,
+        config.timezone,
+        config.display_timezone,
+        config.templates.hashtags,
+        shortener,
+        link_check
+    ))
+}
+
+/// The template the status announcing `event` is rendered from.
+fn next_meeting_template<'a>(config: &'a Config, event: &'a CalendarEvent) -> &'a str {
+    event
+        .template
+        .as_deref()
+        .unwrap_or(templates(config).next_meeting)
+}
+
+/// The text `render` gives for `event` with its posted URL, for the post
+/// under `key`. It is rendered again only when the event, `template` or the
+/// settings it is shown with changed since the last time, so runs every
+/// minute don't render and shorten the links of the same occurrences over
+/// and over.
+async fn render_cached(
+    config: &Config,
+    state: &mut State,
+    key: &str,
+    event: &CalendarEvent,
+    template: &str,
+    render: impl FnOnce(&CalendarEvent) -> Result<String>,
+) -> Result<String> {
+    let inputs = render_inputs(config, event, template);
+    if let Some(text) = state.rendered_text(key, &inputs) {
+        return Ok(text.to_string());
+    }
+    let shown = shortener::with_posted_url(config, state, event)
+        .await
+        .map_err(Error::Fetch)?;
+    let text = render(&shown)?;
+    state.remember_rendered(key.to_string(), inputs, text.clone());
+    Ok(text)
+}
+
+/// Fingerprint of the details an announcement shows about an event.
+fn details_fingerprint(event: &CalendarEvent) -> String {
+    let details = [
+        &event.summary,
+        &event.description,
+        &event.location,
+        &event.url,
+        &event.start_time,
+        &event.end_time,
+        &event.status,
+    ]
+    .map(|detail| detail.as_deref().unwrap_or(""));
+    fingerprint(&details.join("\n"))
+}
+
+/// The state key kind of replies saying an announced meeting was cancelled.
+pub const CORRECTION_KIND: &str = "correction";
+
+/// What `sync` did about an announcement that went out of date.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Synced {
+    /// It was edited to show the meeting as it is now.
+    Edited(Posted),
+    /// It was deleted, with this id, as its meeting was cancelled.
+    Deleted(String),
+    /// A reply to it says its meeting was cancelled.
+    Corrected(Posted),
+}
+
+/// Brings the announcements of meetings that haven't started yet in line
+/// with the calendar. One whose meeting changed or moved is edited, and one
+/// whose meeting was cancelled is deleted or replied to with a correction,
+/// as `cancelled_events` says. Corrections wait for a run after quiet hours.
+///
+/// Announcements of meetings gone from the calendar altogether are left
+/// alone, so a calendar served half empty doesn't take them with it.
+pub async fn sync(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Vec<Synced>> {
+    let calendar = load_calendar(source, clock, config, state).await?;
+    let current_time = clock.now().format("%Y%m%dT%H%M%SZ").to_string();
+    let announced: Vec<(String, PostRecord)> = state
+        .posts
+        .iter()
+        .filter(|(key, _)| {
+            State::split_key(key).is_some_and(|(kind, _, start)| {
+                kind == NEXT_MEETING_KIND && start > current_time.as_str()
+            })
+        })
+        .map(|(key, record)| (key.clone(), record.clone()))
+        .collect();
+
+    let mut synced = Vec::new();
+    for (key, record) in announced {
+        let Some((_, id, start)) = State::split_key(&key) else {
+            continue;
+        };
+        match fate(&calendar, id, start) {
+            Fate::Current(event) => {
+                let event_hash = details_fingerprint(event);
+                match &record.event_hash {
+                    Some(hash) if *hash == event_hash => continue,
+                    Some(_) => {}
+                    // Recorded before details were tracked, so assumed current
+                    None => {
+                        if let Some(record) = state.posts.get_mut(&key) {
+                            record.event_hash = Some(event_hash);
+                        }
+                        state.save()?;
+                        continue;
+                    }
+                }
+
+                let new_key = State::key(NEXT_MEETING_KIND, event);
+                let text = render_cached(
+                    config,
+                    state,
+                    &new_key,
+                    event,
+                    next_meeting_template(config, event),
+                    |shown| {
+                        templates(config)
+                            .next_meeting(Some(shown), None)
+                            .map_err(Error::Parse)
+                    },
+                )
+                .await?;
+                let text = with_change_note(config, state, &key, event, text);
+                let status = config.post.status(text);
+                let edited = match shows(state, &key, &status) {
+                    true => None,
+                    false => Some(publisher.edit(&record.status_id, status.clone()).await?),
+                };
+                // A meeting that moved is remembered at its new time
+                state.posts.remove(&key);
+                state.record(
+                    new_key.clone(),
+                    PostRecord {
+                        event_hash: Some(event_hash),
+                        ..record
+                    },
+                );
+                note_text(state, &new_key, &status);
+                note_details(state, &new_key, event);
+                state.save()?;
+                synced.extend(edited.map(Synced::Edited));
+            }
+            Fate::Cancelled(event) => {
+                let cancelled = CalendarEvent {
+                    start_time: Some(start.to_string()),
+                    ..event.clone()
+                };
+                match config.cancelled_events {
+                    CancelledEvents::Delete => {
+                        publisher.delete(&record.status_id).await?;
+                        state.posts.remove(&key);
+                        state.save()?;
+                        synced.push(Synced::Deleted(record.status_id));
+                    }
+                    CancelledEvents::Correct => {
+                        let correction_key = State::key(CORRECTION_KIND, &cancelled);
+                        if state.is_posted(&correction_key) || holding_back(clock, config, state)? {
+                            continue;
+                        }
+                        let status = NewStatus {
+                            in_reply_to_id: Some(record.status_id.clone()),
+                            ..config
+                                .post
+                                .status(formatter::render_cancellation(&cancelled))
+                        };
+                        let posted = match publisher.publish(status).await {
+                            Err(e) if note_unavailable(clock, config, state, &e) => {
+                                state.save()?;
+                                continue;
+                            }
+                            posted => posted?,
+                        };
+                        record_post(clock, state, correction_key, &posted, None, None);
+                        state.save()?;
+                        synced.push(Synced::Corrected(posted));
+                    }
+                }
+            }
+            Fate::Gone => {}
+        }
+    }
+    Ok(synced)
+}
+
+/// Posts the next `count` upcoming meetings in one status, unless the same
+/// status was posted for them already, or holds it back during quiet hours
+/// and returns `None`.
+pub async fn post_next_meetings(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    count: usize,
+) -> Result<Option<Posted>> {
+    let calendar = load_calendar(source, clock, config, state).await?;
+    let current_time = clock.now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    let next_events: Vec<_> = announced_events(config, &calendar, &current_time)
+        .take(count)
+        .collect();
+
+    let next_events = with_posted_urls(config, state, next_events).await?;
+    let status = templates(config)
+        .next_meetings(&next_events.iter().collect::<Vec<_>>())
+        .map_err(Error::Parse)?;
+
+    // Keyed by the first meeting, and left alone while it shows the same ones
+    let key = match next_events.first() {
+        Some(event) => State::key(NEXT_MEETINGS_KIND, event),
+        None => NEXT_MEETINGS_KIND.to_string(),
+    };
+    let text_hash = status_fingerprint(&config.post.status(status.clone()));
+    if state
+        .posts
+        .get(&key)
+        .is_some_and(|record| record.text_hash.as_ref() == Some(&text_hash))
+    {
+        return Ok(None);
+    }
+
+    let posted =
+        publish_or_defer(publisher, clock, config, state, status, Some(key.clone())).await?;
+    if let Some(posted) = &posted {
+        record_post(clock, state, key.clone(), posted, None, None);
+        if let Some(record) = state.posts.get_mut(&key) {
+            record.text_hash = Some(text_hash);
+        }
+        state.save()?;
+    }
+    Ok(posted)
+}
+
+/// Posts a digest of all upcoming meetings, as a thread when it's too long
+/// and `long_digests = "thread"`, or holds it back during quiet hours and
+/// returns nothing.
+pub async fn post_all_upcoming_meetings(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Vec<Posted>> {
+    let mut calendar = load_calendar(source, clock, config, state).await?;
+    let mut bridged = bridge::bridged_events(config).await.map_err(Error::Fetch)?;
+    leave_out(clock, config, state, &mut bridged)?;
+    calendar.events.extend(bridged);
+
+    // Get current time in iCal format
+    let current_time = clock.now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    // Get all upcoming events (no limit)
+    let upcoming_events: Vec<_> = announced_events(config, &calendar, &current_time).collect();
+
+    let upcoming_events = with_posted_urls(config, state, upcoming_events).await?;
+    let upcoming_events: Vec<_> = upcoming_events.iter().collect();
+    let statuses = match config.long_digests {
+        LongDigests::Shorten => vec![
+            templates(config)
+                .upcoming_meetings(&upcoming_events)
+                .map_err(Error::Parse)?,
+        ],
+        LongDigests::Thread => {
+            let max_chars = max_status_chars(publisher, config).await;
+            templates(config)
+                .upcoming_meetings_thread(&upcoming_events, max_chars)
+                .map_err(Error::Parse)?
+        }
+    };
+    post_thread(publisher, clock, config, state, statuses).await
+}
+
+/// How `backfill` announces the meetings it finds.
+#[derive(Debug, Clone)]
+pub struct Backfill {
+    /// How many days ahead meetings are announced.
+    pub days_ahead: i64,
+    /// How many announcements are sent at once.
+    pub concurrency: usize,
+    /// How long to wait between starting one announcement and the next.
+    pub interval: std::time::Duration,
+}
+
+/// Announces every upcoming meeting in the next `days_ahead` days that
+/// wasn't announced yet, each in its own status as `post-next` would, for a
+/// calendar that already has a season scheduled. `progress` is told how many
+/// of how many are done after each.
+///
+/// Each announcement is recorded as it goes out, so a run that fails part of
+/// the way can be repeated. During quiet hours they are held back instead.
+pub async fn backfill(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    options: &Backfill,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<Vec<Posted>> {
+    use futures::StreamExt;
+
+    let calendar = load_calendar(source, clock, config, state).await?;
+    let current_time = clock.now().format("%Y%m%dT%H%M%SZ").to_string();
+    let until = clock.now() + Duration::days(options.days_ahead);
+
+    let mut pending = Vec::new();
+    for event in announced_events(config, &calendar, &current_time) {
+        if event_time(config, event.start_time.as_deref())?.is_none_or(|start| start > until) {
+            continue;
+        }
+        let key = State::key(NEXT_MEETING_KIND, event);
+        if state.is_posted(&key) {
+            skips::record(event, SkipReason::AlreadyAnnounced);
+            continue;
+        }
+        let text = render_cached(
+            config,
+            state,
+            &key,
+            event,
+            next_meeting_template(config, event),
+            |shown| {
+                templates(config)
+                    .next_meeting(Some(shown), None)
+                    .map_err(Error::Parse)
+            },
+        )
+        .await?;
+        pending.push((event, key, text));
+    }
+
+    if holding_back(clock, config, state)? {
+        for (event, key, text) in pending {
+            let event_hash = Some(details_fingerprint(event));
+            defer(clock, config, state, text, Some(key), None, event_hash)?;
+        }
+        return Ok(Vec::new());
+    }
+
+    let total = pending.len();
+    progress(0, total);
+    let started = tokio::time::Instant::now();
+    let mut announcements = futures::stream::iter(pending.into_iter().enumerate())
+        .map(|(index, (event, key, text))| async move {
+            tokio::time::sleep_until(started + options.interval * index as u32).await;
+            let status = config.post.status(text.clone());
+            let published = match event_image(config, Some(event)).await {
+                Some(image) => publisher.publish_with_media(status.clone(), image).await,
+                None => publisher.publish(status.clone()).await,
+            };
+            (event, key, text, status, published)
+        })
+        .buffer_unordered(options.concurrency.max(1));
+
+    let mut posted = Vec::new();
+    let (mut done, mut failed) = (0, 0);
+    while let Some((event, key, text, status, published)) = announcements.next().await {
+        match published {
+            Ok(announcement) => {
+                record_post(
+                    clock,
+                    state,
+                    key.clone(),
+                    &announcement,
+                    None,
+                    Some(details_fingerprint(event)),
+                );
+                note_text(state, &key, &status);
+                pin_announcement(publisher, config, state, event, &announcement).await?;
+                write_back_link(config, event, &announcement).await;
+                state.save()?;
+                posted.push(announcement);
+            }
+            Err(e) if note_unavailable(clock, config, state, &e) => {
+                let event_hash = Some(details_fingerprint(event));
+                defer(clock, config, state, text, Some(key), None, event_hash)?;
+            }
+            Err(e) => {
+                eprintln!(
+                    "Warning: could not announce {}: {}",
+                    event.summary.as_deref().unwrap_or(&key),
+                    e
+                );
+                failed += 1;
+            }
+        }
+        done += 1;
+        progress(done, total);
+    }
+
+    match failed {
+        0 => Ok(posted),
+        failed => Err(Error::Publish(anyhow!(
+            "{} of {} announcements failed; run backfill again to retry them",
+            failed,
+            total
+        ))),
+    }
+}
+
+/// The longest status to post: as configured, or else as the instance says,
+/// or else Mastodon's default.
+async fn max_status_chars(publisher: &impl Publisher, config: &Config) -> usize {
+    if let Some(max_chars) = config.max_chars {
+        return max_chars;
+    }
+    match publisher.max_status_chars().await {
+        Ok(max_chars) => max_chars.unwrap_or(formatter::MAX_STATUS_CHARS),
+        Err(e) => {
+            eprintln!(
+                "Warning: could not ask the instance how long statuses may be ({}); assuming {} characters",
+                e,
+                formatter::MAX_STATUS_CHARS
+            );
+            formatter::MAX_STATUS_CHARS
+        }
+    }
+}
+
+/// Posts statuses as a thread, each in reply to the one before, or holds
+/// them back during quiet hours, once however many runs do, and returns
+/// nothing.
+pub async fn post_thread(
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    statuses: Vec<String>,
+) -> Result<Vec<Posted>> {
+    let mut statuses = statuses.into_iter();
+    let Some(text) = statuses.next() else {
+        return Ok(Vec::new());
+    };
+    let replies: Vec<_> = statuses.collect();
+    if let Some(at) = schedule_time(clock, config, None)? {
+        if !replies.is_empty() {
+            return Err(error::invalid(
+                "A thread can't be scheduled, as each reply needs the status before it to be out; set long_digests = \"shorten\" to schedule one status",
+            ));
+        }
+        let posted = publisher
+            .schedule(config.post.status(text), None, at)
+            .await?;
+        return Ok(vec![posted]);
+    }
+    let hold = holding_back(clock, config, state)? || !ready_for_thread(config, &replies).await;
+    let first = match hold {
+        true => None,
+        false => match publisher.publish(config.post.status(text.clone())).await {
+            Err(e) if note_unavailable(clock, config, state, &e) => None,
+            first => Some(first?),
+        },
+    };
+    let Some(first) = first else {
+        state.hold_back(DeferredPost {
+            text,
+            replies,
+            post_key: None,
+            template_hash: None,
+            event_hash: None,
+            deferred_at: clock.now(),
+            account: deferred_account(config),
+        });
+        state.save()?;
+        return Ok(Vec::new());
+    };
+    publish_replies(publisher, config, first, replies).await
+}
+
+/// Whether a status with `replies` may go out now: a single status always
+/// may, and a thread when the instance isn't probed or the probe finds it
+/// healthy, so a degraded instance isn't left with half a thread.
+async fn ready_for_thread(config: &Config, replies: &[String]) -> bool {
+    let Some(probe) = &config.probe else {
+        return true;
+    };
+    if replies.is_empty() {
+        return true;
+    }
+    let found = probe::probe(
+        probe,
+        account_instance(config),
+        config.network,
+        Some(&config.url_policy),
+    )
+    .await;
+    if let Some(problem) = &found.problem {
+        eprintln!(
+            "Warning: {} {}; holding the thread back",
+            found.url, problem
+        );
+    }
+    found.healthy
+}
+
+/// Publishes a status and then its replies, each to the one before.
+async fn publish_thread(
+    publisher: &impl Publisher,
+    config: &Config,
+    text: String,
+    replies: Vec<String>,
+) -> Result<Vec<Posted>> {
+    let first = publisher.publish(config.post.status(text)).await?;
+    publish_replies(publisher, config, first, replies).await
+}
+
+/// Publishes replies to a `first` status, each to the one before.
+async fn publish_replies(
+    publisher: &impl Publisher,
+    config: &Config,
+    first: Posted,
+    replies: Vec<String>,
+) -> Result<Vec<Posted>> {
+    let mut posted = vec![first];
+    for reply in replies {
+        let status = NewStatus {
+            in_reply_to_id: posted.last().map(|posted| posted.id.clone()),
+            ..config.post.status(reply)
+        };
+        posted.push(publisher.publish(status).await?);
+    }
+    Ok(posted)
+}
+
+/// Posts a status, or holds it back during quiet hours and returns `None`.
+pub async fn post_status(
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    status: String,
+) -> Result<Option<Posted>> {
+    publish_or_defer(publisher, clock, config, state, status, None).await
+}
+
+/// Posts a status, or holds it back under `post_key` during quiet hours and
+/// returns `None`.
+async fn publish_or_defer(
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    status: String,
+    post_key: Option<String>,
+) -> Result<Option<Posted>> {
+    if let Some(at) = schedule_time(clock, config, None)? {
+        let posted = publisher
+            .schedule(config.post.status(status), None, at)
+            .await?;
+        return Ok(Some(posted));
+    }
+    if holding_back(clock, config, state)? {
+        defer(clock, config, state, status, post_key, None, None)?;
+        return Ok(None);
+    }
+    match publisher.publish(config.post.status(status.clone())).await {
+        Err(e) if note_unavailable(clock, config, state, &e) => {
+            defer(clock, config, state, status, post_key, None, None)?;
+            Ok(None)
+        }
+        posted => Ok(Some(posted?)),
+    }
+}
+
+/// Posts the calls for agenda items that are due and not yet posted. During
+/// quiet hours they are held back instead.
+pub async fn post_agenda_calls(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Vec<Posted>> {
+    let agenda_config = config
+        .agenda_call
+        .as_ref()
+        .ok_or_else(|| error::invalid("No [agenda_call] section found in the configuration"))?;
+
+    let calendar = load_calendar(source, clock, config, state).await?;
+    let due_events = agenda::due_events(&calendar, agenda_config, state, clock.now());
+
+    let mut quiet = holding_back(clock, config, state)?;
+    let mut posted = Vec::new();
+    for event in due_events {
+        let shown = shortener::with_posted_url(config, state, event)
+            .await
+            .map_err(Error::Fetch)?;
+        let status = agenda::render(agenda_config, &shown).map_err(Error::Parse)?;
+        let template_hash = fingerprint(agenda::template_source(agenda_config));
+        if !quiet {
+            let sent = post_and_record(
+                publisher,
+                clock,
+                state,
+                agenda::KIND,
+                event,
+                config.post.status(status.clone()),
+                template_hash.clone(),
+            )
+            .await;
+            match sent {
+                Ok(sent) => {
+                    posted.push(sent);
+                    continue;
+                }
+                // This one and the rest wait for the instance to be back
+                Err(e) if note_unavailable(clock, config, state, &e) => quiet = true,
+                Err(e) => return Err(e),
+            }
+        }
+        let key = State::key(agenda::KIND, event);
+        let event_hash = details_fingerprint(event);
+        defer(
+            clock,
+            config,
+            state,
+            status,
+            Some(key),
+            Some(template_hash),
+            Some(event_hash),
+        )?;
+    }
+    Ok(posted)
+}
+
+/// Edits the agenda calls already posted for meetings that haven't started
+/// yet when the template has changed since, so a formatting fix reaches the
+/// posts still in front of followers.
+///
+/// Calls recorded before templates were tracked are assumed to be current.
+pub async fn update_agenda_calls(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Vec<Posted>> {
+    let agenda_config = config
+        .agenda_call
+        .as_ref()
+        .ok_or_else(|| error::invalid("No [agenda_call] section found in the configuration"))?;
+    let template_hash = fingerprint(agenda::template_source(agenda_config));
+
+    let calendar = load_calendar(source, clock, config, state).await?;
+    let current_time = clock.now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut edited = Vec::new();
+    for event in calendar.get_upcoming_events(&current_time) {
+        let key = State::key(agenda::KIND, event);
+        let Some(record) = state.posts.get_mut(&key) else {
+            continue;
+        };
+        match &record.template_hash {
+            Some(hash) if *hash == template_hash => continue,
+            Some(_) => {}
+            None => {
+                record.template_hash = Some(template_hash.clone());
+                state.save()?;
+                continue;
+            }
+        }
+
+        let status_id = record.status_id.clone();
+
+        let shown = shortener::with_posted_url(config, state, event)
+            .await
+            .map_err(Error::Fetch)?;
+        let status = config
+            .post
+            .status(agenda::render(agenda_config, &shown).map_err(Error::Parse)?);
+        let posted = match shows(state, &key, &status) {
+            true => None,
+            false => Some(publisher.edit(&status_id, status.clone()).await?),
+        };
+        if let Some(record) = state.posts.get_mut(&key) {
+            record.template_hash = Some(template_hash.clone());
+        }
+        note_text(state, &key, &status);
+        state.save()?;
+        edited.extend(posted);
+    }
+    Ok(edited)
+}
+
+/// Posts the minutes follow-ups that are due and not yet posted. During quiet
+/// hours they are held back instead.
+pub async fn post_minutes(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Vec<Posted>> {
+    let minutes_config = config
+        .minutes
+        .as_ref()
+        .ok_or_else(|| error::invalid("No [minutes] section found in the configuration"))?;
+
+    let calendar = load_calendar(source, clock, config, state).await?;
+    let due_events = minutes::due_events(&calendar, minutes_config, state, clock.now());
+
+    let mut quiet = holding_back(clock, config, state)?;
+    let mut posted = Vec::new();
+    for event in due_events {
+        let shown = shortener::with_posted_url(config, state, event)
+            .await
+            .map_err(Error::Fetch)?;
+        let status = minutes::render(minutes_config, &shown).map_err(Error::Parse)?;
+        let template_hash = fingerprint(minutes::template_source(minutes_config));
+        if !quiet {
+            let sent = post_and_record(
+                publisher,
+                clock,
+                state,
+                minutes::KIND,
+                event,
+                config.post.status(status.clone()),
+                template_hash.clone(),
+            )
+            .await;
+            match sent {
+                Ok(sent) => {
+                    posted.push(sent);
+                    continue;
+                }
+                // This one and the rest wait for the instance to be back
+                Err(e) if note_unavailable(clock, config, state, &e) => quiet = true,
+                Err(e) => return Err(e),
+            }
+        }
+        let key = State::key(minutes::KIND, event);
+        let event_hash = details_fingerprint(event);
+        defer(
+            clock,
+            config,
+            state,
+            status,
+            Some(key),
+            Some(template_hash),
+            Some(event_hash),
+        )?;
+    }
+    Ok(posted)
+}
+
+/// Posts how to subscribe to the calendar when `interval_days` have passed
+/// since it was last posted. During quiet hours it is held back instead.
+pub async fn post_subscription_info(
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Option<Posted>> {
+    let subscription_config = config.subscription_post.as_ref().ok_or_else(|| {
+        error::invalid("No [subscription_post] section found in the configuration")
+    })?;
+    if !subscription::is_due(subscription_config, state, clock.now()) {
+        return Ok(None);
+    }
+
+    let status = subscription::render(config, subscription_config).map_err(Error::Parse)?;
+    let template_hash = fingerprint(subscription::template_source(subscription_config));
+    if holding_back(clock, config, state)? {
+        let key = subscription::KIND.to_string();
+        defer(
+            clock,
+            config,
+            state,
+            status,
+            Some(key),
+            Some(template_hash),
+            None,
+        )?;
+        return Ok(None);
+    }
+
+    let posted = match publisher.publish(config.post.status(status.clone())).await {
+        Err(e) if note_unavailable(clock, config, state, &e) => {
+            let key = subscription::KIND.to_string();
+            defer(
+                clock,
+                config,
+                state,
+                status,
+                Some(key),
+                Some(template_hash),
+                None,
+            )?;
+            return Ok(None);
+        }
+        posted => posted?,
+    };
+    record_post(
+        clock,
+        state,
+        subscription::KIND.to_string(),
+        &posted,
+        Some(template_hash),
+        None,
+    );
+    state.save()?;
+    Ok(Some(posted))
+}
+
+/// Posts the recap of the meetings that took place in `period`, unless it
+/// was posted already. Cancelled and private meetings are left out.
+pub async fn post_recap(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    period: &Period,
+) -> Result<Option<Posted>> {
+    let key = period.key();
+    if state.is_posted(&key) {
+        return Ok(None);
+    }
+
+    let (from, to) = period.bounds(quiet::timezone(config).map_err(error::invalid)?);
+    let mut window = expansion_window(config, clock);
+    window.start = window.start.min(from);
+    let calendar = load_filtered_calendar_over(source, clock, config, &window, state).await?;
+    // A second before each end, so a day's all-day events fall on that day
+    let ical_time = |time: DateTime<Utc>| {
+        (time - Duration::seconds(1))
+            .format("%Y%m%dT%H%M%SZ")
+            .to_string()
+    };
+    let events: Vec<_> = calendar
+        .get_events_started_between(&ical_time(from), Some(&ical_time(to)))
+        .into_iter()
+        .filter(|event| !event.is_cancelled() && !event.is_private())
+        .collect();
+
+    let status = recap::render(config, period, &events).map_err(Error::Parse)?;
+    if holding_back(clock, config, state)? {
+        defer(clock, config, state, status, Some(key), None, None)?;
+        return Ok(None);
+    }
+
+    let posted = match publisher.publish(config.post.status(status.clone())).await {
+        Err(e) if note_unavailable(clock, config, state, &e) => {
+            defer(clock, config, state, status, Some(key), None, None)?;
+            return Ok(None);
+        }
+        posted => posted?,
+    };
+    record_post(clock, state, key, &posted, None, None);
+    state.save()?;
+    Ok(Some(posted))
+}
+
+/// Posts the reminders due shortly before meetings, each once per
+/// occurrence and lead time. Reminders aren't held back during quiet hours,
+/// as they would be late once they are over; they are left out instead.
+pub async fn post_reminders(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Vec<Posted>> {
+    let reminders_config = config
+        .reminders
+        .as_ref()
+        .ok_or_else(|| error::invalid("No [reminders] section found in the configuration"))?;
+    if !reminders_config.members.is_empty() && config.network != Network::Mastodon {
+        return Err(error::invalid(
+            "Reminders can only be sent to members from a Mastodon account",
+        ));
+    }
+    if !reminders_config.public && reminders_config.members.is_empty() {
+        return Err(error::invalid(
+            "Reminders that aren't public need the members to send them to",
+        ));
+    }
+
+    let calendar = load_calendar(source, clock, config, state).await?;
+    let current_time = clock.now().format("%Y%m%dT%H%M%SZ").to_string();
+    let events = calendar
+        .get_upcoming_events(&current_time)
+        .into_iter()
+        .filter(|event| skip_reason(config, event).is_none());
+    let due = reminders::due_reminders(events, reminders_config, state, clock.now())
+        .map_err(error::invalid)?;
+    if holding_back(clock, config, state)? {
+        return Ok(Vec::new());
+    }
+
+    // After the bot was down, late reminders don't go out in a burst
+    let (missed, due): (Vec<_>, Vec<_>) = match reminders_config.missed {
+        MissedReminders::Post => (Vec::new(), due),
+        MissedReminders::Skip | MissedReminders::Combine => {
+            due.into_iter().partition(|(event, lead)| {
+                reminders::is_missed(reminders_config, event, *lead, clock.now())
+            })
+        }
+    };
+    let mut posted = Vec::new();
+    match reminders_config.missed {
+        MissedReminders::Post => {}
+        MissedReminders::Skip => {
+            for (event, _) in &missed {
+                skips::record(event, SkipReason::MissedReminder);
+            }
+        }
+        MissedReminders::Combine => {
+            if !missed.is_empty() {
+                match post_missed_reminders(publisher, clock, config, state, &missed).await? {
+                    Some(sent) => posted.push(sent),
+                    None => return Ok(posted),
+                }
+            }
+        }
+    }
+
+    let template_hash = fingerprint(reminders::template_source(reminders_config));
+    'reminders: for (event, lead) in due {
+        let text = render_cached(
+            config,
+            state,
+            &State::key(&lead.kind(), event),
+            event,
+            reminders::template_source(reminders_config),
+            |shown| reminders::render(reminders_config, shown, lead).map_err(Error::Parse),
+        )
+        .await?;
+        let mut statuses = Vec::new();
+        if !reminders_config.members.is_empty() {
+            let mut direct = config
+                .post
+                .status(reminders::direct_message(reminders_config, &text));
+            direct.visibility = Some(Visibility::Direct);
+            statuses.push((reminders::direct_kind(reminders_config, lead), direct));
+        }
+        if reminders_config.public {
+            let mut status = config.post.status(text);
+            if reminders_config.reply_to_announcement
+                && let Some(announcement) = state.posts.get(&State::key(NEXT_MEETING_KIND, event))
+            {
+                status.in_reply_to_id = Some(announcement.status_id.clone());
+            }
+            statuses.push((lead.kind(), status));
+        }
+
+        for (kind, status) in statuses {
+            // Sent before a later one failed
+            if state.is_posted(&State::key(&kind, event)) {
+                continue;
+            }
+            let sent = post_and_record(
+                publisher,
+                clock,
+                state,
+                &kind,
+                event,
+                status,
+                template_hash.clone(),
+            )
+            .await;
+            match sent {
+                Ok(sent) => posted.push(sent),
+                // Like during quiet hours, the reminders are left out
+                Err(e) if note_unavailable(clock, config, state, &e) => {
+                    state.save()?;
+                    break 'reminders;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(posted)
+}
+
+/// Posts one status listing the meetings of the `missed` reminders in their
+/// place, to the members only when reminders aren't public, and remembers
+/// each reminder as sent. `None` when the instance is unavailable.
+async fn post_missed_reminders(
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    missed: &[(&CalendarEvent, LeadTime)],
+) -> Result<Option<Posted>> {
+    let Some(reminders_config) = &config.reminders else {
+        return Ok(None);
+    };
+    let events: Vec<_> = missed.iter().map(|(event, _)| *event).collect();
+    let text = reminders::render_missed(reminders_config, &events).map_err(Error::Parse)?;
+    let status = match reminders_config.public {
+        true => config.post.status(text),
+        false => NewStatus {
+            visibility: Some(Visibility::Direct),
+            ..config
+                .post
+                .status(reminders::direct_message(reminders_config, &text))
+        },
+    };
+    let posted = match publisher.publish(status).await {
+        Err(e) if note_unavailable(clock, config, state, &e) => {
+            state.save()?;
+            return Ok(None);
+        }
+        posted => posted?,
+    };
+    for (event, lead) in missed {
+        outbox::select(event);
+        for kind in [lead.kind(), reminders::direct_kind(reminders_config, *lead)] {
+            record_post(clock, state, State::key(&kind, event), &posted, None, None);
+        }
+    }
+    state.save()?;
+    Ok(Some(posted))
+}
+
+/// What a `remind-me` run did.
+#[derive(Debug, Default)]
+pub struct RemindMeRun {
+    /// Followers who asked to be reminded, each with the post key of the
+    /// announcement they replied to.
+    pub requested: Vec<(String, String)>,
+    /// Followers who asked for no more reminders.
+    pub stopped: Vec<String>,
+    pub reminded: Vec<Posted>,
+}
+
+/// Answers the mentions since the last run: replies asking to be reminded of
+/// the meeting a `post-next` announcement is about are recorded, up to the
+/// cap for the meeting, and a reply with a stop keyword cancels every
+/// reminder asked for. Then each follower who asked is sent a direct message
+/// once the meeting is within the lead time.
+pub async fn remind_me(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<RemindMeRun> {
+    let remind_config = config
+        .remind_me
+        .as_ref()
+        .ok_or_else(|| error::invalid("No [remind_me] section found in the configuration"))?;
+    if config.network != Network::Mastodon {
+        return Err(error::invalid(
+            "Reminders on request can only be sent from a Mastodon account",
+        ));
+    }
+    let lead = remind_me::lead_time(remind_config).map_err(error::invalid)?;
+
+    let announcements: BTreeMap<String, String> = state
+        .posts
+        .iter()
+        .filter(|(key, _)| {
+            State::split_key(key).is_some_and(|(kind, _, _)| kind == NEXT_MEETING_KIND)
+        })
+        .map(|(key, record)| (record.status_id.clone(), key.clone()))
+        .collect();
+    let mut run = RemindMeRun::default();
+    for mention in publisher.mentions(state.last_mention.as_deref()).await? {
+        state.last_mention = Some(mention.id.clone());
+        let account = mention.account;
+        match remind_me::request(remind_config, &mention.text) {
+            Some(remind_me::Request::Stop) => {
+                for accounts in state.reminder_requests.values_mut() {
+                    accounts.retain(|asked| *asked != account);
+                }
+                state
+                    .reminder_requests
+                    .retain(|_, accounts| !accounts.is_empty());
+                run.stopped.push(account);
+            }
+            Some(remind_me::Request::Remind) => {
+                let Some(key) = mention.in_reply_to_id.and_then(|id| announcements.get(&id)) else {
+                    continue;
+                };
+                let accounts = state.reminder_requests.entry(key.clone()).or_default();
+                if accounts.contains(&account) {
+                    continue;
+                }
+                if accounts.len() >= remind_config.max_per_event {
+                    eprintln!(
+                        "Warning: not reminding @{} of {}: {} followers already asked",
+                        account,
+                        key,
+                        accounts.len()
+                    );
+                    continue;
+                }
+                accounts.push(account.clone());
+                run.requested.push((account, key.clone()));
+            }
+            None => {}
+        }
+    }
+    state.save()?;
+
+    let calendar = load_calendar(source, clock, config, state).await?;
+    let current_time = clock.now().format("%Y%m%dT%H%M%SZ").to_string();
+    let events: Vec<_> = calendar
+        .get_upcoming_events(&current_time)
+        .into_iter()
+        .filter(|event| skip_reason(config, event).is_none())
+        .collect();
+    // Meetings that are over, or were cancelled, aren't reminded of
+    state.reminder_requests.retain(|key, _| {
+        events
+            .iter()
+            .any(|event| State::key(NEXT_MEETING_KIND, event) == *key)
+    });
+    state.save()?;
+    if holding_back(clock, config, state)? {
+        return Ok(run);
+    }
+
+    let due_by = (clock.now() + lead.duration())
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string();
+    let template_hash = fingerprint(remind_me::template_source(remind_config));
+    for event in events {
+        let key = State::key(NEXT_MEETING_KIND, event);
+        let Some(accounts) = state.reminder_requests.get(&key).cloned() else {
+            continue;
+        };
+        if event
+            .start_time
+            .as_deref()
+            .is_none_or(|start| start > due_by.as_str())
+        {
+            continue;
+        }
+        for account in accounts {
+            let kind = remind_me::kind(&account);
+            // Sent before a later one failed
+            if !state.is_posted(&State::key(&kind, event)) {
+                let mut status = config.post.status(
+                    remind_me::render(remind_config, event, lead, &account)
+                        .map_err(Error::Parse)?,
+                );
+                status.visibility = Some(Visibility::Direct);
+                let sent = post_and_record(
+                    publisher,
+                    clock,
+                    state,
+                    &kind,
+                    event,
+                    status,
+                    template_hash.clone(),
+                )
+                .await;
+                match sent {
+                    Ok(sent) => run.reminded.push(sent),
+                    Err(e) if note_unavailable(clock, config, state, &e) => {
+                        state.save()?;
+                        return Ok(run);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        state.reminder_requests.remove(&key);
+        state.save()?;
+    }
+    Ok(run)
+}
+
+/// Brings the profile fields for the next meeting, the calendar and the
+/// website up to date, keeping the account's other fields. Returns every
+/// field when they changed, and `None` when they already were up to date.
+pub async fn sync_profile(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Option<Vec<ProfileField>>> {
+    let profile_config = config
+        .profile
+        .as_ref()
+        .ok_or_else(|| error::invalid("No [profile] section found in the configuration"))?;
+    let calendar = load_calendar(source, clock, config, state).await?;
+    let current_time = clock.now().format("%Y%m%dT%H%M%SZ").to_string();
+    let next_event = announced_events(config, &calendar, &current_time).next();
+
+    let current = publisher.profile_fields().await?;
+    let fields = profile::merge(
+        &current,
+        profile::fields(config, profile_config, next_event),
+    )
+    .map_err(error::invalid)?;
+    if fields == current {
+        return Ok(None);
+    }
+    publisher.set_profile_fields(fields.clone()).await?;
+    Ok(Some(fields))
+}
+
+/// Cancels a scheduled status, so that what it announces can be posted
+/// again.
+pub async fn cancel_scheduled(
+    publisher: &impl Publisher,
+    state: &mut State,
+    id: &str,
+) -> Result<()> {
+    publisher.cancel_scheduled(id).await?;
+    state.scheduled.retain(|scheduled| scheduled.id != id);
+    state.save()?;
+    Ok(())
+}
+
+/// The account a scheduled status waits on: the language account that
+/// scheduled it according to the state, or else the main one.
+pub fn scheduled_account(config: &Config, state: &State, id: &str) -> Result<Config> {
+    let Some(target) = state
+        .scheduled
+        .iter()
+        .find(|scheduled| scheduled.id == id)
+        .map(|scheduled| scheduled.target.as_str())
+    else {
+        return Ok(config.clone());
+    };
+    for index in 0..config.language_accounts.len() {
+        let account = config.for_language_account(index);
+        if poster::account_publisher(&account)?.primary().target() == target {
+            return Ok(account);
+        }
+    }
+    Ok(config.clone())
+}
+
+/// Mutes the events `pattern` matches, until `until` if given: an RFC 3339
+/// time, or the start of a date in the configured timezone. A mute of the
+/// same pattern is replaced. Returns when the mute ends.
+pub fn mute(
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    pattern: &str,
+    until: Option<&str>,
+) -> Result<Option<DateTime<Utc>>> {
+    let until = until.map(|until| mute_until(config, until)).transpose()?;
+    state.muted.retain(|mute| mute.pattern != pattern);
+    state.muted.push(Mute {
+        pattern: pattern.to_string(),
+        muted_at: clock.now(),
+        until,
+    });
+    state.save()?;
+    Ok(until)
+}
+
+fn mute_until(config: &Config, until: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(until) {
+        return Ok(time.to_utc());
+    }
+    let date = chrono::NaiveDate::parse_from_str(until, "%Y-%m-%d")
+        .map_err(|_| error::invalid(format!("Expected a date or RFC 3339 time, got {}", until)))?;
+    quiet::timezone(config)
+        .map_err(error::invalid)?
+        .from_local_datetime(&date.and_time(chrono::NaiveTime::MIN))
+        .earliest()
+        .map(|time| time.to_utc())
+        .ok_or_else(|| error::invalid(format!("{} doesn't start in {}", date, config.timezone)))
+}
+
+/// Unmutes the events `pattern` was muting.
+pub fn unmute(state: &mut State, pattern: &str) -> Result<()> {
+    let before = state.muted.len();
+    state.muted.retain(|mute| mute.pattern != pattern);
+    if state.muted.len() == before {
+        return Err(error::invalid(format!("{} isn't muted", pattern)));
+    }
+    state.save()
+}
+
+/// Leaves safe mode, so the next runs post again. Returns whether the bot
+/// was in it.
+pub fn resume(state: &mut State) -> Result<bool> {
+    if !state.health.in_safe_mode() {
+        return Ok(false);
+    }
+    state.health.reset();
+    state.save()?;
+    Ok(true)
+}
+
+/// Forgets what was posted about meetings longer ago than `[gc]` keeps, and
+/// removes the calendars no longer fetched from the cache. A dry run only
+/// counts what the cache would lose.
+pub fn collect_garbage(
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<gc::Collected> {
+    let gc_config = config.gc.clone().unwrap_or_default();
+    let mut collected = gc::collect(&gc_config, state, clock.now());
+    if let Some(cache) = config.calendar_cache() {
+        let cache = FeedCache::new(&cache);
+        gc::collect_cache(
+            &gc_config,
+            &cache,
+            clock.now(),
+            config.dry_run,
+            &mut collected,
+        )
+        .map_err(Error::State)?;
+    }
+    if !collected.is_empty() {
+        state.save()?;
+    }
+    Ok(collected)
+}
+
+/// Posts a poll asking followers to pick between the candidate times of the
+/// next tentative meeting, unless one was already posted for them, fewer
+/// than two are upcoming or it is quiet hours, when a later run posts it.
+pub async fn post_scheduling_poll(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Option<Posted>> {
+    let poll_config = config
+        .scheduling_poll
+        .as_ref()
+        .ok_or_else(|| error::invalid("No [scheduling_poll] section found in the configuration"))?;
+
+    let calendar = load_calendar(source, clock, config, state).await?;
+    let candidates = scheduling::candidates(&calendar, poll_config, clock.now());
+    // Nothing to choose between is the usual case, not a failed run
+    if candidates.len() < 2 {
+        return Ok(None);
+    }
+
+    let key = State::key(scheduling::KIND, candidates[0]);
+    if state.polls.contains_key(&key) || holding_back(clock, config, state)? {
+        return Ok(None);
+    }
+
+    let status = scheduling::render_question(poll_config, &candidates).map_err(Error::Parse)?;
+    let poll = NewPoll {
+        options: candidates
+            .iter()
+            .map(|event| scheduling::option_label(event))
+            .collect(),
+        expires_in: Duration::hours(poll_config.expires_hours).num_seconds(),
+    };
+    // Like during quiet hours, the poll is left for a later run
+    let posted = match publisher
+        .publish_poll(config.post.status(status), poll)
+        .await
+    {
+        Err(e) if note_unavailable(clock, config, state, &e) => {
+            state.save()?;
+            return Ok(None);
+        }
+        posted => posted?,
+    };
+
+    state.polls.insert(
+        key,
+        PollRecord {
+            status_id: posted.id.clone(),
+            url: posted.url.clone(),
+            summary: candidates[0].summary.clone(),
+            starts: candidates
+                .iter()
+                .map(|event| event.start_time.clone().unwrap_or_default())
+                .collect(),
+            expires_at: clock.now() + Duration::hours(poll_config.expires_hours),
+            reported: false,
+        },
+    );
+    state.save()?;
+
+    Ok(Some(posted))
+}
+
+/// Announces the winners of the scheduling polls that have closed, each as a
+/// reply to its poll. During quiet hours they are left for a later run.
+pub async fn post_scheduling_poll_results(
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Vec<Posted>> {
+    let poll_config = config
+        .scheduling_poll
+        .as_ref()
+        .ok_or_else(|| error::invalid("No [scheduling_poll] section found in the configuration"))?;
+    if holding_back(clock, config, state)? {
+        return Ok(Vec::new());
+    }
+
+    let closed: Vec<String> = state
+        .polls
+        .iter()
+        .filter(|(_, poll)| !poll.reported && poll.expires_at <= clock.now())
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    let mut posted = Vec::new();
+    for key in closed {
+        let poll = state.polls[&key].clone();
+        let votes = match publisher.poll_votes(&poll.status_id).await {
+            // Like during quiet hours, the results are left for a later run
+            Err(e) if note_unavailable(clock, config, state, &e) => {
+                state.save()?;
+                break;
+            }
+            votes => votes?,
+        };
+
+        match scheduling::winner(&votes) {
+            Some(winner) => {
+                let mut status = config.post.status(
+                    scheduling::render_result(poll_config, &poll, winner, &votes)
+                        .map_err(Error::Parse)?,
+                );
+                status.in_reply_to_id = Some(poll.status_id.clone());
+                match publisher.publish(status).await {
+                    Err(e) if note_unavailable(clock, config, state, &e) => {
+                        state.save()?;
+                        break;
+                    }
+                    sent => posted.push(sent?),
+                }
+            }
+            None => eprintln!(
+                "Warning: nobody voted in the scheduling poll for {}",
+                poll.summary.as_deref().unwrap_or("(no summary)")
+            ),
+        }
+
+        if let Some(poll) = state.polls.get_mut(&key) {
+            poll.reported = true;
+        }
+        state.save()?;
+    }
+    Ok(posted)
+}
+
+/// Records the statuses that went to the backup account so they can be
+/// posted to the primary later.
+pub fn record_failovers<P: Publisher, B: Publisher>(
+    publisher: &FailoverPublisher<P, B>,
+    clock: &impl Clock,
+    state: &mut State,
+) -> Result<()> {
+    let failovers = publisher.take_failovers();
+    if failovers.is_empty() {
+        return Ok(());
+    }
+    for failover in failovers {
+        let post_key = state
+            .posts
+            .iter()
+            .find(|(_, record)| {
+                record
+                    .links
+                    .get(&failover.posted.target)
+                    .is_some_and(|link| link.status_id == failover.posted.id)
+            })
+            .map(|(key, _)| key.clone());
+        state.catch_up.push(PendingCatchUp {
+            text: failover.text,
+            backup_status_id: failover.posted.id,
+            backup_url: failover.posted.url,
+            failed_over_at: clock.now(),
+            post_key,
+        });
+    }
+    state.save()
+}
+
+/// Posts what went to the backup account to the primary, oldest first.
+///
+/// Stops at the first failure, leaving the rest for the next run.
+pub async fn catch_up(
+    primary: &impl Publisher,
+    config: &Config,
+    state: &mut State,
+) -> Result<Vec<Posted>> {
+    let mut posted = Vec::new();
+    while let Some(pending) = state.catch_up.first() {
+        let status = primary
+            .publish(config.post.status(pending.text.clone()))
+            .await?;
+        if let Some(record) = pending
+            .post_key
+            .as_ref()
+            .and_then(|key| state.posts.get_mut(key))
+        {
+            record.links.insert(status.target.clone(), link(&status));
+        }
+        state.catch_up.remove(0);
+        state.save()?;
+        posted.push(status);
+    }
+    Ok(posted)
+}
+
+/// Posts what was held back during quiet hours or maintenance, oldest
+/// first, once they are over.
+///
+/// Statuses about an event are checked against the calendar first, so they
+/// don't go out stale: when the event changed or moved in the meantime the
+/// status is rendered again, and when it was cancelled or removed a
+/// cancellation is posted in its place (or, for a minutes follow-up,
+/// nothing).
+///
+/// Stops at the first failure, leaving the rest for the next run.
+pub async fn post_deferred(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Vec<Posted>> {
+    if state.deferred.is_empty() || holding_back(clock, config, state)? {
+        return Ok(Vec::new());
+    }
+
+    // Each account's posts wait for the run that posts to it
+    let account = deferred_account(config);
+    let calendar = match state
+        .deferred
+        .iter()
+        .any(|deferred| deferred.account == account && deferred.event_hash.is_some())
+    {
+        true => Some(load_calendar(source, clock, config, state).await?),
+        false => None,
+    };
+
+    let mut posted = Vec::new();
+    while let Some(index) = state
+        .deferred
+        .iter()
+        .position(|deferred| deferred.account == account)
+    {
+        let mut deferred = state.deferred[index].clone();
+        if let Some(calendar) = &calendar {
+            match recheck(config, state, calendar, &deferred).await? {
+                Some(current) => deferred = current,
+                None => {
+                    state.deferred.remove(index);
+                    state.save()?;
+                    continue;
+                }
+            }
+        }
+
+        // A thread waits for a healthy instance, as when it was held back
+        if !ready_for_thread(config, &deferred.replies).await {
+            break;
+        }
+        let status = config.post.status(deferred.text.clone());
+        let thread = match publish_thread(publisher, config, deferred.text, deferred.replies).await
+        {
+            // The rest waits for the instance to be back
+            Err(e) if note_unavailable(clock, config, state, &e) => {
+                state.save()?;
+                break;
+            }
+            thread => thread?,
+        };
+        if let Some(key) = deferred.post_key {
+            record_post(
+                clock,
+                state,
+                key.clone(),
+                &thread[0],
+                deferred.template_hash,
+                deferred.event_hash,
+            );
+            note_text(state, &key, &status);
+        }
+        state.deferred.remove(index);
+        state.save()?;
+        posted.extend(thread);
+    }
+    Ok(posted)
+}
+
+/// A held-back status as it should go out now that the calendar may have
+/// changed, or `None` when there is nothing to post any more.
+async fn recheck(
+    config: &Config,
+    state: &mut State,
+    calendar: &IcalCalendar,
+    deferred: &DeferredPost,
+) -> Result<Option<DeferredPost>> {
+    let (Some(key), Some(event_hash)) = (&deferred.post_key, &deferred.event_hash) else {
+        return Ok(Some(deferred.clone()));
+    };
+    let Some((kind, id, start)) = State::split_key(key) else {
+        return Ok(Some(deferred.clone()));
+    };
+
+    let event = match fate(calendar, id, start) {
+        Fate::Current(event) => event,
+        Fate::Cancelled(event) if kind != minutes::KIND => {
+            let cancelled = CalendarEvent {
+                start_time: Some(start.to_string()),
+                ..event.clone()
+            };
+            return Ok(Some(DeferredPost {
+                text: formatter::render_cancellation(&cancelled),
+                event_hash: None,
+                ..deferred.clone()
+            }));
+        }
+        Fate::Cancelled(_) | Fate::Gone => return Ok(None),
+    };
+
+    let current_hash = details_fingerprint(event);
+    if current_hash == *event_hash {
+        return Ok(Some(deferred.clone()));
+    }
+    let shown = shortener::with_posted_url(config, state, event)
+        .await
+        .map_err(Error::Fetch)?;
+    let text = match kind {
+        agenda::KIND => config
+            .agenda_call
+            .as_ref()
+            .map(|agenda_config| agenda::render(agenda_config, &shown))
+            .transpose()
+            .map_err(Error::Parse)?,
+        minutes::KIND => config
+            .minutes
+            .as_ref()
+            .map(|minutes_config| minutes::render(minutes_config, &shown))
+            .transpose()
+            .map_err(Error::Parse)?,
+        NEXT_MEETING_KIND => Some(
+            templates(config)
+                .next_meeting(Some(&shown), None)
+                .map_err(Error::Parse)?,
+        ),
+        _ => None,
+    };
+    Ok(Some(DeferredPost {
+        text: text.unwrap_or_else(|| deferred.text.clone()),
+        post_key: Some(State::key(kind, event)),
+        event_hash: Some(current_hash),
+        ..deferred.clone()
+    }))
+}
+
+/// What became of the event a status was posted about.
+enum Fate<'a> {
+    /// It still takes place, maybe changed or at another time.
+    Current(&'a CalendarEvent),
+    /// It was cancelled; for an occurrence missing from its series, this is
+    /// the series.
+    Cancelled(&'a CalendarEvent),
+    /// It isn't in the calendar any more.
+    Gone,
+}
+
+/// Finds the event with the id and start of a state key in the calendar.
+fn fate<'a>(calendar: &'a IcalCalendar, id: &str, start: &str) -> Fate<'a> {
+    let same_event: Vec<_> = calendar
+        .events
+        .iter()
+        .filter(|event| State::event_id(event) == id)
+        .collect();
+    // A one-off event found at another time has moved; an occurrence missing
+    // from a series has been cancelled
+    let current = match same_event.as_slice() {
+        [moved] if moved.start_time.as_deref() != Some(start) && !moved.is_cancelled() => {
+            Some(*moved)
+        }
+        events => events
+            .iter()
+            .find(|event| event.start_time.as_deref() == Some(start) && !event.is_cancelled())
+            .copied(),
+    };
+    match (current, same_event.first()) {
+        (Some(event), _) => Fate::Current(event),
+        (None, Some(event)) => Fate::Cancelled(event),
+        (None, None) => Fate::Gone,
+    }
+}
+
+/// Whether posts are held back now: during quiet hours or a maintenance
+/// window, or while the instance said it's unavailable.
+pub fn holding_back(clock: &impl Clock, config: &Config, state: &State) -> Result<bool> {
+    let unavailable = state
+        .unavailable_until
+        .get(account_instance(config))
+        .is_some_and(|until| clock.now() < *until);
+    Ok(unavailable || quiet::is_quiet(config, clock.now()).map_err(error::invalid)?)
+}
+
+/// Whether `error` is the instance saying it's down for maintenance. If so,
+/// posts to it are held back until it said it's back.
+fn note_unavailable(clock: &impl Clock, config: &Config, state: &mut State, error: &Error) -> bool {
+    let Some(unavailable) = poster::unavailable(error) else {
+        return false;
+    };
+    let until = unavailable.until(clock.now());
+    eprintln!(
+        "Warning: {}; holding posts back until {}",
+        unavailable, until
+    );
+    state
+        .unavailable_until
+        .insert(account_instance(config).to_string(), until);
+    true
+}
+
+/// The instance of the account being posted to.
+fn account_instance(config: &Config) -> &str {
+    config
+        .language_account()
+        .map_or(&config.instance, |account| &account.instance)
+}
+
+/// Holds a status back until quiet hours or maintenance are over, once
+/// however many runs hold it back. `event_hash` is the fingerprint of the
+/// details of the event it is about, to check them against before it goes
+/// out.
+fn defer(
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    text: String,
+    post_key: Option<String>,
+    template_hash: Option<String>,
+    event_hash: Option<String>,
+) -> Result<()> {
+    state.hold_back(DeferredPost {
+        text,
+        replies: Vec::new(),
+        post_key,
+        template_hash,
+        event_hash,
+        deferred_at: clock.now(),
+        account: deferred_account(config),
+    });
+    state.save()
+}
+
+/// Publishes a status about an event and records it in the state.
+async fn post_and_record(
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    state: &mut State,
+    kind: &str,
+    event: &CalendarEvent,
+    status: NewStatus,
+    template_hash: String,
+) -> Result<Posted> {
+    outbox::select(event);
+    let key = State::key(kind, event);
+    let posted = publisher.publish(status.clone()).await?;
+
+    // Save after every post so a later failure doesn't cause a repeat
+    record_post(
+        clock,
+        state,
+        key.clone(),
+        &posted,
+        Some(template_hash),
+        Some(details_fingerprint(event)),
+    );
+    note_text(state, &key, &status);
+    state.save()?;
+
+    Ok(posted)
+}
+
+fn record_post(
+    clock: &impl Clock,
+    state: &mut State,
+    key: String,
+    posted: &Posted,
+    template_hash: Option<String>,
+    event_hash: Option<String>,
+) {
+    state.record(
+        key,
+        PostRecord {
+            status_id: posted.id.clone(),
+            url: posted.url.clone(),
+            posted_at: clock.now(),
+            template_hash,
+            event_hash,
+            text_hash: None,
+            links: [(posted.target.clone(), link(posted))].into(),
+            details: None,
+        },
+    );
+}
+
+/// Fingerprint of what a status shows: its text and content warning.
+fn status_fingerprint(status: &NewStatus) -> String {
+    fingerprint(&format!(
+        "{}\n{}",
+        status.spoiler_text.as_deref().unwrap_or_default(),
+        status.status.as_deref().unwrap_or_default()
+    ))
+}
+
+/// Whether the post recorded under `key` already shows `status`, so editing
+/// it would only mark it as edited. Posts recorded before their text was
+/// tracked are assumed to differ.
+fn shows(state: &State, key: &str, status: &NewStatus) -> bool {
+    state
+        .posts
+        .get(key)
+        .and_then(|record| record.text_hash.as_deref())
+        .is_some_and(|hash| hash == status_fingerprint(status))
+}
+
+/// Remembers the text of the post recorded under `key`.
+fn note_text(state: &mut State, key: &str, status: &NewStatus) {
+    if let Some(record) = state.posts.get_mut(key) {
+        record.text_hash = Some(status_fingerprint(status));
+    }
+}
+
+/// Remembers what the post recorded under `key` shows of `event`, to say
+/// what changed when it is edited.
+fn note_details(state: &mut State, key: &str, event: &CalendarEvent) {
+    if let Some(record) = state.posts.get_mut(key) {
+        record.details = Some(AnnouncedDetails::of(event));
+    }
+}
+
+/// `text` with a line saying how `event` changed since the post recorded
+/// under `key` announced it, with `change_notes`.
+fn with_change_note(
+    config: &Config,
+    state: &State,
+    key: &str,
+    event: &CalendarEvent,
+    text: String,
+) -> String {
+    let note = state
+        .posts
+        .get(key)
+        .and_then(|record| record.details.as_ref())
+        .filter(|_| config.change_notes)
+        .and_then(|before| formatter::change_note(before, event));
+    match note {
+        Some(note) => format!("{}\n\n{}", text, note),
+        None => text,
+    }
+}
+
+fn link(posted: &Posted) -> PostLink {
+    PostLink {
+        status_id: posted.id.clone(),
+        url: posted.url.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::{ManualClock, MemorySource, RecordingPublisher};
+    use chrono::{TimeZone, Utc, Weekday};
+    use indoc::indoc;
+
+    const WEEKLY_MEETINGS: &str = indoc! {"
+        BEGIN:VCALENDAR
+        VERSION:2.0
+        PRODID:-//Test//Test//EN
+        BEGIN:VEVENT
+        UID:week-1@example.com
+        DTSTART:20251203T190000Z
+        DTEND:20251203T210000Z
+        SUMMARY:Weekly Meeting
+        END:VEVENT
+        BEGIN:VEVENT
+        UID:week-2@example.com
+        DTSTART:20251210T190000Z
+        DTEND:20251210T210000Z
+        SUMMARY:Weekly Meeting
+        END:VEVENT
+        BEGIN:VEVENT
+        UID:week-3@example.com
+        DTSTART:20251217T190000Z
+        DTEND:20251217T210000Z
+        SUMMARY:Weekly Meeting
+        END:VEVENT
+        END:VCALENDAR
+    "};
+
+    fn config(sections: &str) -> Config {
+        toml::from_str(&format!(
+            "instance = \"https://mastodon.example\"\nwebcal = \"https://example.com/calendar.ics\"\n{}",
+            sections
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_post_next_meeting() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 4, 12, 0, 0).unwrap());
+        let config = config("");
+        let mut state = State::default();
+
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        clock.advance(Duration::weeks(2));
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+
+        let texts = publisher.texts();
+        assert!(texts[0].starts_with("📅 Next Meeting: Weekly Meeting"));
+        assert_eq!(texts[1], "📅 No upcoming meetings found");
+    }
+
+    #[tokio::test]
+    async fn test_custom_templates() {
+        let source = MemorySource::new(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            BEGIN:VEVENT
+            UID:fest@example.com
+            DTSTART:20251203T190000Z
+            SUMMARY:Install Fest
+            CATEGORIES:Linux,Install Fest
+            END:VEVENT
+            END:VCALENDAR
+        "});
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [templates]
+            next = "{summary} on {start} {hashtags}"
+            upcoming = "{#each events}{summary}{/each}"
+            no_meetings = "Nothing planned"
+            hashtags = ["CityLUG", "linux"]
+        "#});
+        let mut state = State::default();
+
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        post_all_upcoming_meetings(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        clock.advance(Duration::weeks(1));
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.texts(),
+            [
+                "Install Fest on Wed, Dec 03, 2025 at 07:00 PM UTC #Linux #InstallFest #CityLUG",
+                "Install Fest",
+                "Nothing planned",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skip_reasons() {
+        let source = MemorySource::new(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            BEGIN:VEVENT
+            UID:past@example.com
+            DTSTART:20251126T190000Z
+            SUMMARY:Last Week
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:cancelled@example.com
+            DTSTART:20251202T190000Z
+            SUMMARY:Cancelled Meeting
+            STATUS:CANCELLED
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:board@example.com
+            DTSTART:20251203T190000Z
+            SUMMARY:Board Meeting
+            CLASS:PRIVATE
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:maybe@example.com
+            DTSTART:20251204T190000Z
+            SUMMARY:Maybe Meeting
+            STATUS:TENTATIVE
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:monthly@example.com
+            DTSTART:20251210T190000Z
+            SUMMARY:Monthly Meeting
+            END:VEVENT
+            END:VCALENDAR
+        "});
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config("tentative_events = \"exclude\"");
+        let mut state = State::default();
+
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert!(publisher.texts()[0].contains("Monthly Meeting"));
+        let skipped = skips::summary();
+        let reason = |uid: &str| {
+            skipped
+                .iter()
+                .find(|skipped| skipped.uid.as_deref() == Some(uid))
+                .map(|skipped| skipped.reason)
+        };
+        assert_eq!(reason("past@example.com"), Some(SkipReason::Past));
+        assert_eq!(reason("cancelled@example.com"), Some(SkipReason::Cancelled));
+        assert_eq!(reason("board@example.com"), Some(SkipReason::Private));
+        assert_eq!(reason("maybe@example.com"), Some(SkipReason::Tentative));
+
+        let events = list_events(&source, &clock, &config, &mut state, false, None)
+            .await
+            .unwrap();
+        let reasons: Vec<_> = events
+            .iter()
+            .map(|event| explain(&config, &state, None, event, clock.now()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                Some(SkipReason::Past),
+                Some(SkipReason::Cancelled),
+                Some(SkipReason::Private),
+                Some(SkipReason::Tentative),
+                Some(SkipReason::AlreadyAnnounced),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filter() {
+        let source = MemorySource::new(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            BEGIN:VEVENT
+            UID:staff-meeting@example.com
+            DTSTART:20251202T190000Z
+            SUMMARY:Staff Meeting
+            CATEGORIES:Internal
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:meetup@example.com
+            DTSTART:20251203T190000Z
+            SUMMARY:Monthly Meetup
+            END:VEVENT
+            END:VCALENDAR
+        "});
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [filter]
+            exclude_categories = ["internal"]
+        "#});
+        let mut state = State::default();
+
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert!(publisher.texts()[0].starts_with("📅 Next Meeting: Monthly Meetup"));
+        assert!(skips::summary().iter().any(|skipped| {
+            skipped.uid.as_deref() == Some("staff-meeting@example.com")
+                && skipped.reason == SkipReason::Filtered
+        }));
+
+        let events = list_events(&source, &clock, &config, &mut state, true, None)
+            .await
+            .unwrap();
+        let filter = event_filter(&config, clock.now()).unwrap();
+        assert_eq!(
+            explain(&config, &state, filter.as_ref(), &events[0], clock.now()),
+            Some(SkipReason::Filtered)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mute() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config("");
+        let mut state = State::default();
+        state.muted.push(Mute {
+            pattern: "week-1@example.com".to_string(),
+            muted_at: clock.now(),
+            until: None,
+        });
+        state.muted.push(Mute {
+            pattern: "weekly *".to_string(),
+            muted_at: clock.now(),
+            until: Some(Utc.with_ymd_and_hms(2025, 12, 5, 0, 0, 0).unwrap()),
+        });
+
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(publisher.texts(), ["📅 No upcoming meetings found"]);
+        assert!(skips::summary().iter().any(|skipped| {
+            skipped.uid.as_deref() == Some("week-2@example.com")
+                && skipped.reason == SkipReason::Muted
+        }));
+
+        // Once the summer break is over only the meeting muted by UID stays
+        // silenced
+        clock.set(Utc.with_ymd_and_hms(2025, 12, 5, 12, 0, 0).unwrap());
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert!(publisher.texts()[1].starts_with("📅 Next Meeting: Weekly Meeting"));
+        assert!(state.is_posted("next-meeting:week-2@example.com@20251210T190000Z"));
+    }
+
+    #[test]
+    fn test_mute_and_unmute() {
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config("timezone = \"Europe/Berlin\"");
+        let mut state = State::default();
+
+        let until = mute(&clock, &config, &mut state, "weekly *", Some("2026-01-05")).unwrap();
+        assert_eq!(
+            until,
+            Some(Utc.with_ymd_and_hms(2026, 1, 4, 23, 0, 0).unwrap())
+        );
+        // Muting again replaces the mute
+        let until = mute(&clock, &config, &mut state, "weekly *", None).unwrap();
+        assert_eq!(until, None);
+        assert_eq!(state.muted.len(), 1);
+        assert!(matches!(
+            mute(&clock, &config, &mut state, "weekly *", Some("next week")),
+            Err(Error::Config(_))
+        ));
+
+        unmute(&mut state, "weekly *").unwrap();
+        assert!(state.muted.is_empty());
+        assert!(matches!(
+            unmute(&mut state, "weekly *"),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_render_cache() {
+        async fn render(
+            config: &Config,
+            state: &mut State,
+            event: &CalendarEvent,
+            renders: &std::cell::Cell<usize>,
+        ) -> String {
+            let key = State::key(NEXT_MEETING_KIND, event);
+            render_cached(
+                config,
+                state,
+                &key,
+                event,
+                next_meeting_template(config, event),
+                |shown| {
+                    renders.set(renders.get() + 1);
+                    templates(config)
+                        .next_meeting(Some(shown), None)
+                        .map_err(Error::Parse)
+                },
+            )
+            .await
+            .unwrap()
+        }
+        let calendar = IcalCalendar::parse_ical_content(WEEKLY_MEETINGS).unwrap();
+        let event = &calendar.events[0];
+        let mut state = State::default();
+        let renders = std::cell::Cell::new(0);
+
+        let config = config("");
+        let text = render(&config, &mut state, event, &renders).await;
+        assert!(text.starts_with("📅 Next Meeting: Weekly Meeting"));
+        assert_eq!(render(&config, &mut state, event, &renders).await, text);
+        assert_eq!(renders.get(), 1);
+
+        // A change to the event or to the templates renders it again
+        let moved = CalendarEvent {
+            location: Some("Room 2".to_string()),
+            ..event.clone()
+        };
+        assert!(
+            render(&config, &mut state, &moved, &renders)
+                .await
+                .contains("📍 Room 2")
+        );
+        let joinable = CalendarEvent {
+            conference: Some("https://meet.example.com/weekly".to_string()),
+            ..moved.clone()
+        };
+        assert!(
+            render(&config, &mut state, &joinable, &renders)
+                .await
+                .contains("🎥 Join: https://meet.example.com/weekly")
+        );
+        assert_eq!(renders.get(), 3);
+
+        // Settings the text doesn't depend on leave it as it was
+        let dry_run = config.with_dry_run(true);
+        render(&dry_run, &mut state, &joinable, &renders).await;
+        assert_eq!(renders.get(), 3);
+
+        let config = Config {
+            templates: crate::config::TemplatesConfig {
+                next: Some("Next up: {summary}".to_string()),
+                ..config.templates.clone()
+            },
+            ..config
+        };
+        assert_eq!(
+            render(&config, &mut state, &joinable, &renders).await,
+            "Next up: Weekly Meeting"
+        );
+        assert_eq!(renders.get(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_changed_events() {
+        let moved = WEEKLY_MEETINGS.replacen(
+            "SUMMARY:Weekly Meeting",
+            "SUMMARY:Weekly Meeting\nLOCATION:Room 2",
+            1,
+        );
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+
+        for (changed_events, posts, edits, deletions) in [
+            ("post", 2, 0, 0),
+            ("edit", 1, 1, 0),
+            ("replace", 2, 0, 1),
+            ("skip", 1, 0, 0),
+        ] {
+            let publisher = RecordingPublisher::new();
+            let config = config(&format!("changed_events = \"{}\"", changed_events));
+            let mut state = State::default();
+
+            for calendar in [WEEKLY_MEETINGS, WEEKLY_MEETINGS, &moved, &moved] {
+                post_next_meeting(
+                    &MemorySource::new(calendar),
+                    &publisher,
+                    &clock,
+                    &config,
+                    &mut state,
+                )
+                .await
+                .unwrap();
+            }
+            assert_eq!(publisher.posts().len(), posts, "{}", changed_events);
+            assert_eq!(publisher.edits().len(), edits, "{}", changed_events);
+            assert_eq!(publisher.deletions().len(), deletions, "{}", changed_events);
+            assert!(state.is_posted("next-meeting:week-1@example.com@20251203T190000Z"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_change_notes() {
+        let moved = WEEKLY_MEETINGS.replacen(
+            "SUMMARY:Weekly Meeting",
+            "SUMMARY:Weekly Meeting\nLOCATION:Room 2",
+            1,
+        );
+        let later = moved.replacen("DTSTART:20251203T190000Z", "DTSTART:20251203T200000Z", 1);
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let publisher = RecordingPublisher::new();
+        let config = config(indoc! {r#"
+            changed_events = "edit"
+            change_notes = true
+        "#});
+        let mut state = State::default();
+        let edited = |index: usize| publisher.edits()[index].1.status.clone().unwrap();
+
+        for calendar in [WEEKLY_MEETINGS, &moved] {
+            post_next_meeting(
+                &MemorySource::new(calendar),
+                &publisher,
+                &clock,
+                &config,
+                &mut state,
+            )
+            .await
+            .unwrap();
+        }
+        assert!(edited(0).ends_with("\n\nUpdated: location changed from none to Room 2"));
+
+        // A meeting that moved is edited by sync, saying from when
+        sync(
+            &MemorySource::new(&later),
+            &publisher,
+            &clock,
+            &config,
+            &mut state,
+        )
+        .await
+        .unwrap();
+        assert!(edited(1).ends_with("\n\nUpdated: time changed from 07:00 PM UTC to 08:00 PM UTC"));
+    }
+
+    #[tokio::test]
+    async fn test_write_back() {
+        let path = std::env::temp_dir().join(format!(
+            "ical-to-masto-write-back-{}.ics",
+            std::process::id()
+        ));
+        std::fs::write(&path, WEEKLY_MEETINGS).unwrap();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let publisher = RecordingPublisher::new();
+        let config: Config = toml::from_str(&format!(
+            "instance = \"https://mastodon.example\"\nwebcal = {:?}\n[write_back]\n",
+            path.display().to_string()
+        ))
+        .unwrap();
+        let source = crate::source::WebcalSource::new(&config.webcal);
+        let posted = post_next_meeting(&source, &publisher, &clock, &config, &mut State::default())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let calendar = IcalCalendar::from_path(&config.webcal).unwrap();
+        assert_eq!(calendar.events[0].posted_url, posted.url);
+        assert_eq!(calendar.events[1].posted_url, None);
+        // Already there, so the file is left alone
+        let url = posted.url.as_deref().unwrap();
+        assert!(
+            !write_back::write(&config, &calendar.events[0], url)
+                .await
+                .unwrap()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_check() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let publisher = RecordingPublisher::new();
+        let config = config(indoc! {r#"
+            [duplicate_check]
+            statuses = 20
+        "#});
+        let mut state = State::default();
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+
+        // With the state lost, the announcement is found on the account
+        let mut state = State::default();
+        let posted = post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert!(posted.is_none());
+        assert_eq!(publisher.posts().len(), 1);
+        let record = &state.posts["next-meeting:week-1@example.com@20251203T190000Z"];
+        assert_eq!(record.status_id, "1");
+
+        assert_eq!(
+            comparable("📅 Next Meeting:  Weekly\nhttps://example.com/a"),
+            comparable("📅 next meeting: weekly https://exa.mple/b")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync() {
+        let moved =
+            WEEKLY_MEETINGS.replacen("DTSTART:20251203T190000Z", "DTSTART:20251204T190000Z", 1);
+        let cancelled = moved.replacen(
+            "SUMMARY:Weekly Meeting",
+            "SUMMARY:Weekly Meeting\nSTATUS:CANCELLED",
+            1,
+        );
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let publisher = RecordingPublisher::new();
+        let config = config("");
+        let mut state = State::default();
+        let sync_with = async |calendar: &str, config: &Config, state: &mut State| {
+            sync(
+                &MemorySource::new(calendar),
+                &publisher,
+                &clock,
+                config,
+                state,
+            )
+            .await
+            .unwrap()
+        };
+
+        post_next_meeting(
+            &MemorySource::new(WEEKLY_MEETINGS),
+            &publisher,
+            &clock,
+            &config,
+            &mut state,
+        )
+        .await
+        .unwrap();
+        assert!(
+            sync_with(WEEKLY_MEETINGS, &config, &mut state)
+                .await
+                .is_empty()
+        );
+
+        let synced = sync_with(&moved, &config, &mut state).await;
+        assert!(matches!(synced.as_slice(), [Synced::Edited(_)]));
+        assert!(
+            publisher.edits()[0]
+                .1
+                .status
+                .as_ref()
+                .unwrap()
+                .contains("Dec 04")
+        );
+        assert!(state.is_posted("next-meeting:week-1@example.com@20251204T190000Z"));
+        assert!(sync_with(&moved, &config, &mut state).await.is_empty());
+
+        let synced = sync_with(&cancelled, &config, &mut state).await;
+        assert!(matches!(synced.as_slice(), [Synced::Corrected(_)]));
+        let correction = &publisher.posts()[1];
+        assert_eq!(correction.in_reply_to_id.as_deref(), Some("1"));
+        assert!(correction.status.as_ref().unwrap().starts_with("❌"));
+        assert!(sync_with(&cancelled, &config, &mut state).await.is_empty());
+
+        let config = self::config("cancelled_events = \"delete\"");
+        let synced = sync_with(&cancelled, &config, &mut state).await;
+        assert_eq!(synced, [Synced::Deleted("1".to_string())]);
+        assert!(
+            state
+                .posts
+                .keys()
+                .all(|key| !key.starts_with("next-meeting:"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_post_next_meetings() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let mut state = State::default();
+
+        post_next_meetings(&source, &publisher, &clock, &config(""), &mut state, 2)
+            .await
+            .unwrap();
+        let text = &publisher.texts()[0];
+        assert!(text.starts_with("📅 Next Meetings:"));
+        assert_eq!(text.matches("Weekly Meeting").count(), 2);
+
+        // The same meetings aren't posted again on the next run
+        clock.advance(Duration::hours(1));
+        let again = post_next_meetings(&source, &publisher, &clock, &config(""), &mut state, 2)
+            .await
+            .unwrap();
+        assert!(again.is_none());
+        assert_eq!(publisher.texts().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_language_accounts() {
+        let source = MemorySource::new(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            DTSTART:20251203T190000Z
+            SUMMARY;LANGUAGE=en-US:Monthly Meeting
+            END:VEVENT
+            BEGIN:VEVENT
+            DTSTART:20251204T190000Z
+            SUMMARY;LANGUAGE=fr:Réunion mensuelle
+            END:VEVENT
+            BEGIN:VEVENT
+            DTSTART:20251205T190000Z
+            SUMMARY:Atelier Linux
+            END:VEVENT
+            END:VCALENDAR
+        "});
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [[language_account]]
+            language = "fr"
+            instance = "https://mastodon.example.fr"
+            token_file = "token-fr.json"
+            events = ["Atelier"]
+            next_template = "📅 Prochaine réunion : {summary}"
+            no_meetings_template = "📅 Aucune réunion à venir"
+        "#});
+        let french = config.for_language_account(0);
+        let mut state = State::default();
+
+        let main = RecordingPublisher::new();
+        post_all_upcoming_meetings(&source, &main, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        let text = &main.texts()[0];
+        assert!(text.starts_with("Upcoming Meetings (1):"));
+        assert!(text.contains("Monthly Meeting"));
+
+        let fr = RecordingPublisher::for_target("mastodon.example.fr");
+        post_next_meeting(&source, &fr, &clock, &french, &mut state)
+            .await
+            .unwrap();
+        post_all_upcoming_meetings(&source, &fr, &clock, &french, &mut state)
+            .await
+            .unwrap();
+        let posts = fr.posts();
+        assert_eq!(
+            posts[0].status.as_deref(),
+            Some("📅 Prochaine réunion : Réunion mensuelle")
+        );
+        assert_eq!(posts[0].language, Some(mastodon_async::Language::Fra));
+        // Without a template of its own, the account uses the built-in one
+        let digest = posts[1].status.as_deref().unwrap();
+        assert!(digest.starts_with("Upcoming Meetings (2):"));
+        assert!(digest.contains("Atelier Linux"));
+
+        clock.advance(Duration::weeks(1));
+        post_next_meeting(&source, &fr, &clock, &french, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(fr.texts()[2], "📅 Aucune réunion à venir");
+
+        // What was held back goes out on the account it was written for
+        for (text, account) in [("Bonjour", Some("fra")), ("Hello", None)] {
+            state.deferred.push(DeferredPost {
+                text: text.to_string(),
+                replies: Vec::new(),
+                post_key: None,
+                template_hash: None,
+                event_hash: None,
+                deferred_at: clock.now(),
+                account: account.map(str::to_string),
+            });
+        }
+        post_deferred(&source, &main, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(main.texts().last().map(String::as_str), Some("Hello"));
+        assert_eq!(state.deferred.len(), 1);
+        post_deferred(&source, &fr, &clock, &french, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(fr.texts().last().map(String::as_str), Some("Bonjour"));
+        assert!(state.deferred.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_events() {
+        let source = MemorySource::new(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:weekly@example.com
+            DTSTART:20251124T190000Z
+            RRULE:FREQ=WEEKLY
+            SUMMARY:Weekly Meeting
+            END:VEVENT
+            BEGIN:VEVENT
+            SUMMARY:Someday
+            END:VEVENT
+            END:VCALENDAR
+        "});
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let mut config = config("");
+        config.lookahead_days = 7;
+        let mut state = State::default();
+        let starts = |events: Vec<CalendarEvent>| -> Vec<_> {
+            events.into_iter().map(|e| e.start_time).collect()
+        };
+
+        // Expanded past the lookahead when asked for more days
+        let events = list_events(&source, &clock, &config, &mut state, true, Some(14))
+            .await
+            .unwrap();
+        assert_eq!(
+            starts(events),
+            [
+                Some("20251201T190000Z".to_string()),
+                Some("20251208T190000Z".to_string())
+            ]
+        );
+
+        // Without filters, past events and those without a start are kept
+        let events = list_events(&source, &clock, &config, &mut state, false, None)
+            .await
+            .unwrap();
+        let starts = starts(events);
+        assert_eq!(starts.first().unwrap().as_deref(), Some("20251124T190000Z"));
+        assert_eq!(starts.last().unwrap(), &None);
+    }
+
+    #[tokio::test]
+    async fn test_subscription_info_monthly() {
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [subscription_post]
+            template = "Subscribe: {subscribe_url}"
+        "#});
+        let mut state = State::default();
+
+        // Run daily for two months
+        for _ in 0..61 {
+            post_subscription_info(&publisher, &clock, &config, &mut state)
+                .await
+                .unwrap();
+            clock.advance(Duration::days(1));
+        }
+        assert_eq!(
+            publisher.texts(),
+            ["Subscribe: webcal://example.com/calendar.ics"; 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recap_last_week() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        // The Monday after the second meeting
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 15, 9, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [recap]
+            template = "{period}: {#each events}{summary} on {start}{/each}"
+        "#});
+        let mut state = State::default();
+        let period = Period::last_week(clock.now().date_naive(), Weekday::Mon, false);
+
+        let posted = post_recap(&source, &publisher, &clock, &config, &mut state, &period)
+            .await
+            .unwrap();
+        assert!(posted.is_some());
+        let again = post_recap(&source, &publisher, &clock, &config, &mut state, &period)
+            .await
+            .unwrap();
+        assert_eq!(again, None);
+        assert_eq!(
+            publisher.texts(),
+            ["last week: Weekly Meeting on Wed, Dec 10, 2025 at 07:00 PM UTC"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reminders_over_a_week() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 8, 0, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [reminders]
+            lead_times = ["1h", "24h"]
+            reply_to_announcement = true
+            template = "{summary} in {lead}"
+        "#});
+        let mut state = State::default();
+        let announced = post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Run every five minutes until the meeting
+        for _ in 0..(12 * 24 * 3) {
+            post_reminders(&source, &publisher, &clock, &config, &mut state)
+                .await
+                .unwrap();
+            clock.advance(Duration::minutes(5));
+        }
+
+        let posts = publisher.posts();
+        let reminders: Vec<_> = posts[1..]
+            .iter()
+            .map(|post| {
+                (
+                    post.status.as_deref().unwrap_or_default(),
+                    post.in_reply_to_id.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            reminders,
+            [
+                ("Weekly Meeting in 1 day", Some(announced.id.clone())),
+                ("Weekly Meeting in 1 hour", Some(announced.id.clone())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_announcement_within_max_chars() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config("max_chars = 30");
+        let mut state = State::default();
+
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(publisher.texts(), ["📅 Next Meeting: Weekly Meetin…"]);
+    }
+
+    #[tokio::test]
+    async fn test_remind_me() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 8, 0, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [remind_me]
+            max_per_event = 2
+            template = "{summary} in {lead}"
+        "#});
+        let mut state = State::default();
+        let announced = post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let reply = Some(announced.id.as_str());
+        publisher.add_mention("alice@example.social", reply, "@bot Remind me!");
+        publisher.add_mention("bob@example.social", None, "@bot remind me");
+        publisher.add_mention("carol@example.social", reply, "@bot remind me");
+        publisher.add_mention("dave@example.social", reply, "@bot remind me");
+        publisher.add_mention("carol@example.social", None, "@bot stop");
+        let run = remind_me(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        let asked: Vec<_> = run.requested.iter().map(|(account, _)| account).collect();
+        // Dave asked once the meeting had the most reminders allowed
+        assert_eq!(asked, ["alice@example.social", "carol@example.social"]);
+        assert_eq!(run.stopped, ["carol@example.social"]);
+        assert!(run.reminded.is_empty());
+        assert_eq!(state.last_mention.as_deref(), Some("5"));
+
+        clock.set(Utc.with_ymd_and_hms(2025, 12, 10, 18, 5, 0).unwrap());
+        for _ in 0..2 {
+            remind_me(&source, &publisher, &clock, &config, &mut state)
+                .await
+                .unwrap();
+        }
+        let posts = publisher.posts();
+        assert_eq!(posts.len(), 2);
+        assert_eq!(
+            posts[1].status.as_deref(),
+            Some("@alice@example.social\nWeekly Meeting in 1 hour")
+        );
+        assert_eq!(posts[1].visibility, Some(Visibility::Direct));
+        assert!(state.reminder_requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pin_important_meetings() {
+        let source = MemorySource::new(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:election@example.com
+            DTSTART:20251203T190000Z
+            DTEND:20251203T210000Z
+            SUMMARY:Board Election
+            PRIORITY:1
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:social@example.com
+            DTSTART:20251210T190000Z
+            DTEND:20251210T210000Z
+            SUMMARY:Social
+            END:VEVENT
+            END:VCALENDAR
+        "});
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config("pin_important = true");
+        let mut state = State::default();
+
+        let election = post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            publisher.texts()[0],
+            "📅 Next Meeting: Board Election (❗important)\n🕒 Wed, Dec 03, 2025 at 07:00 PM UTC"
+        );
+        assert_eq!(publisher.pins(), [election.id]);
+
+        // Once the election is over, its announcement is unpinned
+        clock.set(Utc.with_ymd_and_hms(2025, 12, 3, 22, 0, 0).unwrap());
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(publisher.pins().is_empty());
+        assert!(state.pinned.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backfill() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config("");
+        let mut state = State::default();
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+
+        // The meeting already announced and the one too far ahead are left out
+        let options = Backfill {
+            days_ahead: 10,
+            concurrency: 2,
+            interval: std::time::Duration::ZERO,
+        };
+        let mut steps = Vec::new();
+        let posted = backfill(
+            &source,
+            &publisher,
+            &clock,
+            &config,
+            &mut state,
+            &options,
+            |done, total| steps.push((done, total)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(posted.len(), 1);
+        assert_eq!(steps, [(0, 1), (1, 1)]);
+        assert_eq!(
+            publisher.texts()[1],
+            "📅 Next Meeting: Weekly Meeting\n🕒 Wed, Dec 10, 2025 at 07:00 PM UTC"
+        );
+
+        let options = Backfill {
+            days_ahead: 30,
+            ..options
+        };
+        let posted = backfill(
+            &source,
+            &publisher,
+            &clock,
+            &config,
+            &mut state,
+            &options,
+            |_, _| {},
+        )
+        .await
+        .unwrap();
+        assert_eq!(posted.len(), 1);
+        assert!(state.is_posted("next-meeting:week-3@example.com@20251217T190000Z"));
+        assert_eq!(publisher.posts().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_missed_reminders() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        // Back after being down while both reminders were due
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 3, 12, 0, 0).unwrap());
+        let reminders = |missed: &str| {
+            config(&format!(
+                "[reminders]\nlead_times = [\"8d\"]\nmissed = \"{}\"\ntemplate = \"{{summary}} in {{lead}}\"",
+                missed
+            ))
+        };
+
+        let publisher = RecordingPublisher::new();
+        let mut state = State::default();
+        let config = reminders("post");
+        post_reminders(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(publisher.posts().len(), 2);
+
+        let publisher = RecordingPublisher::new();
+        let mut state = State::default();
+        let config = reminders("skip");
+        post_reminders(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert!(publisher.posts().is_empty());
+
+        let publisher = RecordingPublisher::new();
+        let mut state = State::default();
+        let config = reminders("combine");
+        for _ in 0..2 {
+            post_reminders(&source, &publisher, &clock, &config, &mut state)
+                .await
+                .unwrap();
+        }
+        let [combined] = &publisher.texts()[..] else {
+            panic!("expected one post, got {:?}", publisher.texts());
+        };
+        assert!(combined.starts_with("⏰ Coming up soon (2):"));
+        assert!(state.is_posted("reminder-11520m:week-2@example.com@20251210T190000Z"));
+
+        // A reminder on time is posted as usual
+        clock.advance(Duration::days(6) + Duration::hours(7) + Duration::minutes(5));
+        post_reminders(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(publisher.texts()[1], "Weekly Meeting in 8 days");
+    }
+
+    #[tokio::test]
+    async fn test_direct_reminders() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 10, 18, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [reminders]
+            lead_times = ["1h"]
+            members = ["alice@example.org", "@bob"]
+            public = false
+            template = "{summary} in {lead}"
+        "#});
+        let mut state = State::default();
+
+        for _ in 0..2 {
+            post_reminders(&source, &publisher, &clock, &config, &mut state)
+                .await
+                .unwrap();
+        }
+        let posts = publisher.posts();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(
+            posts[0].status.as_deref(),
+            Some("@alice@example.org @bob\nWeekly Meeting in 1 hour")
+        );
+        assert_eq!(posts[0].visibility, Some(Visibility::Direct));
+    }
+
+    #[tokio::test]
+    async fn test_schedule_announcement_before_meeting() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 4, 12, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [post]
+            schedule_before = "2d"
+        "#});
+        let mut state = State::default();
+
+        let posted = post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap()
+            .unwrap();
+        let at = Utc.with_ymd_and_hms(2025, 12, 8, 19, 0, 0).unwrap();
+        assert_eq!(posted.scheduled_at, Some(at));
+        assert!(publisher.texts().is_empty());
+
+        // Scheduled counts as announced
+        let again = post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(again, None);
+        assert_eq!(publisher.scheduled().len(), 1);
+
+        // Once cancelled, the meeting is announced again, now right away
+        cancel_scheduled(&publisher, &mut state, &posted.id)
+            .await
+            .unwrap();
+        assert!(publisher.scheduled_statuses().await.unwrap().is_empty());
+        clock.set(Utc.with_ymd_and_hms(2025, 12, 9, 12, 0, 0).unwrap());
+        let posted = post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(posted.scheduled_at, None);
+        assert_eq!(publisher.texts().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sync_profile() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new().with_profile_fields(vec![ProfileField {
+            name: "Pronouns".to_string(),
+            value: "it/its".to_string(),
+        }]);
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 4, 12, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [profile]
+            website = "https://example.com"
+        "#});
+        let mut state = State::default();
+
+        let fields = sync_profile(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap()
+            .unwrap();
+        let values: Vec<_> = fields
+            .iter()
+            .map(|field| format!("{}: {}", field.name, field.value))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                "Pronouns: it/its",
+                "Next meeting: Wed, Dec 10, 2025 at 07:00 PM UTC",
+                "Calendar: https://example.com/calendar.ics",
+                "Website: https://example.com",
+            ]
+        );
+
+        // Nothing changed, so the profile is left alone
+        let unchanged = sync_profile(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(unchanged, None);
+        assert_eq!(publisher.profile_updates(), 1);
+
+        clock.advance(Duration::weeks(2));
+        let fields = sync_profile(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fields[1].value, "None scheduled");
+    }
+
+    #[tokio::test]
+    async fn test_agenda_calls_and_minutes_over_several_weeks() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 0, 0, 0).unwrap());
+        let mut state = State::default();
+
+        let config = config(indoc! {r#"
+            [agenda_call]
+            days_before = 2
+            template = "Agenda for {summary}?"
+
+            [minutes]
+            template = "Minutes for {summary}"
+        "#});
+
+        // Run every hour for three weeks
+        for _ in 0..(24 * 21) {
+            post_agenda_calls(&source, &publisher, &clock, &config, &mut state)
+                .await
+                .unwrap();
+            post_minutes(&source, &publisher, &clock, &config, &mut state)
+                .await
+                .unwrap();
+            clock.advance(Duration::hours(1));
+        }
+
+        let texts = publisher.texts();
+        assert_eq!(texts.len(), 6);
+        assert_eq!(texts.iter().filter(|t| t.starts_with("Agenda")).count(), 3);
+        assert_eq!(texts.iter().filter(|t| t.starts_with("Minutes")).count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_update_agenda_calls_after_template_change() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 2, 12, 0, 0).unwrap());
+        let mut state = State::default();
+
+        let original = config(indoc! {r#"
+            [agenda_call]
+            days_before = 2
+            template = "Agenda for {summary}?"
+        "#});
+        post_agenda_calls(&source, &publisher, &clock, &original, &mut state)
+            .await
+            .unwrap();
+        let edited = update_agenda_calls(&source, &publisher, &clock, &original, &mut state)
+            .await
+            .unwrap();
+        assert!(edited.is_empty());
+
+        let fixed = config(indoc! {r#"
+            [agenda_call]
+            days_before = 2
+            template = "Agenda items for {summary}?"
+        "#});
+        let edited = update_agenda_calls(&source, &publisher, &clock, &fixed, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(edited.len(), 1);
+        let edits = publisher.edits();
+        assert_eq!(edits[0].0, "1");
+        assert_eq!(
+            edits[0].1.status.as_deref(),
+            Some("Agenda items for Weekly Meeting?")
+        );
+
+        // A template change that doesn't change the text leaves the call be
+        let rewritten = config(indoc! {r#"
+            [agenda_call]
+            days_before = 2
+            template = "Agenda items for {#if summary}{summary}{else}the meeting{/if}?"
+        "#});
+        let edited = update_agenda_calls(&source, &publisher, &clock, &rewritten, &mut state)
+            .await
+            .unwrap();
+        assert!(edited.is_empty());
+        assert_eq!(publisher.edits().len(), 1);
+
+        // Once the meeting has started its call is left alone
+        clock.advance(Duration::days(2));
+        let later = config(indoc! {r#"
+            [agenda_call]
+            days_before = 2
+            template = "Agenda: {summary}"
+        "#});
+        post_agenda_calls(&source, &publisher, &clock, &later, &mut state)
+            .await
+            .unwrap();
+        update_agenda_calls(&source, &publisher, &clock, &later, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(publisher.edits().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scheduling_poll() {
+        let source = MemorySource::new(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:option-1@example.com
+            DTSTART:20251210T190000
+            SUMMARY:Install Fest
+            STATUS:TENTATIVE
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:option-2@example.com
+            DTSTART:20251213T100000
+            SUMMARY:Install Fest
+            STATUS:TENTATIVE
+            END:VEVENT
+            END:VCALENDAR
+        "});
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config("[scheduling_poll]\nexpires_hours = 24\n");
+        let mut state = State::default();
+
+        let posted = post_scheduling_poll(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap()
+            .unwrap();
+        let poll = publisher.poll(&posted.id).unwrap();
+        assert_eq!(
+            poll.options,
+            vec![
+                "Wed, Dec 10, 2025 at 07:00 PM",
+                "Sat, Dec 13, 2025 at 10:00 AM"
+            ]
+        );
+        assert_eq!(poll.expires_in, 24 * 60 * 60);
+
+        // The same candidates aren't polled twice
+        assert!(
+            post_scheduling_poll(&source, &publisher, &clock, &config, &mut state)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Without two candidates there is nothing to poll
+        let later = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 11, 12, 0, 0).unwrap());
+        let posts = publisher.posts().len();
+        assert!(
+            post_scheduling_poll(&source, &publisher, &later, &config, &mut State::default())
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(publisher.posts().len(), posts);
+
+        // Nothing is announced while the poll is open
+        publisher.set_votes(&posted.id, vec![2, 5]);
+        assert!(
+            post_scheduling_poll_results(&publisher, &clock, &config, &mut state)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        clock.advance(Duration::hours(24));
+        let results = post_scheduling_poll_results(&publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        let reply = publisher.posts().pop().unwrap();
+        assert_eq!(reply.in_reply_to_id, Some(posted.id.clone()));
+        assert_eq!(
+            reply.status.as_deref(),
+            Some(
+                "🗳️ The votes are in: Install Fest will be held Sat, Dec 13, 2025 at 10:00 AM (5 of 7 votes). Thanks for voting!"
+            )
+        );
+
+        assert!(
+            post_scheduling_poll_results(&publisher, &clock, &config, &mut state)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 4, 12, 0, 0).unwrap());
+        let config = config("");
+        let publisher = RecordingPublisher::new();
+        let mut state = State::default();
+
+        let error = post_minutes(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Config(_)), "{:?}", error);
+        assert_eq!(
+            error.to_string(),
+            "No [minutes] section found in the configuration"
+        );
+
+        publisher.set_reachable(false);
+        let error = post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Publish(_)), "{:?}", error);
+
+        let broken = MemorySource::new("BEGIN:VCALENDAR\nBEGIN:VEVENT\n");
+        let error = post_next_meeting(&broken, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Parse(_)), "{:?}", error);
+    }
+
+    #[tokio::test]
+    async fn test_failover_and_catch_up() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 4, 12, 0, 0).unwrap());
+        let config = config("");
+        let mut state = State::default();
+
+        let primary = RecordingPublisher::new();
+        primary.set_reachable(false);
+        let backup = RecordingPublisher::new();
+        let publisher =
+            FailoverPublisher::new(&primary).with_backup(&backup, 3, std::time::Duration::ZERO);
+
+        post_next_meeting(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        record_failovers(&publisher, &clock, &mut state).unwrap();
+        assert!(primary.texts().is_empty());
+        assert_eq!(backup.texts().len(), 1);
+        assert_eq!(state.catch_up.len(), 1);
+        assert_eq!(state.catch_up[0].backup_status_id, "1");
+
+        // Still down: the catch-up waits for a later run
+        assert!(matches!(
+            catch_up(publisher.primary(), &config, &mut state).await,
+            Err(Error::Publish(_))
+        ));
+        assert_eq!(state.catch_up.len(), 1);
+
+        primary.set_reachable(true);
+        let posted = catch_up(publisher.primary(), &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(posted.len(), 1);
+        assert_eq!(primary.texts(), backup.texts());
+        assert!(state.catch_up.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_quiet_hours_defer_posts() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        // Saturday, November 29th
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 11, 29, 12, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [quiet_hours]
+            sat = []
+            sun = []
+
+            [agenda_call]
+            days_before = 5
+            template = "Agenda for {summary}?"
+        "#});
+        let mut state = State::default();
+
+        post_agenda_calls(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        post_agenda_calls(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert!(publisher.texts().is_empty());
+        assert_eq!(state.deferred.len(), 1);
+
+        // Still quiet on Sunday
+        clock.advance(Duration::days(1));
+        assert!(
+            post_deferred(&source, &publisher, &clock, &config, &mut state)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        clock.advance(Duration::days(1));
+        let posted = post_deferred(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(posted.len(), 1);
+        assert_eq!(publisher.texts(), ["Agenda for Weekly Meeting?"]);
+        assert!(state.deferred.is_empty());
+        assert!(state.is_posted("agenda-call:week-1@example.com@20251203T190000Z"));
+        assert!(
+            post_agenda_calls(&source, &publisher, &clock, &config, &mut state)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_defers_posts() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 11, 29, 12, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [[maintenance]]
+            start = "2025-11-30T02:00:00Z"
+            end = "2025-11-30T04:00:00Z"
+        "#});
+        let mut state = State::default();
+
+        // Unannounced maintenance, answered with a Retry-After
+        publisher.set_maintenance(Some(Utc.with_ymd_and_hms(2025, 11, 29, 13, 0, 0).unwrap()));
+        assert_eq!(
+            post_status(&publisher, &clock, &config, &mut state, "First".to_string())
+                .await
+                .unwrap(),
+            None
+        );
+        // Posts wait for when it said, even if it's back earlier
+        publisher.set_maintenance(None);
+        clock.advance(Duration::minutes(30));
+        assert_eq!(
+            post_status(
+                &publisher,
+                &clock,
+                &config,
+                &mut state,
+                "Second".to_string()
+            )
+            .await
+            .unwrap(),
+            None
+        );
+        assert!(publisher.texts().is_empty());
+
+        clock.advance(Duration::minutes(30));
+        let posted = post_deferred(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(posted.len(), 2);
+        assert_eq!(publisher.texts(), ["First", "Second"]);
+
+        // And during the announced window
+        clock.set(Utc.with_ymd_and_hms(2025, 11, 30, 3, 0, 0).unwrap());
+        assert_eq!(
+            post_status(&publisher, &clock, &config, &mut state, "Third".to_string())
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(state.deferred.len(), 1);
+
+        // Held back on another run, it still goes out once
+        clock.advance(Duration::minutes(30));
+        assert_eq!(
+            post_status(&publisher, &clock, &config, &mut state, "Third".to_string())
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(state.deferred.len(), 1);
+        clock.set(Utc.with_ymd_and_hms(2025, 11, 30, 5, 0, 0).unwrap());
+        post_deferred(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(publisher.texts(), ["First", "Second", "Third"]);
+    }
+
+    #[tokio::test]
+    async fn test_deferred_posts_checked_before_posting() {
+        let config = config(indoc! {r#"
+            [quiet_hours]
+            sat = []
+            sun = []
+
+            [agenda_call]
+            days_before = 5
+            template = "Agenda for {summary}{#if location} in {location}{/if} ({start})?"
+        "#});
+        let changes = [
+            (
+                WEEKLY_MEETINGS.to_string(),
+                "Agenda for Weekly Meeting (Wed, Dec 03, 2025 at 07:00 PM UTC)?",
+                "20251203T190000Z",
+            ),
+            (
+                WEEKLY_MEETINGS.replacen(
+                    "SUMMARY:Weekly Meeting",
+                    "SUMMARY:Weekly Meeting\nLOCATION:Room 2",
+                    1,
+                ),
+                "Agenda for Weekly Meeting in Room 2 (Wed, Dec 03, 2025 at 07:00 PM UTC)?",
+                "20251203T190000Z",
+            ),
+            (
+                WEEKLY_MEETINGS.replacen("DTSTART:20251203T190000Z", "DTSTART:20251204T190000Z", 1),
+                "Agenda for Weekly Meeting (Thu, Dec 04, 2025 at 07:00 PM UTC)?",
+                "20251204T190000Z",
+            ),
+            (
+                WEEKLY_MEETINGS.replacen(
+                    "SUMMARY:Weekly Meeting",
+                    "SUMMARY:Weekly Meeting\nSTATUS:CANCELLED",
+                    1,
+                ),
+                "❌ Cancelled: Weekly Meeting\n🕒 Wed, Dec 03, 2025 at 07:00 PM UTC",
+                "20251203T190000Z",
+            ),
+        ];
+
+        for (calendar, text, start) in changes {
+            let publisher = RecordingPublisher::new();
+            // Saturday, November 29th
+            let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 11, 29, 12, 0, 0).unwrap());
+            let mut state = State::default();
+            post_agenda_calls(
+                &MemorySource::new(WEEKLY_MEETINGS),
+                &publisher,
+                &clock,
+                &config,
+                &mut state,
+            )
+            .await
+            .unwrap();
+            assert_eq!(state.deferred.len(), 1);
+
+            clock.advance(Duration::days(2));
+            post_deferred(
+                &MemorySource::new(&calendar),
+                &publisher,
+                &clock,
+                &config,
+                &mut state,
+            )
+            .await
+            .unwrap();
+            assert_eq!(publisher.texts(), [text]);
+            assert!(state.is_posted(&format!("agenda-call:week-1@example.com@{}", start)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_links_across_targets() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 2, 12, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            [agenda_call]
+            days_before = 2
+        "#});
+        let mut state = State::default();
+
+        let primary = RecordingPublisher::for_target("primary.test");
+        primary.set_reachable(false);
+        let backup = RecordingPublisher::for_target("backup.test");
+        let publisher =
+            FailoverPublisher::new(&primary).with_backup(&backup, 1, std::time::Duration::ZERO);
+
+        post_agenda_calls(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        record_failovers(&publisher, &clock, &mut state).unwrap();
+        primary.set_reachable(true);
+        catch_up(publisher.primary(), &config, &mut state)
+            .await
+            .unwrap();
+
+        let about = state.posts_about("week-1@example.com");
+        assert_eq!(about.len(), 1);
+        let (kind, start, record) = about[0];
+        assert_eq!((kind, start), (agenda::KIND, "20251203T190000Z"));
+        let urls: Vec<_> = record
+            .links
+            .iter()
+            .map(|(target, link)| (target.as_str(), link.url.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                ("backup.test", "https://backup.test/@bot/1"),
+                ("primary.test", "https://primary.test/@bot/1"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_excluded_tentative_events() {
+        let source = MemorySource::new(&WEEKLY_MEETINGS.replacen(
+            "SUMMARY:Weekly Meeting",
+            "SUMMARY:Weekly Meeting\nSTATUS:TENTATIVE",
+            1,
+        ));
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let mut state = State::default();
+
+        post_next_meeting(&source, &publisher, &clock, &config(""), &mut state)
+            .await
+            .unwrap();
+        let excluding = config("tentative_events = \"exclude\"\n");
+        post_next_meeting(&source, &publisher, &clock, &excluding, &mut state)
+            .await
+            .unwrap();
+        post_all_upcoming_meetings(&source, &publisher, &clock, &excluding, &mut state)
+            .await
+            .unwrap();
+
+        let texts = publisher.texts();
+        assert!(texts[0].starts_with(
+            "📅 Next Meeting: Weekly Meeting (tentative)\n🕒 Wed, Dec 03, 2025 at 07:00 PM UTC"
+        ));
+        assert!(
+            texts[1]
+                .starts_with("📅 Next Meeting: Weekly Meeting\n🕒 Wed, Dec 10, 2025 at 07:00 PM")
+        );
+        assert!(texts[2].starts_with("Upcoming Meetings (2):"));
+    }
+
+    #[tokio::test]
+    async fn test_weekly_recurring_meeting() {
+        let source = MemorySource::new(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:weekly@example.com
+            DTSTART:20250903T190000
+            RRULE:FREQ=WEEKLY
+            SUMMARY:Weekly Meeting
+            END:VEVENT
+            END:VCALENDAR
+        "});
+        let publisher = RecordingPublisher::new();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 4, 12, 0, 0).unwrap());
+        let mut state = State::default();
+
+        post_next_meeting(&source, &publisher, &clock, &config(""), &mut state)
+            .await
+            .unwrap();
+        assert_eq!(
+            publisher.texts(),
+            vec!["📅 Next Meeting: Weekly Meeting\n🕒 Wed, Dec 10, 2025 at 07:00 PM"]
+        );
+        assert!(state.occurrences.contains_key("weekly@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_long_digest_thread() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new().with_max_status_chars(100);
+        // Saturday, November 29th
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 11, 29, 12, 0, 0).unwrap());
+        let config = config(indoc! {r#"
+            long_digests = "thread"
+
+            [quiet_hours]
+            sat = []
+            sun = []
+        "#});
+        let mut state = State::default();
+
+        let posted = post_all_upcoming_meetings(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert!(posted.is_empty());
+        assert_eq!(state.deferred[0].replies.len(), 3);
+        // Another quiet run doesn't hold back a second copy
+        post_all_upcoming_meetings(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(state.deferred.len(), 1);
+
+        clock.advance(Duration::days(2));
+        let posted = post_deferred(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(posted.len(), 4);
+        let texts = publisher.texts();
+        assert_eq!(texts[0], "Upcoming Meetings (3), in the 3 replies below 🧵");
+        assert_eq!(
+            texts[3],
+            "(3/3)\n\n📅 Weekly Meeting\n🕒 Wed, Dec 17, 2025 at 07:00 PM UTC\n"
+        );
+        let replies_to: Vec<_> = publisher
+            .posts()
+            .into_iter()
+            .map(|post| post.in_reply_to_id)
+            .collect();
+        assert_eq!(
+            replies_to,
+            [
+                None,
+                Some("1".to_string()),
+                Some("2".to_string()),
+                Some("3".to_string())
+            ]
+        );
+
+        // A digest that fits goes out as one status
+        let config = Config {
+            max_chars: Some(500),
+            quiet_hours: None,
+            ..config
+        };
+        let posted = post_all_upcoming_meetings(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(posted.len(), 1);
+        assert!(publisher.texts()[4].starts_with("Upcoming Meetings (3):"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_before_thread() {
+        let source = MemorySource::new(WEEKLY_MEETINGS);
+        let publisher = RecordingPublisher::new().with_max_status_chars(100);
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 11, 29, 12, 0, 0).unwrap());
+        // Nothing listens on the discard port, so the instance seems down
+        let config = config(indoc! {r#"
+            long_digests = "thread"
+
+            [probe]
+            status_url = "http://127.0.0.1:9/status.json"
+            timeout_seconds = 1
+        "#});
+        let mut state = State::default();
+
+        let posted = post_all_upcoming_meetings(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert!(posted.is_empty());
+        assert!(publisher.texts().is_empty());
+        assert_eq!(state.deferred[0].replies.len(), 3);
+        let posted = post_deferred(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert!(posted.is_empty());
+        assert_eq!(state.deferred.len(), 1);
+        assert!(
+            probe::summary()
+                .iter()
+                .any(|found| found.url == "http://127.0.0.1:9/status.json" && !found.healthy)
+        );
+
+        // A digest that fits isn't held back
+        let config = Config {
+            max_chars: Some(500),
+            ..config
+        };
+        let posted = post_all_upcoming_meetings(&source, &publisher, &clock, &config, &mut state)
+            .await
+            .unwrap();
+        assert_eq!(posted.len(), 1);
+    }
+}
@@ -1,51 +1,1639 @@
-use serde::Deserialize;
+use chrono::{DateTime, Utc, Weekday};
+use mastodon_async::{Language, NewStatus, Visibility};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Config {
+    /// Layout version of the file; see `config migrate`.
+    #[serde(default)]
+    pub config_version: i64,
+    /// May be left out when the instance comes from the environment.
+    #[serde(default)]
     pub instance: String,
     #[serde(default = "default_token_file")]
     pub token_file: String,
+    /// The network the account is on. For Matrix, `instance` is the
+    /// homeserver and `token_file` holds the access token.
+    #[serde(default)]
+    pub network: Network,
+    /// The Matrix room messages are sent to, by id or alias.
+    pub room: Option<String>,
     pub webcal: String,
+    /// Credentials for a calendar behind OAuth2.
+    pub calendar_oauth: Option<CalendarOAuthConfig>,
+    /// Username and password for a calendar behind HTTP basic auth, such as
+    /// a Nextcloud calendar.
+    pub calendar_login: Option<CalendarLoginConfig>,
+    /// How many redirects to follow when fetching the calendar.
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: usize,
+    /// Where fetched calendars are kept, to only download them when changed.
+    pub cache: Option<CacheConfig>,
+    /// Minutes a fetched calendar is used before it is fetched again, in
+    /// place of the cache's `ttl_seconds`; the daemon fetches it this often.
+    pub refresh_minutes: Option<u64>,
+    #[serde(default = "default_state_file")]
+    pub state_file: String,
+    /// How many days ahead recurring events are expanded into occurrences.
+    #[serde(default = "default_lookahead_days")]
+    pub lookahead_days: i64,
+    /// File mapping the UIDs events had before the calendar moved to the
+    /// ones they have now.
+    pub uid_map: Option<String>,
+    /// File changing or leaving out single occurrences of events.
+    pub overrides: Option<String>,
+    /// How `post-next` and `post-all` treat events marked `STATUS:TENTATIVE`.
+    #[serde(default)]
+    pub tentative_events: TentativeEvents,
+    /// What `post-next` does when the next meeting was already announced
+    /// and its details have changed since.
+    #[serde(default)]
+    pub changed_events: ChangedEvents,
+    /// Whether an announcement edited because its meeting changed ends with
+    /// a line saying what changed.
+    #[serde(default)]
+    pub change_notes: bool,
+    /// Whether `post-next` pins the announcements of important meetings,
+    /// with a PRIORITY of 1 to 4, to the profile until they are over.
+    #[serde(default)]
+    pub pin_important: bool,
+    /// What `sync` does about announcements of meetings cancelled since.
+    #[serde(default)]
+    pub cancelled_events: CancelledEvents,
+    /// How `post-all` posts a digest too long for a single status.
+    #[serde(default)]
+    pub long_digests: LongDigests,
+    /// Longest status the instance accepts, in characters; asked of the
+    /// instance when unset.
+    pub max_chars: Option<usize>,
+    /// Largest event image, in bytes, that `post-next` attaches; 0 leaves
+    /// images out.
+    #[serde(default = "default_max_image_bytes")]
+    pub max_image_bytes: u64,
+    /// A generated image of the event attached to announcements of events
+    /// without an image of their own.
+    pub event_card: Option<EventCardConfig>,
+    /// Looking through the account's latest statuses before announcing the
+    /// next meeting, in case the state file was lost.
+    pub duplicate_check: Option<DuplicateCheckConfig>,
+    /// IANA name of the timezone quiet hours are evaluated in.
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// IANA name of the timezone times are shown in, in place of UTC.
+    pub display_timezone: Option<String>,
+    /// The day weeks start on, for weekly recaps and digests.
+    #[serde(default = "default_week_start")]
+    pub week_start: Weekday,
+    /// Whether weekly recaps are named by the week's number, like `week 23`.
+    #[serde(default)]
+    pub week_numbers: bool,
+    pub quiet_hours: Option<QuietHoursConfig>,
+    /// Announced maintenance of the instance, during which posts are held
+    /// back like during quiet hours.
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceWindowConfig>,
+    pub agenda_call: Option<AgendaCallConfig>,
+    pub minutes: Option<MinutesConfig>,
+    pub recap: Option<RecapConfig>,
+    pub reminders: Option<RemindersConfig>,
+    pub remind_me: Option<RemindMeConfig>,
+    pub backup: Option<BackupConfig>,
+    pub ics_upload: Option<IcsUploadConfig>,
+    /// Writes the link of each announcement back into the calendar.
+    pub write_back: Option<WriteBackConfig>,
+    pub scheduling_poll: Option<SchedulingPollConfig>,
+    pub safe_mode: Option<SafeModeConfig>,
+    pub gc: Option<GcConfig>,
+    pub subscription_post: Option<SubscriptionPostConfig>,
+    pub profile: Option<ProfileConfig>,
+    pub shortener: Option<ShortenerConfig>,
+    /// Checks event URLs before they are posted.
+    pub link_check: Option<LinkCheckConfig>,
+    /// Checks the instance is up before a thread is posted.
+    pub probe: Option<ProbeConfig>,
+    /// Which URLs calendars, images and links may be fetched from.
+    #[serde(default)]
+    pub url_policy: UrlPolicyConfig,
+    /// Which events the posting commands may announce at all.
+    pub filter: Option<FilterConfig>,
+    /// The command run when none is given on the command line.
+    pub default_command: Option<CommandLine>,
+    /// Names for commands with their flags, usable in place of a command.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, CommandLine>,
+    /// What `daemon` runs, and when.
+    #[serde(default)]
+    pub schedule: Vec<ScheduleConfig>,
+    /// How every status is posted, unless a command's flags say otherwise.
+    #[serde(default)]
+    pub post: PostOptions,
+    /// Templates for `post-next` and `post-all`, also used by language
+    /// accounts that don't set their own.
+    #[serde(default)]
+    pub templates: TemplatesConfig,
+    /// Accounts announcing the events in their language instead of the main
+    /// account, written as `[[language_account]]`.
+    #[serde(default, rename = "language_account")]
+    pub language_accounts: Vec<LanguageAccountConfig>,
+    /// Further calendars, each announced on an account of its own, written
+    /// as `[[feed]]`.
+    #[serde(default, rename = "feed", alias = "feeds")]
+    pub feeds: Vec<FeedConfig>,
+    /// How feeds on each network are posted, written as `[target.matrix]`.
+    #[serde(default)]
+    pub target: BTreeMap<Network, TargetConfig>,
+    /// Other accounts whose event posts are mirrored into the `post-all`
+    /// digest, written as `[[bridge]]`.
+    #[serde(default, rename = "bridge")]
+    pub bridges: Vec<BridgeConfig>,
+    /// Index of the language account `post-next` and `post-all` announce for;
+    /// `None` for the main account.
+    #[serde(skip)]
+    pub audience: Option<usize>,
+    /// Whether statuses are only shown, not posted, and the state file is
+    /// left as it is; only ever given on the command line.
+    #[serde(skip)]
+    pub dry_run: bool,
+    /// The main account's access token when given in the environment rather
+    /// than in its token file.
+    #[serde(skip)]
+    pub access_token: Option<String>,
+    /// The directory a tenant's files are kept in when the configuration
+    /// doesn't name them; `None` for the working directory.
+    #[serde(skip)]
+    pub home: Option<String>,
+}
+
+impl Config {
+    /// The OAuth scopes the configured features need, and no more.
+    pub fn required_scopes(&self) -> Vec<&'static str> {
+        // Every command posts statuses; the backup account posts the same ones
+        let mut scopes = vec!["write:statuses"];
+        if self.max_image_bytes > 0 || self.event_card.is_some() {
+            scopes.push("write:media");
+        }
+        if self.scheduling_poll.is_some() {
+            // Poll results are read back from the poll's status
+            scopes.push("read:statuses");
+        }
+        if self.profile.is_some() {
+            // The fields the bot doesn't manage are read so they can be kept
+            scopes.extend(["read:accounts", "write:accounts"]);
+        }
+        if self.pin_important {
+            scopes.push("write:accounts");
+        }
+        if self.duplicate_check.is_some() {
+            // The account is looked up to list its statuses
+            scopes.extend(["read:statuses", "read:accounts"]);
+        }
+        // Several features may need the same scope
+        let mut unique = Vec::new();
+        for scope in scopes {
+            if !unique.contains(&scope) {
+                unique.push(scope);
+            }
+        }
+        unique
+    }
+
+    /// This configuration for announcing on the `index`th language account,
+    /// whose statuses are marked as written in its language.
+    pub fn for_language_account(&self, index: usize) -> Config {
+        let account = &self.language_accounts[index];
+        Config {
+            audience: Some(index),
+            post: PostOptions {
+                language: Some(account.language),
+                ..self.post.clone()
+            },
+            ..self.clone()
+        }
+    }
+
+    /// The language account announcements go to; `None` for the main account.
+    pub fn language_account(&self) -> Option<&LanguageAccountConfig> {
+        self.audience.map(|index| &self.language_accounts[index])
+    }
+
+    /// This configuration for the calendar and account of a feed. Language
+    /// accounts, bridges and the backup belong to the main account, and
+    /// writing links back to the main calendar, so a feed has none.
+    pub fn for_feed(&self, feed: &FeedConfig) -> Config {
+        let target = self.target.get(&feed.network);
+        Config {
+            webcal: feed.webcal.clone(),
+            instance: feed.instance.clone(),
+            token_file: feed.token_file.clone(),
+            network: feed.network,
+            room: feed.room.clone(),
+            calendar_oauth: feed.calendar_oauth.clone(),
+            calendar_login: feed.calendar_login.clone(),
+            refresh_minutes: feed.refresh_minutes,
+            state_file: feed
+                .state_file
+                .clone()
+                .unwrap_or_else(|| self.in_home(&format!("state-{}.json", feed.name))),
+            templates: feed
+                .templates
+                .clone()
+                .or_else(|| target.and_then(|target| target.templates.clone()))
+                .unwrap_or_else(|| self.templates.clone()),
+            // The main account's limit is for its own network
+            max_chars: target.and_then(|target| target.max_chars).or(
+                match feed.network == self.network {
+                    true => self.max_chars,
+                    false => None,
+                },
+            ),
+            post: match &feed.post {
+                Some(post) => self.post.overridden_by(post),
+                None => self.post.clone(),
+            },
+            backup: None,
+            write_back: None,
+            language_accounts: Vec::new(),
+            feeds: Vec::new(),
+            bridges: Vec::new(),
+            access_token: None,
+            ..self.clone()
+        }
+    }
+
+    /// How the calendar is cached: as configured, for `refresh_minutes` when
+    /// set, in the default directory without a `[cache]` section.
+    pub fn calendar_cache(&self) -> Option<CacheConfig> {
+        let Some(minutes) = self.refresh_minutes else {
+            return self.cache.clone();
+        };
+        let cache = self.cache.clone().unwrap_or_else(|| CacheConfig {
+            dir: self.in_home(&default_cache_dir()),
+            ..CacheConfig::default()
+        });
+        Some(CacheConfig {
+            ttl_seconds: minutes * 60,
+            ..cache
+        })
+    }
+
+    /// Where a file at the relative `path` belongs: in the tenant's `home`,
+    /// or the working directory.
+    pub fn in_home(&self, path: &str) -> String {
+        match &self.home {
+            Some(home) => std::path::Path::new(home).join(path).display().to_string(),
+            None => path.to_string(),
+        }
+    }
+
+    /// The feeds `--feed` selects, by name: the main calendar and account
+    /// for `None`, one feed, or the main one and every feed for `all`.
+    pub fn select_feeds(
+        &self,
+        selector: Option<&str>,
+    ) -> Result<Vec<(Option<&str>, Config)>, String> {
+        match selector {
+            None => Ok(vec![(None, self.clone())]),
+            Some(ALL_FEEDS) => Ok(std::iter::once((None, self.clone()))
+                .chain(
+                    self.feeds
+                        .iter()
+                        .map(|feed| (Some(feed.name.as_str()), self.for_feed(feed))),
+                )
+                .collect()),
+            Some(name) => {
+                let feed = self
+                    .feeds
+                    .iter()
+                    .find(|feed| feed.name == name)
+                    .ok_or_else(|| format!("no feed named '{}' in the configuration", name))?;
+                Ok(vec![(Some(feed.name.as_str()), self.for_feed(feed))])
+            }
+        }
+    }
+
+    /// This configuration only showing what it would post, or not.
+    pub fn with_dry_run(&self, dry_run: bool) -> Config {
+        Config {
+            dry_run,
+            ..self.clone()
+        }
+    }
+
+    /// This configuration with `overrides` taking precedence over `[post]`.
+    pub fn with_post_options(&self, overrides: &PostOptions) -> Config {
+        Config {
+            post: self.post.overridden_by(overrides),
+            ..self.clone()
+        }
+    }
+}
+
+/// Mastodon's options for a new status.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct PostOptions {
+    /// `public`, `unlisted`, `private` (followers only) or `direct`.
+    pub visibility: Option<Visibility>,
+    /// Whether attached media is hidden behind a warning.
+    pub sensitive: Option<bool>,
+    /// The content warning shown instead of the text until expanded.
+    pub spoiler_text: Option<String>,
+    /// ISO 639 code of the language statuses are written in.
+    #[serde(default, deserialize_with = "deserialize_language")]
+    pub language: Option<Language>,
+    /// The status to reply to; only ever given on the command line.
+    #[serde(skip)]
+    pub in_reply_to_id: Option<String>,
+    /// When the instance is to post the status; only ever given on the
+    /// command line.
+    #[serde(skip)]
+    pub schedule_at: Option<DateTime<Utc>>,
+    /// How long before a meeting `post-next` schedules its announcement to go
+    /// out, like `2d`, rather than posting it right away.
+    pub schedule_before: Option<String>,
+}
+
+impl PostOptions {
+    pub fn overridden_by(&self, overrides: &PostOptions) -> PostOptions {
+        PostOptions {
+            visibility: overrides.visibility.or(self.visibility),
+            sensitive: overrides.sensitive.or(self.sensitive),
+            spoiler_text: overrides
+                .spoiler_text
+                .clone()
+                .or_else(|| self.spoiler_text.clone()),
+            language: overrides.language.or(self.language),
+            in_reply_to_id: overrides
+                .in_reply_to_id
+                .clone()
+                .or_else(|| self.in_reply_to_id.clone()),
+            schedule_at: overrides.schedule_at.or(self.schedule_at),
+            schedule_before: overrides
+                .schedule_before
+                .clone()
+                .or_else(|| self.schedule_before.clone()),
+        }
+    }
+
+    /// A status with the given text, posted with these options. Behind a
+    /// content warning, attached media is hidden too unless `sensitive` is
+    /// set to `false`, as not every instance does that on its own.
+    pub fn status(&self, text: String) -> NewStatus {
+        let warned = self
+            .spoiler_text
+            .as_deref()
+            .is_some_and(|spoiler| !spoiler.trim().is_empty());
+        NewStatus {
+            status: Some(text),
+            visibility: self.visibility,
+            sensitive: self.sensitive.or(warned.then_some(true)),
+            spoiler_text: self.spoiler_text.clone(),
+            language: self.language,
+            in_reply_to_id: self.in_reply_to_id.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Parses a status visibility.
+pub fn parse_visibility(value: &str) -> Result<Visibility, String> {
+    value.parse().map_err(|_| {
+        format!(
+            "invalid visibility '{}', expected public, unlisted, private or direct",
+            value
+        )
+    })
+}
+
+/// Parses a time such as `2025-12-01T09:00:00+01:00`.
+pub fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| {
+            format!(
+                "invalid time '{}', expected e.g. 2025-12-01T09:00:00+01:00",
+                value
+            )
+        })
+}
+
+/// Parses a two- or three-letter ISO 639 language code, such as `en`.
+pub fn parse_language(code: &str) -> Result<Language, String> {
+    Language::from_639_1(code)
+        .or_else(|| Language::from_639_3(code))
+        .ok_or_else(|| format!("unknown language code '{}', expected e.g. 'en'", code))
+}
+
+fn deserialize_language<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Language>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|code| parse_language(&code).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+fn deserialize_required_language<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Language, D::Error> {
+    parse_language(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// A command and its flags, written as one string split on whitespace or,
+/// when an argument contains spaces, as a list.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum CommandLine {
+    Line(String),
+    Args(Vec<String>),
+}
+
+impl CommandLine {
+    pub fn args(&self) -> Vec<String> {
+        match self {
+            CommandLine::Line(line) => line.split_whitespace().map(str::to_string).collect(),
+            CommandLine::Args(args) => args.clone(),
+        }
+    }
+}
+
+/// A command `daemon` runs on cron schedules, read in the configured timezone.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleConfig {
+    pub command: CommandLine,
+    /// Cron expressions, each with an optional leading seconds field.
+    pub cron: Vec<String>,
+}
+
+/// Settings for `post-scheduling-poll`, which asks followers to pick between
+/// the candidate times of a tentative meeting.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SchedulingPollConfig {
+    /// How many hours the poll stays open.
+    #[serde(default = "default_poll_expires_hours")]
+    pub expires_hours: i64,
+    /// Summaries (matched case-insensitively as substrings) of the tentative
+    /// events that can be polled. When empty, every tentative event can be.
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub template: Option<String>,
+    pub result_template: Option<String>,
+}
+
+/// A second account that statuses go to when the primary instance is down.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BackupConfig {
+    pub instance: String,
+    #[serde(default = "default_backup_token_file")]
+    pub token_file: String,
+    /// How many times posting to the primary instance is tried before
+    /// falling back to the backup.
+    #[serde(default = "default_backup_retries")]
+    pub retries: u32,
+    /// Seconds to wait between those tries.
+    #[serde(default = "default_backup_retry_delay_seconds")]
+    pub retry_delay_seconds: u64,
+}
+
+/// What `--feed` takes to select the main calendar and every feed.
+pub const ALL_FEEDS: &str = "all";
+
+/// A calendar announced on an account of its own. Everything it doesn't
+/// set, such as the schedule and quiet hours, comes from the main
+/// configuration.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedConfig {
+    /// What `--feed` selects it by.
+    pub name: String,
+    pub webcal: String,
+    pub instance: String,
+    pub token_file: String,
+    #[serde(default)]
+    pub network: Network,
+    pub room: Option<String>,
+    pub calendar_oauth: Option<CalendarOAuthConfig>,
+    pub calendar_login: Option<CalendarLoginConfig>,
+    /// Minutes the feed's calendar is used before it is fetched again.
+    pub refresh_minutes: Option<u64>,
+    /// Where what was posted for the feed is remembered; `state-<name>.json`
+    /// by default.
+    pub state_file: Option<String>,
+    /// Templates for the feed in place of the main `[templates]`.
+    pub templates: Option<TemplatesConfig>,
+    /// Post options for the feed, over those of the main `[post]`.
+    pub post: Option<PostOptions>,
+}
+
+/// How statuses are adapted to a network, for the feeds posting to it.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TargetConfig {
+    /// Templates for feeds on the network without templates of their own,
+    /// in place of the main `[templates]`.
+    pub templates: Option<TemplatesConfig>,
+    /// Longest status the network takes, in characters.
+    pub max_chars: Option<usize>,
+}
+
+/// Templates replacing the built-in `post-next` and `post-all` statuses.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TemplatesConfig {
+    /// The status about the next meeting.
+    pub next: Option<String>,
+    /// `post-next --count`, listing several meetings.
+    pub next_meetings: Option<String>,
+    /// `post-all`, listing every upcoming meeting.
+    pub upcoming: Option<String>,
+    /// Either command when there are no meetings.
+    pub no_meetings: Option<String>,
+    /// Hashtags every event gets in `{hashtags}`, after its CATEGORIES.
+    #[serde(default)]
+    pub hashtags: Vec<String>,
+}
+
+/// An account that announces the events in one language, with its own
+/// templates for `post-next` and `post-all`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LanguageAccountConfig {
+    /// ISO 639 code of the language, matched against the LANGUAGE parameter
+    /// of event summaries.
+    #[serde(deserialize_with = "deserialize_required_language")]
+    pub language: Language,
+    pub instance: String,
+    pub token_file: String,
+    /// Summaries (matched case-insensitively as substrings) of events without
+    /// a LANGUAGE that this account announces too. When empty, it announces
+    /// only events in its language.
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub next_template: Option<String>,
+    pub next_meetings_template: Option<String>,
+    pub upcoming_template: Option<String>,
+    pub no_meetings_template: Option<String>,
+}
+
+/// Settings for `post-subscription-info`, the periodic reminder of how to
+/// subscribe to the calendar.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionPostConfig {
+    /// The public subscription link, when it isn't the `webcal` URL.
+    pub url: Option<String>,
+    /// Days between two reminders.
+    #[serde(default = "default_subscription_interval_days")]
+    pub interval_days: i64,
+    pub template: Option<String>,
+}
+
+/// Settings for `sync-profile`, which keeps fields of the account's profile
+/// up to date with the calendar. A field with an empty name is left out.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProfileConfig {
+    /// Name of the field telling when the next meeting is.
+    #[serde(default = "default_next_meeting_field")]
+    pub next_meeting_field: String,
+    /// What that field says when no meeting is coming up.
+    #[serde(default = "default_no_meeting")]
+    pub no_meeting: String,
+    /// Name of the field linking to the calendar.
+    #[serde(default = "default_calendar_field")]
+    pub calendar_field: String,
+    /// The calendar link, when it is neither the `[subscription_post]` one
+    /// nor the `webcal` URL.
+    pub calendar_url: Option<String>,
+    /// Name of the field linking to `website`.
+    #[serde(default = "default_website_field")]
+    pub website_field: String,
+    /// The group's website; there is no website field without one.
+    pub website: Option<String>,
+}
+
+/// OAuth2 client credentials the calendar is fetched with. The bearer token
+/// they are exchanged for is only sent to the calendar's own host.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CalendarOAuthConfig {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// HTTP basic auth credentials the calendar is fetched with, such as a
+/// Nextcloud app password. They are only sent to the calendar's own host.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CalendarLoginConfig {
+    pub username: String,
+    pub password: Option<String>,
+}
+
+/// Rules leaving events out of everything the bot posts, such as internal
+/// meetings on a shared calendar.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FilterConfig {
+    /// Only events in one of these CATEGORIES, ignoring case; any event when
+    /// empty.
+    #[serde(default)]
+    pub include_categories: Vec<String>,
+    /// No events in any of these CATEGORIES, ignoring case.
+    #[serde(default)]
+    pub exclude_categories: Vec<String>,
+    /// Only events whose SUMMARY or DESCRIPTION matches this regex.
+    pub include_pattern: Option<String>,
+    /// No events whose SUMMARY or DESCRIPTION matches this regex.
+    pub exclude_pattern: Option<String>,
+    /// No events starting more than this many days from now.
+    pub max_days_ahead: Option<i64>,
+}
+
+/// A self-hosted link shortener long event URLs are posted through.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShortenerConfig {
+    /// Endpoint taking a POST of `{"url": "..."}` and answering with the
+    /// short link.
+    pub api_url: String,
+    /// Sent as a bearer token, when the shortener wants one.
+    pub token: Option<String>,
+    /// Field of the JSON answer holding the short link.
+    #[serde(default = "default_shortener_response_field")]
+    pub response_field: String,
+    /// URLs up to this many characters are posted as they are.
+    #[serde(default = "default_shortener_min_length")]
+    pub min_length: usize,
+    /// Seconds to wait for the shortener before posting the URL unshortened.
+    #[serde(default = "default_shortener_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+/// How event URLs are checked before they are posted.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LinkCheckConfig {
+    /// What to do about a URL the site says is gone.
+    #[serde(default)]
+    pub dead_links: DeadLinks,
+    /// Seconds to wait for the site before posting the URL unchecked.
+    #[serde(default = "default_link_check_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+/// How the instance is asked whether it's up to a thread of posts.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProbeConfig {
+    /// A status page to ask in place of the instance's `/api/v1/instance`.
+    pub status_url: Option<String>,
+    /// Seconds to wait for an answer before taking the instance to be
+    /// degraded.
+    #[serde(default = "default_probe_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+/// Which URLs the bot fetches, so a calendar can't have it ask services on
+/// its own network.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UrlPolicyConfig {
+    /// The schemes URLs may have.
+    #[serde(default = "default_url_schemes")]
+    pub schemes: Vec<String>,
+    /// Hosts, addresses and ranges like `192.168.1.0/24` that may be
+    /// fetched from even though they are private.
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl Default for UrlPolicyConfig {
+    fn default() -> Self {
+        UrlPolicyConfig {
+            schemes: default_url_schemes(),
+            allow: Vec::new(),
+        }
+    }
+}
+
+/// An account, such as a venue's, whose posts announcing events are
+/// mirrored into the digest.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BridgeConfig {
+    /// The account, as `name@instance.example`.
+    pub account: String,
+    /// Regex a post's text must match to be an event, with a `start` group
+    /// and optionally `summary`, `end` and `location` groups.
+    pub pattern: String,
+    /// How `start` and `end` are written, as a [chrono format]; times are in
+    /// `timezone`, and a format without a time gives all-day events.
+    ///
+    /// [chrono format]: https://docs.rs/chrono/latest/chrono/format/strftime/
+    #[serde(default = "default_bridge_time_format")]
+    pub time_format: String,
+    /// How many of the account's latest posts are looked through.
+    #[serde(default = "default_bridge_posts")]
+    pub posts: usize,
+}
+
+/// What to do about an event URL that answers 404 or 410.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeadLinks {
+    /// Post it with a warning.
+    #[default]
+    Warn,
+    /// Post the event without it.
+    Drop,
+}
+
+/// When to stop posting because runs keep failing, and where to say so.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SafeModeConfig {
+    /// Failed posting runs in a row that put the bot in safe mode.
+    #[serde(default = "default_safe_mode_after_failures")]
+    pub after_failures: u32,
+    /// Webhook the alert about entering safe mode is posted to.
+    pub alert_webhook: Option<String>,
+}
+
+/// How `post-next` looks for the announcement on the account before posting it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DuplicateCheckConfig {
+    /// How many of the latest statuses are looked through, at most 40.
+    #[serde(default = "default_duplicate_statuses")]
+    pub statuses: usize,
+    /// Whether to look before every announcement, rather than only while the
+    /// state remembers no posts.
+    #[serde(default)]
+    pub always: bool,
+}
+
+fn default_duplicate_statuses() -> usize {
+    40
+}
+
+/// Colors of the event card, each written like `#1d3557`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventCardConfig {
+    #[serde(default = "default_card_background")]
+    pub background: String,
+    /// The bar down the left side.
+    #[serde(default = "default_card_accent")]
+    pub accent: String,
+    #[serde(default = "default_card_text")]
+    pub text: String,
+}
+
+fn default_card_background() -> String {
+    "#1d3557".to_string()
+}
+
+fn default_card_accent() -> String {
+    "#e63946".to_string()
+}
+
+fn default_card_text() -> String {
+    "#f1faee".to_string()
+}
+
+/// How long the state remembers past meetings, for `gc`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GcConfig {
+    /// Days after a meeting what was posted about it is forgotten.
+    #[serde(default = "default_retention_days")]
+    pub retention_days: i64,
+    /// Whether each posting run collects garbage after it's done, besides
+    /// `gc` itself.
+    #[serde(default = "default_gc_automatic")]
+    pub automatic: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            retention_days: default_retention_days(),
+            automatic: default_gc_automatic(),
+        }
+    }
+}
+
+fn default_retention_days() -> i64 {
+    180
+}
+
+fn default_gc_automatic() -> bool {
+    true
+}
+
+/// The network an account posts to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Mastodon,
+    /// A Matrix room, with statuses sent as messages.
+    Matrix,
+}
+
+/// What to do with events that aren't confirmed yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TentativeEvents {
+    /// Announce them with a "(tentative)" marker.
+    #[default]
+    Mark,
+    /// Leave them out.
+    Exclude,
+}
+
+/// What to do about an announcement that has gone out of date.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangedEvents {
+    /// Announce the meeting again in a new status.
+    #[default]
+    Post,
+    /// Edit the earlier announcement.
+    Edit,
+    /// Delete the earlier announcement and post a new one.
+    Replace,
+    /// Leave the earlier announcement as it is.
+    Skip,
+}
+
+/// What to do about an announcement of a meeting that was cancelled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CancelledEvents {
+    /// Reply to the announcement that the meeting is cancelled.
+    #[default]
+    Correct,
+    /// Delete the announcement.
+    Delete,
+}
+
+/// How to post a list of meetings that doesn't fit in one status.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LongDigests {
+    /// Leave the last meetings out, saying how many there are.
+    #[default]
+    Shorten,
+    /// Post the meetings as a thread of replies to a first status.
+    Thread,
+}
+
+/// Settings for the `post-agenda-call` companion post.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AgendaCallConfig {
+    /// How many days before an event the call for agenda items goes out.
+    #[serde(default = "default_agenda_days_before")]
+    pub days_before: i64,
+    /// Summaries (matched case-insensitively as substrings) of the events that
+    /// get an agenda call. When empty, every event gets one.
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub template: Option<String>,
+}
+
+/// Settings for the `post-minutes` follow-up post.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MinutesConfig {
+    /// How many hours after an event ends the follow-up goes out.
+    #[serde(default = "default_minutes_delay_hours")]
+    pub delay_hours: i64,
+    /// How long after it becomes due the follow-up may still be posted, so a
+    /// first run doesn't post about every meeting in the calendar's history.
+    #[serde(default = "default_minutes_window_hours")]
+    pub window_hours: i64,
+    /// Where the minutes will be published.
+    pub url: Option<String>,
+    /// Summaries (matched case-insensitively as substrings) of the events that
+    /// get a follow-up. When empty, every event gets one.
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub template: Option<String>,
+}
+
+/// Settings for `recap`, the summary of the meetings of a past period.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RecapConfig {
+    /// Where the minutes of meetings are, by UID or by summary (matched
+    /// case-insensitively as a substring); `[minutes]` `url` for the others.
+    #[serde(default)]
+    pub minutes: BTreeMap<String, String>,
+    pub template: Option<String>,
+}
+
+/// Settings for `post-reminders`, the reminders shortly before meetings.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemindersConfig {
+    /// How long before a meeting each reminder goes out, like `24h` or `30m`.
+    #[serde(default = "default_reminder_lead_times")]
+    pub lead_times: Vec<String>,
+    /// Whether reminders are posted in reply to the `post-next` announcement
+    /// of the meeting, when there is one.
+    #[serde(default)]
+    pub reply_to_announcement: bool,
+    /// Members, like `@alice@example.org`, each reminder is also sent to in a
+    /// direct message mentioning them.
+    #[serde(default)]
+    pub members: Vec<String>,
+    /// Whether reminders are posted publicly; `false` only sends them to the
+    /// `members`.
+    #[serde(default = "default_reminders_public")]
+    pub public: bool,
+    /// Summaries of the events reminded of (matched case-insensitively as a
+    /// substring); all of them when empty.
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub template: Option<String>,
+    /// What is done with reminders found late, as after the bot was down.
+    #[serde(default)]
+    pub missed: MissedReminders,
+    /// Minutes a reminder may be late before it counts as missed.
+    #[serde(default = "default_reminder_late_minutes")]
+    pub late_minutes: i64,
+    /// The post taking the place of missed reminders, with `missed = "combine"`.
+    pub missed_template: Option<String>,
+}
+
+/// What `post-reminders` does with the reminders it finds late.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissedReminders {
+    /// Post each of them, late.
+    #[default]
+    Post,
+    /// Leave them out.
+    Skip,
+    /// Post one status listing their meetings.
+    Combine,
+}
+
+fn default_reminder_late_minutes() -> i64 {
+    15
+}
+
+fn default_reminders_public() -> bool {
+    true
+}
+
+fn default_reminder_lead_times() -> Vec<String> {
+    vec!["24h".to_string(), "1h".to_string()]
+}
+
+/// Settings for `remind-me`: followers replying "remind me" to a `post-next`
+/// announcement get a direct message shortly before the meeting.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemindMeConfig {
+    /// How long before the meeting the reminder is sent, like `1h`.
+    #[serde(default = "default_remind_me_lead_time")]
+    pub lead_time: String,
+    /// What a reply asking for a reminder says (matched case-insensitively).
+    #[serde(default = "default_remind_me_keywords")]
+    pub keywords: Vec<String>,
+    /// What a reply cancelling every reminder asked for says.
+    #[serde(default = "default_remind_me_stop_keywords")]
+    pub stop_keywords: Vec<String>,
+    /// Most followers reminded of one meeting.
+    #[serde(default = "default_remind_me_max_per_event")]
+    pub max_per_event: usize,
+    pub template: Option<String>,
+}
+
+fn default_remind_me_lead_time() -> String {
+    "1h".to_string()
+}
+
+fn default_remind_me_keywords() -> Vec<String> {
+    vec!["remind me".to_string()]
+}
+
+fn default_remind_me_stop_keywords() -> Vec<String> {
+    vec!["stop".to_string()]
+}
+
+fn default_remind_me_max_per_event() -> usize {
+    100
 }
 
 fn default_token_file() -> String {
     "token.json".to_string()
 }
 
-pub fn load_config(config_path: &str) -> Result<Config, Box<dyn std::error::Error>> {
-    let content = std::fs::read_to_string(config_path)?;
-    let config: Config = toml::from_str(&content)?;
-    println!("Configuration loaded from: {}", config_path);
-    println!("Instance: {}", config.instance);
+/// Where the next meeting is uploaded as an `.ics` file for `post-next`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IcsUploadConfig {
+    /// Directory URL the file is PUT into.
+    pub upload_url: String,
+    /// Public URL of that directory, when it's served from somewhere else.
+    pub public_url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Where the links of announcements are written back to: the calendar's
+/// CalDAV collection, or the local calendar file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WriteBackConfig {
+    /// The CalDAV collection the calendar's events are in, written to with
+    /// the `calendar_login` credentials.
+    pub caldav_url: Option<String>,
+}
+
+/// A time the instance is down for maintenance.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MaintenanceWindowConfig {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// When the bot may post, per weekday in the configured timezone. Each day
+/// lists its posting windows, such as `"08:00-20:00"`; an empty list keeps the
+/// bot quiet all day and a day left out doesn't restrict it.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct QuietHoursConfig {
+    pub mon: Option<Vec<String>>,
+    pub tue: Option<Vec<String>>,
+    pub wed: Option<Vec<String>>,
+    pub thu: Option<Vec<String>>,
+    pub fri: Option<Vec<String>>,
+    pub sat: Option<Vec<String>>,
+    pub sun: Option<Vec<String>>,
+}
+
+fn default_poll_expires_hours() -> i64 {
+    72
+}
+
+fn default_backup_token_file() -> String {
+    "backup-token.json".to_string()
+}
+
+fn default_backup_retries() -> u32 {
+    3
+}
+
+fn default_backup_retry_delay_seconds() -> u64 {
+    10
+}
+
+fn default_subscription_interval_days() -> i64 {
+    30
+}
+
+fn default_next_meeting_field() -> String {
+    "Next meeting".to_string()
+}
+
+fn default_no_meeting() -> String {
+    "None scheduled".to_string()
+}
+
+fn default_calendar_field() -> String {
+    "Calendar".to_string()
+}
+
+fn default_website_field() -> String {
+    "Website".to_string()
+}
+
+fn default_shortener_response_field() -> String {
+    "short_url".to_string()
+}
+
+fn default_shortener_min_length() -> usize {
+    40
+}
+
+fn default_shortener_timeout_seconds() -> u64 {
+    5
+}
+
+fn default_link_check_timeout_seconds() -> u64 {
+    5
+}
+
+fn default_week_start() -> Weekday {
+    Weekday::Mon
+}
+
+fn default_probe_timeout_seconds() -> u64 {
+    10
+}
+
+fn default_url_schemes() -> Vec<String> {
+    vec!["https".to_string(), "http".to_string()]
+}
+
+fn default_bridge_time_format() -> String {
+    "%Y-%m-%d %H:%M".to_string()
+}
+
+fn default_bridge_posts() -> usize {
+    40
+}
+
+fn default_safe_mode_after_failures() -> u32 {
+    5
+}
+
+fn default_max_redirects() -> usize {
+    crate::fetch::DEFAULT_MAX_REDIRECTS
+}
+
+/// Where fetched calendars are kept between runs, and for how long they are
+/// used without asking the server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheConfig {
+    #[serde(default = "default_cache_dir")]
+    pub dir: String,
+    /// Seconds a fetched calendar is used as is; after that the server is
+    /// asked whether it changed.
+    #[serde(default)]
+    pub ttl_seconds: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            dir: default_cache_dir(),
+            ttl_seconds: 0,
+        }
+    }
+}
+
+fn default_cache_dir() -> String {
+    "cache".to_string()
+}
+
+fn default_state_file() -> String {
+    "state.json".to_string()
+}
+
+fn default_lookahead_days() -> i64 {
+    90
+}
+
+fn default_max_image_bytes() -> u64 {
+    crate::media::DEFAULT_MAX_IMAGE_BYTES
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_agenda_days_before() -> i64 {
+    7
+}
+
+fn default_minutes_delay_hours() -> i64 {
+    1
+}
+
+fn default_minutes_window_hours() -> i64 {
+    24
+}
+
+/// Why the configuration or a token couldn't be loaded or saved.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A file couldn't be read or written.
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    /// The configuration isn't TOML with the expected fields.
+    Parse(toml::de::Error),
+    /// The configuration parses but can't be used as it is.
+    Invalid(String),
+    /// The account hasn't signed in yet.
+    MissingToken { path: String },
+    /// The token file doesn't hold a token.
+    Token {
+        path: String,
+        source: serde_json::Error,
+    },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "{}: {}", path, source),
+            ConfigError::Parse(e) => write!(f, "{}", e),
+            ConfigError::Invalid(message) => write!(f, "{}", message),
+            ConfigError::MissingToken { path } => write!(
+                f,
+                "No authentication token found at {}. Please run 'login' command first.",
+                path
+            ),
+            ConfigError::Token { path, source } => {
+                write!(f, "Invalid token file {}: {}", path, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse(e) => Some(e),
+            ConfigError::Token { source, .. } => Some(source),
+            ConfigError::Invalid(_) | ConfigError::MissingToken { .. } => None,
+        }
+    }
+}
+
+pub(crate) fn io_error(path: &str) -> impl FnOnce(std::io::Error) -> ConfigError {
+    let path = path.to_string();
+    move |source| ConfigError::Io { path, source }
+}
+
+pub fn load_config(config_path: &str) -> Result<Config, ConfigError> {
+    load(config_path, true)
+}
+
+/// Loads a tenant's configuration, which takes nothing from the environment:
+/// that is the host's, not the tenant's.
+pub fn load_tenant_config(config_path: &str) -> Result<Config, ConfigError> {
+    load(config_path, false)
+}
+
+fn load(config_path: &str, environment: bool) -> Result<Config, ConfigError> {
+    let content = std::fs::read_to_string(config_path).map_err(io_error(config_path))?;
+    let mut config: Config = toml::from_str(&content).map_err(ConfigError::Parse)?;
+    if environment && let Some(instance) = crate::secrets::instance() {
+        config.instance = instance;
+    }
+    if config.instance.is_empty() {
+        return Err(ConfigError::Invalid(format!(
+            "No instance configured; set 'instance' or {}",
+            crate::secrets::INSTANCE_VAR
+        )));
+    }
+    if environment {
+        config.access_token =
+            crate::secrets::access_token().map_err(|e| ConfigError::Invalid(e.to_string()))?;
+    }
+    let table: toml::Table = toml::from_str(&content).map_err(ConfigError::Parse)?;
+    for warning in crate::migrate::deprecation_warnings(&table) {
+        eprintln!("Warning: {}", warning);
+    }
+    validate(&config)?;
+    if config.config_version < crate::migrate::CURRENT_VERSION {
+        eprintln!(
+            "Warning: configuration uses an older layout; run 'config migrate' to upgrade it"
+        );
+    }
+    // On stderr, so stdout only carries what commands output, such as JSON
+    eprintln!("Configuration loaded from: {}", config_path);
+    eprintln!("Instance: {}", config.instance);
     Ok(config)
 }
 
-pub fn load_token(config: &Config) -> Result<mastodon_async::Data, Box<dyn std::error::Error>> {
-    let token_file_path = &config.token_file;
+/// Checks what the configuration's fields can't say on their own.
+fn validate(config: &Config) -> Result<(), ConfigError> {
+    if config.config_version > crate::migrate::CURRENT_VERSION {
+        return Err(ConfigError::Invalid(format!(
+            "Configuration version {} is newer than this release supports ({})",
+            config.config_version,
+            crate::migrate::CURRENT_VERSION
+        )));
+    }
+    for (index, feed) in config.feeds.iter().enumerate() {
+        if feed.name == ALL_FEEDS || config.feeds[..index].iter().any(|f| f.name == feed.name) {
+            return Err(ConfigError::Invalid(format!(
+                "Feed name '{}' is reserved or used twice; each [[feed]] needs a name of its own",
+                feed.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The main account's token: the one from the environment, or the token file.
+pub fn load_token(config: &Config) -> Result<mastodon_async::Data, ConfigError> {
+    match &config.access_token {
+        Some(token) => Ok(mastodon_async::Data {
+            base: config.instance.trim_end_matches('/').to_string().into(),
+            token: token.clone().into(),
+            ..Default::default()
+        }),
+        None => load_token_file(&config.token_file),
+    }
+}
 
+pub fn load_token_file(token_file_path: &str) -> Result<mastodon_async::Data, ConfigError> {
     if !std::path::Path::new(token_file_path).exists() {
-        return Err("No authentication token found. Please run 'login' command first.".into());
+        return Err(ConfigError::MissingToken {
+            path: token_file_path.to_string(),
+        });
     }
 
-    let content = std::fs::read_to_string(token_file_path)?;
-    let data: mastodon_async::Data = serde_json::from_str(&content)?;
-    Ok(data)
+    crate::secrets::warn_if_exposed(std::path::Path::new(token_file_path));
+    let content = std::fs::read_to_string(token_file_path).map_err(io_error(token_file_path))?;
+    serde_json::from_str(&content).map_err(|source| ConfigError::Token {
+        path: token_file_path.to_string(),
+        source,
+    })
 }
 
-pub fn save_token(
-    config: &Config,
-    token_data: &mastodon_async::Data,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let token_file_path = &config.token_file;
+pub fn save_token(config: &Config, token_data: &mastodon_async::Data) -> Result<(), ConfigError> {
+    save_token_file(&config.token_file, token_data)
+}
 
+pub fn save_token_file(
+    token_file_path: &str,
+    token_data: &mastodon_async::Data,
+) -> Result<(), ConfigError> {
     // Create parent directory if it doesn't exist
     if let Some(parent) = std::path::Path::new(token_file_path).parent() {
-        std::fs::create_dir_all(parent)?;
+        std::fs::create_dir_all(parent).map_err(io_error(token_file_path))?;
     }
 
-    let json = serde_json::to_string_pretty(token_data)?;
-    std::fs::write(token_file_path, json)?;
+    let json = serde_json::to_string_pretty(token_data).map_err(|source| ConfigError::Token {
+        path: token_file_path.to_string(),
+        source,
+    })?;
+    crate::secrets::write(std::path::Path::new(token_file_path), &json)
+        .map_err(io_error(token_file_path))?;
 
     println!("Authentication token saved to: {}", token_file_path);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_scopes() {
+        let config: Config = toml::from_str(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            max_image_bytes = 0
+
+            [agenda_call]
+        "#})
+        .unwrap();
+        assert_eq!(config.required_scopes(), vec!["write:statuses"]);
+
+        let config: Config = toml::from_str(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            [scheduling_poll]
+        "#})
+        .unwrap();
+        assert_eq!(
+            config.required_scopes(),
+            vec!["write:statuses", "write:media", "read:statuses"]
+        );
+        let config: Config = toml::from_str(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            max_image_bytes = 0
+
+            [profile]
+        "#})
+        .unwrap();
+        assert_eq!(
+            config.required_scopes(),
+            vec!["write:statuses", "read:accounts", "write:accounts"]
+        );
+        let config: Config = toml::from_str(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            max_image_bytes = 0
+
+            [profile]
+            [duplicate_check]
+        "#})
+        .unwrap();
+        assert_eq!(
+            config.required_scopes(),
+            vec![
+                "write:statuses",
+                "read:accounts",
+                "write:accounts",
+                "read:statuses"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_post_options() {
+        let config: Config = toml::from_str(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            [post]
+            visibility = "unlisted"
+            spoiler_text = "Meeting announcement"
+            language = "en"
+        "#})
+        .unwrap();
+        let overrides = PostOptions {
+            visibility: Some(Visibility::Private),
+            in_reply_to_id: Some("42".to_string()),
+            ..Default::default()
+        };
+        let status = config
+            .with_post_options(&overrides)
+            .post
+            .status("Hello".to_string());
+        assert_eq!(status.visibility, Some(Visibility::Private));
+        assert_eq!(status.spoiler_text.as_deref(), Some("Meeting announcement"));
+        assert_eq!(status.language, Some(Language::Eng));
+        assert_eq!(status.in_reply_to_id.as_deref(), Some("42"));
+        // Media is hidden behind the content warning too, unless it says otherwise
+        assert_eq!(status.sensitive, Some(true));
+        let shown = PostOptions {
+            sensitive: Some(false),
+            ..Default::default()
+        };
+        let status = config
+            .with_post_options(&shown)
+            .post
+            .status("Hello".to_string());
+        assert_eq!(status.sensitive, Some(false));
+
+        let invalid = toml::from_str::<Config>(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            [post]
+            visibility = "everyone"
+        "#});
+        assert!(invalid.is_err());
+        assert!(parse_language("xx").is_err());
+        assert!(parse_visibility("Unlisted").is_ok());
+    }
+
+    #[test]
+    fn test_command_lines() {
+        let config: Config = toml::from_str(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+            default_command = "post-next  --count 3"
+
+            [aliases]
+            agenda = "post-agenda-call --update-changed"
+            hello = ["post-status", "Hello, world!"]
+        "#})
+        .unwrap();
+        assert_eq!(
+            config.default_command.unwrap().args(),
+            ["post-next", "--count", "3"]
+        );
+        assert_eq!(
+            config.aliases["hello"].args(),
+            ["post-status", "Hello, world!"]
+        );
+    }
+
+    #[test]
+    fn test_feeds() {
+        let config: Config = toml::from_str(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            [templates]
+            next = "Next: {summary}"
+
+            [post]
+            visibility = "unlisted"
+
+            [[feed]]
+            name = "docs"
+            webcal = "https://example.com/docs.ics"
+            instance = "https://docs.example"
+            token_file = "token-docs.json"
+
+            [feed.templates]
+            next = "Docs sprint: {summary}"
+
+            [[feed]]
+            name = "infra"
+            webcal = "https://example.com/infra.ics"
+            instance = "https://infra.example"
+            token_file = "token-infra.json"
+            state_file = "infra/state.json"
+
+            [feed.post]
+            spoiler_text = "Outage planning"
+        "#})
+        .unwrap();
+
+        let main = config.select_feeds(None).unwrap();
+        assert_eq!(main.len(), 1);
+        assert_eq!(main[0].1.webcal, "https://example.com/calendar.ics");
+
+        let all = config.select_feeds(Some("all")).unwrap();
+        let names: Vec<_> = all.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, [None, Some("docs"), Some("infra")]);
+        let docs = &all[1].1;
+        assert_eq!(docs.instance, "https://docs.example");
+        assert_eq!(docs.state_file, "state-docs.json");
+        assert_eq!(
+            docs.templates.next.as_deref(),
+            Some("Docs sprint: {summary}")
+        );
+        assert!(docs.feeds.is_empty());
+        let infra = &all[2].1;
+        assert_eq!(infra.state_file, "infra/state.json");
+        assert_eq!(infra.templates.next.as_deref(), Some("Next: {summary}"));
+        assert_eq!(infra.post.visibility, Some(Visibility::Unlisted));
+        assert_eq!(infra.post.spoiler_text.as_deref(), Some("Outage planning"));
+        assert_eq!(docs.post.spoiler_text, None);
+
+        assert_eq!(config.select_feeds(Some("infra")).unwrap().len(), 1);
+        assert!(config.select_feeds(Some("sales")).is_err());
+    }
+
+    #[test]
+    fn test_targets() {
+        let config: Config = toml::from_str(indoc::indoc! {r##"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+            max_chars = 500
+
+            [templates]
+            next = "📅 {summary} #meetup"
+
+            [target.matrix]
+            max_chars = 200
+
+            [target.matrix.templates]
+            next = "Next: {summary}"
+
+            [[feed]]
+            name = "room"
+            webcal = "https://example.com/calendar.ics"
+            network = "matrix"
+            instance = "https://matrix.example.org"
+            room = "#meetings:example.org"
+            token_file = "token-room.json"
+
+            [[feed]]
+            name = "docs"
+            webcal = "https://example.com/docs.ics"
+            instance = "https://docs.example"
+            token_file = "token-docs.json"
+        "##})
+        .unwrap();
+
+        let feeds = config.select_feeds(Some("all")).unwrap();
+        let room = &feeds[1].1;
+        assert_eq!(room.templates.next.as_deref(), Some("Next: {summary}"));
+        assert_eq!(room.max_chars, Some(200));
+        let docs = &feeds[2].1;
+        assert_eq!(docs.templates.next.as_deref(), Some("📅 {summary} #meetup"));
+        assert_eq!(docs.max_chars, Some(500));
+    }
+
+    #[test]
+    fn test_default_url_policy() {
+        let config: Config = toml::from_str(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+        "#})
+        .unwrap();
+        assert_eq!(config.url_policy.schemes, ["https", "http"]);
+        assert!(config.url_policy.allow.is_empty());
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let missing = std::env::temp_dir().join("ical-to-masto-missing.toml");
+        assert!(matches!(
+            load_config(missing.to_str().unwrap()),
+            Err(ConfigError::Io { .. })
+        ));
+
+        let config: Config = toml::from_str(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            [[feed]]
+            name = "all"
+            webcal = "https://example.com/docs.ics"
+            instance = "https://docs.example"
+            token_file = "token-docs.json"
+        "#})
+        .unwrap();
+        let error = validate(&config).unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid(_)));
+        assert!(error.to_string().contains("'all' is reserved"));
+
+        let token_file =
+            std::env::temp_dir().join(format!("ical-to-masto-token-{}.json", std::process::id()));
+        let token_path = token_file.to_str().unwrap();
+        assert!(matches!(
+            load_token_file(token_path),
+            Err(ConfigError::MissingToken { .. })
+        ));
+        std::fs::write(&token_file, "not json").unwrap();
+        assert!(matches!(
+            load_token_file(token_path),
+            Err(ConfigError::Token { .. })
+        ));
+        std::fs::remove_file(&token_file).unwrap();
+    }
+}
@@ -0,0 +1,539 @@
+use crate::config::{Config, ScheduleConfig};
+use crate::quiet;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use croner::parser::{CronParser, Seconds};
+use tracing::Instrument;
+
+/// Parses a cron expression: the five standard fields, optionally preceded
+/// by a seconds field.
+pub fn parse_cron(expression: &str) -> Result<Cron> {
+    CronParser::builder()
+        .seconds(Seconds::Optional)
+        .build()
+        .parse(expression)
+        .map_err(|e| anyhow!("Invalid cron expression {:?}: {}", expression, e))
+}
+
+/// A command the daemon runs, and when.
+pub struct Job {
+    /// The command line, as it would be given after `ical-to-masto`.
+    pub args: Vec<String>,
+    schedules: Vec<Cron>,
+}
+
+impl Job {
+    pub fn new(config: &ScheduleConfig) -> Result<Self> {
+        let args = config.command.args();
+        if args.is_empty() || config.cron.is_empty() {
+            return Err(anyhow!(
+                "Each [[schedule]] needs a command and a cron expression"
+            ));
+        }
+        let schedules = config
+            .cron
+            .iter()
+            .map(|expression| parse_cron(expression))
+            .collect::<Result<_>>()?;
+        Ok(Job { args, schedules })
+    }
+
+    pub fn name(&self) -> String {
+        self.args.join(" ")
+    }
+
+    /// When the job next fires after `after`, going by `timezone`'s clock.
+    pub fn next_fire(&self, after: DateTime<Utc>, timezone: Tz) -> Option<DateTime<Utc>> {
+        let after = after.with_timezone(&timezone);
+        self.schedules
+            .iter()
+            .filter_map(|cron| cron.find_next_occurrence(&after, false).ok())
+            .min()
+            .map(|next| next.with_timezone(&Utc))
+    }
+}
+
+/// A calendar the daemon fetches on an interval of its own.
+pub struct Refresh {
+    /// The feed of the calendar; `None` for the main one.
+    pub feed: Option<String>,
+    pub every: Duration,
+}
+
+impl Refresh {
+    pub fn name(&self) -> &str {
+        self.feed.as_deref().unwrap_or("the main calendar")
+    }
+
+    /// When the calendar is next fetched after `after`, every `every` from
+    /// `since`.
+    pub fn next_fire(&self, after: DateTime<Utc>, since: DateTime<Utc>) -> DateTime<Utc> {
+        let every = self.every.num_seconds().max(1);
+        let intervals = (after - since).num_seconds().max(0) / every + 1;
+        since + Duration::seconds(intervals * every)
+    }
+}
+
+/// The `[[schedule]]` jobs, read in the configured timezone, and the
+/// calendars with a `refresh_minutes` of their own.
+pub struct Scheduler {
+    pub jobs: Vec<Job>,
+    pub refreshes: Vec<Refresh>,
+    pub timezone: Tz,
+}
+
+impl Scheduler {
+    pub fn new(config: &Config) -> Result<Self> {
+        if config.schedule.is_empty() {
+            return Err(anyhow!("No [[schedule]] entries in the configuration"));
+        }
+        Ok(Scheduler {
+            jobs: config
+                .schedule
+                .iter()
+                .map(Job::new)
+                .collect::<Result<_>>()?,
+            refreshes: refreshes(config),
+            timezone: quiet::timezone(config)?,
+        })
+    }
+
+    /// The earliest time after `after` that any job fires, with the jobs that
+    /// fire then in configuration order.
+    pub fn next(&self, after: DateTime<Utc>) -> Option<(DateTime<Utc>, Vec<&Job>)> {
+        let fires: Vec<_> = self
+            .jobs
+            .iter()
+            .filter_map(|job| Some((job.next_fire(after, self.timezone)?, job)))
+            .collect();
+        let at = fires.iter().map(|(at, _)| *at).min()?;
+        let jobs = fires
+            .into_iter()
+            .filter(|(fire, _)| *fire == at)
+            .map(|(_, job)| job)
+            .collect();
+        Some((at, jobs))
+    }
+
+    /// When calendars are next fetched after `after`, refreshing since
+    /// `since`, with the calendars fetched then.
+    pub fn next_refresh(
+        &self,
+        after: DateTime<Utc>,
+        since: DateTime<Utc>,
+    ) -> Option<(DateTime<Utc>, Vec<&Refresh>)> {
+        let at = self
+            .refreshes
+            .iter()
+            .map(|refresh| refresh.next_fire(after, since))
+            .min()?;
+        let due = self
+            .refreshes
+            .iter()
+            .filter(|refresh| refresh.next_fire(after, since) == at)
+            .collect();
+        Some((at, due))
+    }
+}
+
+/// The work of several tenants due at once, taken in turns so none waits for
+/// all of another's: one item of each in turn, starting with the `first`
+/// tenant. Each item comes with the index of its tenant.
+pub fn in_turns<T>(queues: Vec<Vec<T>>, first: usize) -> Vec<(usize, T)> {
+    let count = queues.len();
+    let mut queues: Vec<_> = queues.into_iter().map(Vec::into_iter).collect();
+    let mut turns = Vec::new();
+    loop {
+        let before = turns.len();
+        for offset in 0..count {
+            let tenant = (first + offset) % count;
+            if let Some(item) = queues[tenant].next() {
+                turns.push((tenant, item));
+            }
+        }
+        if turns.len() == before {
+            return turns;
+        }
+    }
+}
+
+/// A configuration the daemon runs the `[[schedule]]` commands of, for the
+/// feeds it selects, and the tenant it belongs to, if any.
+pub struct Scheduled<'a> {
+    pub tenant: Option<&'a str>,
+    pub config_path: &'a str,
+    pub config: &'a Config,
+    pub feeds: Vec<(Option<&'a str>, Config)>,
+    pub scheduler: Scheduler,
+}
+
+/// Something due in the daemon.
+pub enum Due<'a> {
+    Refresh(&'a Refresh),
+    Job(&'a Job),
+}
+
+impl<'a> Scheduled<'a> {
+    /// The schedule of `config`, refreshing only the calendars of the feeds
+    /// `feed` selects.
+    pub fn new(
+        tenant: Option<&'a str>,
+        config_path: &'a str,
+        config: &'a Config,
+        feed: Option<&str>,
+    ) -> Result<Self> {
+        let feeds = config.select_feeds(feed).map_err(|e| anyhow!(e))?;
+        let mut scheduler = Scheduler::new(config)?;
+        scheduler.refreshes.retain(|refresh| {
+            feeds
+                .iter()
+                .any(|(name, _)| *name == refresh.feed.as_deref())
+        });
+        Ok(Scheduled {
+            tenant,
+            config_path,
+            config,
+            feeds,
+            scheduler,
+        })
+    }
+
+    /// `what` named along with the tenant it is done for.
+    pub fn label(&self, what: &str) -> String {
+        match self.tenant {
+            Some(tenant) => format!("{} of {}", what, tenant),
+            None => what.to_string(),
+        }
+    }
+
+    /// The configuration of the calendar a refresh fetches.
+    pub fn feed_config(&self, refresh: &Refresh) -> Option<&Config> {
+        self.feeds
+            .iter()
+            .find(|(name, _)| *name == refresh.feed.as_deref())
+            .map(|(_, config)| config)
+    }
+
+    /// What is due at `at`: the calendars refreshed then, followed by the
+    /// commands, so they see the refreshed calendars.
+    pub fn due_at(
+        &self,
+        at: DateTime<Utc>,
+        after: DateTime<Utc>,
+        since: DateTime<Utc>,
+    ) -> Vec<Due<'_>> {
+        let refreshes = self
+            .scheduler
+            .next_refresh(after, since)
+            .filter(|(refresh_at, _)| *refresh_at == at)
+            .map(|(_, due)| due)
+            .unwrap_or_default();
+        let jobs = self
+            .scheduler
+            .next(after)
+            .filter(|(jobs_at, _)| *jobs_at == at)
+            .map(|(_, jobs)| jobs)
+            .unwrap_or_default();
+        refreshes
+            .into_iter()
+            .map(Due::Refresh)
+            .chain(jobs.into_iter().map(Due::Job))
+            .collect()
+    }
+}
+
+/// The earliest time after `after` that anything of `scheduled` is due,
+/// refreshing since `since`, with what each has due then.
+pub fn next_due<'s>(
+    scheduled: &'s [Scheduled<'_>],
+    after: DateTime<Utc>,
+    since: DateTime<Utc>,
+) -> Option<(DateTime<Utc>, Vec<Vec<Due<'s>>>)> {
+    let at = scheduled
+        .iter()
+        .flat_map(|scheduled| {
+            let scheduler = &scheduled.scheduler;
+            [
+                scheduler.next(after).map(|(at, _)| at),
+                scheduler.next_refresh(after, since).map(|(at, _)| at),
+            ]
+        })
+        .flatten()
+        .min()?;
+    let due = scheduled
+        .iter()
+        .map(|scheduled| scheduled.due_at(at, after, since))
+        .collect();
+    Some((at, due))
+}
+
+/// Refreshes the calendars and runs the commands the daemon has due.
+pub trait Runner {
+    /// Refreshes a calendar or runs a command of `scheduled`, reporting what
+    /// fails.
+    fn run(&self, scheduled: &Scheduled<'_>, due: Due<'_>) -> impl Future<Output = ()>;
+}
+
+/// Has `runner` run what `scheduled` has due at its times, one after another,
+/// until none is due again. What several have due at once is run in turns,
+/// `pace` apart, with another going first each time.
+pub async fn run_schedules(
+    scheduled: &[Scheduled<'_>],
+    pace: std::time::Duration,
+    runner: &impl Runner,
+) -> Result<()> {
+    let now = Utc::now();
+    let mut after = now;
+    let mut first = 0;
+    loop {
+        let (at, due) = next_due(scheduled, after, now)
+            .ok_or_else(|| anyhow!("none of the schedules is due again"))?;
+        let wait = (at - Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+
+        let mut last = None;
+        for (index, due) in in_turns(due, first) {
+            if last.is_some_and(|last| last != index) {
+                tokio::time::sleep(pace).await;
+            }
+            last = Some(index);
+            let scheduled = &scheduled[index];
+            let span = match scheduled.tenant {
+                Some(tenant) => tracing::info_span!("tenant", tenant),
+                None => tracing::Span::none(),
+            };
+            runner.run(scheduled, due).instrument(span).await;
+        }
+        first = (first + 1) % scheduled.len();
+        // Times missed while the commands ran are skipped
+        after = at.max(Utc::now());
+    }
+}
+
+fn refreshes(config: &Config) -> Vec<Refresh> {
+    let main = (None, config.refresh_minutes);
+    let feeds = config
+        .feeds
+        .iter()
+        .map(|feed| (Some(feed.name.clone()), feed.refresh_minutes));
+    std::iter::once(main)
+        .chain(feeds)
+        .filter_map(|(feed, minutes)| {
+            Some(Refresh {
+                feed,
+                every: Duration::minutes(i64::try_from(minutes?).ok()?.max(1)),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use indoc::indoc;
+
+    #[test]
+    fn test_scheduler() {
+        let config: Config = toml::from_str(indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+            timezone = "America/New_York"
+
+            [[schedule]]
+            command = "post-next --count 3"
+            cron = ["0 9 * * Mon"]
+
+            [[schedule]]
+            command = "post-agenda-call"
+            cron = ["*/15 * * * *", "30 0 12 * * *"]
+        "#})
+        .unwrap();
+        let scheduler = Scheduler::new(&config).unwrap();
+
+        // Monday, December 1st, 08:50 in New York
+        let now = Utc.with_ymd_and_hms(2025, 12, 1, 13, 50, 0).unwrap();
+        let (at, jobs) = scheduler.next(now).unwrap();
+        assert_eq!(at, Utc.with_ymd_and_hms(2025, 12, 1, 14, 0, 0).unwrap());
+        let names: Vec<_> = jobs.iter().map(|job| job.name()).collect();
+        assert_eq!(names, ["post-next --count 3", "post-agenda-call"]);
+
+        let (at, jobs) = scheduler.next(at).unwrap();
+        assert_eq!(at, Utc.with_ymd_and_hms(2025, 12, 1, 14, 15, 0).unwrap());
+        assert_eq!(jobs.len(), 1);
+
+        // The seconds field is honored
+        let noon = Utc.with_ymd_and_hms(2025, 12, 1, 16, 55, 0).unwrap();
+        assert_eq!(
+            scheduler.jobs[1].next_fire(noon, scheduler.timezone),
+            Some(Utc.with_ymd_and_hms(2025, 12, 1, 17, 0, 0).unwrap())
+        );
+        let after_noon = Utc.with_ymd_and_hms(2025, 12, 1, 17, 0, 0).unwrap();
+        assert_eq!(
+            scheduler.jobs[1].next_fire(after_noon, scheduler.timezone),
+            Some(Utc.with_ymd_and_hms(2025, 12, 1, 17, 0, 30).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_calendar_refreshes() {
+        let config: Config = toml::from_str(indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/community.ics"
+            refresh_minutes = 10
+
+            [[schedule]]
+            command = "post-next"
+            cron = ["0 9 * * *"]
+
+            [[feed]]
+            name = "holidays"
+            webcal = "https://example.com/holidays.ics"
+            instance = "https://mastodon.example"
+            token_file = "holidays.json"
+            refresh_minutes = 1440
+
+            [[feed]]
+            name = "board"
+            webcal = "https://example.com/board.ics"
+            instance = "https://mastodon.example"
+            token_file = "board.json"
+        "#})
+        .unwrap();
+        let scheduler = Scheduler::new(&config).unwrap();
+        let names: Vec<_> = scheduler.refreshes.iter().map(Refresh::name).collect();
+        assert_eq!(names, ["the main calendar", "holidays"]);
+
+        let since = Utc.with_ymd_and_hms(2025, 12, 1, 8, 0, 0).unwrap();
+        let (at, due) = scheduler.next_refresh(since, since).unwrap();
+        assert_eq!(at, Utc.with_ymd_and_hms(2025, 12, 1, 8, 10, 0).unwrap());
+        assert_eq!(due.len(), 1);
+
+        // Long after, both are due together at the holiday feed's time
+        let day_later = Utc.with_ymd_and_hms(2025, 12, 2, 7, 55, 0).unwrap();
+        let (at, due) = scheduler.next_refresh(day_later, since).unwrap();
+        assert_eq!(at, Utc.with_ymd_and_hms(2025, 12, 2, 8, 0, 0).unwrap());
+        assert_eq!(due.len(), 2);
+
+        // Without a [cache], a refreshed calendar is cached in the default place
+        let cache = config.calendar_cache().unwrap();
+        assert_eq!((cache.dir.as_str(), cache.ttl_seconds), ("cache", 600));
+        assert!(config.for_feed(&config.feeds[1]).calendar_cache().is_none());
+    }
+
+    #[test]
+    fn test_scheduled() {
+        let config: Config = toml::from_str(indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/community.ics"
+            refresh_minutes = 30
+
+            [[schedule]]
+            command = "post-next"
+            cron = ["0 9 * * *"]
+
+            [[feed]]
+            name = "holidays"
+            webcal = "https://example.com/holidays.ics"
+            instance = "https://mastodon.example"
+            token_file = "holidays.json"
+            refresh_minutes = 60
+        "#})
+        .unwrap();
+        let main = Scheduled::new(None, "config.toml", &config, None).unwrap();
+        let holidays =
+            Scheduled::new(Some("city"), "city.toml", &config, Some("holidays")).unwrap();
+        assert!(Scheduled::new(None, "config.toml", &config, Some("board")).is_err());
+
+        // Only the selected calendars are refreshed
+        let names: Vec<_> = main.scheduler.refreshes.iter().map(Refresh::name).collect();
+        assert_eq!(names, ["the main calendar"]);
+        let refresh = &holidays.scheduler.refreshes[0];
+        assert_eq!(refresh.name(), "holidays");
+        let feed = holidays.feed_config(refresh).unwrap();
+        assert_eq!(feed.webcal, "https://example.com/holidays.ics");
+        assert!(main.feed_config(refresh).is_none());
+
+        assert_eq!(main.label("'post-next'"), "'post-next'");
+        assert_eq!(holidays.label("'post-next'"), "'post-next' of city");
+
+        // At 09:00 the calendars are refreshed before the command runs
+        let since = Utc.with_ymd_and_hms(2025, 12, 1, 8, 0, 0).unwrap();
+        let after = Utc.with_ymd_and_hms(2025, 12, 1, 8, 45, 0).unwrap();
+        let scheduled = [main, holidays];
+        let (at, due) = next_due(&scheduled, after, since).unwrap();
+        assert_eq!(at, Utc.with_ymd_and_hms(2025, 12, 1, 9, 0, 0).unwrap());
+        let names: Vec<Vec<_>> = due
+            .iter()
+            .map(|due| {
+                due.iter()
+                    .map(|due| match due {
+                        Due::Refresh(refresh) => refresh.name().to_string(),
+                        Due::Job(job) => job.name(),
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ["the main calendar", "post-next"],
+                ["holidays", "post-next"]
+            ]
+        );
+
+        // At 09:30 only the main calendar is refreshed
+        let (at, due) = next_due(&scheduled, at, since).unwrap();
+        assert_eq!(at, Utc.with_ymd_and_hms(2025, 12, 1, 9, 30, 0).unwrap());
+        assert_eq!(due[0].len(), 1);
+        assert!(due[1].is_empty());
+    }
+
+    struct Unused;
+
+    impl Runner for Unused {
+        async fn run(&self, _: &Scheduled<'_>, _: Due<'_>) {
+            panic!("nothing is due");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_schedules() {
+        let config: Config = toml::from_str(indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            [[schedule]]
+            command = "post-next"
+            cron = ["0 9 30 2 *"]
+        "#})
+        .unwrap();
+        let scheduled = Scheduled::new(None, "config.toml", &config, None).unwrap();
+        let error = run_schedules(&[scheduled], std::time::Duration::ZERO, &Unused)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "none of the schedules is due again");
+    }
+
+    #[test]
+    fn test_in_turns() {
+        let queues = vec![vec!["a1", "a2", "a3"], vec![], vec!["c1"], vec!["d1", "d2"]];
+        let order: Vec<_> = in_turns(queues.clone(), 0)
+            .into_iter()
+            .map(|(_, item)| item)
+            .collect();
+        assert_eq!(order, ["a1", "c1", "d1", "a2", "d2", "a3"]);
+        // The tenant going first moves on each time
+        let order = in_turns(queues, 3);
+        assert_eq!(order[..3], [(3, "d1"), (0, "a1"), (2, "c1")]);
+    }
+
+    #[test]
+    fn test_invalid_cron() {
+        assert!(parse_cron("0 9 * * Mon").is_ok());
+        assert!(parse_cron("every monday").is_err());
+        assert!(parse_cron("0 0 25 * * *").is_err());
+    }
+}
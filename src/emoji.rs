@@ -0,0 +1,278 @@
+use crate::config::{Config, Network, UrlPolicyConfig};
+use crate::outbox::Sent;
+use crate::url_policy;
+use anyhow::Result;
+use reqwest::Url;
+use std::collections::HashSet;
+
+/// Extracts the custom emoji shortcodes (`:name:`) used in a status text.
+///
+/// This follows the same rules Mastodon uses: a name is at least two ASCII
+/// letters, digits or underscores, so text such as `10:00:00` or `:a:` is not
+/// mistaken for a shortcode.
+pub fn shortcodes(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut found = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let at_boundary = i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == ':');
+        if chars[i] != ':' || !at_boundary {
+            i += 1;
+            continue;
+        }
+
+        let mut end = i + 1;
+        while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_') {
+            end += 1;
+        }
+
+        let closes = end < chars.len() && chars[end] == ':' && end - i > 2;
+        let followed_ok = closes
+            && (end + 1 == chars.len()
+                || !(chars[end + 1].is_alphanumeric() || chars[end + 1] == ':'));
+
+        if followed_ok {
+            let name: String = chars[i + 1..end].iter().collect();
+            if !found.contains(&name) {
+                found.push(name);
+            }
+            i = end + 1;
+        } else {
+            i += 1;
+        }
+    }
+
+    found
+}
+
+/// Returns the shortcodes in `text` that are not in the `known` set.
+pub fn unknown_shortcodes(text: &str, known: &HashSet<String>) -> Vec<String> {
+    shortcodes(text)
+        .into_iter()
+        .filter(|code| !known.contains(code))
+        .collect()
+}
+
+/// Fetches the custom emoji shortcodes available on the instance at
+/// `instance`, which anyone may list.
+pub async fn instance_shortcodes(
+    instance: &str,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<HashSet<String>> {
+    let url = format!("{}/api/v1/custom_emojis", instance.trim_end_matches('/'));
+    let emojis: Vec<serde_json::Value> = url_policy::client(policy, &url)?
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(emojis
+        .iter()
+        .filter_map(|emoji| emoji["shortcode"].as_str().map(String::from))
+        .collect())
+}
+
+/// The shortcodes in `texts` that the instance at `instance` doesn't know,
+/// each once. The instance is only asked when there are shortcodes at all.
+pub async fn unknown_on_instance(
+    instance: &str,
+    policy: Option<&UrlPolicyConfig>,
+    texts: &[&str],
+) -> Result<Vec<String>> {
+    let mut used = Vec::new();
+    for code in texts.iter().flat_map(|text| shortcodes(text)) {
+        if !used.contains(&code) {
+            used.push(code);
+        }
+    }
+    if used.is_empty() {
+        return Ok(used);
+    }
+    let known = instance_shortcodes(instance, policy).await?;
+    Ok(used
+        .into_iter()
+        .filter(|code| !known.contains(code))
+        .collect())
+}
+
+/// The Mastodon instances `config` posts to, each with its host, which is
+/// how a status sent there names its target.
+pub fn instances(config: &Config) -> Vec<(String, String)> {
+    let feeds: Vec<_> = config
+        .feeds
+        .iter()
+        .map(|feed| config.for_feed(feed))
+        .collect();
+    let accounts = std::iter::once(config)
+        .chain(&feeds)
+        .filter(|config| config.network == Network::Mastodon)
+        .flat_map(|config| {
+            std::iter::once(config.instance.as_str())
+                .chain(config.backup.iter().map(|backup| backup.instance.as_str()))
+                .chain(
+                    config
+                        .language_accounts
+                        .iter()
+                        .map(|account| account.instance.as_str()),
+                )
+        });
+    let mut instances: Vec<(String, String)> = Vec::new();
+    for instance in accounts {
+        let Some(host) = Url::parse(instance)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+        else {
+            continue;
+        };
+        if !instances.iter().any(|(known, _)| *known == host) {
+            instances.push((host, instance.to_string()));
+        }
+    }
+    instances
+}
+
+/// The shortcodes in the statuses of `sent` that their instance doesn't
+/// know, for each of the instances of `config` with any, by host.
+pub async fn unknown_in_sent(config: &Config, sent: &[Sent]) -> Vec<(String, Result<Vec<String>>)> {
+    let mut unknown = Vec::new();
+    for (host, instance) in instances(config) {
+        let texts: Vec<&str> = sent
+            .iter()
+            .filter(|sent| sent.target == host)
+            .flat_map(|sent| {
+                sent.status
+                    .status
+                    .as_deref()
+                    .into_iter()
+                    .chain(sent.status.spoiler_text.as_deref())
+            })
+            .collect();
+        match unknown_on_instance(&instance, Some(&config.url_policy), &texts).await {
+            Ok(codes) if codes.is_empty() => {}
+            found => unknown.push((host, found)),
+        }
+    }
+    unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// Lists `:meeting:` as the only custom emoji, counting the requests.
+    fn serve(requests: Arc<Mutex<usize>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                *requests.lock().unwrap() += 1;
+                let body = r#"[{"shortcode":"meeting","url":"https://example.com/meeting.png"}]"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_shortcodes() {
+        assert_eq!(
+            shortcodes(":meeting: Monthly meetup :linux_penguin:"),
+            vec!["meeting".to_string(), "linux_penguin".to_string()]
+        );
+        assert_eq!(shortcodes("Starts at 10:00:00 sharp"), Vec::<String>::new());
+        assert_eq!(
+            shortcodes("https://example.com/a:b:c"),
+            Vec::<String>::new()
+        );
+        assert_eq!(shortcodes("Empty :: pair"), Vec::<String>::new());
+        assert_eq!(shortcodes(":tada::tada:"), Vec::<String>::new());
+        assert_eq!(shortcodes(":tada: :tada:"), vec!["tada".to_string()]);
+        assert_eq!(shortcodes("Plan :a: or b"), Vec::<String>::new());
+        assert_eq!(shortcodes("Meet at :café: today"), Vec::<String>::new());
+        assert_eq!(shortcodes(":ok:"), vec!["ok".to_string()]);
+    }
+
+    #[test]
+    fn test_unknown_shortcodes() {
+        let known: HashSet<String> = ["meeting".to_string()].into_iter().collect();
+        assert_eq!(
+            unknown_shortcodes(":meeting: and :party:", &known),
+            vec!["party".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_on_instance() {
+        let requests = Arc::new(Mutex::new(0));
+        let instance = serve(requests.clone());
+
+        let texts = [":meeting: tonight :party:", ":party: and :tada:"];
+        let unknown = unknown_on_instance(&instance, None, &texts).await.unwrap();
+        assert_eq!(unknown, ["party", "tada"]);
+
+        // Without shortcodes the instance isn't asked
+        let unknown = unknown_on_instance(&instance, None, &["10:00:00"])
+            .await
+            .unwrap();
+        assert!(unknown.is_empty());
+        assert_eq!(*requests.lock().unwrap(), 1);
+
+        assert!(
+            unknown_on_instance("http://127.0.0.1:9", None, &[":party:"])
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_instances() {
+        let config: Config = toml::from_str(
+            r##"
+            instance = "https://mastodon.example/"
+            webcal = "https://example.com/calendar.ics"
+
+            [backup]
+            instance = "https://backup.example"
+
+            [[feed]]
+            name = "room"
+            webcal = "https://example.com/room.ics"
+            network = "matrix"
+            instance = "https://matrix.example.org"
+            room = "#meetings:example.org"
+            token_file = "token-room.json"
+
+            [[feed]]
+            name = "docs"
+            webcal = "https://example.com/docs.ics"
+            instance = "https://mastodon.example"
+            token_file = "token-docs.json"
+            "##,
+        )
+        .unwrap();
+        let hosts: Vec<_> = instances(&config)
+            .into_iter()
+            .map(|(host, _)| host)
+            .collect();
+        assert_eq!(hosts, ["mastodon.example", "backup.example"]);
+    }
+}
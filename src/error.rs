@@ -0,0 +1,90 @@
+use crate::config::ConfigError;
+use std::fmt;
+
+/// Why a command or a publisher failed, by the step that failed, so callers
+/// can tell a bad configuration from an instance that can't be reached.
+///
+/// Apart from [`Error::Config`], each holds the error as it was raised, which
+/// can be downcast for details such as [`crate::poster::Unavailable`].
+#[derive(Debug)]
+pub enum Error {
+    /// The configuration or a token couldn't be loaded, or lacks what the
+    /// command needs.
+    Config(ConfigError),
+    /// The calendar, or another URL the bot reads, couldn't be fetched.
+    Fetch(anyhow::Error),
+    /// The calendar, a template or another input couldn't be understood, or
+    /// the calendar lacks what the command needs.
+    Parse(anyhow::Error),
+    /// The instance or room didn't take, change or return statuses, or
+    /// refused to register the application or accept its token.
+    Publish(anyhow::Error),
+    /// The state file couldn't be read or written.
+    State(anyhow::Error),
+}
+
+/// A result whose error is an [`Error`].
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// The error as it was raised, for all but configuration errors.
+    pub fn cause(&self) -> Option<&anyhow::Error> {
+        match self {
+            Error::Config(_) => None,
+            Error::Fetch(e) | Error::Parse(e) | Error::Publish(e) | Error::State(e) => Some(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "{}", e),
+            Error::Fetch(e) | Error::Parse(e) | Error::Publish(e) | Error::State(e) => {
+                write!(f, "{}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Config(e) => e.source(),
+            Error::Fetch(e) | Error::Parse(e) | Error::Publish(e) | Error::State(e) => e.source(),
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+/// A configuration error saying `message`, such as a section a command
+/// needs being missing or a setting that can't be used.
+pub(crate) fn invalid(message: impl fmt::Display) -> Error {
+    Error::Config(ConfigError::Invalid(message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_error() {
+        let missing = invalid("No [minutes] section found in the configuration");
+        assert!(matches!(missing, Error::Config(ConfigError::Invalid(_))));
+        assert_eq!(
+            missing.to_string(),
+            "No [minutes] section found in the configuration"
+        );
+        assert!(missing.cause().is_none());
+
+        let unreachable = Error::Publish(anyhow!("instance unreachable"));
+        assert_eq!(unreachable.to_string(), "instance unreachable");
+        assert!(unreachable.cause().is_some());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::calendar_links;
+use crate::ical::{self, CalendarEvent, TimeForm};
+use crate::skips::SkipReason;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Version of the layout `events --output json` writes. It only changes when
+/// a field is removed or changes meaning; fields may be added within a version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// What `events --output json` prints.
+#[derive(Debug, Serialize)]
+pub struct EventList {
+    pub schema_version: u32,
+    pub generated_at: DateTime<Utc>,
+    pub events: Vec<Event>,
+}
+
+impl EventList {
+    pub fn new(events: &[CalendarEvent], now: DateTime<Utc>) -> Self {
+        EventList {
+            schema_version: SCHEMA_VERSION,
+            generated_at: now,
+            events: events.iter().map(Event::from).collect(),
+        }
+    }
+}
+
+/// An event with its iCal escapes undone and its times in RFC 3339.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Event {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    /// Like `2025-12-03T19:00:00Z`; without the `Z` when the calendar gives no
+    /// timezone, and only the date for all-day events.
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub all_day: bool,
+    pub location: Option<String>,
+    pub url: Option<String>,
+    /// STATUS in lowercase: `tentative`, `confirmed` or `cancelled`.
+    pub status: Option<String>,
+    /// Whether the event takes place online.
+    pub online: bool,
+    /// Link to join the meeting online.
+    pub conference_url: Option<String>,
+    pub image: Option<String>,
+    /// Links that add the event to Google Calendar and Outlook.
+    pub google_calendar_url: Option<String>,
+    pub outlook_calendar_url: Option<String>,
+    /// Why `post-next` would leave the event out; only with `--explain`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<SkipReason>,
+}
+
+impl From<&CalendarEvent> for Event {
+    fn from(event: &CalendarEvent) -> Self {
+        let text = |value: &Option<String>| value.as_deref().map(ical::unescape_text);
+        Event {
+            uid: event.uid.clone(),
+            summary: text(&event.summary),
+            description: text(&event.description),
+            start: event.start_time.as_deref().and_then(rfc3339),
+            end: event.end_time.as_deref().and_then(rfc3339),
+            all_day: event.is_all_day(),
+            location: text(&event.location),
+            url: event.url.clone(),
+            status: event.status.as_deref().map(str::to_lowercase),
+            online: event.is_online(),
+            conference_url: event.conference.clone(),
+            image: event.image.clone(),
+            google_calendar_url: calendar_links::google(event),
+            outlook_calendar_url: calendar_links::outlook(event),
+            skip_reason: None,
+        }
+    }
+}
+
+fn rfc3339(value: &str) -> Option<String> {
+    let (time, form) = ical::parse_ical_time(value)?;
+    Some(match form {
+        TimeForm::Utc => time.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        TimeForm::Floating => time.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        TimeForm::Date => time.format("%Y-%m-%d").to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use indoc::indoc;
+
+    #[test]
+    fn test_event_list() {
+        let calendar = ical::IcalCalendar::parse_ical_content(indoc! {r"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:meeting@example.com
+            DTSTART:20251203T190000Z
+            DTEND:20251203T210000
+            SUMMARY:Monthly Meeting\, December
+            DESCRIPTION:Agenda:\nNew officers
+            LOCATION:https://meet.example.com/novalug
+            STATUS:TENTATIVE
+            END:VEVENT
+            BEGIN:VEVENT
+            DTSTART;VALUE=DATE:20251213
+            SUMMARY:Install Fest
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+        let now = Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap();
+
+        let json = serde_json::to_value(EventList::new(&calendar.events, now)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "schema_version": 1,
+                "generated_at": "2025-12-01T12:00:00Z",
+                "events": [
+                    {
+                        "uid": "meeting@example.com",
+                        "summary": "Monthly Meeting, December",
+                        "description": "Agenda:\nNew officers",
+                        "start": "2025-12-03T19:00:00Z",
+                        "end": "2025-12-03T21:00:00",
+                        "all_day": false,
+                        "location": "https://meet.example.com/novalug",
+                        "url": null,
+                        "status": "tentative",
+                        "online": true,
+                        "conference_url": null,
+                        "image": null,
+                        "google_calendar_url": "https://calendar.google.com/calendar/render?action=TEMPLATE&text=Monthly+Meeting%2C+December&dates=20251203T190000Z%2F20251203T210000Z&details=Agenda%3A%0ANew+officers&location=https%3A%2F%2Fmeet.example.com%2Fnovalug",
+                        "outlook_calendar_url": "https://outlook.live.com/calendar/0/action/compose?rru=addevent&subject=Monthly+Meeting%2C+December&startdt=2025-12-03T19%3A00%3A00Z&enddt=2025-12-03T21%3A00%3A00Z&allday=false&body=Agenda%3A%0ANew+officers&location=https%3A%2F%2Fmeet.example.com%2Fnovalug"
+                    },
+                    {
+                        "uid": null,
+                        "summary": "Install Fest",
+                        "description": null,
+                        "start": "2025-12-13",
+                        "end": null,
+                        "all_day": true,
+                        "location": null,
+                        "url": null,
+                        "status": null,
+                        "online": false,
+                        "conference_url": null,
+                        "image": null,
+                        "google_calendar_url": "https://calendar.google.com/calendar/render?action=TEMPLATE&text=Install+Fest&dates=20251213%2F20251214",
+                        "outlook_calendar_url": "https://outlook.live.com/calendar/0/action/compose?rru=addevent&subject=Install+Fest&startdt=2025-12-13&enddt=2025-12-14&allday=true"
+                    }
+                ]
+            })
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use crate::cache::CachedFeed;
+use crate::config::UrlPolicyConfig;
+use crate::timing::{self, Phase};
+use crate::url_policy;
+use anyhow::{Result, anyhow};
+use reqwest::StatusCode;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION};
+use std::fmt;
+use std::sync::Mutex;
+
+/// How many redirects (including HTML meta refreshes) are followed by default.
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Where a calendar read from stdin is: `-`.
+pub const STDIN: &str = "-";
+
+/// What was read from stdin, which can only be read once, for every later
+/// fetch of `-` in the process.
+static STDIN_CONTENT: Mutex<Option<String>> = Mutex::new(None);
+
+/// The body of a fetched calendar and the URL it was finally served from.
+pub struct Fetched {
+    pub final_url: String,
+    pub body: String,
+    /// What the server identifies this version of the calendar by, to ask
+    /// next time whether it changed.
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// A calendar server answering with an error status.
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: StatusCode,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP error: {}", self.status)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// Credentials a calendar is fetched with.
+#[derive(Debug, Clone, Copy)]
+pub enum Auth<'a> {
+    Bearer(&'a str),
+    /// HTTP basic auth, like WebDAV servers take.
+    Basic {
+        username: &'a str,
+        password: Option<&'a str>,
+    },
+}
+
+/// Turns `webcal://` URLs into the `https://` URLs they stand for.
+pub fn normalize_url(url: &str) -> String {
+    match url.strip_prefix("webcal://") {
+        Some(rest) => format!("https://{}", rest),
+        None => url.to_string(),
+    }
+}
+
+/// Fetches a calendar, following HTTP redirects and HTML meta refresh landing
+/// pages (which link shorteners and calendar hosts like to hand out) for at
+/// most `max_redirects` hops. The `auth` credentials are sent only while the
+/// requests stay on the host of `url`. With a `cached` copy, its URL is asked
+/// whether the calendar changed since, and the copy is returned if not.
+/// With a `policy`, every hop must be a URL it allows.
+pub async fn fetch_text(
+    url: &str,
+    max_redirects: usize,
+    auth: Option<Auth<'_>>,
+    cached: Option<&CachedFeed>,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<Fetched> {
+    timing::time_async(
+        Phase::Fetch,
+        follow_redirects(url, max_redirects, auth, cached, policy),
+    )
+    .await
+}
+
+/// Whether a calendar location is on this machine: a path, a `file://` URL,
+/// or `-` for stdin, rather than a webcal/HTTP(S) URL.
+pub fn is_local(location: &str) -> bool {
+    location == STDIN || location.starts_with("file://") || !location.contains("://")
+}
+
+/// Reads a calendar from a local location, as [`is_local`] takes it.
+pub fn read_local(location: &str) -> Result<String> {
+    timing::time(Phase::Fetch, || {
+        if location == STDIN {
+            let mut content = STDIN_CONTENT.lock().unwrap();
+            if content.is_none() {
+                *content = Some(std::io::read_to_string(std::io::stdin())?);
+            }
+            return Ok(content.clone().unwrap_or_default());
+        }
+        let path = match location.strip_prefix("file://") {
+            Some(_) => reqwest::Url::parse(location)
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+                .ok_or_else(|| anyhow!("Not a local file URL: {}", location))?,
+            None => location.into(),
+        };
+        std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Could not read the calendar {}: {}", path.display(), e))
+    })
+}
+
+/// Whether two URLs point at the same host.
+pub fn same_host(a: &str, b: &str) -> bool {
+    let host = |url: &str| {
+        reqwest::Url::parse(&normalize_url(url))
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+    };
+    host(a).is_some() && host(a) == host(b)
+}
+
+async fn follow_redirects(
+    url: &str,
+    max_redirects: usize,
+    auth: Option<Auth<'_>>,
+    cached: Option<&CachedFeed>,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<Fetched> {
+    let client = url_policy::client_builder(policy)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let mut current = reqwest::Url::parse(&normalize_url(url))?;
+    let host = current.host_str().map(str::to_string);
+    let mut hops = 0;
+
+    loop {
+        if let Some(policy) = policy {
+            url_policy::check(policy, &current)?;
+        }
+        let mut request = client.get(current.clone());
+        if let Some(auth) = auth
+            && current.host_str().map(str::to_string) == host
+        {
+            request = match auth {
+                Auth::Bearer(token) => request.bearer_auth(token),
+                Auth::Basic { username, password } => request.basic_auth(username, password),
+            };
+        }
+        let cached = cached.filter(|cached| cached.final_url == current.as_str());
+        if let Some(cached) = cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+
+        if let (StatusCode::NOT_MODIFIED, Some(cached)) = (status, cached) {
+            return Ok(Fetched {
+                final_url: cached.final_url.clone(),
+                body: cached.body.clone(),
+                etag: header(ETAG).or_else(|| cached.etag.clone()),
+                last_modified: header(LAST_MODIFIED).or_else(|| cached.last_modified.clone()),
+            });
+        }
+
+        let next = if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .ok_or_else(|| anyhow!("HTTP {} redirect without a Location header", status))?
+                .to_str()?;
+            current.join(&normalize_url(location))?
+        } else if status.is_success() {
+            let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+            let body = response.text().await?;
+            match meta_refresh_target(&body) {
+                Some(target) if !looks_like_calendar(&body) => {
+                    current.join(&normalize_url(&target))?
+                }
+                _ => {
+                    return Ok(Fetched {
+                        final_url: current.to_string(),
+                        body,
+                        etag,
+                        last_modified,
+                    });
+                }
+            }
+        } else {
+            return Err(HttpError { status }.into());
+        };
+
+        hops += 1;
+        if hops > max_redirects {
+            return Err(anyhow!(
+                "Too many redirects (more than {}) fetching {}",
+                max_redirects,
+                url
+            ));
+        }
+        current = next;
+    }
+}
+
+fn looks_like_calendar(body: &str) -> bool {
+    body.trim_start().starts_with("BEGIN:VCALENDAR")
+}
+
+/// Finds the target of a `<meta http-equiv="refresh" content="0; url=...">` tag.
+pub fn meta_refresh_target(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let mut rest = lower.as_str();
+    let mut offset = 0;
+
+    while let Some(start) = rest.find("<meta") {
+        let tag_start = offset + start;
+        let tag_end = tag_start + lower[tag_start..].find('>')?;
+        let tag = &lower[tag_start..tag_end];
+
+        if let Some(equiv) = attribute(tag, "http-equiv")
+            && equiv == "refresh"
+            && let Some(content) = attribute(tag, "content")
+        {
+            // Take the URL from the original text to keep its case
+            let content_start = tag_start + tag.find(&content)?;
+            let content = &html[content_start..content_start + content.len()];
+            let url_start = content.to_ascii_lowercase().find("url=")? + 4;
+            let target = content[url_start..].trim().trim_matches(['\'', '"']);
+            if !target.is_empty() {
+                return Some(target.to_string());
+            }
+        }
+
+        offset = tag_end;
+        rest = &lower[offset..];
+    }
+
+    None
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[start..];
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()?,
+    };
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            normalize_url("webcal://example.com/calendar.ics"),
+            "https://example.com/calendar.ics"
+        );
+        assert_eq!(
+            normalize_url("https://example.com/calendar.ics"),
+            "https://example.com/calendar.ics"
+        );
+    }
+
+    #[test]
+    fn test_read_local() {
+        assert!(is_local("-"));
+        assert!(is_local("calendars/meetings.ics"));
+        assert!(is_local("file:///var/lib/calendar.ics"));
+        assert!(!is_local("webcal://example.com/calendar.ics"));
+
+        let path = std::env::temp_dir().join(format!("ical-to-masto-{}.ics", std::process::id()));
+        std::fs::write(&path, "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").unwrap();
+        let url = reqwest::Url::from_file_path(&path).unwrap();
+        assert_eq!(
+            read_local(url.as_str()).unwrap(),
+            read_local(path.to_str().unwrap()).unwrap()
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_local(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_same_host() {
+        assert!(same_host(
+            "webcal://example.com/calendar.ics",
+            "https://Example.com/other.ics"
+        ));
+        assert!(!same_host(
+            "https://example.com/calendar.ics",
+            "https://cdn.example.net/calendar.ics"
+        ));
+        assert!(!same_host("not a url", "not a url"));
+    }
+
+    #[test]
+    fn test_meta_refresh_target() {
+        let html = r#"<html><head><META HTTP-EQUIV="Refresh" CONTENT="0; URL=https://Example.com/Calendar.ics"></head></html>"#;
+        assert_eq!(
+            meta_refresh_target(html),
+            Some("https://Example.com/Calendar.ics".to_string())
+        );
+
+        let html = "<meta charset=utf-8><meta http-equiv=refresh content='5;url=/feed.ics'>";
+        assert_eq!(meta_refresh_target(html), Some("/feed.ics".to_string()));
+
+        assert_eq!(meta_refresh_target("<meta charset=\"utf-8\">"), None);
+        assert_eq!(meta_refresh_target("BEGIN:VCALENDAR"), None);
+    }
+}
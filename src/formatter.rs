@@ -0,0 +1,708 @@
+use crate::calendar_links;
+use crate::ical::{self, CalendarEvent};
+use crate::state::AnnouncedDetails;
+use crate::template::{Context, Template};
+use anyhow::Result;
+use chrono::{Datelike, Duration, IsoWeek, NaiveDate, Weekday};
+use regex::Regex;
+use std::sync::LazyLock;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Kind of the `post-next` announcements in the state's post keys.
+pub const NEXT_MEETING_KIND: &str = "next-meeting";
+
+/// Kind of the `post-next --count` statuses in the state's post keys.
+pub const NEXT_MEETINGS_KIND: &str = "next-meetings";
+
+/// Template for the `post-next` status when there is an upcoming meeting.
+pub const NEXT_MEETING_TEMPLATE: &str = "📅 Next Meeting: {#if summary}{summary}{else}Meeting{/if}\
+{#if is_tentative} (tentative){/if}{#if is_important} (❗important){/if}\
+{#if is_online}\n💻 {#if location}{location}{else}Online{/if}\
+{else}{#if location}\n📍 {location}{/if}{/if}\
+{#if conference_url}\n🎥 Join: {conference_url}{/if}\
+{#if start}\n🕒 {start}{/if}\
+{#if url}\n🔗 {url}{/if}\
+{#if ics_url}\n📆 Add to your calendar: {ics_url}{/if}";
+
+/// Template for the `post-all` digest of upcoming meetings.
+pub const UPCOMING_MEETINGS_TEMPLATE: &str = "Upcoming Meetings ({count}):\n\n\
+{#each events}{#unless first}\n\n{/unless}\
+📅 {#if summary}{summary}{else}Meeting{/if}{#if is_tentative} (tentative){/if}{#if is_important} (❗important){/if}\
+{#if is_online}\n💻 {#if location}{location}{else}Online{/if}\
+{else}{#if location}\n📍 {location}{/if}{/if}\
+{#if conference_url}\n🎥 Join: {conference_url}{/if}\
+{#if start}\n🕒 {start}{/if}\
+{#if url}\n🔗 {url}{/if}\n\
+{/each}\
+{#if more}\n…and {more} more{/if}";
+
+/// Template for `post-next --count`, announcing the next few meetings at once.
+pub const NEXT_MEETINGS_TEMPLATE: &str = "📅 Next Meetings:\n\n\
+{#each events}{#unless first}\n\n{/unless}\
+{#if summary}{summary}{else}Meeting{/if}{#if is_tentative} (tentative){/if}{#if is_important} (❗important){/if}\
+{#if is_online}\n💻 {#if location}{location}{else}Online{/if}\
+{else}{#if location}\n📍 {location}{/if}{/if}\
+{#if conference_url}\n🎥 Join: {conference_url}{/if}\
+{#if start}\n🕒 {start}{/if}\
+{#if url}\n🔗 {url}{/if}\n\
+{/each}\
+{#if more}\n…and {more} more{/if}";
+
+/// Template for the first status of a `post-all` thread.
+pub const THREAD_START_TEMPLATE: &str =
+    "Upcoming Meetings ({count}), in the {parts} replies below 🧵";
+
+/// Template for each reply of a `post-all` thread.
+pub const THREAD_PART_TEMPLATE: &str = "({part}/{parts})\n\n\
+{#each events}{#unless first}\n\n{/unless}\
+📅 {#if summary}{summary}{else}Meeting{/if}{#if is_tentative} (tentative){/if}{#if is_important} (❗important){/if}\
+{#if is_online}\n💻 {#if location}{location}{else}Online{/if}\
+{else}{#if location}\n📍 {location}{/if}{/if}\
+{#if conference_url}\n🎥 Join: {conference_url}{/if}\
+{#if start}\n🕒 {start}{/if}\
+{#if url}\n🔗 {url}{/if}\n\
+{/each}";
+
+/// The longest status Mastodon accepts by default, in characters.
+pub const MAX_STATUS_CHARS: usize = 500;
+
+/// How many characters Mastodon counts a link as, however long it is.
+pub const URL_CHARS: usize = 23;
+
+/// The length of a status as Mastodon counts it against the limit: each
+/// link as [`URL_CHARS`] characters, mentions of remote accounts without
+/// their domain, and characters as they are seen, so an emoji made of
+/// several code points counts once.
+pub fn status_length(text: &str) -> usize {
+    static URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"https?://\S+").unwrap());
+    static MENTION: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(^|[^/\w])(@\w+(?:[\w.-]*\w)?)@[\w.-]*\w").unwrap());
+
+    let mut countable = String::with_capacity(text.len());
+    let mut rest = 0;
+    for link in URL.find_iter(text) {
+        // Punctuation after a link ends the sentence rather than the link
+        let url = link
+            .as_str()
+            .trim_end_matches(['.', ',', ':', ';', '!', '?', '"', '\'', ')', ']']);
+        countable.push_str(&text[rest..link.start()]);
+        countable.push_str(&"x".repeat(URL_CHARS));
+        rest = link.start() + url.len();
+    }
+    countable.push_str(&text[rest..]);
+    MENTION
+        .replace_all(&countable, "$1$2")
+        .graphemes(true)
+        .count()
+}
+
+/// `text` cut to at most `max` characters as [`status_length`] counts them,
+/// ending with `…` when anything was cut. An emoji or a letter with its
+/// combining marks is kept whole or left out whole.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.graphemes(true).count() <= max {
+        return text.to_string();
+    }
+    let mut cut: String = text.graphemes(true).take(max.saturating_sub(1)).collect();
+    cut.truncate(cut.trim_end().len());
+    cut.push('…');
+    cut
+}
+
+/// First strong isolate: what follows, up to the pop, gets its direction
+/// from its own first letter.
+const FIRST_STRONG_ISOLATE: char = '\u{2068}';
+const POP_DIRECTIONAL_ISOLATE: char = '\u{2069}';
+
+/// Whether `c` is a letter of a script written right to left, such as
+/// Hebrew or Arabic.
+fn is_right_to_left(c: char) -> bool {
+    matches!(c,
+        '\u{0590}'..='\u{08FF}'
+        | '\u{FB1D}'..='\u{FDFF}'
+        | '\u{FE70}'..='\u{FEFC}'
+        | '\u{10800}'..='\u{10FFF}'
+        | '\u{1E800}'..='\u{1EFFF}')
+}
+
+/// `text` isolated from what is around it when it has right-to-left
+/// letters, so a Hebrew or Arabic summary keeps its direction without
+/// pulling the labels and punctuation next to it along.
+pub fn isolate(text: &str) -> String {
+    if text.starts_with(FIRST_STRONG_ISOLATE) || !text.chars().any(is_right_to_left) {
+        return text.to_string();
+    }
+    format!(
+        "{}{}{}",
+        FIRST_STRONG_ISOLATE, text, POP_DIRECTIONAL_ISOLATE
+    )
+}
+
+/// Template used when the calendar has no upcoming meetings.
+pub const NO_MEETINGS_TEMPLATE: &str = "📅 No upcoming meetings found";
+
+/// Template for the status posted in place of one held back during quiet
+/// hours about a meeting that has since been cancelled.
+pub const CANCELLATION_TEMPLATE: &str = "❌ Cancelled: {#if summary}{summary}{else}Meeting{/if}\
+{#if start}\n🕒 {start}{/if}";
+
+/// The week `date` is in, for weeks starting on `week_start`, numbered as
+/// the ISO week of the Monday in it.
+pub fn week_of(date: NaiveDate, week_start: Weekday) -> IsoWeek {
+    let shift = (7 - week_start.num_days_from_monday()) % 7;
+    (date + Duration::days(shift.into())).iso_week()
+}
+
+/// Builds the template variables describing a single event.
+pub fn event_context(event: &CalendarEvent) -> Context {
+    let mut context = Context::new();
+    context
+        .set_opt_text("summary", event.summary.as_deref().map(isolate))
+        .set_opt_text("description", event.description.clone())
+        .set_opt_text("location", event.location.as_deref().map(isolate))
+        .set_opt_text("url", event.url.clone())
+        // Left out when the location already is the link
+        .set_opt_text(
+            "conference_url",
+            event
+                .conference
+                .clone()
+                .filter(|conference| event.location.as_ref() != Some(conference)),
+        )
+        .set_opt_text("start", event.start_time_formatted())
+        .set_opt_text("end", event.end_time_formatted())
+        .set_opt_text(
+            "week",
+            event.start_date().map(|date| {
+                week_of(date, event.week_start.unwrap_or(Weekday::Mon))
+                    .week()
+                    .to_string()
+            }),
+        )
+        .set_opt_text(
+            "month",
+            event.start_date().map(|date| date.format("%B").to_string()),
+        )
+        .set_opt_text("hashtags", event.hashtags_text())
+        .set_opt_text("google_calendar_url", calendar_links::google(event))
+        .set_opt_text("outlook_calendar_url", calendar_links::outlook(event))
+        .set_opt_text(
+            "priority",
+            event.priority.map(|priority| priority.to_string()),
+        )
+        .set_bool("has_location", event.location.is_some())
+        .set_bool("has_url", event.url.is_some())
+        .set_bool("is_all_day", event.is_all_day())
+        .set_bool("is_online", event.is_online())
+        .set_bool("is_tentative", event.is_tentative())
+        .set_bool("is_important", event.is_important());
+    context
+}
+
+/// Builds the template variables for a list of events. Each one also says
+/// whether it is the first of its week or month, for headings between them.
+pub fn events_context(events: &[&CalendarEvent]) -> Context {
+    let mut previous = None;
+    let listed = events
+        .iter()
+        .map(|event| {
+            let date = event.start_date();
+            let week = date.map(|date| week_of(date, event.week_start.unwrap_or(Weekday::Mon)));
+            let month = date.map(|date| (date.year(), date.month()));
+            let mut context = event_context(event);
+            context
+                .set_bool(
+                    "new_week",
+                    previous.is_none_or(|(before, _)| before != week),
+                )
+                .set_bool(
+                    "new_month",
+                    previous.is_none_or(|(_, before)| before != month),
+                );
+            previous = Some((week, month));
+            context
+        })
+        .collect();
+    let mut context = Context::new();
+    context
+        .set_text("count", events.len().to_string())
+        .set_list("events", listed);
+    context
+}
+
+/// The variables of the `post-next` status about an event, with the link to
+/// its `.ics` file when one was uploaded.
+pub fn next_meeting_context(event: &CalendarEvent, ics_url: Option<&str>) -> Context {
+    let mut context = event_context(event);
+    context.set_opt_text("ics_url", ics_url);
+    context
+}
+
+/// The variables of a list of meetings with `more` of them left out.
+pub fn digest_context(events: &[&CalendarEvent], more: usize) -> Context {
+    let mut context = events_context(events);
+    context.set_opt_text("more", (more > 0).then(|| more.to_string()));
+    context
+}
+
+/// The templates the meeting announcements are rendered from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Templates<'a> {
+    pub next_meeting: &'a str,
+    pub next_meetings: &'a str,
+    pub upcoming_meetings: &'a str,
+    pub no_meetings: &'a str,
+}
+
+/// The templates used unless a language account has its own.
+pub const BUILTIN_TEMPLATES: Templates<'static> = Templates {
+    next_meeting: NEXT_MEETING_TEMPLATE,
+    next_meetings: NEXT_MEETINGS_TEMPLATE,
+    upcoming_meetings: UPCOMING_MEETINGS_TEMPLATE,
+    no_meetings: NO_MEETINGS_TEMPLATE,
+};
+
+impl Templates<'_> {
+    /// Renders the status announcing the next meeting, if there is one, with
+    /// a link to it as an `.ics` file when one was uploaded.
+    pub fn next_meeting(
+        &self,
+        event: Option<&CalendarEvent>,
+        ics_url: Option<&str>,
+    ) -> Result<String> {
+        match event {
+            Some(event) => {
+                let template = event.template.as_deref().unwrap_or(self.next_meeting);
+                Ok(Template::parse(template)?.render(&next_meeting_context(event, ics_url)))
+            }
+            None => Ok(Template::parse(self.no_meetings)?.render(&Context::new())),
+        }
+    }
+
+    /// Renders the status listing all of the given upcoming meetings.
+    pub fn upcoming_meetings(&self, events: &[&CalendarEvent]) -> Result<String> {
+        self.digest(self.upcoming_meetings, events)
+    }
+
+    /// Renders the upcoming meetings as a thread of statuses of at most
+    /// `max_chars` characters: the digest alone when it fits, or else a
+    /// first status saying how many replies follow and the replies, each
+    /// listing as many of the meetings as fit.
+    pub fn upcoming_meetings_thread(
+        &self,
+        events: &[&CalendarEvent],
+        max_chars: usize,
+    ) -> Result<Vec<String>> {
+        let digest = Template::parse(self.upcoming_meetings)?.render(&digest_context(events, 0));
+        if events.is_empty() || status_length(&digest) <= max_chars {
+            return self.upcoming_meetings(events).map(|status| vec![status]);
+        }
+
+        let template = Template::parse(THREAD_PART_TEMPLATE)?;
+        let render = |events: &[&CalendarEvent], part: usize, parts: usize| {
+            let mut context = events_context(events);
+            context
+                .set_text("part", part.to_string())
+                .set_text("parts", parts.to_string());
+            template.render(&context)
+        };
+
+        // Fill each reply with as many meetings as fit, numbered as if every
+        // meeting needed one so the final numbers can only be shorter
+        let mut parts: Vec<&[&CalendarEvent]> = Vec::new();
+        let mut rest = events;
+        while !rest.is_empty() {
+            let mut taken = 1;
+            while taken < rest.len()
+                && status_length(&render(&rest[..=taken], parts.len() + 1, events.len()))
+                    <= max_chars
+            {
+                taken += 1;
+            }
+            parts.push(&rest[..taken]);
+            rest = &rest[taken..];
+        }
+
+        let mut start = events_context(events);
+        start.set_text("parts", parts.len().to_string());
+        let mut statuses = vec![Template::parse(THREAD_START_TEMPLATE)?.render(&start)];
+        statuses.extend(
+            parts
+                .iter()
+                .enumerate()
+                .map(|(index, part)| render(part, index + 1, parts.len())),
+        );
+        Ok(statuses)
+    }
+
+    /// Renders the status announcing the given next meetings together.
+    pub fn next_meetings(&self, events: &[&CalendarEvent]) -> Result<String> {
+        self.digest(self.next_meetings, events)
+    }
+
+    /// Renders a list of events, leaving the last ones out (and counting them
+    /// in `more`) until the status fits in [`MAX_STATUS_CHARS`]. Important
+    /// events are only left out once no other is left.
+    fn digest(&self, source: &str, events: &[&CalendarEvent]) -> Result<String> {
+        if events.is_empty() {
+            return Ok(Template::parse(self.no_meetings)?.render(&Context::new()));
+        }
+
+        let template = Template::parse(source)?;
+        let mut shown = events.to_vec();
+        loop {
+            let context = digest_context(&shown, events.len() - shown.len());
+            let status = template.render(&context);
+            if shown.len() == 1 || status_length(&status) <= MAX_STATUS_CHARS {
+                return Ok(status);
+            }
+            let left_out = shown
+                .iter()
+                .rposition(|event| !event.is_important())
+                .unwrap_or(shown.len() - 1);
+            shown.remove(left_out);
+        }
+    }
+}
+
+/// Renders the status announcing the next meeting with the built-in template.
+pub fn render_next_meeting(event: Option<&CalendarEvent>, ics_url: Option<&str>) -> String {
+    BUILTIN_TEMPLATES
+        .next_meeting(event, ics_url)
+        .expect("built-in templates are valid")
+}
+
+/// Renders the notice that a meeting was cancelled.
+pub fn render_cancellation(event: &CalendarEvent) -> String {
+    Template::parse(CANCELLATION_TEMPLATE)
+        .expect("built-in templates are valid")
+        .render(&event_context(event))
+}
+
+/// A line saying how `event` differs from what an announcement showed of
+/// it, such as "Updated: time changed from 06:00 PM UTC to 07:00 PM UTC";
+/// `None` when nothing it shows changed.
+pub fn change_note(before: &AnnouncedDetails, event: &CalendarEvent) -> Option<String> {
+    let text = |value: &Option<String>| match value {
+        Some(value) => isolate(&ical::unescape_text(value)),
+        None => "none".to_string(),
+    };
+    // The day is left out when it stayed the same
+    let times = |before: &str, after: &str| {
+        let shown = |time: &str| {
+            CalendarEvent {
+                start_time: Some(time.to_string()),
+                display_timezone: event.display_timezone,
+                ..CalendarEvent::default()
+            }
+            .start_time_formatted()
+            .unwrap_or_default()
+        };
+        let (before, after) = (shown(before), shown(after));
+        match (before.split_once(" at "), after.split_once(" at ")) {
+            (Some((day, old)), Some((same_day, new))) if day == same_day => {
+                format!("from {} to {}", old, new)
+            }
+            _ => format!("from {} to {}", before, after),
+        }
+    };
+
+    let mut changes = Vec::new();
+    if before.summary != event.summary {
+        changes.push(format!(
+            "title changed from {} to {}",
+            text(&before.summary),
+            text(&event.summary)
+        ));
+    }
+    match (&before.start, &event.start_time) {
+        (Some(old), Some(new)) if old != new => {
+            changes.push(format!("time changed {}", times(old, new)))
+        }
+        _ => {
+            if let (Some(old), Some(new)) = (&before.end, &event.end_time)
+                && old != new
+            {
+                changes.push(format!("end changed {}", times(old, new)));
+            }
+        }
+    }
+    if before.location != event.location {
+        changes.push(format!(
+            "location changed from {} to {}",
+            text(&before.location),
+            text(&event.location)
+        ));
+    }
+    if before.url != event.url {
+        changes.push("link changed".to_string());
+    }
+    (!changes.is_empty()).then(|| format!("Updated: {}", changes.join("; ")))
+}
+
+/// Renders the upcoming meetings digest with the built-in template.
+pub fn render_upcoming_meetings(events: &[&CalendarEvent]) -> String {
+    BUILTIN_TEMPLATES
+        .upcoming_meetings(events)
+        .expect("built-in templates are valid")
+}
+
+/// Renders the next meetings together with the built-in template.
+pub fn render_next_meetings(events: &[&CalendarEvent]) -> String {
+    BUILTIN_TEMPLATES
+        .next_meetings(events)
+        .expect("built-in templates are valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(summary: &str, location: Option<&str>, url: Option<&str>) -> CalendarEvent {
+        CalendarEvent {
+            summary: Some(summary.to_string()),
+            start_time: Some("20251207T100000".to_string()),
+            location: location.map(String::from),
+            url: url.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_status_length() {
+        assert_eq!(status_length("Weekly Meeting"), 14);
+        // Every link counts as 23, without the punctuation after it
+        assert_eq!(
+            status_length("🔗 https://example.com/meetings/2025-12-03/agenda-and-minutes."),
+            2 + URL_CHARS + 1
+        );
+        assert_eq!(status_length("(http://a.example)"), URL_CHARS + 2);
+        // Remote mentions count without their domain
+        assert_eq!(status_length("Hosted by @alice@social.example"), 16);
+        assert_eq!(status_length("mail bob@example.com"), 20);
+        // A family emoji is one character, however many code points it has
+        assert_eq!(status_length("👨‍👩‍👧 é"), 3);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("Weekly Meeting", 14), "Weekly Meeting");
+        assert_eq!(truncate("Weekly Meeting", 10), "Weekly Me…");
+        // Emoji made of several code points and letters with combining
+        // marks are kept whole
+        assert_eq!(truncate("👨‍👩‍👧👨‍👩‍👧👨‍👩‍👧", 2), "👨‍👩‍👧…");
+        assert_eq!(truncate("🇺🇦🇺🇦🇺🇦", 3), "🇺🇦🇺🇦🇺🇦");
+        assert_eq!(truncate("🇺🇦🇺🇦🇺🇦", 2), "🇺🇦…");
+        assert_eq!(truncate("Cafe\u{301} cre\u{300}me", 5), "Cafe\u{301}…");
+        assert_eq!(truncate("اجتماع الأعضاء الشهري", 8), "اجتماع…");
+        assert_eq!(truncate("פגישת חברים", 5), "פגיש…");
+    }
+
+    #[test]
+    fn test_right_to_left_summaries() {
+        assert_eq!(isolate("Weekly Meeting 📅"), "Weekly Meeting 📅");
+        assert_eq!(isolate("פגישה (חודשית)"), "\u{2068}פגישה (חודשית)\u{2069}");
+        assert_eq!(isolate(&isolate("اجتماع")), isolate("اجتماع"));
+
+        let mut meeting = event("פגישת חברים", Some("ساحة المدينة"), None);
+        meeting.status = Some("TENTATIVE".to_string());
+        let status = render_next_meeting(Some(&meeting), None);
+        assert!(status.starts_with("📅 Next Meeting: \u{2068}פגישת חברים\u{2069} (tentative)\n"));
+        assert!(status.contains("\n📍 \u{2068}ساحة المدينة\u{2069}\n"));
+        // The isolates count as Mastodon counts them, once each
+        assert_eq!(
+            status_length(&isolate("פגישת חברים 🎉")),
+            "פגישת חברים 🎉".chars().count() + 2
+        );
+    }
+
+    #[test]
+    fn test_render_next_meeting() {
+        let meeting = event(
+            "Team Standup",
+            Some("Conference Room A"),
+            Some("https://example.com/meeting-link"),
+        );
+        assert_eq!(
+            render_next_meeting(Some(&meeting), None),
+            "📅 Next Meeting: Team Standup\n📍 Conference Room A\n🕒 Sun, Dec 07, 2025 at 10:00 AM\n🔗 https://example.com/meeting-link"
+        );
+
+        let meeting = event("Team Standup", None, None);
+        assert_eq!(
+            render_next_meeting(Some(&meeting), None),
+            "📅 Next Meeting: Team Standup\n🕒 Sun, Dec 07, 2025 at 10:00 AM"
+        );
+        assert_eq!(
+            render_next_meeting(Some(&meeting), Some("https://example.com/standup.ics")),
+            "📅 Next Meeting: Team Standup\n🕒 Sun, Dec 07, 2025 at 10:00 AM\n📆 Add to your calendar: https://example.com/standup.ics"
+        );
+
+        assert_eq!(
+            render_next_meeting(None, None),
+            "📅 No upcoming meetings found"
+        );
+    }
+
+    #[test]
+    fn test_render_upcoming_meetings() {
+        let first = event("Install Fest", Some("Library"), None);
+        let second = event("Meetup", None, Some("https://example.com"));
+        assert_eq!(
+            render_upcoming_meetings(&[&first, &second]),
+            "Upcoming Meetings (2):\n\n\
+             📅 Install Fest\n📍 Library\n🕒 Sun, Dec 07, 2025 at 10:00 AM\n\n\n\
+             📅 Meetup\n🕒 Sun, Dec 07, 2025 at 10:00 AM\n🔗 https://example.com\n"
+        );
+        assert_eq!(
+            render_upcoming_meetings(&[]),
+            "📅 No upcoming meetings found"
+        );
+    }
+
+    #[test]
+    fn test_render_next_meetings() {
+        let first = event("Install Fest", Some("Library"), None);
+        let second = event("Meetup", None, None);
+        assert_eq!(
+            render_next_meetings(&[&first, &second]),
+            "📅 Next Meetings:\n\n\
+             Install Fest\n📍 Library\n🕒 Sun, Dec 07, 2025 at 10:00 AM\n\n\n\
+             Meetup\n🕒 Sun, Dec 07, 2025 at 10:00 AM\n"
+        );
+    }
+
+    #[test]
+    fn test_week_and_month_headings() {
+        let at = |start: &str| CalendarEvent {
+            summary: Some("Meetup".to_string()),
+            start_time: Some(start.to_string()),
+            week_start: Some(Weekday::Sun),
+            ..Default::default()
+        };
+        // Saturday and Sunday, Sunday again, then Monday in the next month
+        let events = [
+            at("20251129T100000"),
+            at("20251130T100000"),
+            at("20251130T180000"),
+            at("20251201T100000"),
+        ];
+        let template = Template::parse(
+            "{#each events}{#if new_month}[{month}]{/if}{#if new_week}<week {week}>{/if}.{/each}",
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&events_context(&events.iter().collect::<Vec<_>>())),
+            "[November]<week 48>.<week 49>..[December]."
+        );
+    }
+
+    #[test]
+    fn test_render_digest_truncates() {
+        let meetings: Vec<_> = (1..=20)
+            .map(|n| event(&format!("Meeting {}", n), Some("Library"), None))
+            .collect();
+        let events: Vec<_> = meetings.iter().collect();
+        let status = render_upcoming_meetings(&events);
+        assert!(status_length(&status) <= MAX_STATUS_CHARS);
+        assert!(status.starts_with("Upcoming Meetings (8):"));
+        assert!(status.ends_with("\n…and 12 more"));
+
+        // An important meeting stays in, in place of the last other one
+        let mut meetings = meetings;
+        meetings[14].priority = Some(1);
+        let events: Vec<_> = meetings.iter().collect();
+        let status = render_upcoming_meetings(&events);
+        assert!(status_length(&status) <= MAX_STATUS_CHARS);
+        assert!(status.contains("📅 Meeting 7\n"));
+        assert!(!status.contains("📅 Meeting 8\n"));
+        assert!(status.contains("📅 Meeting 15 (❗important)\n"));
+        assert!(status.ends_with("\n…and 12 more"));
+    }
+
+    #[test]
+    fn test_upcoming_meetings_thread() {
+        let events: Vec<_> = (0..20)
+            .map(|i| event(&format!("Meeting {}", i), Some("Room 101"), None))
+            .collect();
+        let events: Vec<_> = events.iter().collect();
+
+        let statuses = BUILTIN_TEMPLATES
+            .upcoming_meetings_thread(&events[..2], MAX_STATUS_CHARS)
+            .unwrap();
+        assert_eq!(statuses, [render_upcoming_meetings(&events[..2])]);
+
+        let statuses = BUILTIN_TEMPLATES
+            .upcoming_meetings_thread(&events, MAX_STATUS_CHARS)
+            .unwrap();
+        let parts = statuses.len() - 1;
+        assert!(parts > 1);
+        assert_eq!(
+            statuses[0],
+            format!("Upcoming Meetings (20), in the {} replies below 🧵", parts)
+        );
+        assert!(statuses[1].starts_with(&format!("(1/{})\n\n📅 Meeting 0\n📍 Room 101", parts)));
+        assert!(statuses[parts].contains("Meeting 19"));
+        for status in &statuses {
+            assert!(status_length(status) <= MAX_STATUS_CHARS);
+        }
+        let listed: usize = statuses.iter().map(|s| s.matches("📅").count()).sum();
+        assert_eq!(listed, 20);
+    }
+
+    #[test]
+    fn test_render_tentative() {
+        let mut meeting = event("Install Fest", None, None);
+        meeting.status = Some("TENTATIVE".to_string());
+        assert_eq!(
+            render_next_meeting(Some(&meeting), None),
+            "📅 Next Meeting: Install Fest (tentative)\n🕒 Sun, Dec 07, 2025 at 10:00 AM"
+        );
+        assert!(render_upcoming_meetings(&[&meeting]).contains("📅 Install Fest (tentative)\n"));
+    }
+
+    #[test]
+    fn test_render_online() {
+        let mut call = event("Monthly Meeting", None, None);
+        call.conference = Some("https://meet.example.com/monthly".to_string());
+        assert_eq!(
+            render_next_meeting(Some(&call), None),
+            "📅 Next Meeting: Monthly Meeting\n💻 Online\n\
+             🎥 Join: https://meet.example.com/monthly\n🕒 Sun, Dec 07, 2025 at 10:00 AM"
+        );
+
+        // The link isn't repeated when it is the location
+        call.location = call.conference.clone();
+        assert!(
+            render_upcoming_meetings(&[&call])
+                .contains("📅 Monthly Meeting\n💻 https://meet.example.com/monthly\n🕒")
+        );
+
+        let mut hybrid = event("Install Fest", Some("Library"), None);
+        hybrid.conference = Some("https://meet.example.com/fest".to_string());
+        hybrid.online = Some(false);
+        assert!(
+            render_next_meetings(&[&hybrid])
+                .contains("Install Fest\n📍 Library\n🎥 Join: https://meet.example.com/fest\n🕒")
+        );
+    }
+
+    #[test]
+    fn test_event_context_flags() {
+        let mut meeting = event("Meetup", Some("Online"), None);
+        meeting.start_time = Some("20251207".to_string());
+        let context = event_context(&meeting);
+        assert_eq!(
+            context.get("is_all_day"),
+            Some(&crate::template::Value::Bool(true))
+        );
+        assert_eq!(
+            context.get("is_online"),
+            Some(&crate::template::Value::Bool(true))
+        );
+        assert_eq!(
+            context.get("has_url"),
+            Some(&crate::template::Value::Bool(false))
+        );
+    }
+}
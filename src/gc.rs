@@ -0,0 +1,184 @@
+use crate::cache::FeedCache;
+use crate::config::GcConfig;
+use crate::recap;
+use crate::state::State;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+/// How many entries a collection removed from the state, and calendars
+/// from the cache.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Collected {
+    pub posts: usize,
+    pub polls: usize,
+    pub occurrences: usize,
+    pub cached_calendars: usize,
+}
+
+impl Collected {
+    pub fn is_empty(&self) -> bool {
+        *self == Collected::default()
+    }
+}
+
+/// The day, as `YYYYMMDD`, a post is about: the start of its occurrence, or
+/// the end of the period of a recap. `None` for posts about no day in
+/// particular, which are kept.
+fn day_of(key: &str) -> Option<&str> {
+    let day = match State::split_key(key) {
+        Some((_, _, start)) => start,
+        None => {
+            key.strip_prefix(recap::KIND)?
+                .strip_prefix(':')?
+                .split_once('-')?
+                .1
+        }
+    };
+    day.get(..8)
+}
+
+/// Forgets what was posted about occurrences before `retention_days` ago,
+/// scheduling polls that closed and were reported on before, expansions of
+/// recurring events that ended before, and statuses rendered for those
+/// occurrences. Posts still waiting to go out are kept, and so are short
+/// links, which events might be posted with again.
+pub fn collect(config: &GcConfig, state: &mut State, now: DateTime<Utc>) -> Collected {
+    let before = now - Duration::days(config.retention_days);
+    let before_day = before.format("%Y%m%d").to_string();
+    let is_old = |key: &str| day_of(key).is_some_and(|day| day < before_day.as_str());
+    let waiting: Vec<String> = state
+        .deferred
+        .iter()
+        .filter_map(|deferred| deferred.post_key.clone())
+        .chain(
+            state
+                .catch_up
+                .iter()
+                .filter_map(|pending| pending.post_key.clone()),
+        )
+        .collect();
+
+    let mut collected = Collected::default();
+    let posts = state.posts.len() + state.scheduled.len();
+    state
+        .posts
+        .retain(|key, _| !is_old(key) || waiting.contains(key));
+    // Scheduled statuses that went out before then can't be cancelled any more
+    state
+        .scheduled
+        .retain(|scheduled| scheduled.scheduled_at >= before);
+    collected.posts = posts - state.posts.len() - state.scheduled.len();
+
+    let polls = state.polls.len();
+    state
+        .polls
+        .retain(|_, poll| !poll.reported || poll.expires_at >= before);
+    collected.polls = polls - state.polls.len();
+
+    let occurrences = state.occurrences.len();
+    state
+        .occurrences
+        .retain(|_, cached| cached.window_end >= before);
+    collected.occurrences = occurrences - state.occurrences.len();
+    state.rendered.retain(|key, _| !is_old(key));
+    state.reminder_requests.retain(|key, _| !is_old(key));
+
+    collected
+}
+
+/// Removes the calendars last fetched before `retention_days` ago from the
+/// cache. Those still configured are fetched again every run, so these are
+/// calendars the bot no longer reads. With `dry_run`, they are only counted.
+pub fn collect_cache(
+    config: &GcConfig,
+    cache: &FeedCache,
+    now: DateTime<Utc>,
+    dry_run: bool,
+    collected: &mut Collected,
+) -> Result<()> {
+    let before = now - Duration::days(config.retention_days);
+    collected.cached_calendars = cache.trim(before, dry_run)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{CachedOccurrences, PollRecord, PostRecord};
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_collect() {
+        let now = Utc.with_ymd_and_hms(2026, 7, 1, 12, 0, 0).unwrap();
+        let long_ago = Utc.with_ymd_and_hms(2025, 12, 3, 19, 0, 0).unwrap();
+        let record = PostRecord {
+            status_id: "1".to_string(),
+            url: None,
+            posted_at: long_ago,
+            template_hash: None,
+            event_hash: None,
+            text_hash: None,
+            links: Default::default(),
+            details: None,
+        };
+        let mut state = State::default();
+        for key in [
+            "next-meeting:old@example.com@20251203T190000Z",
+            "minutes:all-day@example.com@20251203",
+            "recap:20251124-20251201",
+            "next-meeting:recent@example.com@20260610T190000Z",
+            "recap:20260615-20260622",
+            "subscription-info",
+        ] {
+            state.record(key.to_string(), record.clone());
+        }
+        let poll = |reported| PollRecord {
+            status_id: "2".to_string(),
+            url: None,
+            summary: None,
+            starts: Vec::new(),
+            expires_at: long_ago,
+            reported,
+        };
+        state
+            .polls
+            .insert("scheduling-poll:a@20251203".to_string(), poll(true));
+        state
+            .polls
+            .insert("scheduling-poll:b@20251203".to_string(), poll(false));
+        state.occurrences.insert(
+            "weekly@example.com".to_string(),
+            CachedOccurrences {
+                fingerprint: String::new(),
+                sequence: 0,
+                window_start: long_ago - Duration::days(7),
+                window_end: long_ago,
+                starts: Vec::new(),
+            },
+        );
+
+        let config = GcConfig::default();
+        let collected = collect(&config, &mut state, now);
+        assert_eq!(
+            collected,
+            Collected {
+                posts: 3,
+                polls: 1,
+                occurrences: 1,
+                cached_calendars: 0,
+            }
+        );
+        assert_eq!(
+            state.posts.keys().collect::<Vec<_>>(),
+            [
+                "next-meeting:recent@example.com@20260610T190000Z",
+                "recap:20260615-20260622",
+                "subscription-info",
+            ]
+        );
+        // An unreported poll still has its result to post
+        assert!(state.polls.contains_key("scheduling-poll:b@20251203"));
+        assert!(collect(&config, &mut state, now).is_empty());
+    }
+}
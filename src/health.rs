@@ -0,0 +1,126 @@
+use crate::config::{SafeModeConfig, UrlPolicyConfig};
+use crate::url_policy;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How the last posting runs went, kept in the state file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    /// Posting runs that failed in a row.
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// The error of the last failed run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// When safe mode was entered, while the bot is in it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safe_mode_since: Option<DateTime<Utc>>,
+    /// Whether the alert about entering safe mode went out.
+    #[serde(default)]
+    pub alert_sent: bool,
+}
+
+impl Health {
+    pub fn in_safe_mode(&self) -> bool {
+        self.safe_mode_since.is_some()
+    }
+
+    /// Records how a posting run went, entering safe mode after
+    /// `after_failures` failures in a row. Returns whether it was entered.
+    pub fn record_run(
+        &mut self,
+        config: &SafeModeConfig,
+        now: DateTime<Utc>,
+        error: Option<String>,
+    ) -> bool {
+        let Some(error) = error else {
+            self.consecutive_failures = 0;
+            self.last_error = None;
+            return false;
+        };
+
+        self.consecutive_failures += 1;
+        self.last_error = Some(error);
+        if self.in_safe_mode() || self.consecutive_failures < config.after_failures {
+            return false;
+        }
+        self.safe_mode_since = Some(now);
+        self.alert_sent = false;
+        true
+    }
+
+    /// Leaves safe mode and forgets the failures that led to it.
+    pub fn reset(&mut self) {
+        *self = Health::default();
+    }
+
+    /// The alert announcing safe mode.
+    pub fn alert_text(&self) -> String {
+        format!(
+            "ical-to-masto stopped posting after {} failed runs in a row{}. It keeps fetching the calendar; run `ical-to-masto resume` once the problem is fixed.",
+            self.consecutive_failures,
+            match &self.last_error {
+                Some(error) => format!(" (last error: {})", error),
+                None => String::new(),
+            }
+        )
+    }
+}
+
+/// Sends an alert to a webhook as a JSON object with a `text` field, which
+/// chat services such as Slack, Mattermost and Discord-compatible bridges accept.
+pub async fn send_alert(
+    webhook_url: &str,
+    text: &str,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<()> {
+    let response = url_policy::client(policy, webhook_url)?
+        .post(webhook_url)
+        .json(&serde_json::json!({ "text": text }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("HTTP error {}", response.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_record_run() {
+        let config = SafeModeConfig {
+            after_failures: 3,
+            alert_webhook: None,
+        };
+        let now = Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap();
+        let mut health = Health::default();
+
+        assert!(!health.record_run(&config, now, Some("timeout".to_string())));
+        assert!(!health.record_run(&config, now, None));
+        assert_eq!(health.consecutive_failures, 0);
+
+        for _ in 0..2 {
+            assert!(!health.record_run(&config, now, Some("timeout".to_string())));
+        }
+        assert!(health.record_run(&config, now, Some("HTTP error: 502".to_string())));
+        assert_eq!(health.safe_mode_since, Some(now));
+        assert!(
+            health
+                .alert_text()
+                .contains("3 failed runs in a row (last error: HTTP error: 502)")
+        );
+
+        // Entered once; later failures don't alert again
+        assert!(!health.record_run(&config, now, Some("timeout".to_string())));
+        assert!(health.in_safe_mode());
+
+        health.reset();
+        assert!(!health.in_safe_mode());
+        assert_eq!(health.consecutive_failures, 0);
+    }
+}
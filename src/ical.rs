@@ -1,27 +1,328 @@
+use crate::config::{FilterConfig, UrlPolicyConfig};
+use crate::fetch;
+use crate::timing::{self, Phase};
 use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
 use ical::parser::ical::IcalParser;
+use regex::Regex;
+use std::collections::HashMap;
 use std::fs;
 use std::io::BufReader;
 
+const DATE_TIME_FORMAT: &str = "%a, %b %d, %Y at %I:%M %p";
+
+#[derive(Debug, Clone, Default)]
 pub struct CalendarEvent {
+    pub uid: Option<String>,
     pub summary: Option<String>,
     pub description: Option<String>,
     pub start_time: Option<String>,
     pub end_time: Option<String>,
     pub location: Option<String>,
     pub url: Option<String>,
+    /// The RRULE of a recurring event; occurrences expanded from it have none.
+    pub rrule: Option<String>,
+    /// RDATE values: occurrences in addition to (or instead of) the RRULE
+    /// ones. Occurrences expanded from them have none.
+    pub rdates: Vec<String>,
+    /// EXDATE values: occurrences of the RRULE or RDATEs that are skipped.
+    pub exdates: Vec<String>,
+    /// RECURRENCE-ID: the start of the occurrence of the recurring event
+    /// with the same UID that this event replaces.
+    pub recurrence_id: Option<String>,
+    pub sequence: Option<i64>,
+    /// PRIORITY, from 1 (highest) to 9 (lowest); 0, for none, is left out.
+    pub priority: Option<u8>,
+    /// STATUS: `TENTATIVE`, `CONFIRMED` or `CANCELLED`.
+    pub status: Option<String>,
+    /// CLASS: `PUBLIC`, `PRIVATE` or `CONFIDENTIAL`.
+    pub class: Option<String>,
+    /// URL of an image for the event, from IMAGE (RFC 7986) or, failing
+    /// that, X-IMAGE or X-WR-IMAGE.
+    pub image: Option<String>,
+    /// Link to join the meeting online, from CONFERENCE (RFC 7986) or, failing
+    /// that, the properties Google and Microsoft calendars write it to.
+    pub conference: Option<String>,
+    /// X-MASTO-ONLINE: whether the event takes place online, in place of the
+    /// guess from its location and conference link.
+    pub online: Option<bool>,
+    /// X-MASTO-POSTED-URL: the announcement the bot wrote back last.
+    pub posted_url: Option<String>,
+    /// CATEGORIES, as written.
+    pub categories: Vec<String>,
+    /// Hashtags for the event, without the `#`: its CATEGORIES, then those
+    /// configured for every event.
+    pub hashtags: Vec<String>,
+    /// The LANGUAGE parameter of SUMMARY, like `fr` or `en-US`.
+    pub language: Option<String>,
+    /// The TZID of DTSTART, as written.
+    pub tzid: Option<String>,
+    /// The zone that TZID names, when it is known. Times given with a TZID
+    /// are converted to UTC when read; the zone is kept so occurrences of a
+    /// recurring event stay at the same local time across DST changes.
+    pub timezone: Option<Tz>,
+    /// The zone UTC times are shown in; UTC itself when not set.
+    pub display_timezone: Option<Tz>,
+    /// The day weeks start on; Monday when not set.
+    pub week_start: Option<Weekday>,
+    /// Template announcing this occurrence, from an override.
+    pub template: Option<String>,
+    /// An override leaves this occurrence out.
+    pub skip: bool,
 }
 
 impl CalendarEvent {
     pub fn end_time_formatted(&self) -> Option<String> {
-        self.end_time.as_ref().map(|t| format_ical_date(t))
+        self.end_time
+            .as_ref()
+            .map(|t| format_ical_date(t, self.display_timezone))
+    }
+
+    /// The day the event starts on, in the zone its times are shown in.
+    pub fn start_date(&self) -> Option<NaiveDate> {
+        let (start, form) = self.start_time.as_deref().and_then(parse_ical_time)?;
+        Some(match (form, self.display_timezone) {
+            (TimeForm::Utc, Some(zone)) => zone.from_utc_datetime(&start).date_naive(),
+            _ => start.date(),
+        })
     }
 
     pub fn start_time_formatted(&self) -> Option<String> {
-        self.start_time.as_ref().map(|t| format_ical_date(t))
+        self.start_time
+            .as_ref()
+            .map(|t| format_ical_date(t, self.display_timezone))
+    }
+
+    /// Whether the summary contains any of `patterns`, ignoring case. An empty
+    /// list of patterns matches every event.
+    pub fn summary_matches(&self, patterns: &[String]) -> bool {
+        if patterns.is_empty() {
+            return true;
+        }
+        let summary = self.summary.as_deref().unwrap_or("").to_lowercase();
+        patterns
+            .iter()
+            .any(|pattern| summary.contains(&pattern.to_lowercase()))
+    }
+
+    /// Whether the event is marked `STATUS:TENTATIVE`, i.e. not confirmed yet.
+    pub fn is_tentative(&self) -> bool {
+        self.status
+            .as_deref()
+            .is_some_and(|status| status.eq_ignore_ascii_case("TENTATIVE"))
+    }
+
+    /// Whether PRIORITY marks the event as important: 1 to 4, the high
+    /// priorities of RFC 5545.
+    pub fn is_important(&self) -> bool {
+        matches!(self.priority, Some(1..=4))
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.status
+            .as_deref()
+            .is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED"))
+    }
+
+    /// Whether the calendar marks the event as not for the public.
+    pub fn is_private(&self) -> bool {
+        self.class.as_deref().is_some_and(|class| {
+            class.eq_ignore_ascii_case("PRIVATE") || class.eq_ignore_ascii_case("CONFIDENTIAL")
+        })
+    }
+
+    /// Whether the event repeats, through an RRULE or RDATEs.
+    pub fn is_recurring(&self) -> bool {
+        self.rrule.is_some() || !self.rdates.is_empty()
+    }
+
+    /// Adds hashtags for the given words, leaving out those it already has
+    /// (in any case). Characters hashtags can't contain are dropped, so
+    /// `Install Fest` becomes `InstallFest`.
+    pub fn add_hashtags<'a>(&mut self, words: impl IntoIterator<Item = &'a str>) {
+        for word in words {
+            let hashtag: String = word
+                .chars()
+                .filter(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            if !hashtag.is_empty()
+                && !self
+                    .hashtags
+                    .iter()
+                    .any(|existing| existing.to_lowercase() == hashtag.to_lowercase())
+            {
+                self.hashtags.push(hashtag);
+            }
+        }
+    }
+
+    /// The hashtags as they are posted, like `#Linux #InstallFest`.
+    pub fn hashtags_text(&self) -> Option<String> {
+        let tags: Vec<_> = self
+            .hashtags
+            .iter()
+            .map(|tag| format!("#{}", tag))
+            .collect();
+        (!tags.is_empty()).then(|| tags.join(" "))
+    }
+
+    /// An event is all-day when its start is a DATE rather than a DATE-TIME.
+    pub fn is_all_day(&self) -> bool {
+        self.start_time.as_deref().is_some_and(|t| !t.contains('T'))
+    }
+
+    /// The event as a standalone iCalendar file followers can import.
+    ///
+    /// Text values are written back exactly as they were read, so escapes
+    /// from the source calendar survive the round trip.
+    pub fn to_ics(&self, stamp: chrono::DateTime<chrono::Utc>) -> String {
+        let start = self.start_time.as_deref().unwrap_or("");
+        let uid = match &self.uid {
+            Some(uid) => uid.clone(),
+            None => format!(
+                "{}@ical-to-masto",
+                crate::state::fingerprint(&format!(
+                    "{}@{}",
+                    self.summary.as_deref().unwrap_or(""),
+                    start
+                ))
+            ),
+        };
+
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_string(),
+            "VERSION:2.0".to_string(),
+            "PRODID:-//ical-to-masto//EN".to_string(),
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}", uid),
+            format!("DTSTAMP:{}", stamp.format("%Y%m%dT%H%M%SZ")),
+        ];
+        for (name, value) in [("DTSTART", &self.start_time), ("DTEND", &self.end_time)] {
+            if let Some(value) = value {
+                let date_only = matches!(parse_ical_time(value), Some((_, TimeForm::Date)));
+                let parameter = if date_only { ";VALUE=DATE" } else { "" };
+                lines.push(format!("{}{}:{}", name, parameter, value));
+            }
+        }
+        if let Some(sequence) = self.sequence {
+            lines.push(format!("SEQUENCE:{}", sequence));
+        }
+        if let Some(priority) = self.priority {
+            lines.push(format!("PRIORITY:{}", priority));
+        }
+        for (name, value) in [
+            ("STATUS", &self.status),
+            ("SUMMARY", &self.summary),
+            ("DESCRIPTION", &self.description),
+            ("LOCATION", &self.location),
+            ("URL", &self.url),
+        ] {
+            if let Some(value) = value {
+                lines.push(format!("{}:{}", name, value));
+            }
+        }
+        if let Some(image) = &self.image {
+            lines.push(format!("IMAGE;VALUE=URI:{}", image));
+        }
+        if let Some(conference) = &self.conference {
+            lines.push(format!("CONFERENCE;VALUE=URI:{}", conference));
+        }
+        lines.push("END:VEVENT".to_string());
+        lines.push("END:VCALENDAR".to_string());
+
+        lines.iter().map(|line| fold_line(line) + "\r\n").collect()
+    }
+
+    /// Whether the event takes place online rather than in person: as its
+    /// X-MASTO-ONLINE says, or else when it has a conference link or its
+    /// location looks like one.
+    pub fn is_online(&self) -> bool {
+        if let Some(online) = self.online {
+            return online;
+        }
+        self.conference.is_some()
+            || self.location.as_deref().is_some_and(|location| {
+                let location = location.to_lowercase();
+                location.starts_with("http://")
+                    || location.starts_with("https://")
+                    || ["online", "virtual", "remote"]
+                        .iter()
+                        .any(|word| location.contains(word))
+            })
+    }
+}
+
+/// The `[filter]` rules, ready to be checked against events.
+pub struct EventFilter {
+    include_categories: Vec<String>,
+    exclude_categories: Vec<String>,
+    include_pattern: Option<Regex>,
+    exclude_pattern: Option<Regex>,
+    /// The latest start allowed, in iCal form.
+    max_start: Option<String>,
+}
+
+impl EventFilter {
+    /// The rules of `config`, with `max_days_ahead` counted from `now`.
+    pub fn new(config: &FilterConfig, now: DateTime<Utc>) -> Result<Self> {
+        let pattern = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(|pattern| {
+                    Regex::new(pattern)
+                        .map_err(|e| anyhow!("Invalid filter pattern '{}': {}", pattern, e))
+                })
+                .transpose()
+        };
+        let lowercase = |categories: &[String]| {
+            categories
+                .iter()
+                .map(|category| category.to_lowercase())
+                .collect()
+        };
+        Ok(EventFilter {
+            include_categories: lowercase(&config.include_categories),
+            exclude_categories: lowercase(&config.exclude_categories),
+            include_pattern: pattern(&config.include_pattern)?,
+            exclude_pattern: pattern(&config.exclude_pattern)?,
+            max_start: config.max_days_ahead.map(|days| {
+                (now + Duration::days(days))
+                    .format("%Y%m%dT%H%M%SZ")
+                    .to_string()
+            }),
+        })
+    }
+
+    /// Whether the rules let the event be announced.
+    pub fn allows(&self, event: &CalendarEvent) -> bool {
+        let categories: Vec<_> = event
+            .categories
+            .iter()
+            .map(|category| category.to_lowercase())
+            .collect();
+        let texts = [event.summary.as_deref(), event.description.as_deref()];
+        let matches = |pattern: &Regex| texts.iter().flatten().any(|text| pattern.is_match(text));
+
+        (self.include_categories.is_empty()
+            || categories
+                .iter()
+                .any(|category| self.include_categories.contains(category)))
+            && !categories
+                .iter()
+                .any(|category| self.exclude_categories.contains(category))
+            && self.include_pattern.as_ref().is_none_or(matches)
+            && !self.exclude_pattern.as_ref().is_some_and(matches)
+            && self.max_start.as_deref().is_none_or(|max_start| {
+                event
+                    .start_time
+                    .as_deref()
+                    .is_none_or(|start| start <= max_start)
+            })
     }
 }
 
+#[derive(Clone)]
 pub struct IcalCalendar {
     pub events: Vec<CalendarEvent>,
 }
@@ -32,25 +333,32 @@ impl IcalCalendar {
         Self::parse_ical_content(&content)
     }
 
+    /// Reads a calendar from a path, a `file://` URL, or stdin for `-`.
+    pub fn from_path(path: &str) -> Result<Self> {
+        Self::parse_ical_content(&fetch::read_local(path)?)
+    }
+
+    /// Fetches a calendar, held to the default URL policy.
     pub async fn from_url(url: &str) -> Result<Self> {
-        let response = reqwest::get(url).await?;
-        if !response.status().is_success() {
-            return Err(anyhow!("HTTP error: {}", response.status()));
-        }
-        let content = response.text().await?;
-        Self::parse_ical_content(&content)
+        let policy = UrlPolicyConfig::default();
+        let fetched =
+            fetch::fetch_text(url, fetch::DEFAULT_MAX_REDIRECTS, None, None, Some(&policy)).await?;
+        Self::parse_ical_content(&fetched.body)
     }
 
+    /// Like [`IcalCalendar::from_url`], for code without a runtime.
     pub fn from_url_blocking(url: &str) -> Result<Self> {
-        let response = reqwest::blocking::get(url)?;
-        if !response.status().is_success() {
-            return Err(anyhow!("HTTP error: {}", response.status()));
-        }
-        let content = response.text()?;
-        Self::parse_ical_content(&content)
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(Self::from_url(url))
+    }
+
+    pub(crate) fn parse_ical_content(content: &str) -> Result<Self> {
+        timing::time(Phase::Parse, || Self::parse_events(content))
     }
 
-    fn parse_ical_content(content: &str) -> Result<Self> {
+    fn parse_events(content: &str) -> Result<Self> {
         let reader = BufReader::new(content.as_bytes());
         let parser = IcalParser::new(reader);
 
@@ -59,29 +367,35 @@ impl IcalCalendar {
         for calendar_result in parser {
             match calendar_result {
                 Ok(calendar) => {
+                    let locations = timezone_locations(&calendar.timezones);
                     for event in calendar.events {
-                        let mut calendar_event = CalendarEvent {
-                            summary: None,
-                            description: None,
-                            start_time: None,
-                            end_time: None,
-                            location: None,
-                            url: None,
-                        };
+                        let mut calendar_event = CalendarEvent::default();
+                        let mut has_standard_image = false;
+                        let mut has_standard_conference = false;
 
                         for property in event.properties {
+                            let zone = parameter(&property, "TZID")
+                                .and_then(|tzid| resolve_tzid(&tzid, &locations));
                             match property.name.as_str() {
+                                "UID" => {
+                                    calendar_event.uid = property.value.clone();
+                                }
                                 "SUMMARY" => {
                                     calendar_event.summary = property.value.clone();
+                                    calendar_event.language = parameter(&property, "LANGUAGE");
                                 }
                                 "DESCRIPTION" => {
                                     calendar_event.description = property.value.clone();
                                 }
                                 "DTSTART" => {
-                                    calendar_event.start_time = property.value.clone();
+                                    calendar_event.start_time =
+                                        property.value.as_deref().map(|v| to_utc(v, zone));
+                                    calendar_event.tzid = parameter(&property, "TZID");
+                                    calendar_event.timezone = zone;
                                 }
                                 "DTEND" => {
-                                    calendar_event.end_time = property.value.clone();
+                                    calendar_event.end_time =
+                                        property.value.as_deref().map(|v| to_utc(v, zone));
                                 }
                                 "LOCATION" => {
                                     calendar_event.location = property.value.clone();
@@ -89,6 +403,75 @@ impl IcalCalendar {
                                 "URL" => {
                                     calendar_event.url = property.value.clone();
                                 }
+                                "CATEGORIES" => {
+                                    let categories = property.value.as_deref().unwrap_or("");
+                                    calendar_event.categories.extend(
+                                        categories
+                                            .split(',')
+                                            .map(str::trim)
+                                            .filter(|category| !category.is_empty())
+                                            .map(str::to_string),
+                                    );
+                                    calendar_event.add_hashtags(categories.split(','));
+                                }
+                                "RRULE" => {
+                                    calendar_event.rrule = property.value.clone();
+                                }
+                                // One property can list several dates, and
+                                // a PERIOD value is reduced to its start
+                                "RDATE" => {
+                                    calendar_event.rdates.extend(date_list(&property, zone));
+                                }
+                                "EXDATE" => {
+                                    calendar_event.exdates.extend(date_list(&property, zone));
+                                }
+                                "RECURRENCE-ID" => {
+                                    calendar_event.recurrence_id =
+                                        property.value.as_deref().map(|v| to_utc(v, zone));
+                                }
+                                "SEQUENCE" => {
+                                    calendar_event.sequence =
+                                        property.value.as_deref().and_then(|v| v.parse().ok());
+                                }
+                                "PRIORITY" => {
+                                    calendar_event.priority = property
+                                        .value
+                                        .as_deref()
+                                        .and_then(|v| v.trim().parse().ok())
+                                        .filter(|priority| (1..=9).contains(priority));
+                                }
+                                "STATUS" => {
+                                    calendar_event.status = property.value.clone();
+                                }
+                                "CLASS" => {
+                                    calendar_event.class = property.value.clone();
+                                }
+                                // Inline (VALUE=BINARY) images aren't supported
+                                "IMAGE" if !has_standard_image && !is_binary(&property) => {
+                                    calendar_event.image = property.value.clone();
+                                    has_standard_image = true;
+                                }
+                                "X-IMAGE" | "X-WR-IMAGE" if calendar_event.image.is_none() => {
+                                    calendar_event.image = property.value.clone();
+                                }
+                                "CONFERENCE" if !has_standard_conference => {
+                                    calendar_event.conference = property.value.clone();
+                                    has_standard_conference = true;
+                                }
+                                "X-GOOGLE-CONFERENCE"
+                                | "X-MICROSOFT-SKYPETEAMSMEETINGURL"
+                                | "X-MICROSOFT-ONLINEMEETINGCONFLINK"
+                                    if calendar_event.conference.is_none() =>
+                                {
+                                    calendar_event.conference = property.value.clone();
+                                }
+                                "X-MASTO-ONLINE" => {
+                                    calendar_event.online =
+                                        property.value.as_deref().and_then(parse_flag);
+                                }
+                                "X-MASTO-POSTED-URL" => {
+                                    calendar_event.posted_url = property.value.clone();
+                                }
                                 _ => {}
                             }
                         }
@@ -123,52 +506,236 @@ impl IcalCalendar {
         max_date: Option<&str>,
         limit: Option<usize>,
     ) -> Vec<&CalendarEvent> {
-        let mut upcoming_events: Vec<&CalendarEvent> = self
+        let mut upcoming_events = self.get_events_started_between(current_time, max_date);
+        if let Some(limit) = limit {
+            upcoming_events.truncate(limit);
+        }
+        upcoming_events
+    }
+
+    /// Events that started after `from` and no later than `to` (if given),
+    /// oldest first. Looking back, this is what happened in a past period.
+    pub fn get_events_started_between(&self, from: &str, to: Option<&str>) -> Vec<&CalendarEvent> {
+        let mut events: Vec<&CalendarEvent> = self
             .events
             .iter()
             .filter(|event| {
-                if let Some(start_time) = &event.start_time {
-                    let start_time_str = start_time.as_str();
-                    let is_future = start_time_str > current_time;
-
-                    let is_before_max = if let Some(max_date) = max_date {
-                        start_time_str <= max_date
-                    } else {
-                        true
-                    };
-
-                    is_future && is_before_max
-                } else {
-                    false
-                }
+                event
+                    .start_time
+                    .as_deref()
+                    .is_some_and(|start| start > from && to.is_none_or(|to| start <= to))
             })
             .collect();
 
-        upcoming_events.sort_by(|a, b| match (&a.start_time, &b.start_time) {
-            (Some(a_time), Some(b_time)) => a_time.cmp(b_time),
-            (Some(_), None) => std::cmp::Ordering::Less,
-            (None, Some(_)) => std::cmp::Ordering::Greater,
-            (None, None) => std::cmp::Ordering::Equal,
-        });
+        events.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+        events
+    }
 
-        if let Some(limit) = limit {
-            upcoming_events.truncate(limit);
+    /// Events that ended after `from` and no later than `to`, oldest first.
+    /// Events without a DTEND are treated as ending when they start.
+    pub fn get_events_ended_between(&self, from: &str, to: &str) -> Vec<&CalendarEvent> {
+        let mut ended_events: Vec<&CalendarEvent> = self
+            .events
+            .iter()
+            .filter(|event| {
+                event
+                    .end_time
+                    .as_deref()
+                    .or(event.start_time.as_deref())
+                    .is_some_and(|end| end > from && end <= to)
+            })
+            .collect();
+
+        ended_events.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+        ended_events
+    }
+}
+
+/// How an iCal date or date-time value is written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeForm {
+    /// `20251203T190000Z`
+    Utc,
+    /// `20251203T190000`, in whatever zone the reader is in
+    Floating,
+    /// `20251203`, for all-day events
+    Date,
+}
+
+/// Parses an iCal DATE or DATE-TIME value, remembering how it was written.
+pub fn parse_ical_time(value: &str) -> Option<(chrono::NaiveDateTime, TimeForm)> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        chrono::NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|dt| (dt, TimeForm::Utc))
+    } else if value.contains('T') {
+        chrono::NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|dt| (dt, TimeForm::Floating))
+    } else {
+        chrono::NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .map(|date| (date.and_time(chrono::NaiveTime::MIN), TimeForm::Date))
+    }
+}
+
+/// Writes a time back out in the given iCal form.
+pub fn format_ical_time(time: chrono::NaiveDateTime, form: TimeForm) -> String {
+    match form {
+        TimeForm::Utc => time.format("%Y%m%dT%H%M%SZ").to_string(),
+        TimeForm::Floating => time.format("%Y%m%dT%H%M%S").to_string(),
+        TimeForm::Date => time.format("%Y%m%d").to_string(),
+    }
+}
+
+/// Escapes text for an iCal TEXT value, as [`unescape_text`] reads it.
+pub fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+/// Undoes the escaping of an iCal TEXT value: `\n`, `\,`, `\;` and `\\`.
+pub fn unescape_text(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => text.push('\n'),
+            Some(escaped) => text.push(escaped),
+            None => text.push('\\'),
         }
+    }
+    text
+}
 
-        upcoming_events
+/// Folds a content line so no line is longer than 75 octets, as RFC 5545
+/// asks, without splitting a character.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
+
+/// The dates of an RDATE or EXDATE, with PERIOD values reduced to their start.
+fn date_list(
+    property: &ical::property::Property,
+    zone: Option<Tz>,
+) -> impl Iterator<Item = String> + '_ {
+    property
+        .value
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(move |v| to_utc(v.split('/').next().unwrap_or(v).trim(), zone))
+}
+
+/// The X-LIC-LOCATION of each VTIMEZONE, by TZID. Calendar apps that make up
+/// their own TZIDs often name the IANA zone there.
+fn timezone_locations(
+    timezones: &[ical::parser::ical::component::IcalTimeZone],
+) -> HashMap<String, String> {
+    timezones
+        .iter()
+        .filter_map(|timezone| {
+            let value = |name: &str| {
+                timezone
+                    .properties
+                    .iter()
+                    .find(|property| property.name == name)
+                    .and_then(|property| property.value.clone())
+            };
+            Some((value("TZID")?, value("X-LIC-LOCATION")?))
+        })
+        .collect()
+}
+
+/// The zone a TZID names: an IANA name, possibly behind a prefix like
+/// `/mozilla.org/20050126_1/`, or a VTIMEZONE located in one.
+pub fn resolve_tzid(tzid: &str, locations: &HashMap<String, String>) -> Option<Tz> {
+    let tzid = tzid.trim_matches('"');
+    [Some(tzid), locations.get(tzid).map(String::as_str)]
+        .into_iter()
+        .flatten()
+        .find_map(|name| {
+            let parts: Vec<_> = name.split('/').collect();
+            (0..parts.len()).find_map(|skip| parts[skip..].join("/").parse().ok())
+        })
+}
+
+/// A DATE-TIME written in `zone`'s local time, converted to UTC. Other values
+/// are kept as written, as are all times when the zone isn't known.
+fn to_utc(value: &str, zone: Option<Tz>) -> String {
+    let (Some(zone), Some((time, TimeForm::Floating))) = (zone, parse_ical_time(value)) else {
+        return value.to_string();
+    };
+    // A time in the hour skipped when clocks go forward moves forward with them
+    let local = zone.from_local_datetime(&time).earliest().or_else(|| {
+        zone.from_local_datetime(&(time + chrono::Duration::hours(1)))
+            .earliest()
+    });
+    match local {
+        Some(local) => format_ical_time(local.naive_utc(), TimeForm::Utc),
+        None => value.to_string(),
+    }
+}
+
+/// The first value of a property's parameter, whatever the case of its name.
+fn parameter(property: &ical::property::Property, name: &str) -> Option<String> {
+    property
+        .params
+        .iter()
+        .flatten()
+        .find(|(param, _)| param.eq_ignore_ascii_case(name))
+        .and_then(|(_, values)| values.first().cloned())
+}
+
+/// Whether a property carries its value inline rather than as a URI.
+fn is_binary(property: &ical::property::Property) -> bool {
+    property.params.iter().flatten().any(|(name, values)| {
+        name.eq_ignore_ascii_case("VALUE")
+            && values.iter().any(|v| v.eq_ignore_ascii_case("BINARY"))
+    })
+}
+
+/// Reads a yes/no property like X-MASTO-ONLINE; `None` when it is neither.
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_uppercase().as_str() {
+        "TRUE" | "YES" | "1" => Some(true),
+        "FALSE" | "NO" | "0" => Some(false),
+        _ => None,
     }
 }
 
-fn format_ical_date(ical_date: &str) -> String {
-    match chrono::DateTime::parse_from_str(ical_date, "%Y%m%dT%H%M%SZ") {
-        Ok(dt) => dt.format("%a, %b %d, %Y at %I:%M %p").to_string(),
-        Err(_) => {
-            // Try parsing without timezone
-            match chrono::NaiveDateTime::parse_from_str(ical_date, "%Y%m%dT%H%M%S") {
-                Ok(dt) => dt.format("%a, %b %d, %Y at %I:%M %p").to_string(),
-                Err(_) => ical_date.to_string(), // Return original if parsing fails
+/// Formats an iCal time for a status. UTC times are shown in `display`, or
+/// in UTC without one, followed by the zone's abbreviation; floating and date
+/// values as written.
+fn format_ical_date(ical_date: &str, display: Option<Tz>) -> String {
+    match parse_ical_time(ical_date) {
+        Some((time, TimeForm::Utc)) => {
+            let time = chrono::Utc.from_utc_datetime(&time);
+            let format = format!("{} %Z", DATE_TIME_FORMAT);
+            match display {
+                Some(zone) => time.with_timezone(&zone).format(&format).to_string(),
+                None => time.format(&format).to_string(),
             }
         }
+        Some((time, TimeForm::Floating)) => time.format(DATE_TIME_FORMAT).to_string(),
+        // All-day events only carry a date
+        Some((time, TimeForm::Date)) => time.format("%a, %b %d, %Y").to_string(),
+        None => ical_date.to_string(),
     }
 }
 
@@ -204,6 +771,10 @@ mod tests {
         assert_eq!(calendar.events.len(), 2);
 
         let first_event = &calendar.events[0];
+        assert_eq!(
+            first_event.uid,
+            Some("test-event-1@example.com".to_string())
+        );
         assert_eq!(first_event.summary, Some("Test Meeting".to_string()));
         assert_eq!(
             first_event.description,
@@ -357,4 +928,320 @@ mod tests {
             Some("Near Future Event".to_string())
         );
     }
+
+    #[test]
+    fn test_get_events_ended_between() {
+        let ical_content = indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:old-event@example.com
+            DTSTART:20251120T100000Z
+            DTEND:20251120T110000Z
+            SUMMARY:Old Event
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:recent-event@example.com
+            DTSTART:20251203T100000Z
+            DTEND:20251203T110000Z
+            SUMMARY:Recent Event
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:no-end@example.com
+            DTSTART:20251203T090000Z
+            SUMMARY:No End Event
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:future-event@example.com
+            DTSTART:20251205T100000Z
+            DTEND:20251205T110000Z
+            SUMMARY:Future Event
+            END:VEVENT
+            END:VCALENDAR
+        "};
+
+        let calendar = IcalCalendar::parse_ical_content(ical_content).unwrap();
+        let ended = calendar.get_events_ended_between("20251202T120000Z", "20251203T120000Z");
+        assert_eq!(ended.len(), 2);
+        assert_eq!(ended[0].summary, Some("No End Event".to_string()));
+        assert_eq!(ended[1].summary, Some("Recent Event".to_string()));
+    }
+
+    #[test]
+    fn test_timezones() {
+        let calendar = IcalCalendar::parse_ical_content(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VTIMEZONE
+            TZID:Eastern Standard Time
+            X-LIC-LOCATION:America/New_York
+            END:VTIMEZONE
+            BEGIN:VEVENT
+            DTSTART;TZID=America/New_York:20251203T190000
+            DTEND;TZID=America/New_York:20251203T210000
+            EXDATE;TZID=America/New_York:20251210T190000
+            END:VEVENT
+            BEGIN:VEVENT
+            DTSTART;TZID=/mozilla.org/20050126_1/Europe/Paris:20250701T190000
+            END:VEVENT
+            BEGIN:VEVENT
+            DTSTART;TZID=Eastern Standard Time:20250701T190000
+            END:VEVENT
+            BEGIN:VEVENT
+            DTSTART;TZID=Somewhere/Else:20250701T190000
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+        let starts: Vec<_> = calendar
+            .events
+            .iter()
+            .map(|e| e.start_time.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                "20251204T000000Z",
+                "20250701T170000Z",
+                "20250701T230000Z",
+                "20250701T190000"
+            ]
+        );
+        let first = &calendar.events[0];
+        assert_eq!(first.end_time.as_deref(), Some("20251204T020000Z"));
+        assert_eq!(first.exdates, ["20251211T000000Z"]);
+        assert_eq!(first.timezone, Some(chrono_tz::America::New_York));
+        assert_eq!(calendar.events[3].tzid.as_deref(), Some("Somewhere/Else"));
+        assert_eq!(calendar.events[3].timezone, None);
+
+        assert_eq!(
+            first.start_time_formatted().as_deref(),
+            Some("Thu, Dec 04, 2025 at 12:00 AM UTC")
+        );
+        let shown = CalendarEvent {
+            display_timezone: Some(chrono_tz::America::New_York),
+            ..first.clone()
+        };
+        assert_eq!(
+            shown.start_time_formatted().as_deref(),
+            Some("Wed, Dec 03, 2025 at 07:00 PM EST")
+        );
+    }
+
+    #[test]
+    fn test_image() {
+        let calendar = IcalCalendar::parse_ical_content(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:standard@example.com
+            X-WR-IMAGE:https://example.com/old.png
+            IMAGE;VALUE=BINARY;ENCODING=BASE64:iVBORw0KGgo=
+            IMAGE;VALUE=URI;DISPLAY=BADGE:https://example.com/badge.png
+            IMAGE;VALUE=URI:https://example.com/poster.png
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:extension@example.com
+            X-IMAGE:https://example.com/flyer.jpg
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+        assert_eq!(
+            calendar.events[0].image.as_deref(),
+            Some("https://example.com/badge.png")
+        );
+        assert_eq!(
+            calendar.events[1].image.as_deref(),
+            Some("https://example.com/flyer.jpg")
+        );
+    }
+
+    #[test]
+    fn test_online() {
+        let calendar = IcalCalendar::parse_ical_content(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:room@example.com
+            LOCATION:Room 101\\, Central Library
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:call@example.com
+            LOCATION:Room 101\\, Central Library
+            X-GOOGLE-CONFERENCE:https://meet.google.com/abc-defg-hij
+            CONFERENCE;VALUE=URI;FEATURE=VIDEO:https://meet.example.com/monthly
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:remote@example.com
+            LOCATION:Remote (Jitsi)
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:hybrid@example.com
+            LOCATION:Room 101\\, Central Library
+            X-MICROSOFT-SKYPETEAMSMEETINGURL:https://teams.example.com/l/meetup
+            X-MASTO-ONLINE:FALSE
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:stream@example.com
+            X-MASTO-ONLINE:yes
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+        let online: Vec<_> = calendar.events.iter().map(|e| e.is_online()).collect();
+        assert_eq!(online, vec![false, true, true, false, true]);
+        assert_eq!(
+            calendar.events[1].conference.as_deref(),
+            Some("https://meet.example.com/monthly")
+        );
+        assert_eq!(
+            calendar.events[3].conference.as_deref(),
+            Some("https://teams.example.com/l/meetup")
+        );
+    }
+
+    #[test]
+    fn test_event_filter() {
+        let calendar = IcalCalendar::parse_ical_content(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:meetup@example.com
+            DTSTART:20251203T190000Z
+            SUMMARY:Monthly Meetup
+            CATEGORIES:Public, Linux
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:board@example.com
+            DTSTART:20251204T190000Z
+            SUMMARY:Board Meeting
+            CATEGORIES:public,internal
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:staff@example.com
+            DTSTART:20251205T190000Z
+            SUMMARY:Planning
+            DESCRIPTION:Staff only
+            CATEGORIES:Public
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:uncategorized@example.com
+            DTSTART:20251206T190000Z
+            SUMMARY:Install Fest
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:later@example.com
+            DTSTART:20260301T190000Z
+            SUMMARY:Spring Meetup
+            CATEGORIES:Public
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+        assert_eq!(calendar.events[0].categories, ["Public", "Linux"]);
+
+        let now = Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap();
+        let allowed = |config: FilterConfig| {
+            let filter = EventFilter::new(&config, now).unwrap();
+            calendar
+                .events
+                .iter()
+                .filter(|event| filter.allows(event))
+                .map(|event| event.uid.as_deref().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            allowed(FilterConfig {
+                include_categories: vec!["PUBLIC".to_string()],
+                exclude_categories: vec!["Internal".to_string()],
+                exclude_pattern: Some("(?i)staff".to_string()),
+                max_days_ahead: Some(14),
+                ..Default::default()
+            }),
+            ["meetup@example.com"]
+        );
+        assert_eq!(
+            allowed(FilterConfig {
+                include_pattern: Some("Meet(up|ing)$".to_string()),
+                ..Default::default()
+            }),
+            [
+                "meetup@example.com",
+                "board@example.com",
+                "later@example.com"
+            ]
+        );
+
+        let invalid = FilterConfig {
+            exclude_pattern: Some("(unclosed".to_string()),
+            ..Default::default()
+        };
+        assert!(EventFilter::new(&invalid, now).is_err());
+    }
+
+    #[test]
+    fn test_priority() {
+        let calendar = IcalCalendar::parse_ical_content(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:election@example.com
+            PRIORITY:1
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:social@example.com
+            PRIORITY:5
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:unset@example.com
+            PRIORITY:0
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:plain@example.com
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+        let priorities: Vec<_> = calendar.events.iter().map(|e| e.priority).collect();
+        assert_eq!(priorities, vec![Some(1), Some(5), None, None]);
+        let important: Vec<_> = calendar.events.iter().map(|e| e.is_important()).collect();
+        assert_eq!(important, vec![true, false, false, false]);
+    }
+
+    #[test]
+    fn test_to_ics() {
+        let event = CalendarEvent {
+            uid: Some("install-fest@example.com".to_string()),
+            summary: Some("Install Fest".to_string()),
+            start_time: Some("20251207".to_string()),
+            location: Some("Library".to_string()),
+            description: Some("x".repeat(100)),
+            ..Default::default()
+        };
+        let stamp = chrono::DateTime::parse_from_rfc3339("2025-12-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+
+        let ics = event.to_ics(stamp);
+        assert!(ics.contains("UID:install-fest@example.com\r\n"));
+        assert!(ics.contains("DTSTAMP:20251201T120000Z\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20251207\r\n"));
+        assert!(ics.contains(&format!(
+            "DESCRIPTION:{}\r\n {}\r\n",
+            "x".repeat(63),
+            "x".repeat(37)
+        )));
+        assert!(ics.lines().all(|line| line.len() <= 76));
+
+        let calendar = IcalCalendar::parse_ical_content(&ics).unwrap();
+        assert_eq!(calendar.events[0].summary, event.summary);
+        assert_eq!(calendar.events[0].description, event.description);
+    }
 }
@@ -0,0 +1,99 @@
+use crate::config::{IcsUploadConfig, UrlPolicyConfig};
+use crate::ical::CalendarEvent;
+use crate::url_policy;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+
+/// The file name an event is uploaded as, stable across runs so a re-post
+/// replaces the same file.
+pub fn file_name(event: &CalendarEvent) -> String {
+    let id = event
+        .uid
+        .as_deref()
+        .or(event.summary.as_deref())
+        .unwrap_or("event");
+    let start = event.start_time.as_deref().unwrap_or("");
+
+    let mut name = String::new();
+    for c in format!("{}-{}", id, start).chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        } else if !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    format!("{}.ics", name.trim_matches('-'))
+}
+
+fn join(base: &str, name: &str) -> String {
+    format!("{}/{}", base.trim_end_matches('/'), name)
+}
+
+/// Uploads the event as an `.ics` file with an HTTP PUT (WebDAV, or an
+/// S3-compatible bucket that accepts PUTs) and returns its public link.
+/// Nothing is uploaded where `policy` doesn't allow it.
+pub async fn upload(
+    config: &IcsUploadConfig,
+    event: &CalendarEvent,
+    now: DateTime<Utc>,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<String> {
+    let name = file_name(event);
+    let url = join(&config.upload_url, &name);
+
+    let mut request = url_policy::client(policy, &url)?
+        .put(&url)
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "text/calendar; charset=utf-8",
+        )
+        .body(event.to_ics(now));
+    if let Some(username) = &config.username {
+        request = request.basic_auth(username, config.password.as_ref());
+    }
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "HTTP error uploading {}: {}",
+            name,
+            response.status()
+        ));
+    }
+
+    let public_url = config.public_url.as_deref().unwrap_or(&config.upload_url);
+    Ok(join(public_url, &name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        let event = CalendarEvent {
+            uid: Some("Week-1@Example.com".to_string()),
+            start_time: Some("20251203T190000Z".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(file_name(&event), "week-1-example-com-20251203t190000z.ics");
+
+        let event = CalendarEvent {
+            summary: Some("Install Fest!".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(file_name(&event), "install-fest.ics");
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            join("https://dav.example.com/events/", "a.ics"),
+            "https://dav.example.com/events/a.ics"
+        );
+        assert_eq!(
+            join("https://dav.example.com/events", "a.ics"),
+            "https://dav.example.com/events/a.ics"
+        );
+    }
+}
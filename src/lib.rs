@@ -1,2 +1,60 @@
+pub mod agenda;
+pub mod bridge;
+pub mod bundle;
+pub mod cache;
+pub mod cadence;
+pub mod calendar_links;
+pub mod card;
+pub mod clock;
+pub mod commands;
 pub mod config;
+pub mod daemon;
+pub mod emoji;
+pub mod error;
+pub mod export;
+pub mod fetch;
+pub mod formatter;
+pub mod gc;
+pub mod health;
 pub mod ical;
+pub mod ics_upload;
+pub mod link_check;
+pub mod matrix;
+pub mod media;
+pub mod migrate;
+pub mod minutes;
+pub mod nextcloud;
+pub mod oauth;
+pub mod outbox;
+pub mod overrides;
+pub mod poster;
+pub mod probe;
+pub mod profile;
+pub mod quiet;
+pub mod recap;
+pub mod recurrence;
+pub mod registration;
+pub mod remind_me;
+pub mod reminders;
+pub mod run;
+pub mod scheduling;
+pub mod scope;
+pub mod secrets;
+pub mod service;
+pub mod shortener;
+pub mod skips;
+pub mod source;
+pub mod state;
+pub mod subscription;
+pub mod template;
+pub mod template_check;
+pub mod tenants;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
+pub mod timing;
+pub mod uid_map;
+pub mod url_policy;
+pub mod validate;
+pub mod write_back;
+
+pub use error::{Error, Result};
@@ -0,0 +1,177 @@
+use crate::config::{DeadLinks, LinkCheckConfig, UrlPolicyConfig};
+use crate::{scope, url_policy};
+
+/// What a site said about a URL.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Health {
+    Alive,
+    /// It answered 404 or 410, with this status.
+    Dead(u16),
+    /// It couldn't be asked, or gave another error.
+    Unknown,
+}
+
+/// Asks the site whether `url` is still there, with a HEAD request, or a GET
+/// for sites that don't take HEAD. Each URL is only asked about once per run,
+/// and not at all if `policy` doesn't allow it.
+pub async fn check(
+    config: &LinkCheckConfig,
+    url: &str,
+    policy: Option<&UrlPolicyConfig>,
+) -> Health {
+    let records = scope::current();
+    if let Some(health) = records.checked.lock().unwrap().get(url) {
+        return *health;
+    }
+    if let Some(policy) = policy
+        && let Err(e) = reqwest::Url::parse(url)
+            .map_err(Into::into)
+            .and_then(|parsed| url_policy::check(policy, &parsed))
+    {
+        eprintln!("Warning: not checking {}: {}", url, e);
+        return Health::Unknown;
+    }
+    let Ok(client) = url_policy::client_builder(policy).build() else {
+        return Health::Unknown;
+    };
+    let timeout = std::time::Duration::from_secs(config.timeout_seconds);
+    let mut answer = client.head(url).timeout(timeout).send().await;
+    if let Ok(response) = &answer
+        && matches!(response.status().as_u16(), 405 | 501)
+    {
+        answer = client.get(url).timeout(timeout).send().await;
+    }
+    let health = match answer.map(|response| response.status()) {
+        Ok(status) if status.is_success() || status.is_redirection() => Health::Alive,
+        Ok(status) if matches!(status.as_u16(), 404 | 410) => Health::Dead(status.as_u16()),
+        Ok(_) | Err(_) => Health::Unknown,
+    };
+    records
+        .checked
+        .lock()
+        .unwrap()
+        .insert(url.to_string(), health);
+    health
+}
+
+/// `url`, or `None` when it is dead and `dead_links = "drop"`. A dead link
+/// is warned about either way; one that can't be checked is posted.
+pub async fn checked(
+    config: &LinkCheckConfig,
+    url: String,
+    policy: Option<&UrlPolicyConfig>,
+) -> Option<String> {
+    let Health::Dead(status) = check(config, &url, policy).await else {
+        return Some(url);
+    };
+    match config.dead_links {
+        DeadLinks::Warn => {
+            eprintln!("Warning: posting {}, which answers HTTP {}", url, status);
+            Some(url)
+        }
+        DeadLinks::Drop => {
+            eprintln!(
+                "Warning: leaving out {}, which answers HTTP {}",
+                url, status
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// Answers HEAD requests with 404 for `/gone` and 405 for the rest, and
+    /// GET requests with 200, noting each request line.
+    fn serve(requests: Arc<Mutex<Vec<String>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                let status = match request.split(' ').take(2).collect::<Vec<_>>()[..] {
+                    ["HEAD", "/gone"] => "404 Not Found",
+                    ["HEAD", _] => "405 Method Not Allowed",
+                    _ => "200 OK",
+                };
+                requests.lock().unwrap().push(request.trim().to_string());
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_dead_links() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let base = serve(requests.clone());
+        let mut config = LinkCheckConfig {
+            dead_links: DeadLinks::Warn,
+            timeout_seconds: 5,
+        };
+        let gone = format!("{}/gone", base);
+        let register = format!("{}/register", base);
+
+        assert_eq!(
+            checked(&config, gone.clone(), None).await,
+            Some(gone.clone())
+        );
+        config.dead_links = DeadLinks::Drop;
+        assert_eq!(checked(&config, gone.clone(), None).await, None);
+        assert_eq!(
+            checked(&config, register.clone(), None).await,
+            Some(register.clone())
+        );
+        assert_eq!(
+            check(&config, "http://127.0.0.1:9/", None).await,
+            Health::Unknown
+        );
+        // Nor asked at all when the policy keeps the bot off this machine
+        let policy = UrlPolicyConfig {
+            schemes: vec!["http".to_string()],
+            allow: Vec::new(),
+        };
+        let private = format!("{}/private", base);
+        assert_eq!(
+            check(&config, &private, Some(&policy)).await,
+            Health::Unknown
+        );
+
+        // Each URL was only asked about once, and with GET where HEAD isn't
+        // allowed
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "HEAD /gone HTTP/1.1",
+                "HEAD /register HTTP/1.1",
+                "GET /register HTTP/1.1"
+            ]
+        );
+
+        // A job of the daemon asks again
+        assert_eq!(
+            scope::job(check(&config, &gone, None)).await,
+            Health::Dead(404)
+        );
+        assert_eq!(requests.lock().unwrap().len(), 4);
+    }
+}
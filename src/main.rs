@@ -1,7 +1,48 @@
 use clap::{Parser, Subcommand};
-use std::str::FromStr;
-mod config;
-use ical_to_masto::ical::IcalCalendar;
+use ical_to_masto::bundle;
+use ical_to_masto::cadence;
+use ical_to_masto::clock::{Clock, SystemClock};
+use ical_to_masto::commands::{self, Synced};
+use ical_to_masto::config;
+use ical_to_masto::daemon;
+use ical_to_masto::emoji;
+use ical_to_masto::export;
+use ical_to_masto::formatter;
+use ical_to_masto::migrate;
+use ical_to_masto::outbox;
+use ical_to_masto::poster::{self, Posted, Publisher};
+use ical_to_masto::probe;
+use ical_to_masto::quiet;
+use ical_to_masto::recap::Period;
+use ical_to_masto::registration;
+use ical_to_masto::run::{self, Outcome, Run};
+use ical_to_masto::scope;
+use ical_to_masto::service;
+use ical_to_masto::skips;
+use ical_to_masto::source::{CalendarSource, WebcalSource};
+use ical_to_masto::template_check;
+use ical_to_masto::tenants;
+use ical_to_masto::timing;
+use ical_to_masto::validate;
+use mastodon_async::{Language, Visibility};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Whether stdout is kept for JSON output, with progress messages going to
+/// stderr instead.
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Prints a progress message: to stdout, or to stderr with `--output json`.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if JSON_OUTPUT.load(Ordering::Relaxed) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
 
 #[derive(Parser)]
 #[command(name = "ical-to-masto")]
@@ -15,11 +56,96 @@ struct Cli {
     )]
     config: Option<String>,
 
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Print debug output, such as how long each phase took"
+    )]
+    verbose: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Write a JSON summary of the run to a file, or to stdout for -"
+    )]
+    summary_json: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Run for the [[feed]] with this name, or for the main calendar and every feed with 'all'"
+    )]
+    feed: Option<String>,
+
+    #[command(flatten)]
+    flags: RunFlags,
+
+    /// Without one, the configured `default_command` runs.
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+/// A command line an alias or the default command expands to.
+#[derive(Parser)]
+#[command(name = "ical-to-masto")]
+struct Expanded {
+    #[command(flatten)]
+    flags: RunFlags,
+
     #[command(subcommand)]
     command: Commands,
 }
 
-#[derive(Subcommand)]
+/// How a command runs, which aliases and schedules can also say.
+#[derive(Clone, Copy, Default, clap::Args)]
+struct RunFlags {
+    #[arg(
+        long,
+        global = true,
+        help = "Show the statuses that would be posted instead of posting them, leaving the state file as it is"
+    )]
+    dry_run: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "JSON prints the events for 'events', or what was picked and posted for the posting commands"
+    )]
+    output: OutputFormat,
+}
+
+impl RunFlags {
+    /// These flags with those also given to an alias or schedule.
+    fn and(self, other: RunFlags) -> RunFlags {
+        RunFlags {
+            dry_run: self.dry_run || other.dry_run,
+            output: match other.output {
+                OutputFormat::Json => OutputFormat::Json,
+                OutputFormat::Text => self.output,
+            },
+        }
+    }
+
+    fn json(self) -> bool {
+        self.output == OutputFormat::Json
+    }
+}
+
+/// How `schedule-suggest` writes its schedules.
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum TimerFormat {
+    /// Lines for a crontab.
+    Crontab,
+    /// `OnCalendar=` lines for systemd timers.
+    Systemd,
+}
+
+#[derive(Clone, Subcommand)]
 enum Commands {
     #[command(about = "Register an application with a Mastodon instance")]
     Register {
@@ -27,284 +153,1887 @@ enum Commands {
         client_name: String,
         #[arg(short, long)]
         redirect_uri: Option<String>,
-        #[arg(short, long, default_values = ["write:statuses"])]
+        #[arg(
+            short,
+            long,
+            help = "Scopes to request instead of the ones the configured features need"
+        )]
         scopes: Vec<String>,
         #[arg(short, long)]
         website: Option<String>,
+        #[arg(
+            long,
+            env = "ICAL_TO_MASTO_AUTH_CODE",
+            help = "Authorization code to use instead of prompting for it on stdin"
+        )]
+        auth_code: Option<String>,
+        #[arg(
+            long,
+            default_value_t = 300,
+            help = "Seconds to wait for the authorization code to be pasted"
+        )]
+        timeout: u64,
+        #[arg(long, help = "Register the account from the [backup] section instead")]
+        backup: bool,
+        #[arg(
+            long,
+            value_parser = config::parse_language,
+            conflicts_with = "backup",
+            help = "Register the [[language_account]] for this language instead"
+        )]
+        language: Option<Language>,
+    },
+    #[command(about = "Sign in with an application created in the instance's settings")]
+    Login {
+        #[arg(
+            long,
+            required_unless_present = "access_token",
+            help = "Client ID (client key) of the application"
+        )]
+        client_id: Option<String>,
+        #[arg(
+            long,
+            required_unless_present = "access_token",
+            help = "Client secret of the application"
+        )]
+        client_secret: Option<String>,
+        #[arg(
+            long,
+            conflicts_with_all = ["client_id", "client_secret"],
+            help = "Access token of the application, saved without authorizing it again"
+        )]
+        access_token: Option<String>,
+        #[arg(short, long, default_value = "urn:ietf:wg:oauth:2.0:oob")]
+        redirect_uri: String,
+        #[arg(
+            short,
+            long,
+            help = "Scopes to request instead of the ones the configured features need"
+        )]
+        scopes: Vec<String>,
+        #[arg(
+            long,
+            env = "ICAL_TO_MASTO_AUTH_CODE",
+            help = "Authorization code to use instead of prompting for it on stdin"
+        )]
+        auth_code: Option<String>,
+        #[arg(
+            long,
+            default_value_t = 300,
+            help = "Seconds to wait for the authorization code to be pasted"
+        )]
+        timeout: u64,
+        #[arg(
+            long,
+            help = "Sign in to the account from the [backup] section instead"
+        )]
+        backup: bool,
+        #[arg(
+            long,
+            value_parser = config::parse_language,
+            conflicts_with = "backup",
+            help = "Sign in to the [[language_account]] for this language instead"
+        )]
+        language: Option<Language>,
+    },
+    #[command(
+        visible_alias = "revoke",
+        about = "Revoke the account's access token at the instance and shred the token file"
+    )]
+    Unregister {
+        #[arg(
+            long,
+            help = "Shred the token file even when the instance can't revoke the token"
+        )]
+        force: bool,
+        #[arg(
+            long,
+            help = "Unregister the account from the [backup] section instead"
+        )]
+        backup: bool,
+        #[arg(
+            long,
+            value_parser = config::parse_language,
+            conflicts_with = "backup",
+            help = "Unregister the [[language_account]] for this language instead"
+        )]
+        language: Option<Language>,
     },
     #[command(about = "Post the next meeting from iCal to Mastodon")]
-    PostNext {},
+    PostNext {
+        #[arg(
+            long,
+            default_value_t = 1,
+            help = "Announce this many of the next meetings together in one status"
+        )]
+        count: usize,
+        #[command(flatten)]
+        post: PostFlags,
+    },
     #[command(about = "Post all upcoming meetings from iCal to Mastodon")]
-    PostAll {},
+    PostAll {
+        #[command(flatten)]
+        post: PostFlags,
+    },
+    #[command(about = "Announce every upcoming meeting that wasn't announced yet, each on its own")]
+    Backfill {
+        #[arg(long, help = "Announce the meetings starting within this many days")]
+        days_ahead: u32,
+        #[arg(
+            long,
+            default_value_t = 3,
+            help = "Send at most this many announcements at once"
+        )]
+        concurrency: usize,
+        #[arg(
+            long,
+            default_value_t = 2,
+            help = "Seconds to wait between starting one announcement and the next"
+        )]
+        interval: u64,
+    },
+    #[command(about = "Ask for agenda items ahead of upcoming meetings")]
+    PostAgendaCall {
+        #[arg(
+            long,
+            help = "Also edit agenda calls already posted for upcoming meetings if the template changed"
+        )]
+        update_changed: bool,
+    },
+    #[command(about = "Follow up on meetings that just ended with where to find the minutes")]
+    PostMinutes {},
+    #[command(about = "Remind followers of meetings starting soon, at the configured lead times")]
+    PostReminders {},
+    #[command(
+        about = "Take replies asking to be reminded of an announced meeting, and send the reminders due"
+    )]
+    RemindMe {},
+    #[command(
+        about = "Edit announcements of meetings that changed, and delete or correct those of cancelled ones"
+    )]
+    Sync {},
+    #[command(about = "Post a poll asking followers to pick a time for a tentative meeting")]
+    PostSchedulingPoll {},
+    #[command(about = "Announce the winners of scheduling polls that have closed")]
+    PostSchedulingPollResults {},
+    #[command(about = "Remind followers how to subscribe to the calendar, when it's due")]
+    PostSubscriptionInfo {},
+    #[command(about = "Post a recap of the meetings that took place in a past period")]
+    Recap {
+        #[arg(
+            long,
+            conflicts_with = "days",
+            help = "Recap Monday to Sunday of last week"
+        )]
+        last_week: bool,
+        #[arg(
+            long,
+            default_value_t = 7,
+            value_parser = clap::value_parser!(i64).range(1..),
+            help = "Recap this many days before today"
+        )]
+        days: i64,
+    },
+    #[command(
+        about = "Update the account's profile fields with the next meeting, calendar and website"
+    )]
+    SyncProfile {},
+    #[command(about = "List the calendar's events, with recurring ones expanded")]
+    Events {
+        #[arg(long, help = "Only events that haven't started yet")]
+        upcoming: bool,
+        #[arg(long, help = "Only events starting within this many days")]
+        days: Option<i64>,
+        #[arg(long, help = "Tell why post-next would leave each event out")]
+        explain: bool,
+    },
+    #[command(about = "Check the calendar for problems the bot works around or can't handle")]
+    ValidateCalendar {},
+    #[command(about = "Keep running, and run the [[schedule]] commands at their times")]
+    Daemon {
+        #[arg(
+            long,
+            value_name = "DIR",
+            help = "Run for every tenant in DIR: each directory in it with a bot.toml, in place of -c"
+        )]
+        tenants: Option<String>,
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            default_value_t = 0,
+            help = "Seconds to wait between the runs of different tenants due at the same time"
+        )]
+        pace_seconds: u64,
+    },
+    #[command(about = "Install the daemon as a launchd agent on macOS or a Windows service")]
+    Service {
+        #[command(subcommand)]
+        command: ServiceCommands,
+    },
+    #[command(about = "Suggest when to run the posting commands, from when meetings take place")]
+    ScheduleSuggest {
+        #[arg(long, value_enum, default_value_t = TimerFormat::Crontab)]
+        format: TimerFormat,
+    },
+    #[command(about = "Leave safe mode and start posting again")]
+    Resume {},
+    #[command(about = "Stop posting about an event until unmuted, or list what is muted")]
+    Mute {
+        #[arg(help = "UID of the event, or its summary or UID with * for any text")]
+        pattern: Option<String>,
+        #[arg(
+            long,
+            help = "Post again from this date or RFC 3339 time on, without unmuting"
+        )]
+        until: Option<String>,
+    },
+    #[command(about = "Start posting about a muted event again")]
+    Unmute {
+        #[arg(help = "The pattern it was muted with")]
+        pattern: String,
+    },
+    #[command(about = "List the statuses scheduled on the instance that haven't gone out yet")]
+    ListScheduled {},
+    #[command(about = "Cancel a scheduled status before it goes out")]
+    CancelScheduled {
+        #[arg(help = "ID of the scheduled status, as list-scheduled shows it")]
+        id: String,
+    },
+    #[command(about = "Forget what was posted about long-past meetings and outdated caches")]
+    Gc {},
+    #[command(about = "Bundle the configuration, state and caches into one file to move the bot")]
+    Backup {
+        #[arg(help = "File to write the bundle to")]
+        output: String,
+        #[arg(long, help = "Include the configuration's secrets and the token files")]
+        include_secrets: bool,
+    },
+    #[command(about = "Put the files of a bundle written by backup back in place")]
+    Restore {
+        #[arg(help = "The bundle backup wrote")]
+        bundle: String,
+        #[arg(long, help = "Replace files that already exist")]
+        force: bool,
+    },
+    #[command(about = "Print the links to every post about an event, on every account")]
+    Links {
+        #[arg(help = "UID of the event")]
+        uid: String,
+    },
+    #[command(about = "Manage the configuration file")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    #[command(about = "Work with post templates")]
+    Template {
+        #[command(subcommand)]
+        command: TemplateCommands,
+    },
     #[command(about = "Post a status to Mastodon")]
     PostStatus {
         #[arg(help = "Status text to post")]
         status: String,
+        #[command(flatten)]
+        post: PostFlags,
+    },
+    /// An alias from the configuration, with any further arguments.
+    #[command(external_subcommand)]
+    Alias(Vec<String>),
+}
+
+/// Options for the status, overriding the `[post]` section.
+#[derive(Clone, clap::Args)]
+struct PostFlags {
+    #[arg(long, value_parser = config::parse_visibility, help = "public, unlisted, private or direct")]
+    visibility: Option<Visibility>,
+    #[arg(long, help = "Hide attached media behind a warning")]
+    sensitive: bool,
+    #[arg(
+        long,
+        help = "Content warning shown instead of the text until expanded"
+    )]
+    spoiler_text: Option<String>,
+    #[arg(long, value_parser = config::parse_language, help = "ISO 639 language code, such as en")]
+    language: Option<Language>,
+    #[arg(long, help = "ID of the status to reply to")]
+    in_reply_to_id: Option<String>,
+    #[arg(
+        long,
+        value_parser = config::parse_time,
+        help = "Have the instance post it at this time, such as 2025-12-01T09:00:00+01:00"
+    )]
+    schedule_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl PostFlags {
+    fn options(self) -> config::PostOptions {
+        config::PostOptions {
+            visibility: self.visibility,
+            sensitive: self.sensitive.then_some(true),
+            spoiler_text: self.spoiler_text,
+            language: self.language,
+            in_reply_to_id: self.in_reply_to_id,
+            schedule_at: self.schedule_at,
+            schedule_before: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+enum OutputFormat {
+    /// For people: one line per event, and progress messages.
+    #[default]
+    Text,
+    /// As JSON, in the layouts described in the README.
+    Json,
+}
+
+#[derive(Clone, Subcommand)]
+enum ConfigCommands {
+    #[command(about = "Upgrade the configuration file to the current layout, keeping a backup")]
+    Migrate {},
+}
+
+#[derive(Clone, Subcommand)]
+enum ServiceCommands {
+    #[command(about = "Install the daemon as a service and start it")]
+    Install {
+        #[arg(long, default_value = service::DEFAULT_NAME, help = "Name of the service")]
+        name: String,
+    },
+    #[command(about = "Stop the service and remove it")]
+    Uninstall {
+        #[arg(long, default_value = service::DEFAULT_NAME, help = "Name of the service")]
+        name: String,
+    },
+    /// What Windows starts the service with.
+    #[command(hide = true)]
+    Run {
+        #[arg(long, default_value = service::DEFAULT_NAME)]
+        name: String,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum TemplateCommands {
+    #[command(about = "Check a template's syntax and variables, and render it with a sample event")]
+    Check {
+        #[arg(help = "File holding the template")]
+        file: String,
+        #[arg(
+            long,
+            default_value = "next",
+            value_parser = clap::builder::PossibleValuesParser::new(
+                template_check::KINDS.iter().map(|(kind, _)| *kind)
+            ),
+            help = "What the template is for"
+        )]
+        kind: String,
     },
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
+    init_logging(cli.verbose);
+    let config_path = cli.config.as_ref().unwrap();
+
+    let (action, result) = run(config_path, cli.feed.as_deref(), cli.flags, cli.command).await;
+    if let Some(path) = &cli.summary_json
+        && let Err(e) = write_summary(path, &result)
+    {
+        eprintln!(
+            "Warning: could not write the run summary to {}: {}",
+            path, e
+        );
+    }
+    if let Err(e) = result {
+        eprintln!("Error {}: {}", action, e);
+        std::process::exit(1);
+    }
+}
+
+/// Runs a command, returning what it was doing (for error messages) and how
+/// it went.
+async fn run(
+    config_path: &str,
+    feed: Option<&str>,
+    flags: RunFlags,
+    command: Option<Commands>,
+) -> (&'static str, Result<(), Box<dyn std::error::Error>>) {
+    // Migration has to work on files the current layout can't load
+    if let Some(Commands::Config {
+        command: ConfigCommands::Migrate {},
+    }) = command
+    {
+        return ("migrating configuration", migrate_config(config_path));
+    }
+    // Templates can be checked before they are in a configuration
+    if let Some(Commands::Template {
+        command: TemplateCommands::Check { file, kind },
+    }) = &command
+    {
+        return (
+            "checking template",
+            check_template(config_path, file, kind).await,
+        );
+    }
+
+    // The configuration is one of the files being restored
+    if let Some(Commands::Restore { bundle, force }) = &command {
+        return ("restoring", restore(bundle, *force));
+    }
+    // Each tenant has a configuration of its own
+    if let Some(Commands::Daemon {
+        tenants: Some(dir),
+        pace_seconds,
+    }) = &command
+    {
+        JSON_OUTPUT.store(flags.json(), Ordering::Relaxed);
+        return (
+            "running daemon",
+            tenant_daemon(dir, feed, flags, *pace_seconds).await,
+        );
+    }
 
     // Load configuration file (will use default "bot.toml" if not specified)
-    let config = match config::load_config(cli.config.as_ref().unwrap()) {
+    let config = match config::load_config(config_path) {
         Ok(config) => config,
-        Err(e) => {
-            eprintln!("Error loading configuration: {}", e);
-            std::process::exit(1);
+        Err(e) => return ("loading configuration", Err(e.into())),
+    };
+
+    let expanded = match command {
+        Some(Commands::Alias(args)) => expand_command(&config, Some(args)),
+        None => expand_command(&config, None),
+        Some(command) => Ok((command, RunFlags::default())),
+    };
+    let (command, flags) = match expanded {
+        Ok((command, more)) => (command, flags.and(more)),
+        Err(e) => return ("expanding command", Err(e)),
+    };
+    JSON_OUTPUT.store(flags.json(), Ordering::Relaxed);
+    let config = config.with_dry_run(flags.dry_run);
+    if flags.dry_run {
+        say!("Dry run: nothing is posted and the state file is left as it is");
+    }
+
+    // The event list is the output of `events`
+    let listing = matches!(command, Commands::Events { .. });
+    let (action, result) = match command {
+        Commands::Daemon { .. } => (
+            "running daemon",
+            daemon(config_path, &config, feed, flags).await,
+        ),
+        Commands::Service { command } => (
+            "managing service",
+            manage_service(config_path, &config, feed, flags, command).await,
+        ),
+        command => execute_feeds(config_path, &config, feed, command).await,
+    };
+    if !listing && let Err(e) = report_sent(flags, &result) {
+        eprintln!("Warning: could not print what was posted: {}", e);
+    }
+    if flags.dry_run {
+        check_sent_emoji(&config).await;
+    }
+    (action, result)
+}
+
+/// Warns about the custom emoji in what a dry run would have posted that
+/// the instance it would have gone to doesn't have.
+async fn check_sent_emoji(config: &config::Config) {
+    for (host, unknown) in emoji::unknown_in_sent(config, &outbox::sent()).await {
+        warn_unknown_emoji(&host, unknown);
+    }
+}
+
+fn warn_unknown_emoji(instance: &str, unknown: anyhow::Result<Vec<String>>) {
+    match unknown {
+        Ok(codes) => {
+            for code in codes {
+                eprintln!(
+                    "Warning: custom emoji :{}: does not exist on {} and will appear as plain text",
+                    code, instance
+                );
+            }
         }
+        Err(e) => eprintln!(
+            "Warning: could not fetch the custom emoji of {}: {}",
+            instance, e
+        ),
+    }
+}
+
+/// Prints what the run posted, or would have in a dry run: as JSON with
+/// `--output json`, along with the events it picked and left out, or else
+/// the text of each status in a dry run.
+fn report_sent(
+    flags: RunFlags,
+    result: &Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if flags.json() {
+        let report = serde_json::json!({
+            "success": result.is_ok(),
+            "error": result.as_ref().err().map(|e| e.to_string()),
+            "dry_run": flags.dry_run,
+            "selected": outbox::selected(),
+            "statuses": outbox::sent(),
+            "skipped": skips::summary(),
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if flags.dry_run {
+        for sent in outbox::sent() {
+            match sent.action {
+                outbox::Action::Post => println!("\nWould post to {}:", sent.target),
+                outbox::Action::Edit => {
+                    println!("\nWould edit status {} on {} to:", sent.id, sent.target)
+                }
+                outbox::Action::Delete => {
+                    println!("\nWould delete status {} on {}", sent.id, sent.target);
+                    continue;
+                }
+                outbox::Action::Schedule => println!(
+                    "\nWould schedule for {} on {}:",
+                    sent.scheduled_at
+                        .map(|at| at.to_rfc3339())
+                        .unwrap_or_default(),
+                    sent.target
+                ),
+                outbox::Action::Cancel => {
+                    println!(
+                        "\nWould cancel scheduled status {} on {}",
+                        sent.id, sent.target
+                    );
+                    continue;
+                }
+            }
+            println!("{}", sent.status.status.as_deref().unwrap_or_default());
+            if let Some(poll) = &sent.poll {
+                for option in &poll.options {
+                    println!("  ( ) {}", option);
+                }
+            }
+            if let Some(media) = &sent.media {
+                println!("[attached: {}]", media);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs a command for each feed `--feed` selects, going on with the others
+/// when one fails.
+async fn execute_feeds(
+    config_path: &str,
+    config: &config::Config,
+    feed: Option<&str>,
+    command: Commands,
+) -> (&'static str, Result<(), Box<dyn std::error::Error>>) {
+    let feeds = match config.select_feeds(feed) {
+        Ok(feeds) => feeds,
+        Err(e) => return ("selecting feed", Err(e.into())),
     };
+    if let [(_, config)] = feeds.as_slice() {
+        return Box::pin(execute(config_path, config, command)).await;
+    }
 
-    match cli.command {
+    let mut failed = 0;
+    for (name, config) in feeds {
+        let label = match name {
+            Some(name) => format!("feed '{}'", name),
+            None => "the main calendar".to_string(),
+        };
+        say!("Running for {}", label);
+        let (action, result) = Box::pin(execute(config_path, &config, command.clone())).await;
+        if let Err(e) = result {
+            eprintln!("Error {} for {}: {}", action, label, e);
+            failed += 1;
+        }
+    }
+    match failed {
+        0 => ("running feeds", Ok(())),
+        failed => (
+            "running feeds",
+            Err(format!("{} feed(s) failed", failed).into()),
+        ),
+    }
+}
+
+/// Runs a command with the loaded configuration.
+async fn execute(
+    config_path: &str,
+    config: &config::Config,
+    command: Commands,
+) -> (&'static str, Result<(), Box<dyn std::error::Error>>) {
+    match command {
         Commands::Register {
             client_name,
             redirect_uri,
             scopes,
             website,
+            auth_code,
+            timeout,
+            backup,
+            language,
         } => {
-            if let Err(e) = register(
-                &config,
-                &client_name,
-                redirect_uri.as_deref(),
-                Some(&scopes.join(" ")),
-                website.as_deref(),
+            let result = match registration::account(config, backup, language) {
+                Ok(account) => {
+                    registration::register(
+                        account,
+                        &client_name,
+                        redirect_uri.as_deref(),
+                        Some(&registration::requested_scopes(config, scopes)),
+                        website.as_deref(),
+                        auth_code.as_deref(),
+                        std::time::Duration::from_secs(timeout),
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            ("registering app", result.map_err(Into::into))
+        }
+        Commands::Login {
+            client_id,
+            client_secret,
+            access_token,
+            redirect_uri,
+            scopes,
+            auth_code,
+            timeout,
+            backup,
+            language,
+        } => {
+            let result = match (
+                registration::account(config, backup, language),
+                access_token,
+            ) {
+                (Ok(account), Some(token)) => {
+                    registration::save_access_token(account, &token).await
+                }
+                (Ok(account), None) => {
+                    registration::login(
+                        account,
+                        client_id.as_deref().unwrap_or_default(),
+                        client_secret.as_deref().unwrap_or_default(),
+                        &redirect_uri,
+                        &registration::requested_scopes(config, scopes),
+                        auth_code.as_deref(),
+                        std::time::Duration::from_secs(timeout),
+                    )
+                    .await
+                }
+                (Err(e), _) => Err(e),
+            };
+            ("logging in", result.map_err(Into::into))
+        }
+        Commands::Unregister {
+            force,
+            backup,
+            language,
+        } => {
+            let result = match registration::account(config, backup, language) {
+                Ok(account) => {
+                    let main = !backup && language.is_none();
+                    registration::unregister_account(config, account, main, force).await
+                }
+                Err(e) => Err(e),
+            };
+            ("unregistering", result.map_err(Into::into))
+        }
+        Commands::PostNext { count, post } => {
+            let config = &config.with_post_options(&post.options());
+            (
+                "posting next meeting",
+                posting_run(config, post_next_meeting(config, count)).await,
             )
-            .await
-            {
-                eprintln!("Error registering app: {}", e);
-                std::process::exit(1);
-            }
         }
-        Commands::PostNext {} => {
-            if let Err(e) = post_next_meeting(&config).await {
-                eprintln!("Error posting next meeting: {}", e);
-                std::process::exit(1);
-            }
+        Commands::PostAll { post } => {
+            let config = &config.with_post_options(&post.options());
+            (
+                "posting all upcoming meetings",
+                posting_run(config, post_all_upcoming_meetings(config)).await,
+            )
         }
-        Commands::PostAll {} => {
-            if let Err(e) = post_all_upcoming_meetings(&config).await {
-                eprintln!("Error posting all upcoming meetings: {}", e);
-                std::process::exit(1);
-            }
+        Commands::Backfill {
+            days_ahead,
+            concurrency,
+            interval,
+        } => {
+            let options = commands::Backfill {
+                days_ahead: days_ahead.into(),
+                concurrency,
+                interval: std::time::Duration::from_secs(interval),
+            };
+            (
+                "backfilling announcements",
+                posting_run(config, backfill(config, &options)).await,
+            )
         }
-        Commands::PostStatus { status } => {
-            if let Err(e) = post_status(&config, &status).await {
-                eprintln!("Error posting status: {}", e);
-                std::process::exit(1);
-            }
+        Commands::PostAgendaCall { update_changed } => (
+            "posting agenda calls",
+            posting_run(config, post_agenda_calls(config, update_changed)).await,
+        ),
+        Commands::PostMinutes {} => (
+            "posting minutes follow-ups",
+            posting_run(config, post_minutes(config)).await,
+        ),
+        Commands::PostReminders {} => (
+            "posting reminders",
+            posting_run(config, post_reminders(config)).await,
+        ),
+        Commands::RemindMe {} => (
+            "answering remind me replies",
+            posting_run(config, remind_me(config)).await,
+        ),
+        Commands::Sync {} => (
+            "syncing announcements",
+            posting_run(config, sync(config)).await,
+        ),
+        Commands::PostSchedulingPoll {} => (
+            "posting scheduling poll",
+            posting_run(config, post_scheduling_poll(config)).await,
+        ),
+        Commands::PostSchedulingPollResults {} => (
+            "posting scheduling poll results",
+            posting_run(config, post_scheduling_poll_results(config)).await,
+        ),
+        Commands::PostSubscriptionInfo {} => (
+            "posting subscription info",
+            posting_run(config, post_subscription_info(config)).await,
+        ),
+        Commands::Recap { last_week, days } => {
+            ("posting recap", post_recap(config, last_week, days).await)
+        }
+        Commands::SyncProfile {} => (
+            "syncing profile",
+            sync_profile(config).await.map_err(Into::into),
+        ),
+        Commands::Events {
+            upcoming,
+            days,
+            explain,
+        } => (
+            "listing events",
+            list_events(config, upcoming, days, explain).await,
+        ),
+        Commands::ValidateCalendar {} => ("validating calendar", validate_calendar(config).await),
+        Commands::ScheduleSuggest { format } => (
+            "suggesting schedules",
+            suggest_schedule(config_path, config, format).await,
+        ),
+        Commands::Daemon { .. } => (
+            "running daemon",
+            daemon(config_path, config, None, RunFlags::default()).await,
+        ),
+        Commands::Service { command } => (
+            "managing service",
+            manage_service(config_path, config, None, RunFlags::default(), command).await,
+        ),
+        Commands::Resume {} => ("leaving safe mode", resume(config)),
+        Commands::Mute { pattern, until } => {
+            ("muting event", mute(config, pattern, until.as_deref()))
+        }
+        Commands::Unmute { pattern } => ("unmuting event", unmute(config, &pattern)),
+        Commands::ListScheduled {} => (
+            "listing scheduled statuses",
+            list_scheduled(config).await.map_err(Into::into),
+        ),
+        Commands::CancelScheduled { id } => (
+            "cancelling scheduled status",
+            cancel_scheduled(config, &id).await.map_err(Into::into),
+        ),
+        Commands::Gc {} => ("collecting garbage", collect_garbage(config)),
+        Commands::Backup {
+            output,
+            include_secrets,
+        } => (
+            "backing up",
+            backup(config_path, config, &output, include_secrets),
+        ),
+        Commands::Restore { bundle, force } => ("restoring", restore(&bundle, force)),
+        Commands::Links { uid } => ("listing links", print_links(config, &uid)),
+        Commands::Config { .. } => ("migrating configuration", migrate_config(config_path)),
+        Commands::Template {
+            command: TemplateCommands::Check { file, kind },
+        } => (
+            "checking template",
+            check_template(config_path, &file, &kind).await,
+        ),
+        Commands::Alias(_) => unreachable!("expanded above"),
+        Commands::PostStatus { status, post } => {
+            let config = &config.with_post_options(&post.options());
+            (
+                "posting status",
+                post_status(config, &status).await.map_err(Into::into),
+            )
         }
     }
 }
 
-async fn register(
+/// The command an alias stands for, with the arguments given after it, or
+/// without one the configured default command, and the flags it gives.
+fn expand_command(
     config: &config::Config,
-    client_name: &str,
-    redirect_uri: Option<&str>,
-    scopes: Option<&str>,
-    website: Option<&str>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    use mastodon_async::Registration;
-
-    let mut registration = Registration::new(&config.instance);
-    registration.client_name(client_name);
+    args: Option<Vec<String>>,
+) -> Result<(Commands, RunFlags), Box<dyn std::error::Error>> {
+    let expanded = match args {
+        None => config
+            .default_command
+            .as_ref()
+            .ok_or("no command given and no default_command configured; see --help")?
+            .args(),
+        Some(args) => {
+            let (name, rest) = args.split_first().ok_or("empty command")?;
+            let alias = config
+                .aliases
+                .get(name)
+                .ok_or_else(|| format!("unrecognized command or alias '{}'", name))?;
+            alias
+                .args()
+                .into_iter()
+                .chain(rest.iter().cloned())
+                .collect()
+        }
+    };
 
-    if let Some(uri) = redirect_uri {
-        registration.redirect_uris(uri);
+    let expanded =
+        Expanded::try_parse_from(std::iter::once("ical-to-masto".to_string()).chain(expanded))?;
+    match expanded.command {
+        Commands::Alias(args) => Err(format!(
+            "'{}' is not a command; aliases can't refer to other aliases",
+            args[0]
+        )
+        .into()),
+        command => Ok((command, expanded.flags)),
     }
+}
 
-    if let Some(scope_str) = scopes {
-        use mastodon_async::prelude::Scopes;
-        let scopes = Scopes::from_str(scope_str)?;
-        registration.scopes(scopes);
+/// The command a `[[schedule]]` entry runs, which may be an alias, and the
+/// flags it gives.
+fn job_command(
+    config: &config::Config,
+    args: &[String],
+) -> Result<(Commands, RunFlags), Box<dyn std::error::Error>> {
+    let parsed = Expanded::try_parse_from(
+        std::iter::once("ical-to-masto").chain(args.iter().map(String::as_str)),
+    )?;
+    let (command, flags) = match parsed.command {
+        Commands::Alias(args) => {
+            let (command, flags) = expand_command(config, Some(args))?;
+            (command, parsed.flags.and(flags))
+        }
+        command => (command, parsed.flags),
+    };
+    if let Commands::Daemon { .. } | Commands::Service { .. } = command {
+        return Err("the daemon can't schedule itself".into());
     }
+    Ok((command, flags))
+}
 
-    if let Some(website_url) = website {
-        registration.website(website_url);
+/// The schedule of `config`, once its commands are checked to run, saying
+/// when each first does.
+fn schedule<'a>(
+    tenant: Option<&'a str>,
+    config_path: &'a str,
+    config: &'a config::Config,
+    feed: Option<&str>,
+) -> Result<daemon::Scheduled<'a>, Box<dyn std::error::Error>> {
+    let scheduled = daemon::Scheduled::new(tenant, config_path, config, feed)?;
+    let now = chrono::Utc::now();
+    let scheduler = &scheduled.scheduler;
+    for refresh in &scheduler.refreshes {
+        say!(
+            "Fetching {} every {} minutes",
+            scheduled.label(refresh.name()),
+            refresh.every.num_minutes()
+        );
     }
+    for job in &scheduler.jobs {
+        job_command(config, &job.args)
+            .map_err(|e| format!("in the schedule for '{}': {}", job.name(), e))?;
+        let name = scheduled.label(&format!("'{}'", job.name()));
+        match job.next_fire(now, scheduler.timezone) {
+            Some(next) => say!(
+                "Next run of {}: {}",
+                name,
+                next.with_timezone(&scheduler.timezone)
+            ),
+            None => say!("{} is never due", name),
+        }
+    }
+    Ok(scheduled)
+}
 
-    let app = registration.build().await?;
+/// Runs what the daemon has due for the feeds `feed` selects, each command
+/// with `flags` and those of its schedule.
+struct Jobs<'a> {
+    feed: Option<&'a str>,
+    flags: RunFlags,
+}
 
-    println!("Application registered successfully!");
+impl daemon::Runner for Jobs<'_> {
+    /// Refreshes a calendar or runs a command, reporting what fails. Each
+    /// starts with nothing recorded, so what it reports, probes and checks is
+    /// its own and its tenant's, not an earlier one's.
+    async fn run(&self, scheduled: &daemon::Scheduled<'_>, due: daemon::Due<'_>) {
+        scope::job(async {
+            match due {
+                daemon::Due::Refresh(refresh) => {
+                    tracing::debug!(calendar = refresh.name(), "refreshing calendar");
+                    let Some(config) = scheduled.feed_config(refresh) else {
+                        return;
+                    };
+                    if let Err(e) = refresh_calendar(config).await {
+                        eprintln!(
+                            "Warning: could not refresh {}: {}",
+                            scheduled.label(refresh.name()),
+                            e
+                        );
+                    }
+                }
+                daemon::Due::Job(job) => {
+                    tracing::debug!(job = job.name(), "running scheduled command");
+                    let (action, result) = self.run_job(scheduled, job).await;
+                    if let Err(e) = result {
+                        let name = scheduled.label(&format!("'{}'", job.name()));
+                        eprintln!("Error {} for {}: {}", action, name, e);
+                    }
+                }
+            }
+        })
+        .await
+    }
+}
 
-    match app.authorize_url() {
-        Ok(authorize_url) => {
-            println!("\nPlease open this URL in your browser to authorize the application:");
-            println!("{}", authorize_url);
+impl Jobs<'_> {
+    /// Runs a scheduled command and prints what it posted.
+    async fn run_job(
+        &self,
+        scheduled: &daemon::Scheduled<'_>,
+        job: &daemon::Job,
+    ) -> (&'static str, Result<(), Box<dyn std::error::Error>>) {
+        let (command, flags) = match job_command(scheduled.config, &job.args) {
+            Ok((command, job_flags)) => (command, self.flags.and(job_flags)),
+            Err(e) => return ("expanding command", Err(e)),
+        };
+        JSON_OUTPUT.store(flags.json(), Ordering::Relaxed);
+        let config = scheduled.config.with_dry_run(flags.dry_run);
+        let listing = matches!(command, Commands::Events { .. });
+        let (action, result) = Box::pin(execute_feeds(
+            scheduled.config_path,
+            &config,
+            self.feed,
+            command,
+        ))
+        .await;
+        if !listing && let Err(e) = report_sent(flags, &result) {
+            eprintln!("Warning: could not print what was posted: {}", e);
+        }
+        (action, result)
+    }
+}
 
-            println!("\nAfter authorizing, paste the authorization code here:");
-            let mut code = String::new();
-            std::io::stdin().read_line(&mut code)?;
-            let code = code.trim();
+/// Runs the `[[schedule]]` commands at their times, one after another, until
+/// stopped, for the feeds `feed` selects. A command that fails is reported and
+/// tried again at its next time. Each runs with `flags` and those of its
+/// schedule.
+async fn daemon(
+    config_path: &str,
+    config: &config::Config,
+    feed: Option<&str>,
+    flags: RunFlags,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let scheduled = schedule(None, config_path, config, feed)?;
+    let jobs = Jobs { feed, flags };
+    Ok(daemon::run_schedules(&[scheduled], std::time::Duration::ZERO, &jobs).await?)
+}
+
+/// Runs the schedules of every tenant in `dir` like [`daemon`] runs one.
+/// What several tenants have due at once is run in turns, `pace_seconds`
+/// apart, with another tenant going first each time.
+async fn tenant_daemon(
+    dir: &str,
+    feed: Option<&str>,
+    flags: RunFlags,
+    pace_seconds: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenants = tenants::load(dir)?;
+    let mut scheduled = Vec::new();
+    for tenant in &tenants {
+        let name = Some(tenant.name.as_str());
+        match schedule(name, &tenant.config_path, &tenant.config, feed) {
+            Ok(tenant) => scheduled.push(tenant),
+            Err(e) => eprintln!("Warning: leaving out the tenant {}: {}", tenant.name, e),
+        }
+    }
+    if scheduled.is_empty() {
+        return Err(format!("none of the tenants in {} has a schedule to run", dir).into());
+    }
+    say!("Running for {} tenant(s)", scheduled.len());
+    let pace = std::time::Duration::from_secs(pace_seconds);
+    let jobs = Jobs { feed, flags };
+    Ok(daemon::run_schedules(&scheduled, pace, &jobs).await?)
+}
 
-            match app.complete(code).await {
-                Ok(mastodon) => {
-                    println!("Authentication successful!");
+/// Fetches a calendar into its cache, asking the server even while the
+/// cached copy is fresh.
+async fn refresh_calendar(config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = run::load_state(config)?;
+    let source = WebcalSource::for_config(config, &state).revalidating();
+    source.fetch().await?;
+    Ok(run::remember_resolved_url(config, &mut state, &source)?)
+}
 
-                    // Save the authenticated data
-                    let token_data = mastodon.data.clone();
-                    config::save_token(config, &token_data)?;
-                }
-                Err(e) => {
-                    println!("Error completing authentication: {}", e);
-                    println!("You may need to use the 'login' command with client credentials.");
-                }
+/// Installs, removes or, when Windows starts it, runs the daemon as a
+/// service of the operating system.
+async fn manage_service(
+    config_path: &str,
+    config: &config::Config,
+    feed: Option<&str>,
+    flags: RunFlags,
+    command: ServiceCommands,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        ServiceCommands::Install { name } => {
+            // A daemon that can't start would only fail over and over
+            daemon::Scheduler::new(config)?;
+            let service = service::Service::new(&name, config_path, feed)?;
+            let installed = service.install()?;
+            say!("Installed and started {}", installed);
+        }
+        ServiceCommands::Uninstall { name } => {
+            service::Service::new(&name, config_path, feed)?.uninstall()?;
+            say!("Removed the service {}", name);
+        }
+        #[cfg(windows)]
+        ServiceCommands::Run { name } => {
+            // Services start in the system directory
+            let service = service::Service::new(&name, config_path, feed)?;
+            std::env::set_current_dir(service.working_directory())?;
+            let daemon = daemon(config_path, config, feed, flags);
+            if let Some(result) = service::run_windows_service(&name, daemon).await? {
+                return result;
             }
         }
-        Err(e) => {
-            println!("Error generating authorize URL: {}", e);
-            println!("Use the 'login' command with the client credentials to authenticate.");
+        #[cfg(not(windows))]
+        ServiceCommands::Run { .. } => {
+            let _ = flags;
+            return Err("'service run' is only for Windows to start the service with".into());
         }
     }
+    Ok(())
+}
 
+/// Sends this crate's debug output, such as phase timings, to stderr when
+/// asked to. Everything else only logs warnings, which are printed anyway.
+fn init_logging(verbose: bool) {
+    let level = if verbose {
+        tracing::Level::DEBUG
+    } else {
+        tracing::Level::WARN
+    };
+    let filter = tracing_subscriber::filter::Targets::new().with_target("ical_to_masto", level);
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(filter)
+        .init();
+}
+
+/// Writes whether the run succeeded and how long each phase took as JSON, to
+/// a file or, for `-`, to stdout.
+fn write_summary(
+    path: &str,
+    result: &Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let summary = serde_json::json!({
+        "success": result.is_ok(),
+        "error": result.as_ref().err().map(|e| e.to_string()),
+        "phases": timing::summary(),
+        "skipped": skips::summary(),
+        "probes": probe::summary(),
+    });
+    let json = serde_json::to_string_pretty(&summary)?;
+    if path == "-" {
+        println!("{}", json);
+    } else {
+        std::fs::write(path, json + "\n")?;
+    }
     Ok(())
 }
 
-async fn post_next_meeting(config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
-    use mastodon_async::{Mastodon, NewStatus};
+fn migrate_config(config_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let report = migrate::migrate_file(config_path)?;
 
-    let data = config::load_token(config)?;
-    let mastodon = Mastodon::from(data);
+    for warning in &report.warnings {
+        eprintln!("Warning: {}", warning);
+    }
 
-    // Load calendar from webcal URL
-    let calendar = IcalCalendar::from_url(&config.webcal).await?;
+    match report.backup_path {
+        Some(backup_path) => {
+            for step in &report.applied {
+                println!("Applied migration {}", step);
+            }
+            println!("Configuration upgraded: {}", config_path);
+            println!("Previous version saved to: {}", backup_path);
+        }
+        None => println!("Configuration is already up to date: {}", config_path),
+    }
 
-    // Get current time in iCal format
-    let current_time = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    Ok(())
+}
 
-    // Get upcoming events (limit to 1 for next meeting)
-    let upcoming_events = calendar.get_upcoming_events_limited(&current_time, Some(1));
+/// Checks a template and prints it rendered with a sample event, and when
+/// the configuration can be loaded, the custom emoji in it that its instance
+/// doesn't have.
+async fn check_template(
+    config_path: &str,
+    file: &str,
+    kind: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = std::fs::read_to_string(file)?;
+    let report = template_check::check(&source, kind)?;
 
-    let status = if let Some(event) = upcoming_events.first() {
-        // Format meeting details
-        let summary = event.summary.as_deref().unwrap_or("Meeting");
-        let location = event.location.as_deref().unwrap_or("Location TBD");
-        let start_time = event
-            .start_time_formatted()
-            .unwrap_or("Time TBD".to_string());
-        let event_url = event.url.as_deref();
+    for (name, suggestion) in &report.unknown {
+        match suggestion {
+            Some(suggestion) => eprintln!(
+                "Unknown variable '{}' for a {} template (did you mean '{}'?)",
+                name, kind, suggestion
+            ),
+            None => eprintln!("Unknown variable '{}' for a {} template", name, kind),
+        }
+    }
+    println!("Rendered with a sample event:\n");
+    println!("{}", report.rendered);
+    if let Ok(config) = config::load_config(config_path)
+        && config.network == config::Network::Mastodon
+    {
+        let texts = [report.rendered.as_str()];
+        let unknown =
+            emoji::unknown_on_instance(&config.instance, Some(&config.url_policy), &texts).await;
+        warn_unknown_emoji(&config.instance, unknown);
+    }
 
-        if let Some(url) = event_url {
-            format!(
-                "📅 Next Meeting: {}\n📍 {}\n🕒 {}\n🔗 {}",
-                summary, location, start_time, url
-            )
-        } else {
+    if report.unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} unknown variable(s) in {}", report.unknown.len(), file).into())
+    }
+}
+
+/// Tells what a posting run posted before its command ran, and how many
+/// statuses the command held back until quiet hours are over.
+fn report_run<T>(run: &Run<T>) {
+    for posted in &run.caught_up {
+        say!("Caught up on the primary instance");
+        print_posted(posted);
+    }
+    for posted in &run.released {
+        say!("Posted a status held back during quiet hours");
+        print_posted(posted);
+    }
+    if run.held_back > 0 {
+        say!(
+            "Quiet hours: {} status(es) held back until they are over",
+            run.held_back
+        );
+    }
+}
+
+/// Runs a posting command as a run that counts towards [safe mode], telling
+/// when the bot is in it and only fetched the calendar.
+///
+/// [safe mode]: config::SafeModeConfig
+async fn posting_run(
+    config: &config::Config,
+    post: impl Future<Output = ical_to_masto::Result<()>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Outcome::SafeMode { since, events } = run::tracked(&SystemClock, config, post).await? {
+        say!(
+            "Safe mode since {}: fetched {} event(s) without posting; run 'resume' once the problem is fixed",
+            since.map(|since| since.to_rfc3339()).unwrap_or_default(),
+            events
+        );
+    }
+    Ok(())
+}
+
+async fn list_events(
+    config: &config::Config,
+    upcoming: bool,
+    days: Option<i64>,
+    explain: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = run::load_state(config)?;
+    let source = WebcalSource::for_config(config, &state);
+    let clock = SystemClock;
+    let events = commands::list_events(&source, &clock, config, &mut state, upcoming, days).await?;
+    run::remember_resolved_url(config, &mut state, &source)?;
+    let filter = commands::event_filter(config, clock.now())?;
+    let reasons: Vec<_> = events
+        .iter()
+        .map(|event| match explain {
+            true => commands::explain(config, &state, filter.as_ref(), event, clock.now()),
+            false => None,
+        })
+        .collect();
+
+    match JSON_OUTPUT.load(Ordering::Relaxed) {
+        true => {
+            let mut list = export::EventList::new(&events, clock.now());
+            for (event, reason) in list.events.iter_mut().zip(reasons) {
+                event.skip_reason = reason;
+            }
+            println!("{}", serde_json::to_string_pretty(&list)?);
+        }
+        false => {
+            for (event, reason) in events.iter().zip(reasons) {
+                let skipped = reason
+                    .map(|reason| format!("  (skipped: {})", reason.name()))
+                    .unwrap_or_default();
+                println!(
+                    "{}  {}{}",
+                    event.start_time_formatted().unwrap_or_default(),
+                    formatter::isolate(event.summary.as_deref().unwrap_or("(no summary)")),
+                    skipped
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn validate_calendar(config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = run::load_state(config)?;
+    let source = WebcalSource::for_config(config, &state);
+    let calendar = source.fetch().await?;
+    run::remember_resolved_url(config, &mut state, &source)?;
+
+    let problems = validate::check(&calendar);
+    for problem in &problems {
+        println!("Warning: {}: {}", problem.event, problem.message);
+    }
+    if problems.is_empty() {
+        println!("No problems found in {} event(s)", calendar.events.len());
+    }
+    Ok(())
+}
+
+async fn suggest_schedule(
+    config_path: &str,
+    config: &config::Config,
+    format: TimerFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = run::load_state(config)?;
+    let source = WebcalSource::for_config(config, &state);
+    let events =
+        commands::list_events(&source, &SystemClock, config, &mut state, false, None).await?;
+    run::remember_resolved_url(config, &mut state, &source)?;
+
+    let found = cadence::suggest(config, &events)?;
+    if found.slots.is_empty() {
+        return Err("No timed meetings in the calendar to suggest schedules from".into());
+    }
+    let slots: Vec<_> = found
+        .slots
+        .iter()
+        .map(|slot| {
             format!(
-                "📅 Next Meeting: {}\n📍 {}\n🕒 {}",
-                summary, location, start_time
+                "{} at {} ({}x)",
+                slot.weekday,
+                slot.time.format("%H:%M"),
+                slot.meetings
             )
+        })
+        .collect();
+    println!(
+        "# {} meeting(s), mostly on {}, in {}",
+        found.meetings,
+        slots.join(", "),
+        found.timezone
+    );
+    let program = format!("ical-to-masto -c {}", config_path);
+    match format {
+        TimerFormat::Crontab => {
+            println!("CRON_TZ={}", found.timezone);
+            for suggestion in &found.suggestions {
+                println!("# {}", suggestion.note);
+                println!(
+                    "{}  {} {}",
+                    suggestion.crontab(),
+                    program,
+                    suggestion.command
+                );
+            }
         }
-    } else {
-        "📅 No upcoming meetings found".to_string()
-    };
+        TimerFormat::Systemd => {
+            for suggestion in &found.suggestions {
+                println!("# {}: {} {}", suggestion.note, program, suggestion.command);
+                println!("OnCalendar={}", suggestion.on_calendar(found.timezone));
+            }
+        }
+    }
+    Ok(())
+}
 
-    let new_status = NewStatus {
-        status: Some(status),
-        ..Default::default()
+fn resume(config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = run::load_state(config)?;
+    match commands::resume(&mut state)? {
+        true => println!("Left safe mode; the next runs post again"),
+        false => println!("Not in safe mode"),
+    }
+    Ok(())
+}
+
+fn mute(
+    config: &config::Config,
+    pattern: Option<String>,
+    until: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = run::load_state(config)?;
+    let Some(pattern) = pattern else {
+        if state.muted.is_empty() {
+            println!("Nothing is muted");
+        }
+        for mute in &state.muted {
+            match mute.until {
+                Some(until) => println!("{} until {}", mute.pattern, until.to_rfc3339()),
+                None => println!("{}", mute.pattern),
+            }
+        }
+        return Ok(());
     };
+    match commands::mute(&SystemClock, config, &mut state, &pattern, until)? {
+        Some(until) => println!("Muted {} until {}", pattern, until.to_rfc3339()),
+        None => println!("Muted {}", pattern),
+    }
+    Ok(())
+}
 
-    let posted_status = mastodon.new_status(new_status).await?;
+fn unmute(config: &config::Config, pattern: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = run::load_state(config)?;
+    commands::unmute(&mut state, pattern)?;
+    println!("Unmuted {}", pattern);
+    Ok(())
+}
 
-    println!("Next meeting posted successfully!");
-    println!("ID: {}", posted_status.id);
-    if let Some(url) = posted_status.url {
-        println!("URL: {}", url);
+fn print_posted(posted: &Posted) {
+    say!("ID: {}", posted.id);
+    if let Some(url) = &posted.url {
+        say!("URL: {}", url);
+    }
+    if let Some(at) = posted.scheduled_at {
+        say!("Scheduled for: {}", at.to_rfc3339());
     }
+}
 
+/// Runs a `post-next` or `post-all` announcement for the main account, then
+/// for each language account.
+async fn for_each_account<F: Future<Output = ical_to_masto::Result<()>>>(
+    config: &config::Config,
+    announce: impl Fn(config::Config) -> F,
+) -> ical_to_masto::Result<()> {
+    announce(config.clone()).await?;
+    for (index, account) in config.language_accounts.iter().enumerate() {
+        say!(
+            "Announcing for the '{}' account on {}",
+            account.language.to_639_3(),
+            account.instance
+        );
+        announce(config.for_language_account(index)).await?;
+    }
     Ok(())
 }
 
-async fn post_all_upcoming_meetings(
+async fn post_next_meeting(config: &config::Config, count: usize) -> ical_to_masto::Result<()> {
+    for_each_account(config, |config| async move {
+        let run = run::posting(
+            &SystemClock,
+            &config,
+            async |source, publisher, state| match count > 1 {
+                true => {
+                    commands::post_next_meetings(
+                        source,
+                        publisher,
+                        &SystemClock,
+                        &config,
+                        state,
+                        count,
+                    )
+                    .await
+                }
+                false => {
+                    commands::post_next_meeting(source, publisher, &SystemClock, &config, state)
+                        .await
+                }
+            },
+        )
+        .await?;
+
+        report_run(&run);
+        if let Some(posted) = &run.output {
+            say!("Next meeting posted successfully!");
+            print_posted(posted);
+        } else if run.held_back == 0 {
+            say!("The next meeting was already announced");
+        }
+        Ok(())
+    })
+    .await
+}
+
+async fn backfill(
     config: &config::Config,
-) -> Result<(), Box<dyn std::error::Error>> {
-    use mastodon_async::{Mastodon, NewStatus};
+    options: &commands::Backfill,
+) -> ical_to_masto::Result<()> {
+    for_each_account(config, |config| async move {
+        let run = run::posting(&SystemClock, &config, async |source, publisher, state| {
+            commands::backfill(
+                source,
+                publisher,
+                &SystemClock,
+                &config,
+                state,
+                options,
+                draw_progress,
+            )
+            .await
+        })
+        .await?;
 
-    let data = config::load_token(config)?;
-    let mastodon = Mastodon::from(data);
+        report_run(&run);
+        match run.output.len() {
+            0 if run.held_back == 0 => {
+                say!(
+                    "Every meeting in the next {} days was already announced",
+                    options.days_ahead
+                )
+            }
+            0 => {}
+            count => say!("Announced {} meeting(s)", count),
+        }
+        Ok(())
+    })
+    .await
+}
 
-    // Load calendar from webcal URL
-    let calendar = IcalCalendar::from_url(&config.webcal).await?;
+/// Draws a bar of how many of `total` announcements are done on a terminal.
+fn draw_progress(done: usize, total: usize) {
+    use std::io::IsTerminal;
 
-    // Get current time in iCal format
-    let current_time = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    const WIDTH: usize = 30;
+    if total == 0 || !std::io::stderr().is_terminal() {
+        return;
+    }
+    let filled = WIDTH * done / total;
+    eprint!(
+        "\r[{}{}] {}/{}",
+        "#".repeat(filled),
+        "-".repeat(WIDTH - filled),
+        done,
+        total
+    );
+    if done == total {
+        eprintln!();
+    }
+}
 
-    // Get all upcoming events (no limit)
-    let upcoming_events = calendar.get_upcoming_events(&current_time);
+async fn post_all_upcoming_meetings(config: &config::Config) -> ical_to_masto::Result<()> {
+    for_each_account(config, |config| async move {
+        let run = run::posting(&SystemClock, &config, async |source, publisher, state| {
+            commands::post_all_upcoming_meetings(source, publisher, &SystemClock, &config, state)
+                .await
+        })
+        .await?;
 
-    let status = if upcoming_events.is_empty() {
-        "📅 No upcoming meetings found".to_string()
-    } else {
-        let mut meeting_list = String::new();
-
-        for (i, event) in upcoming_events.iter().enumerate() {
-            // Format meeting details
-            let summary = event.summary.as_deref().unwrap_or("Meeting");
-            let location = event.location.as_deref().unwrap_or("Location TBD");
-            let start_time = event
-                .start_time_formatted()
-                .unwrap_or("Time TBD".to_string());
-
-            if i > 0 {
-                meeting_list.push_str("\n\n");
+        report_run(&run);
+        if let Some((first, replies)) = run.output.split_first() {
+            say!("Posted upcoming meetings status: {}", first.id);
+            if let Some(url) = &first.url {
+                say!("URL: {}", url);
+            }
+            if !replies.is_empty() {
+                say!("Replied to it with {} more status(es)", replies.len());
             }
+        }
+        Ok(())
+    })
+    .await
+}
 
-            let event_url = event.url.as_deref();
-            let meeting_line = if let Some(url) = event_url {
-                format!(
-                    "📅 {}\n📍 {}\n🕒 {}\n🔗 {}\n",
-                    summary, location, start_time, url
-                )
-            } else {
-                format!("📅 {}\n📍 {}\n🕒 {}\n", summary, location, start_time)
-            };
+async fn post_agenda_calls(
+    config: &config::Config,
+    update_changed: bool,
+) -> ical_to_masto::Result<()> {
+    let run = run::posting(&SystemClock, config, async |source, publisher, state| {
+        let posted =
+            commands::post_agenda_calls(source, publisher, &SystemClock, config, state).await?;
+        let edited = match update_changed {
+            true => {
+                commands::update_agenda_calls(source, publisher, &SystemClock, config, state)
+                    .await?
+            }
+            false => Vec::new(),
+        };
+        Ok((posted, edited))
+    })
+    .await?;
+
+    report_run(&run);
+    let (posted, edited) = &run.output;
+    if posted.is_empty() && run.held_back == 0 {
+        say!("No agenda calls due");
+    }
+    for posted in posted {
+        say!("Agenda call posted successfully!");
+        print_posted(posted);
+    }
+    for posted in edited {
+        say!("Agenda call updated to the current template");
+        print_posted(posted);
+    }
+    Ok(())
+}
+
+async fn post_minutes(config: &config::Config) -> ical_to_masto::Result<()> {
+    let run = run::posting(&SystemClock, config, async |source, publisher, state| {
+        commands::post_minutes(source, publisher, &SystemClock, config, state).await
+    })
+    .await?;
 
-            meeting_list.push_str(&meeting_line);
+    report_run(&run);
+    if run.output.is_empty() && run.held_back == 0 {
+        say!("No minutes follow-ups due");
+    }
+    for posted in &run.output {
+        say!("Minutes follow-up posted successfully!");
+        print_posted(posted);
+    }
+    Ok(())
+}
+
+async fn post_reminders(config: &config::Config) -> ical_to_masto::Result<()> {
+    for_each_account(config, |config| async move {
+        let run = run::posting(&SystemClock, &config, async |source, publisher, state| {
+            commands::post_reminders(source, publisher, &SystemClock, &config, state).await
+        })
+        .await?;
+
+        report_run(&run);
+        if run.output.is_empty() {
+            say!("No reminders due");
         }
+        for posted in &run.output {
+            say!("Reminder posted successfully!");
+            print_posted(posted);
+        }
+        Ok(())
+    })
+    .await
+}
 
-        format!(
-            "Upcoming Meetings ({}):\n\n{}",
-            upcoming_events.len(),
-            meeting_list
-        )
-    };
+async fn remind_me(config: &config::Config) -> ical_to_masto::Result<()> {
+    for_each_account(config, |config| async move {
+        let run = run::posting(&SystemClock, &config, async |source, publisher, state| {
+            commands::remind_me(source, publisher, &SystemClock, &config, state).await
+        })
+        .await?;
 
-    let new_status = NewStatus {
-        status: Some(status),
-        ..Default::default()
-    };
+        report_run(&run);
+        for (account, key) in &run.output.requested {
+            say!("@{} asked to be reminded of {}", account, key);
+        }
+        for account in &run.output.stopped {
+            say!("@{} asked for no more reminders", account);
+        }
+        if run.output.reminded.is_empty() {
+            say!("No reminders due");
+        }
+        for posted in &run.output.reminded {
+            say!("Reminder sent successfully!");
+            print_posted(posted);
+        }
+        Ok(())
+    })
+    .await
+}
 
-    let posted_status = mastodon.new_status(new_status).await?;
+async fn sync(config: &config::Config) -> ical_to_masto::Result<()> {
+    for_each_account(config, |config| async move {
+        let run = run::posting(&SystemClock, &config, async |source, publisher, state| {
+            commands::sync(source, publisher, &SystemClock, &config, state).await
+        })
+        .await?;
 
-    println!("Posted upcoming meetings status: {}", posted_status.id);
-    if let Some(url) = posted_status.url {
-        println!("URL: {}", url);
+        report_run(&run);
+        if run.output.is_empty() {
+            say!("Announcements are up to date");
+        }
+        for synced in &run.output {
+            match synced {
+                Synced::Edited(posted) => {
+                    say!("Announcement edited");
+                    print_posted(posted);
+                }
+                Synced::Deleted(id) => say!("Announcement {} deleted", id),
+                Synced::Corrected(posted) => {
+                    say!("Cancellation posted");
+                    print_posted(posted);
+                }
+            }
+        }
+        Ok(())
+    })
+    .await
+}
+
+async fn post_scheduling_poll(config: &config::Config) -> ical_to_masto::Result<()> {
+    let run = run::posting(&SystemClock, config, async |source, publisher, state| {
+        commands::post_scheduling_poll(source, publisher, &SystemClock, config, state).await
+    })
+    .await?;
+
+    report_run(&run);
+    match &run.output {
+        Some(posted) => {
+            say!("Scheduling poll posted successfully!");
+            print_posted(posted);
+        }
+        None if run.holding_back => {
+            say!("Holding posts back: the scheduling poll will be posted on a later run")
+        }
+        None => say!(
+            "No scheduling poll to post: it was already posted for these times, or fewer than two tentative events are upcoming"
+        ),
     }
+    Ok(())
+}
 
+async fn post_scheduling_poll_results(config: &config::Config) -> ical_to_masto::Result<()> {
+    let run = run::posting(&SystemClock, config, async |_, publisher, state| {
+        commands::post_scheduling_poll_results(publisher, &SystemClock, config, state).await
+    })
+    .await?;
+
+    report_run(&run);
+    if run.output.is_empty() {
+        if run.holding_back {
+            say!("Holding posts back: results will be announced on a later run");
+        } else {
+            say!("No scheduling poll results to announce");
+        }
+    }
+    for posted in &run.output {
+        say!("Scheduling poll result posted successfully!");
+        print_posted(posted);
+    }
     Ok(())
 }
 
-async fn post_status(
+async fn post_subscription_info(config: &config::Config) -> ical_to_masto::Result<()> {
+    let run = run::posting(&SystemClock, config, async |_, publisher, state| {
+        commands::post_subscription_info(publisher, &SystemClock, config, state).await
+    })
+    .await?;
+
+    report_run(&run);
+    match &run.output {
+        Some(posted) => {
+            say!("Subscription info posted successfully!");
+            print_posted(posted);
+        }
+        None if run.held_back == 0 => say!("Subscription info not due yet"),
+        None => {}
+    }
+    Ok(())
+}
+
+async fn post_recap(
     config: &config::Config,
-    status: &str,
+    last_week: bool,
+    days: i64,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use mastodon_async::{Mastodon, NewStatus};
+    let today = SystemClock
+        .now()
+        .with_timezone(&quiet::timezone(config)?)
+        .date_naive();
+    let period = if last_week {
+        Period::last_week(today, config.week_start, config.week_numbers)
+    } else {
+        Period::last_days(today, days)
+    };
 
-    let data = config::load_token(config)?;
-    let mastodon = Mastodon::from(data);
+    posting_run(config, async {
+        let run = run::posting(&SystemClock, config, async |source, publisher, state| {
+            commands::post_recap(source, publisher, &SystemClock, config, state, &period).await
+        })
+        .await?;
 
-    let new_status = NewStatus {
-        status: Some(status.to_string()),
-        ..Default::default()
-    };
+        report_run(&run);
+        match &run.output {
+            Some(posted) => {
+                say!("Recap of {} posted successfully!", period.label);
+                print_posted(posted);
+            }
+            None if run.held_back == 0 => {
+                say!("The recap of {} was already posted", period.label)
+            }
+            None => {}
+        }
+        Ok(())
+    })
+    .await
+}
+
+async fn sync_profile(config: &config::Config) -> ical_to_masto::Result<()> {
+    for_each_account(config, |config| async move {
+        let publisher = poster::account_publisher(&config)?;
+        let mut state = run::load_state(&config)?;
+        let source = WebcalSource::for_config(&config, &state);
+
+        let fields =
+            commands::sync_profile(&source, &publisher, &SystemClock, &config, &mut state).await?;
+        run::remember_resolved_url(&config, &mut state, &source)?;
+
+        match fields {
+            Some(fields) => {
+                if config.dry_run {
+                    say!("Would set the profile fields to:");
+                } else {
+                    say!("Profile fields updated:");
+                }
+                for field in fields {
+                    say!("  {}: {}", field.name, field.value);
+                }
+            }
+            None => say!("The profile is up to date"),
+        }
+        Ok(())
+    })
+    .await
+}
+
+async fn post_status(config: &config::Config, status: &str) -> ical_to_masto::Result<()> {
+    let run = run::posting(&SystemClock, config, async |_, publisher, state| {
+        commands::post_status(publisher, &SystemClock, config, state, status.to_string()).await
+    })
+    .await?;
+
+    report_run(&run);
+    if let Some(posted) = &run.output {
+        say!("Status posted successfully!");
+        print_posted(posted);
+    }
+    Ok(())
+}
 
-    let posted_status = mastodon.new_status(new_status).await?;
+async fn list_scheduled(config: &config::Config) -> ical_to_masto::Result<()> {
+    for_each_account(config, |config| async move {
+        let publisher = poster::account_publisher(&config)?;
+        let scheduled = publisher.scheduled_statuses().await?;
+        if scheduled.is_empty() {
+            println!("No scheduled statuses");
+        }
+        for scheduled in scheduled {
+            let text = scheduled.text.unwrap_or_default();
+            println!(
+                "{}  {}  {}",
+                scheduled.id,
+                scheduled.scheduled_at.to_rfc3339(),
+                text.lines().next().unwrap_or_default()
+            );
+        }
+        Ok(())
+    })
+    .await
+}
 
-    println!("Status posted successfully!");
-    println!("ID: {}", posted_status.id);
-    if let Some(url) = posted_status.url {
-        println!("URL: {}", url);
+async fn cancel_scheduled(config: &config::Config, id: &str) -> ical_to_masto::Result<()> {
+    let account = commands::scheduled_account(config, &run::load_state(config)?, id)?;
+    let publisher = poster::account_publisher(&account)?;
+    let mut state = run::load_state(&account)?;
+    commands::cancel_scheduled(&publisher, &mut state, id).await?;
+    println!("Cancelled scheduled status {}", id);
+    Ok(())
+}
+
+fn collect_garbage(config: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = run::load_state(config)?;
+    let collected = commands::collect_garbage(&SystemClock, config, &mut state)?;
+    if collected.is_empty() {
+        println!("Nothing to collect");
+        return Ok(());
     }
+    println!(
+        "{} {} posts, {} polls, {} expansions of recurring events and {} cached calendars from more than {} days ago",
+        if config.dry_run {
+            "Would forget"
+        } else {
+            "Forgot"
+        },
+        collected.posts,
+        collected.polls,
+        collected.occurrences,
+        collected.cached_calendars,
+        config.gc.clone().unwrap_or_default().retention_days
+    );
+    Ok(())
+}
 
+fn backup(
+    config_path: &str,
+    config: &config::Config,
+    output: &str,
+    include_secrets: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (bundle, left_out) = bundle::write(
+        config_path,
+        config,
+        output,
+        include_secrets,
+        SystemClock.now(),
+    )?;
+    for file in &bundle.files {
+        println!("Bundled {}", file.path);
+    }
+    if !left_out.is_empty() {
+        println!(
+            "Left out of the configuration: {}; add them back after restoring, or back up with --include-secrets",
+            left_out.join(", ")
+        );
+    }
+    println!("Wrote {}", output);
+    Ok(())
+}
+
+fn restore(path: &str, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    for path in bundle::restore(&bundle::read(path)?, force)? {
+        println!("Restored {}", path);
+    }
+    Ok(())
+}
+
+fn print_links(config: &config::Config, uid: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let state = run::load_state(config)?;
+    let posts = state.posts_about(uid);
+    if posts.is_empty() {
+        return Err(format!("Nothing has been posted about {}", uid).into());
+    }
+
+    for (kind, start, record) in posts {
+        println!("{} {}", kind, start);
+        if record.links.is_empty() {
+            // Posted before targets were recorded
+            println!("  {}", record.url.as_deref().unwrap_or(&record.status_id));
+        }
+        for (target, link) in &record.links {
+            println!(
+                "  {}: {}",
+                target,
+                link.url.as_deref().unwrap_or(&link.status_id)
+            );
+        }
+    }
     Ok(())
 }
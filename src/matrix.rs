@@ -0,0 +1,293 @@
+use crate::config::UrlPolicyConfig;
+use crate::error::{Error, Result};
+use crate::media::Media;
+use crate::outbox::{self, Action, Sent};
+use crate::poster::{
+    self, Mention, NewPoll, Posted, ProfileField, Publisher, RecentStatus, ScheduledStatus,
+    dry_run_id, publishing,
+};
+use crate::secrets;
+use crate::timing::{self, Phase};
+use crate::url_policy;
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use mastodon_async::NewStatus;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How long a message may be, in characters: well within the 64 KiB a
+/// Matrix event may take.
+pub const MAX_MESSAGE_CHARS: usize = 16_000;
+
+/// How many messages were sent so far in this process, telling their
+/// transaction ids apart.
+static TRANSACTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// What a Matrix token file holds.
+#[derive(Deserialize)]
+struct TokenFile {
+    access_token: String,
+}
+
+/// Reads the access token of a Matrix account from a JSON file like
+/// `{"access_token": "..."}`.
+pub fn load_token_file(path: &str) -> anyhow::Result<String> {
+    secrets::warn_if_exposed(Path::new(path));
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read the Matrix token file {}: {}", path, e))?;
+    let token: TokenFile = serde_json::from_str(&content)
+        .map_err(|e| anyhow!("Invalid Matrix token file {}: {}", path, e))?;
+    Ok(token.access_token)
+}
+
+/// Posts statuses as messages to a Matrix room. Replies become Matrix
+/// replies, edits replace the message and deletions redact it; polls,
+/// profile fields and scheduling have no counterpart.
+pub struct MatrixPublisher {
+    homeserver: String,
+    access_token: String,
+    room: String,
+    dry_run: bool,
+    /// Which URLs messages may be sent to.
+    url_policy: Option<UrlPolicyConfig>,
+}
+
+impl MatrixPublisher {
+    /// A publisher for `room` (an id like `!abc:example.org` or an alias
+    /// like `#meetings:example.org`) on the account of `access_token`.
+    pub fn new(homeserver: &str, access_token: &str, room: &str) -> Self {
+        MatrixPublisher {
+            homeserver: homeserver.trim_end_matches('/').to_string(),
+            access_token: access_token.to_string(),
+            room: room.to_string(),
+            dry_run: false,
+            url_policy: None,
+        }
+    }
+
+    /// Makes the publisher only pretend to send, edit and redact messages.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_url_policy(mut self, url_policy: Option<UrlPolicyConfig>) -> Self {
+        self.url_policy = url_policy;
+        self
+    }
+
+    /// The room, naming where messages went.
+    pub fn target(&self) -> String {
+        self.room.clone()
+    }
+
+    /// An authenticated request to a client API endpoint of the room, each
+    /// of `path` a segment of the URL after the room's, if the URL policy
+    /// allows it.
+    fn request(
+        &self,
+        method: reqwest::Method,
+        path: &[&str],
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        let mut url = reqwest::Url::parse(&self.homeserver)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid homeserver URL {}", self.homeserver))?
+            .extend(["_matrix", "client", "v3", "rooms", &self.room])
+            .extend(path);
+        Ok(url_policy::client(self.url_policy.as_ref(), url.as_str())?
+            .request(method, url)
+            .bearer_auth(&self.access_token))
+    }
+
+    /// Sends a message event to the room, returning its id.
+    async fn send_message(&self, content: &Value, action: &str) -> anyhow::Result<String> {
+        let request = self
+            .request(
+                reqwest::Method::PUT,
+                &["send", "m.room.message", &transaction_id()],
+            )?
+            .json(content);
+        let sent = poster::send(request, action).await?;
+        sent["event_id"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow!("Message without an event id in the response"))
+    }
+
+    fn posted(&self, event_id: String) -> Posted {
+        Posted {
+            url: Some(format!("https://matrix.to/#/{}/{}", self.room, event_id)),
+            id: event_id,
+            target: self.target(),
+            scheduled_at: None,
+        }
+    }
+
+    fn unsupported(what: &str) -> Error {
+        Error::Publish(anyhow!("Matrix rooms have no {}", what))
+    }
+}
+
+/// A transaction id the homeserver hasn't seen from this account, so a
+/// retried request isn't sent twice but a new one is.
+fn transaction_id() -> String {
+    let count = TRANSACTIONS.fetch_add(1, Ordering::Relaxed);
+    format!(
+        "ical-to-masto-{}-{}",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        count
+    )
+}
+
+/// The content of a text message with the text of `status`.
+fn message(status: &NewStatus) -> Value {
+    json!({
+        "msgtype": "m.text",
+        "body": status.status.clone().unwrap_or_default(),
+    })
+}
+
+impl Publisher for MatrixPublisher {
+    async fn publish(&self, status: NewStatus) -> Result<Posted> {
+        publishing(timing::time_async(Phase::Post, async {
+            let posted = match self.dry_run {
+                true => self.posted(dry_run_id()),
+                false => {
+                    let mut content = message(&status);
+                    if let Some(id) = &status.in_reply_to_id {
+                        content["m.relates_to"] = json!({ "m.in_reply_to": { "event_id": id } });
+                    }
+                    self.posted(self.send_message(&content, "sending message").await?)
+                }
+            };
+            outbox::record(Sent::new(Action::Post, &posted, &status, self.dry_run));
+            Ok(posted)
+        }))
+        .await
+    }
+
+    async fn publish_with_media(&self, status: NewStatus, media: Media) -> Result<Posted> {
+        eprintln!(
+            "Warning: posting to Matrix without {}, as images aren't sent there",
+            media.file_name
+        );
+        self.publish(status).await
+    }
+
+    async fn edit(&self, id: &str, status: NewStatus) -> Result<Posted> {
+        publishing(timing::time_async(Phase::Post, async {
+            if !self.dry_run {
+                // Clients without edits show the fallback body, marked with `*`
+                let new_content = message(&status);
+                let content = json!({
+                    "msgtype": "m.text",
+                    "body": format!("* {}", new_content["body"].as_str().unwrap_or_default()),
+                    "m.new_content": new_content,
+                    "m.relates_to": { "rel_type": "m.replace", "event_id": id },
+                });
+                self.send_message(&content, &format!("editing message {}", id))
+                    .await?;
+            }
+            let posted = self.posted(id.to_string());
+            outbox::record(Sent::new(Action::Edit, &posted, &status, self.dry_run));
+            Ok(posted)
+        }))
+        .await
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        publishing(timing::time_async(Phase::Post, async {
+            if !self.dry_run {
+                let request = self
+                    .request(reqwest::Method::PUT, &["redact", id, &transaction_id()])?
+                    .json(&json!({}));
+                poster::send(request, &format!("redacting message {}", id)).await?;
+            }
+            let deleted = self.posted(id.to_string());
+            outbox::record(Sent::new(
+                Action::Delete,
+                &deleted,
+                &NewStatus::default(),
+                self.dry_run,
+            ));
+            Ok(())
+        }))
+        .await
+    }
+
+    async fn publish_poll(&self, _status: NewStatus, _poll: NewPoll) -> Result<Posted> {
+        Err(Self::unsupported("polls"))
+    }
+
+    async fn poll_votes(&self, _id: &str) -> Result<Vec<u64>> {
+        Err(Self::unsupported("polls"))
+    }
+
+    async fn max_status_chars(&self) -> Result<Option<usize>> {
+        Ok(Some(MAX_MESSAGE_CHARS))
+    }
+
+    async fn profile_fields(&self) -> Result<Vec<ProfileField>> {
+        Err(Self::unsupported("profile fields"))
+    }
+
+    async fn set_profile_fields(&self, _fields: Vec<ProfileField>) -> Result<()> {
+        Err(Self::unsupported("profile fields"))
+    }
+
+    async fn schedule(
+        &self,
+        _status: NewStatus,
+        _media: Option<Media>,
+        _at: DateTime<Utc>,
+    ) -> Result<Posted> {
+        Err(Self::unsupported("scheduled messages"))
+    }
+
+    async fn scheduled_statuses(&self) -> Result<Vec<ScheduledStatus>> {
+        Ok(Vec::new())
+    }
+
+    async fn cancel_scheduled(&self, _id: &str) -> Result<()> {
+        Err(Self::unsupported("scheduled messages"))
+    }
+
+    async fn recent_statuses(&self, _limit: usize) -> Result<Vec<RecentStatus>> {
+        // Rooms have the messages of everyone, so none are looked through
+        Ok(Vec::new())
+    }
+
+    async fn set_pinned(&self, _id: &str, _pinned: bool) -> Result<()> {
+        Err(Self::unsupported("pinned messages"))
+    }
+
+    async fn mentions(&self, _since_id: Option<&str>) -> Result<Vec<Mention>> {
+        Err(Self::unsupported("mentions"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_url() {
+        let publisher = MatrixPublisher::new(
+            "https://matrix.example.org/",
+            "secret",
+            "#meetings:example.org",
+        );
+        let request = publisher
+            .request(reqwest::Method::PUT, &["redact", "$event/1", "txn"])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "https://matrix.example.org/_matrix/client/v3/rooms/%23meetings:example.org/redact/$event%2F1/txn"
+        );
+        assert_eq!(request.headers()["authorization"], "Bearer secret");
+    }
+}
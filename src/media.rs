@@ -0,0 +1,115 @@
+use crate::config::UrlPolicyConfig;
+use crate::ical::CalendarEvent;
+use crate::url_policy;
+use anyhow::{Result, anyhow};
+
+/// Largest event image attached by default (8 MiB), which every Mastodon
+/// release accepts.
+pub const DEFAULT_MAX_IMAGE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// A file to attach to a status.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Media {
+    pub data: Vec<u8>,
+    /// Name the file is uploaded as; its extension tells the instance the type.
+    pub file_name: String,
+    /// Alt text describing the file.
+    pub description: String,
+}
+
+/// The alt text of an event's image.
+pub fn alt_text(event: &CalendarEvent) -> String {
+    match &event.summary {
+        Some(summary) => format!("Image for {}", summary),
+        None => "Image for the meeting".to_string(),
+    }
+}
+
+/// The name of the file an image URL points to.
+pub fn file_name(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.path_segments()?.next_back().map(String::from))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "image".to_string())
+}
+
+/// Downloads a file, refusing it as soon as it turns out to be larger than
+/// `max_bytes`, or if `policy` doesn't allow its URL.
+pub async fn download(
+    url: &str,
+    max_bytes: u64,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<Vec<u8>> {
+    if let Some(policy) = policy {
+        url_policy::check(policy, &reqwest::Url::parse(url)?)?;
+    }
+    let mut response = url_policy::client_builder(policy)
+        .build()?
+        .get(url)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("HTTP error: {}", response.status()));
+    }
+    let too_large = || anyhow!("{} is larger than {} bytes", url, max_bytes);
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_bytes)
+    {
+        return Err(too_large());
+    }
+
+    // The length header may be missing or wrong, so count as well
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        data.extend_from_slice(&chunk);
+        if data.len() as u64 > max_bytes {
+            return Err(too_large());
+        }
+    }
+    Ok(data)
+}
+
+/// Downloads the image of an event, if it has one and images are enabled.
+pub async fn event_image(
+    event: &CalendarEvent,
+    max_bytes: u64,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<Option<Media>> {
+    let Some(url) = &event.image else {
+        return Ok(None);
+    };
+    if max_bytes == 0 {
+        return Ok(None);
+    }
+    Ok(Some(Media {
+        data: download(url, max_bytes, policy).await?,
+        file_name: file_name(url),
+        description: alt_text(event),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(
+            file_name("https://example.com/images/poster.png?size=large"),
+            "poster.png"
+        );
+        assert_eq!(file_name("https://example.com/"), "image");
+        assert_eq!(file_name("https://example.com"), "image");
+    }
+
+    #[test]
+    fn test_alt_text() {
+        let event = CalendarEvent {
+            summary: Some("Install Fest".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(alt_text(&event), "Image for Install Fest");
+    }
+}
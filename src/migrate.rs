@@ -0,0 +1,143 @@
+use anyhow::{Result, anyhow};
+use toml::{Table, Value};
+
+/// The configuration layout version written by this release.
+pub const CURRENT_VERSION: i64 = 1;
+
+/// A step upgrading a configuration from `from_version` to the next version.
+struct Migration {
+    from_version: i64,
+    description: &'static str,
+    apply: fn(&mut Table),
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    from_version: 0,
+    description: "Record the configuration layout version",
+    apply: |_| {},
+}];
+
+/// Keys that are still accepted but should no longer be used, with a hint on
+/// what replaces them. Dotted names refer to keys inside sections.
+const DEPRECATED_KEYS: &[(&str, &str)] = &[];
+
+/// The layout version of a configuration; files without one predate versioning.
+pub fn version(table: &Table) -> Result<i64> {
+    match table.get("config_version") {
+        None => Ok(0),
+        Some(Value::Integer(version)) => Ok(*version),
+        Some(_) => Err(anyhow!("config_version must be an integer")),
+    }
+}
+
+/// Upgrades the configuration in place to [`CURRENT_VERSION`], returning a
+/// description of each step applied.
+pub fn migrate(table: &mut Table) -> Result<Vec<String>> {
+    let mut version = version(table)?;
+    if version > CURRENT_VERSION {
+        return Err(anyhow!(
+            "Configuration version {} is newer than this release supports ({})",
+            version,
+            CURRENT_VERSION
+        ));
+    }
+
+    let mut applied = Vec::new();
+    while version < CURRENT_VERSION {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.from_version == version)
+            .ok_or_else(|| anyhow!("No migration from configuration version {}", version))?;
+        (migration.apply)(table);
+        version += 1;
+        table.insert("config_version".to_string(), Value::Integer(version));
+        applied.push(format!(
+            "{} -> {}: {}",
+            version - 1,
+            version,
+            migration.description
+        ));
+    }
+
+    Ok(applied)
+}
+
+/// Warnings about deprecated keys present in the configuration.
+pub fn deprecation_warnings(table: &Table) -> Vec<String> {
+    DEPRECATED_KEYS
+        .iter()
+        .filter(|(key, _)| lookup(table, key).is_some())
+        .map(|(key, hint)| format!("'{}' is deprecated: {}", key, hint))
+        .collect()
+}
+
+fn lookup<'a>(table: &'a Table, dotted_key: &str) -> Option<&'a Value> {
+    let mut parts = dotted_key.split('.');
+    let mut value = table.get(parts.next()?)?;
+    for part in parts {
+        value = value.as_table()?.get(part)?;
+    }
+    Some(value)
+}
+
+/// The outcome of migrating a configuration file.
+pub struct MigrationReport {
+    pub applied: Vec<String>,
+    pub warnings: Vec<String>,
+    /// Where the original file was copied to, if it was rewritten.
+    pub backup_path: Option<String>,
+}
+
+/// Migrates a configuration file in place, keeping a copy of the original next
+/// to it. Files that are already current are left untouched.
+pub fn migrate_file(path: &str) -> Result<MigrationReport> {
+    let content = std::fs::read_to_string(path)?;
+    let mut table: Table = toml::from_str(&content)?;
+
+    let applied = migrate(&mut table)?;
+    let warnings = deprecation_warnings(&table);
+
+    let backup_path = if applied.is_empty() {
+        None
+    } else {
+        let backup_path = format!("{}.bak", path);
+        std::fs::copy(path, &backup_path)?;
+        std::fs::write(path, toml::to_string_pretty(&table)?)?;
+        Some(backup_path)
+    };
+
+    Ok(MigrationReport {
+        applied,
+        warnings,
+        backup_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_migrate_legacy_config() {
+        let mut table: Table = toml::from_str(indoc! {r#"
+            instance = "https://mastodon.social"
+            webcal = "https://example.com/calendar.ics"
+        "#})
+        .unwrap();
+
+        assert_eq!(version(&table).unwrap(), 0);
+        let applied = migrate(&mut table).unwrap();
+        assert_eq!(applied.len(), 1);
+        assert_eq!(version(&table).unwrap(), CURRENT_VERSION);
+
+        // Migrating again is a no-op
+        assert!(migrate(&mut table).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_migrate_rejects_newer_config() {
+        let mut table: Table = toml::from_str("config_version = 999").unwrap();
+        assert!(migrate(&mut table).is_err());
+    }
+}
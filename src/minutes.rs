@@ -0,0 +1,118 @@
+use crate::config::MinutesConfig;
+use crate::formatter;
+use crate::ical::{CalendarEvent, IcalCalendar};
+use crate::state::State;
+use crate::template::{Context, Template};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+/// The state key kind used to remember which follow-ups were posted.
+pub const KIND: &str = "minutes";
+
+/// Default template for the follow-up post.
+pub const MINUTES_TEMPLATE: &str = "📝 Thanks to everyone who joined {#if summary}{summary}{else}today's meeting{/if}!\
+{#if minutes_url}\nMinutes will be published at {minutes_url}{else}\nMinutes will be published soon.{/if}";
+
+/// The selected events whose follow-up is due at `now` and hasn't been posted.
+///
+/// A follow-up is due `delay_hours` after the event ends and stays due for
+/// `window_hours` after that.
+pub fn due_events<'a>(
+    calendar: &'a IcalCalendar,
+    config: &MinutesConfig,
+    state: &State,
+    now: DateTime<Utc>,
+) -> Vec<&'a CalendarEvent> {
+    let latest_end = now - Duration::hours(config.delay_hours);
+    let earliest_end = latest_end - Duration::hours(config.window_hours);
+
+    calendar
+        .get_events_ended_between(
+            &earliest_end.format("%Y%m%dT%H%M%SZ").to_string(),
+            &latest_end.format("%Y%m%dT%H%M%SZ").to_string(),
+        )
+        .into_iter()
+        .filter(|event| event.summary_matches(&config.events))
+        .filter(|event| !state.is_posted(&State::key(KIND, event)))
+        .collect()
+}
+
+/// The template the follow-up is rendered from: the configured one, or the default.
+pub fn template_source(config: &MinutesConfig) -> &str {
+    config.template.as_deref().unwrap_or(MINUTES_TEMPLATE)
+}
+
+/// The variables of the follow-up for an event.
+pub fn context(config: &MinutesConfig, event: &CalendarEvent) -> Context {
+    let mut context = formatter::event_context(event);
+    context.set_opt_text("minutes_url", config.url.clone());
+    context
+}
+
+/// Renders the follow-up for an event using the configured template.
+pub fn render(config: &MinutesConfig, event: &CalendarEvent) -> Result<String> {
+    let template = Template::parse(template_source(config))?;
+    Ok(template.render(&context(config, event)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use indoc::indoc;
+
+    fn config() -> MinutesConfig {
+        MinutesConfig {
+            delay_hours: 1,
+            window_hours: 24,
+            url: Some("https://example.com/minutes".to_string()),
+            events: Vec::new(),
+            template: None,
+        }
+    }
+
+    #[test]
+    fn test_due_events() {
+        let calendar = IcalCalendar::parse_ical_content(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:last-month@example.com
+            DTSTART:20251105T190000Z
+            DTEND:20251105T210000Z
+            SUMMARY:Monthly Meeting
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:tonight@example.com
+            DTSTART:20251203T190000Z
+            DTEND:20251203T210000Z
+            SUMMARY:Monthly Meeting
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+        let state = State::default();
+
+        // Not due until an hour after the meeting ends
+        let now = Utc.with_ymd_and_hms(2025, 12, 3, 21, 30, 0).unwrap();
+        assert!(due_events(&calendar, &config(), &state, now).is_empty());
+
+        let now = Utc.with_ymd_and_hms(2025, 12, 3, 22, 30, 0).unwrap();
+        let due = due_events(&calendar, &config(), &state, now);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].uid, Some("tonight@example.com".to_string()));
+    }
+
+    #[test]
+    fn test_render() {
+        let event = CalendarEvent {
+            summary: Some("Monthly Meeting".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&config(), &event).unwrap(),
+            "📝 Thanks to everyone who joined Monthly Meeting!\nMinutes will be published at https://example.com/minutes"
+        );
+    }
+}
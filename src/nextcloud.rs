@@ -0,0 +1,114 @@
+use anyhow::{Result, anyhow};
+use reqwest::Url;
+
+/// Where the iCalendar export of a Nextcloud calendar link is: a public share
+/// link of the Calendar app (`/apps/calendar/p/<token>`, also in an embed)
+/// becomes its public WebDAV calendar, and a CalDAV collection under
+/// `/remote.php/dav/` gets the `?export` it only serves iCalendar with.
+/// Other URLs give `None`, as do links that already are exports.
+pub fn export_url(url: &str) -> Result<Option<String>> {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return Ok(None);
+    };
+    let segments: Vec<String> = match parsed.path_segments() {
+        Some(segments) => segments.map(String::from).collect(),
+        None => return Ok(None),
+    };
+    let at = |index: usize, expected: &[&str]| {
+        segments
+            .get(index)
+            .is_some_and(|segment| expected.contains(&segment.as_str()))
+    };
+
+    let collection = if let Some(apps) = (0..segments.len()).find(|&index| {
+        at(index, &["apps"]) && at(index + 1, &["calendar"]) && at(index + 2, &["p", "embed"])
+    }) {
+        let token = segments
+            .get(apps + 3)
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| anyhow!("The Nextcloud share link {} has no token", url))?;
+        if token.contains('-') {
+            return Err(anyhow!(
+                "The Nextcloud share link {} shares several calendars; configure a feed for each of them",
+                url
+            ));
+        }
+        // Nextcloud may live in a subdirectory, and links may go through index.php
+        let mut base = segments[..apps].to_vec();
+        if base.last().is_some_and(|segment| segment == "index.php") {
+            base.pop();
+        }
+        base.extend(["remote.php", "dav", "public-calendars", token].map(String::from));
+        base
+    } else if let Some(dav) =
+        (0..segments.len()).find(|&index| at(index, &["remote.php"]) && at(index + 1, &["dav"]))
+    {
+        // `public-calendars/<token>` or `calendars/<user>/<calendar>`
+        let length = match segments.get(dav + 2).map(String::as_str) {
+            Some("public-calendars") => 2,
+            Some("calendars") => 3,
+            _ => return Ok(None),
+        };
+        let end = dav + 2 + length;
+        if segments.len() < end
+            || segments[end - 1].is_empty()
+            || parsed.query_pairs().any(|(key, _)| key == "export")
+        {
+            return Ok(None);
+        }
+        segments[..end].to_vec()
+    } else {
+        return Ok(None);
+    };
+
+    // Collections are only found with the trailing slash
+    parsed.set_path(&format!("{}/", collection.join("/")));
+    parsed.query_pairs_mut().append_key_only("export");
+    Ok(Some(parsed.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_url() {
+        let export = |url| export_url(url).unwrap();
+        assert_eq!(
+            export("https://cloud.example.com/index.php/apps/calendar/p/Wd8sKq3nTzRj2PaM"),
+            Some("https://cloud.example.com/remote.php/dav/public-calendars/Wd8sKq3nTzRj2PaM/?export".to_string())
+        );
+        assert_eq!(
+            export("https://example.com/nextcloud/apps/calendar/p/Wd8sKq3nTzRj2PaM/dayGridMonth/now"),
+            Some("https://example.com/nextcloud/remote.php/dav/public-calendars/Wd8sKq3nTzRj2PaM/?export".to_string())
+        );
+        assert_eq!(
+            export("https://cloud.example.com/apps/calendar/embed/Wd8sKq3nTzRj2PaM"),
+            Some("https://cloud.example.com/remote.php/dav/public-calendars/Wd8sKq3nTzRj2PaM/?export".to_string())
+        );
+        assert_eq!(
+            export("https://cloud.example.com/remote.php/dav/calendars/bot/meetings"),
+            Some(
+                "https://cloud.example.com/remote.php/dav/calendars/bot/meetings/?export"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            export(
+                "https://cloud.example.com/remote.php/dav/public-calendars/Wd8sKq3nTzRj2PaM?export"
+            ),
+            None
+        );
+        assert_eq!(
+            export("https://cloud.example.com/remote.php/dav/calendars/bot/"),
+            None
+        );
+        assert_eq!(export("https://example.com/calendar.ics"), None);
+        assert!(
+            export_url(
+                "https://cloud.example.com/apps/calendar/p/Wd8sKq3nTzRj2PaM-Xb4vLm9cQe7HsN2d"
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use crate::config::{CalendarOAuthConfig, UrlPolicyConfig};
+use crate::url_policy;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long before it expires a token is replaced, so it doesn't run out
+/// halfway through a fetch.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+struct CachedToken {
+    access_token: String,
+    expires_at: Option<Instant>,
+}
+
+/// Tokens granted so far in this process, by token URL and client id, so
+/// the daemon doesn't ask for a new one on every run.
+static TOKENS: Mutex<BTreeMap<(String, String), CachedToken>> = Mutex::new(BTreeMap::new());
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// Seconds the token is valid for; some servers leave it out.
+    expires_in: Option<u64>,
+}
+
+fn cache_key(config: &CalendarOAuthConfig) -> (String, String) {
+    (config.token_url.clone(), config.client_id.clone())
+}
+
+/// A bearer token for the calendar: the one granted earlier while it is
+/// still valid, or else a new one from the token endpoint, if `policy`
+/// allows asking it.
+pub async fn bearer_token(
+    config: &CalendarOAuthConfig,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<String> {
+    let key = cache_key(config);
+    if let Some(cached) = TOKENS.lock().unwrap().get(&key)
+        && cached
+            .expires_at
+            .is_none_or(|expires_at| Instant::now() + EXPIRY_MARGIN < expires_at)
+    {
+        return Ok(cached.access_token.clone());
+    }
+
+    let granted = request_token(config, policy).await?;
+    let access_token = granted.access_token.clone();
+    TOKENS.lock().unwrap().insert(
+        key,
+        CachedToken {
+            access_token: granted.access_token,
+            expires_at: granted
+                .expires_in
+                .map(|seconds| Instant::now() + Duration::from_secs(seconds)),
+        },
+    );
+    Ok(access_token)
+}
+
+/// Drops the token granted for `config`, after the calendar turned it down.
+pub fn forget(config: &CalendarOAuthConfig) {
+    TOKENS.lock().unwrap().remove(&cache_key(config));
+}
+
+/// Asks for a token with the client credentials grant (RFC 6749, section
+/// 4.4), authenticating the client with HTTP Basic.
+async fn request_token(
+    config: &CalendarOAuthConfig,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<TokenResponse> {
+    let mut form = vec![("grant_type", "client_credentials".to_string())];
+    if !config.scopes.is_empty() {
+        form.push(("scope", config.scopes.join(" ")));
+    }
+    let response = url_policy::client(policy, &config.token_url)?
+        .post(&config.token_url)
+        .basic_auth(&config.client_id, Some(&config.client_secret))
+        .form(&form)
+        .send()
+        .await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(anyhow!(
+            "HTTP error {} getting a calendar token from {}: {}",
+            status,
+            config.token_url,
+            body
+        ));
+    }
+    serde_json::from_str(&body).map_err(|e| {
+        anyhow!(
+            "No access token in the answer from {}: {}",
+            config.token_url,
+            e
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cached_tokens() {
+        let config = CalendarOAuthConfig {
+            // Nothing listens there, so a token can only come from the cache
+            token_url: "http://127.0.0.1:9/token".to_string(),
+            client_id: "bot".to_string(),
+            client_secret: "secret".to_string(),
+            scopes: vec!["calendar.read".to_string()],
+        };
+        assert!(bearer_token(&config, None).await.is_err());
+
+        let cache = |expires_at| {
+            TOKENS.lock().unwrap().insert(
+                cache_key(&config),
+                CachedToken {
+                    access_token: "abc".to_string(),
+                    expires_at,
+                },
+            );
+        };
+        cache(Some(Instant::now() + Duration::from_secs(3600)));
+        assert_eq!(bearer_token(&config, None).await.unwrap(), "abc");
+        cache(None);
+        assert_eq!(bearer_token(&config, None).await.unwrap(), "abc");
+
+        // A token about to expire is replaced
+        cache(Some(Instant::now() + Duration::from_secs(10)));
+        assert!(bearer_token(&config, None).await.is_err());
+
+        cache(None);
+        forget(&config);
+        assert!(bearer_token(&config, None).await.is_err());
+    }
+}
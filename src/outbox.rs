@@ -0,0 +1,127 @@
+use crate::ical::CalendarEvent;
+use crate::poster::{NewPoll, Posted};
+use crate::scope;
+use chrono::{DateTime, Utc};
+use mastodon_async::NewStatus;
+use serde::Serialize;
+
+/// What was done to a status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Post,
+    Edit,
+    Delete,
+    Schedule,
+    Cancel,
+}
+
+/// A status the run created, changed or deleted, or would have in a dry run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sent {
+    pub action: Action,
+    pub id: String,
+    pub url: Option<String>,
+    pub target: String,
+    /// When a scheduled status goes out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub dry_run: bool,
+    pub status: NewStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poll: Option<NewPoll>,
+    /// File name of the attached media.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media: Option<String>,
+}
+
+impl Sent {
+    pub fn new(action: Action, posted: &Posted, status: &NewStatus, dry_run: bool) -> Self {
+        Sent {
+            action,
+            id: posted.id.clone(),
+            url: posted.url.clone(),
+            target: posted.target.clone(),
+            scheduled_at: posted.scheduled_at,
+            dry_run,
+            status: status.clone(),
+            poll: None,
+            media: None,
+        }
+    }
+}
+
+/// An event a posting command picked to post about.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Selected {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub start: Option<String>,
+}
+
+/// Notes a status the run created, changed or deleted.
+pub fn record(sent: Sent) {
+    scope::current().sent.lock().unwrap().push(sent);
+}
+
+/// Notes that a command picked an event to post about; an event picked
+/// again is only listed once.
+pub fn select(event: &CalendarEvent) {
+    let selected = Selected {
+        uid: event.uid.clone(),
+        summary: event.summary.clone(),
+        start: event.start_time.clone(),
+    };
+    let records = scope::current();
+    let mut all = records.selected.lock().unwrap();
+    if !all.contains(&selected) {
+        all.push(selected);
+    }
+}
+
+/// The statuses sent so far in this run, oldest first.
+pub fn sent() -> Vec<Sent> {
+    scope::current().sent.lock().unwrap().clone()
+}
+
+/// The events picked so far in this run, in the order they were.
+pub fn selected() -> Vec<Selected> {
+    scope::current().selected.lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_select_once() {
+        let event = CalendarEvent {
+            uid: Some("outbox-test@example.com".to_string()),
+            summary: Some("Monthly Meeting".to_string()),
+            start_time: Some("20251203T190000Z".to_string()),
+            ..Default::default()
+        };
+        let picked = scope::job(async {
+            select(&event);
+            select(&event);
+            select(&CalendarEvent {
+                start_time: Some("20260107T190000Z".to_string()),
+                ..event.clone()
+            });
+            selected()
+        })
+        .await;
+
+        let starts: Vec<_> = picked.into_iter().map(|selected| selected.start).collect();
+        assert_eq!(
+            starts,
+            vec![
+                Some("20251203T190000Z".to_string()),
+                Some("20260107T190000Z".to_string())
+            ]
+        );
+        // Nothing the job picked is left over after it
+        assert!(selected().iter().all(|selected| selected.uid != event.uid));
+        assert!(scope::job(async { selected() }).await.is_empty());
+    }
+}
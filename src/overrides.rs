@@ -0,0 +1,135 @@
+use crate::ical::{self, CalendarEvent, TimeForm};
+use anyhow::{Result, anyhow};
+use chrono::{NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// Changes to single occurrences of the calendar's events, for operators
+/// who can't edit the calendar itself.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Overrides {
+    /// Written as `[[occurrence]]`.
+    #[serde(default, rename = "occurrence")]
+    pub occurrences: Vec<Override>,
+}
+
+/// What changes for the occurrence of the event `uid` starting on `date`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Override {
+    pub uid: String,
+    /// The day the occurrence starts, in the calendar's `timezone`.
+    pub date: NaiveDate,
+    pub summary: Option<String>,
+    pub location: Option<String>,
+    pub url: Option<String>,
+    /// Template announcing the occurrence, in place of the configured one.
+    pub template: Option<String>,
+    /// Leave the occurrence out of every post.
+    #[serde(default)]
+    pub skip: bool,
+}
+
+/// The day the event starts in `timezone`.
+fn start_date(event: &CalendarEvent, timezone: Tz) -> Option<NaiveDate> {
+    let (start, form) = event
+        .start_time
+        .as_deref()
+        .and_then(ical::parse_ical_time)?;
+    Some(match form {
+        TimeForm::Utc => timezone.from_utc_datetime(&start).date_naive(),
+        TimeForm::Floating | TimeForm::Date => start.date(),
+    })
+}
+
+impl Overrides {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read the overrides {}: {}", path, e))?;
+        toml::from_str(&content).map_err(|e| anyhow!("Invalid overrides {}: {}", path, e))
+    }
+
+    /// Applies the overrides to the occurrences they name, with dates in
+    /// `timezone`. Returns how many occurrences were changed.
+    pub fn apply(&self, events: &mut [CalendarEvent], timezone: Tz) -> usize {
+        let mut applied = 0;
+        for event in events {
+            let Some(uid) = event.uid.as_deref() else {
+                continue;
+            };
+            let date = start_date(event, timezone);
+            let Some(found) = self
+                .occurrences
+                .iter()
+                .find(|found| found.uid == uid && Some(found.date) == date)
+            else {
+                continue;
+            };
+            if let Some(summary) = &found.summary {
+                event.summary = Some(ical::escape_text(summary));
+            }
+            if let Some(location) = &found.location {
+                event.location = Some(ical::escape_text(location));
+            }
+            if let Some(url) = &found.url {
+                event.url = Some(url.clone());
+            }
+            if let Some(template) = &found.template {
+                event.template = Some(template.clone());
+            }
+            event.skip |= found.skip;
+            applied += 1;
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_apply() {
+        let overrides: Overrides = toml::from_str(indoc! {r#"
+            [[occurrence]]
+            uid = "weekly@example.com"
+            date = "2025-12-03"
+            location = "Room 2, upstairs"
+            template = "Moved this week: {summary} in {location}"
+
+            [[occurrence]]
+            uid = "weekly@example.com"
+            date = "2025-12-10"
+            skip = true
+        "#})
+        .unwrap();
+        let occurrence = |start: &str| CalendarEvent {
+            uid: Some("weekly@example.com".to_string()),
+            summary: Some("Weekly Meeting".to_string()),
+            start_time: Some(start.to_string()),
+            location: Some("Room 1".to_string()),
+            ..CalendarEvent::default()
+        };
+        // Evening meetings in New York start the next day in UTC
+        let mut events = [
+            occurrence("20251204T000000Z"),
+            occurrence("20251211T000000Z"),
+            occurrence("20251218T000000Z"),
+        ];
+
+        let applied = overrides.apply(&mut events, chrono_tz::America::New_York);
+        assert_eq!(applied, 2);
+        let [moved, skipped, untouched] = &events;
+        assert_eq!(moved.location.as_deref(), Some("Room 2\\, upstairs"));
+        assert_eq!(moved.summary.as_deref(), Some("Weekly Meeting"));
+        assert!(moved.template.is_some());
+        assert!(!moved.skip);
+        assert!(skipped.skip);
+        assert_eq!(untouched.location.as_deref(), Some("Room 1"));
+        assert!(!untouched.skip && untouched.template.is_none());
+
+        // The same times fall on the next days in UTC
+        let mut events = [occurrence("20251204T000000Z")];
+        assert_eq!(overrides.apply(&mut events, chrono_tz::UTC), 0);
+    }
+}
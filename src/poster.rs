@@ -0,0 +1,1373 @@
+use crate::config::{self, Config, Network, UrlPolicyConfig};
+use crate::error::{self, Error, Result};
+use crate::matrix::{self, MatrixPublisher};
+use crate::media::Media;
+use crate::outbox::{self, Action, Sent};
+use crate::timing::{self, Phase};
+use crate::url_policy;
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use mastodon_async::entities::attachment::Attachment;
+use mastodon_async::{Mastodon, NewStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// How long an uploaded file may take to be processed before it is left out.
+const MEDIA_PROCESSING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How many times the media step is tried before the status goes out without
+/// its file.
+const MEDIA_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry of the media step, doubled for each later one.
+const MEDIA_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// How often an uploaded file is checked on while the instance processes it.
+const MEDIA_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How far ahead Mastodon accepts statuses to be scheduled.
+pub const MIN_SCHEDULE_AHEAD: chrono::Duration = chrono::Duration::minutes(5);
+
+/// How long posts are held back after the instance said it's unavailable
+/// without saying until when.
+pub const DEFAULT_RETRY_AFTER: chrono::Duration = chrono::Duration::minutes(15);
+
+/// How many statuses dry runs have pretended to post, numbering their ids.
+static DRY_RUN_STATUSES: AtomicUsize = AtomicUsize::new(0);
+
+/// The id of the next status a dry run pretends to post.
+pub(crate) fn dry_run_id() -> String {
+    let count = DRY_RUN_STATUSES.fetch_add(1, Ordering::Relaxed) + 1;
+    format!("dry-run-{}", count)
+}
+
+/// How many files have been written out for upload, keeping their paths apart.
+static UPLOADS: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary path of its own to write a file to upload to, so uploads
+/// running at the same time never share one, even for files of the same name.
+fn upload_path(file_name: &str) -> std::path::PathBuf {
+    let count = UPLOADS.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!(
+        "ical-to-masto-{}-{}-{}",
+        std::process::id(),
+        count,
+        file_name
+    ))
+}
+
+/// A status that was published.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posted {
+    pub id: String,
+    pub url: Option<String>,
+    /// Name of the account's network, such as the instance's host.
+    pub target: String,
+    /// When a scheduled status goes out; `id` is then that of the scheduled
+    /// status, not of the status it becomes.
+    pub scheduled_at: Option<DateTime<Utc>>,
+}
+
+/// A status the instance is to post later.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScheduledStatus {
+    pub id: String,
+    pub scheduled_at: DateTime<Utc>,
+    pub text: Option<String>,
+}
+
+/// One of the account's statuses as it is on the instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentStatus {
+    pub posted: Posted,
+    /// The text as followers see it, without markup.
+    pub text: String,
+}
+
+/// A status mentioning the account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mention {
+    /// The id of the notification, to read only later ones next time.
+    pub id: String,
+    pub status_id: String,
+    /// The status it replies to, if any.
+    pub in_reply_to_id: Option<String>,
+    /// The account that mentioned this one, like `alice@example.social`.
+    pub account: String,
+    /// The text without markup.
+    pub text: String,
+}
+
+/// The text of a status' HTML content, with paragraphs and line breaks as
+/// line breaks.
+pub fn plain_text(html: &str) -> String {
+    let html = html
+        .replace("</p><p>", "\n\n")
+        .replace("<br>", "\n")
+        .replace("<br/>", "\n")
+        .replace("<br />", "\n");
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The instance answering `503 Service Unavailable`, as it does during
+/// maintenance, with when its `Retry-After` header says to come back.
+#[derive(Debug)]
+pub struct Unavailable {
+    pub retry_after: Option<DateTime<Utc>>,
+}
+
+impl Unavailable {
+    /// When to try the instance again, as of `now`.
+    pub fn until(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.retry_after.unwrap_or(now + DEFAULT_RETRY_AFTER)
+    }
+}
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.retry_after {
+            Some(at) => write!(f, "the instance is unavailable until {}", at),
+            None => write!(f, "the instance is unavailable"),
+        }
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+/// Whether `error` is the instance saying it's unavailable, and if so, that.
+pub fn unavailable(error: &Error) -> Option<&Unavailable> {
+    match error {
+        Error::Publish(e) => e.downcast_ref::<Unavailable>(),
+        _ => None,
+    }
+}
+
+/// Runs a step of publishing, its failure being one to publish.
+pub(crate) async fn publishing<T>(step: impl Future<Output = anyhow::Result<T>>) -> Result<T> {
+    step.await.map_err(Error::Publish)
+}
+
+/// When a `Retry-After` header says to come back: in so many seconds, or at
+/// an HTTP date.
+fn retry_after(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    match value.trim().parse::<i64>() {
+        Ok(seconds) => {
+            chrono::Duration::try_seconds(seconds).and_then(|delay| now.checked_add_signed(delay))
+        }
+        Err(_) => DateTime::parse_from_rfc2822(value.trim())
+            .ok()
+            .map(|at| at.with_timezone(&Utc)),
+    }
+}
+
+/// A poll to attach to a new status.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NewPoll {
+    pub options: Vec<String>,
+    /// Seconds the poll stays open.
+    pub expires_in: i64,
+}
+
+/// A name and value shown in a table on the account's profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileField {
+    pub name: String,
+    pub value: String,
+}
+
+/// Something statuses can be published to.
+pub trait Publisher {
+    fn publish(&self, status: NewStatus) -> impl Future<Output = Result<Posted>>;
+
+    /// Publishes a status with a file attached.
+    fn publish_with_media(
+        &self,
+        status: NewStatus,
+        media: Media,
+    ) -> impl Future<Output = Result<Posted>>;
+
+    /// Replaces the text of a status published earlier.
+    fn edit(&self, id: &str, status: NewStatus) -> impl Future<Output = Result<Posted>>;
+
+    /// Deletes a status published earlier.
+    fn delete(&self, id: &str) -> impl Future<Output = Result<()>>;
+
+    /// Publishes a status with a poll attached.
+    fn publish_poll(
+        &self,
+        status: NewStatus,
+        poll: NewPoll,
+    ) -> impl Future<Output = Result<Posted>>;
+
+    /// The vote counts of a published poll, in the order of its options.
+    fn poll_votes(&self, id: &str) -> impl Future<Output = Result<Vec<u64>>>;
+
+    /// The longest status the account may post, in characters, if the
+    /// instance says.
+    fn max_status_chars(&self) -> impl Future<Output = Result<Option<usize>>>;
+
+    /// The fields of the account's profile, as written.
+    fn profile_fields(&self) -> impl Future<Output = Result<Vec<ProfileField>>>;
+
+    /// Replaces every field of the account's profile.
+    fn set_profile_fields(&self, fields: Vec<ProfileField>) -> impl Future<Output = Result<()>>;
+
+    /// Has the instance post a status, with a file attached if given, at a
+    /// later time.
+    fn schedule(
+        &self,
+        status: NewStatus,
+        media: Option<Media>,
+        at: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Posted>>;
+
+    /// The account's statuses that are scheduled and haven't gone out yet.
+    fn scheduled_statuses(&self) -> impl Future<Output = Result<Vec<ScheduledStatus>>>;
+
+    /// Cancels a scheduled status before it goes out.
+    fn cancel_scheduled(&self, id: &str) -> impl Future<Output = Result<()>>;
+
+    /// Up to `limit` of the account's latest statuses, newest first.
+    fn recent_statuses(&self, limit: usize) -> impl Future<Output = Result<Vec<RecentStatus>>>;
+
+    /// Pins a status published earlier to the account's profile, or unpins it.
+    fn set_pinned(&self, id: &str, pinned: bool) -> impl Future<Output = Result<()>>;
+
+    /// The statuses mentioning the account since the notification with id
+    /// `since_id`, or the latest ones without it, oldest first.
+    fn mentions(&self, since_id: Option<&str>) -> impl Future<Output = Result<Vec<Mention>>>;
+}
+
+impl<T: Publisher> Publisher for &T {
+    fn publish(&self, status: NewStatus) -> impl Future<Output = Result<Posted>> {
+        (**self).publish(status)
+    }
+
+    fn publish_with_media(
+        &self,
+        status: NewStatus,
+        media: Media,
+    ) -> impl Future<Output = Result<Posted>> {
+        (**self).publish_with_media(status, media)
+    }
+
+    fn edit(&self, id: &str, status: NewStatus) -> impl Future<Output = Result<Posted>> {
+        (**self).edit(id, status)
+    }
+
+    fn delete(&self, id: &str) -> impl Future<Output = Result<()>> {
+        (**self).delete(id)
+    }
+
+    fn publish_poll(
+        &self,
+        status: NewStatus,
+        poll: NewPoll,
+    ) -> impl Future<Output = Result<Posted>> {
+        (**self).publish_poll(status, poll)
+    }
+
+    fn poll_votes(&self, id: &str) -> impl Future<Output = Result<Vec<u64>>> {
+        (**self).poll_votes(id)
+    }
+
+    fn max_status_chars(&self) -> impl Future<Output = Result<Option<usize>>> {
+        (**self).max_status_chars()
+    }
+
+    fn profile_fields(&self) -> impl Future<Output = Result<Vec<ProfileField>>> {
+        (**self).profile_fields()
+    }
+
+    fn set_profile_fields(&self, fields: Vec<ProfileField>) -> impl Future<Output = Result<()>> {
+        (**self).set_profile_fields(fields)
+    }
+
+    fn schedule(
+        &self,
+        status: NewStatus,
+        media: Option<Media>,
+        at: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Posted>> {
+        (**self).schedule(status, media, at)
+    }
+
+    fn scheduled_statuses(&self) -> impl Future<Output = Result<Vec<ScheduledStatus>>> {
+        (**self).scheduled_statuses()
+    }
+
+    fn cancel_scheduled(&self, id: &str) -> impl Future<Output = Result<()>> {
+        (**self).cancel_scheduled(id)
+    }
+
+    fn recent_statuses(&self, limit: usize) -> impl Future<Output = Result<Vec<RecentStatus>>> {
+        (**self).recent_statuses(limit)
+    }
+
+    fn set_pinned(&self, id: &str, pinned: bool) -> impl Future<Output = Result<()>> {
+        (**self).set_pinned(id, pinned)
+    }
+
+    fn mentions(&self, since_id: Option<&str>) -> impl Future<Output = Result<Vec<Mention>>> {
+        (**self).mentions(since_id)
+    }
+}
+
+/// Publishes statuses to a Mastodon account.
+pub struct MastodonPublisher {
+    pub mastodon: Mastodon,
+    /// Ids of the files uploaded and processed so far, by [`media_key`], so a
+    /// status that is tried again doesn't upload its file again.
+    uploaded: Mutex<HashMap<u64, String>>,
+    /// Whether statuses are only recorded in the [`outbox`], not posted.
+    dry_run: bool,
+    /// Which URLs the requests mastodon-async doesn't make may go to.
+    url_policy: Option<UrlPolicyConfig>,
+}
+
+impl MastodonPublisher {
+    pub fn new(mastodon: Mastodon) -> Self {
+        MastodonPublisher {
+            mastodon,
+            uploaded: Mutex::new(HashMap::new()),
+            dry_run: false,
+            url_policy: None,
+        }
+    }
+
+    /// Makes the publisher only pretend to post, edit and delete statuses.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_url_policy(mut self, url_policy: Option<UrlPolicyConfig>) -> Self {
+        self.url_policy = url_policy;
+        self
+    }
+
+    /// What a dry run returns in place of a status it didn't post.
+    fn pretend(&self) -> Posted {
+        Posted {
+            id: dry_run_id(),
+            url: None,
+            target: self.target(),
+            scheduled_at: None,
+        }
+    }
+
+    /// An authenticated request to one of the API endpoints mastodon-async
+    /// doesn't cover, if the URL policy allows it.
+    fn request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        let data = &self.mastodon.data;
+        let url = format!("{}/api/v1/{}", data.base.trim_end_matches('/'), path);
+        Ok(url_policy::client(self.url_policy.as_ref(), &url)?
+            .request(method, url)
+            .bearer_auth(&data.token))
+    }
+
+    /// The instance's host, naming where statuses went.
+    pub fn target(&self) -> String {
+        let base = &self.mastodon.data.base;
+        reqwest::Url::parse(base)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_else(|| base.to_string())
+    }
+
+    fn posted_from_json(&self, status: &serde_json::Value) -> anyhow::Result<Posted> {
+        Ok(Posted {
+            id: status["id"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Status without an id in the response"))?
+                .to_string(),
+            url: status["url"].as_str().map(String::from),
+            target: self.target(),
+            scheduled_at: None,
+        })
+    }
+
+    /// Uploads a file and waits for the instance to process it, returning the
+    /// id to attach it with. Files uploaded before are attached again.
+    async fn upload(&self, media: &Media) -> anyhow::Result<String> {
+        let key = media_key(media);
+        if let Some(id) = self.uploaded.lock().unwrap().get(&key) {
+            return Ok(id.clone());
+        }
+        let id = upload_with_retries(self, media, &MediaTiming::default()).await?;
+        self.uploaded.lock().unwrap().insert(key, id.clone());
+        Ok(id)
+    }
+}
+
+impl MediaSteps for MastodonPublisher {
+    type Uploaded = Attachment;
+
+    async fn send_media(&self, media: &Media) -> anyhow::Result<Attachment> {
+        timing::time_async(Phase::Post, async {
+            // mastodon-async only uploads from files
+            let path = upload_path(&media.file_name);
+            std::fs::write(&path, &media.data)?;
+            let uploaded = self
+                .mastodon
+                .media(&path, Some(media.description.clone()))
+                .await;
+            // A file left behind in the temporary directory is no reason to
+            // fail the post
+            let _ = std::fs::remove_file(&path);
+            Ok(uploaded?)
+        })
+        .await
+    }
+
+    async fn processed(&self, attachment: &Attachment) -> anyhow::Result<Option<String>> {
+        // The instance answers `206 Partial Content`, without a URL, until then
+        if attachment.url.is_some() {
+            return Ok(Some(attachment.id.to_string()));
+        }
+        timing::time_async(Phase::Post, async {
+            let current = self.mastodon.attachment(&attachment.id).await?;
+            Ok(current.url.map(|_| current.id.to_string()))
+        })
+        .await
+    }
+}
+
+/// The two steps of getting a file onto an instance, apart so that a retry
+/// can skip the upload once the instance has the file.
+pub(crate) trait MediaSteps {
+    type Uploaded: Clone;
+
+    /// Uploads the file.
+    async fn send_media(&self, media: &Media) -> anyhow::Result<Self::Uploaded>;
+
+    /// The id to attach an uploaded file with, or `None` while the instance
+    /// is still processing it.
+    async fn processed(&self, uploaded: &Self::Uploaded) -> anyhow::Result<Option<String>>;
+}
+
+/// How long the media step waits, between polls and before retries.
+pub(crate) struct MediaTiming {
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for each later one.
+    pub retry_delay: Duration,
+    pub poll_interval: Duration,
+    pub processing_timeout: Duration,
+}
+
+impl Default for MediaTiming {
+    fn default() -> Self {
+        MediaTiming {
+            attempts: MEDIA_ATTEMPTS,
+            retry_delay: MEDIA_RETRY_DELAY,
+            poll_interval: MEDIA_POLL_INTERVAL,
+            processing_timeout: MEDIA_PROCESSING_TIMEOUT,
+        }
+    }
+}
+
+/// Uploads a file and waits for the instance to process it, returning the
+/// id to attach it with.
+///
+/// Failures are retried with a growing delay. Once the instance has the
+/// file, a retry only waits for the processing again rather than
+/// uploading it once more.
+pub(crate) async fn upload_with_retries<S: MediaSteps>(
+    steps: &S,
+    media: &Media,
+    timing: &MediaTiming,
+) -> anyhow::Result<String> {
+    let mut uploaded = None;
+    let mut attempt = 1;
+    loop {
+        let result = match uploaded.clone() {
+            Some(sent) => wait_for_processing(steps, &sent, media, timing).await,
+            None => match steps.send_media(media).await {
+                Ok(sent) => {
+                    uploaded = Some(sent.clone());
+                    wait_for_processing(steps, &sent, media, timing).await
+                }
+                Err(e) => Err(e),
+            },
+        };
+        match result {
+            Ok(id) => return Ok(id),
+            Err(e) if attempt >= timing.attempts => return Err(e),
+            Err(e) => {
+                eprintln!(
+                    "Warning: {} {} failed (attempt {} of {}): {}",
+                    if uploaded.is_some() {
+                        "processing"
+                    } else {
+                        "uploading"
+                    },
+                    media.file_name,
+                    attempt,
+                    timing.attempts,
+                    e
+                );
+                tokio::time::sleep(timing.retry_delay * 2u32.pow(attempt - 1)).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Polls an uploaded file until the instance has processed it.
+async fn wait_for_processing<S: MediaSteps>(
+    steps: &S,
+    uploaded: &S::Uploaded,
+    media: &Media,
+    timing: &MediaTiming,
+) -> anyhow::Result<String> {
+    let processed = async {
+        loop {
+            if let Some(id) = steps.processed(uploaded).await? {
+                return Ok(id);
+            }
+            tokio::time::sleep(timing.poll_interval).await;
+        }
+    };
+    tokio::time::timeout(timing.processing_timeout, processed)
+        .await
+        .map_err(|_| anyhow!("The instance took too long to process {}", media.file_name))?
+}
+
+/// Sends a request to an API and reads its JSON answer, telling an instance
+/// down for maintenance apart from other errors.
+pub(crate) async fn send(
+    request: reqwest::RequestBuilder,
+    action: &str,
+) -> anyhow::Result<serde_json::Value> {
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| retry_after(value, Utc::now()));
+        return Err(Unavailable { retry_after }.into());
+    }
+    if !response.status().is_success() {
+        return Err(anyhow!("HTTP error {}: {}", action, response.status()));
+    }
+    Ok(response.json().await?)
+}
+
+impl Publisher for MastodonPublisher {
+    async fn publish(&self, status: NewStatus) -> Result<Posted> {
+        publishing(timing::time_async(Phase::Post, async {
+            let posted = match self.dry_run {
+                true => self.pretend(),
+                false => {
+                    // Sent by hand, as mastodon-async drops the `Retry-After`
+                    // of an instance down for maintenance
+                    let request = self
+                        .request(reqwest::Method::POST, "statuses")?
+                        .json(&status);
+                    let posted = send(request, "posting status").await?;
+                    self.posted_from_json(&posted)?
+                }
+            };
+            outbox::record(Sent::new(Action::Post, &posted, &status, self.dry_run));
+            Ok(posted)
+        }))
+        .await
+    }
+
+    async fn publish_with_media(&self, mut status: NewStatus, media: Media) -> Result<Posted> {
+        if self.dry_run {
+            let posted = self.pretend();
+            outbox::record(Sent {
+                media: Some(media.file_name.clone()),
+                ..Sent::new(Action::Post, &posted, &status, true)
+            });
+            return Ok(posted);
+        }
+        // The announcement matters more than its picture
+        match self.upload(&media).await {
+            Ok(id) => status.media_ids = Some(vec![id]),
+            Err(e) => eprintln!(
+                "Warning: posting without {}, which could not be uploaded: {}",
+                media.file_name, e
+            ),
+        }
+        self.publish(status).await
+    }
+
+    async fn edit(&self, id: &str, status: NewStatus) -> Result<Posted> {
+        publishing(timing::time_async(Phase::Post, async {
+            let posted = match self.dry_run {
+                true => Posted {
+                    id: id.to_string(),
+                    url: None,
+                    target: self.target(),
+                    scheduled_at: None,
+                },
+                false => {
+                    // mastodon-async has no call for editing statuses (Mastodon 3.5+)
+                    let request = self
+                        .request(reqwest::Method::PUT, &format!("statuses/{}", id))?
+                        .json(&status);
+                    let edited = send(request, &format!("editing status {}", id)).await?;
+                    self.posted_from_json(&edited)?
+                }
+            };
+            outbox::record(Sent::new(Action::Edit, &posted, &status, self.dry_run));
+            Ok(posted)
+        }))
+        .await
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        publishing(timing::time_async(Phase::Post, async {
+            if !self.dry_run {
+                let request = self.request(reqwest::Method::DELETE, &format!("statuses/{}", id))?;
+                send(request, &format!("deleting status {}", id)).await?;
+            }
+            let deleted = Posted {
+                id: id.to_string(),
+                url: None,
+                target: self.target(),
+                scheduled_at: None,
+            };
+            outbox::record(Sent::new(
+                Action::Delete,
+                &deleted,
+                &NewStatus::default(),
+                self.dry_run,
+            ));
+            Ok(())
+        }))
+        .await
+    }
+
+    async fn publish_poll(&self, status: NewStatus, poll: NewPoll) -> Result<Posted> {
+        publishing(timing::time_async(Phase::Post, async {
+            let posted = match self.dry_run {
+                true => self.pretend(),
+                false => {
+                    // NewStatus has no poll field, so the poll is added to its JSON
+                    let mut body = serde_json::to_value(&status)?;
+                    body["poll"] = serde_json::to_value(&poll)?;
+                    let request = self.request(reqwest::Method::POST, "statuses")?.json(&body);
+                    let posted = send(request, "posting poll").await?;
+                    self.posted_from_json(&posted)?
+                }
+            };
+            outbox::record(Sent {
+                poll: Some(poll),
+                ..Sent::new(Action::Post, &posted, &status, self.dry_run)
+            });
+            Ok(posted)
+        }))
+        .await
+    }
+
+    async fn poll_votes(&self, id: &str) -> Result<Vec<u64>> {
+        publishing(timing::time_async(Phase::Post, async {
+            let request = self.request(reqwest::Method::GET, &format!("statuses/{}", id))?;
+            let status = send(request, &format!("fetching status {}", id)).await?;
+            let options = status["poll"]["options"]
+                .as_array()
+                .ok_or_else(|| anyhow!("Status {} has no poll", id))?;
+            Ok(options
+                .iter()
+                .map(|option| option["votes_count"].as_u64().unwrap_or(0))
+                .collect())
+        }))
+        .await
+    }
+
+    async fn max_status_chars(&self) -> Result<Option<usize>> {
+        publishing(async {
+            let request = self.request(reqwest::Method::GET, "instance")?;
+            let instance = send(request, "fetching the instance's limits").await?;
+            // Mastodon 3.5+ has the limit in its configuration, Pleroma and
+            // Akkoma at the top
+            Ok(instance["configuration"]["statuses"]["max_characters"]
+                .as_u64()
+                .or_else(|| instance["max_toot_chars"].as_u64())
+                .map(|chars| chars as usize))
+        })
+        .await
+    }
+
+    async fn profile_fields(&self) -> Result<Vec<ProfileField>> {
+        publishing(async {
+            let request = self.request(reqwest::Method::GET, "accounts/verify_credentials")?;
+            let account = send(request, "fetching the account's profile").await?;
+            // The account's own fields are HTML; its source has them as written
+            let fields = &account["source"]["fields"];
+            if fields.is_null() {
+                return Ok(Vec::new());
+            }
+            Ok(serde_json::from_value(fields.clone())?)
+        })
+        .await
+    }
+
+    async fn set_profile_fields(&self, fields: Vec<ProfileField>) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        publishing(async {
+            // mastodon-async sends this one without the token
+            let request = self
+                .request(reqwest::Method::PATCH, "accounts/update_credentials")?
+                .json(&serde_json::json!({ "fields_attributes": fields }));
+            send(request, "updating the account's profile").await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn schedule(
+        &self,
+        mut status: NewStatus,
+        media: Option<Media>,
+        at: DateTime<Utc>,
+    ) -> Result<Posted> {
+        if let (Some(media), false) = (&media, self.dry_run) {
+            match self.upload(media).await {
+                Ok(id) => status.media_ids = Some(vec![id]),
+                Err(e) => eprintln!(
+                    "Warning: scheduling without {}, which could not be uploaded: {}",
+                    media.file_name, e
+                ),
+            }
+        }
+
+        publishing(timing::time_async(Phase::Post, async {
+            let posted = match self.dry_run {
+                true => self.pretend(),
+                false => {
+                    // NewStatus has no scheduled_at field, so it's added to its JSON
+                    let mut body = serde_json::to_value(&status)?;
+                    body["scheduled_at"] = serde_json::to_value(at)?;
+                    let request = self.request(reqwest::Method::POST, "statuses")?.json(&body);
+                    let scheduled = send(request, "scheduling status").await?;
+                    self.posted_from_json(&scheduled)?
+                }
+            };
+            let posted = Posted {
+                scheduled_at: Some(at),
+                ..posted
+            };
+            outbox::record(Sent {
+                media: media.map(|media| media.file_name),
+                ..Sent::new(Action::Schedule, &posted, &status, self.dry_run)
+            });
+            Ok(posted)
+        }))
+        .await
+    }
+
+    async fn scheduled_statuses(&self) -> Result<Vec<ScheduledStatus>> {
+        publishing(timing::time_async(Phase::Post, async {
+            let request = self.request(reqwest::Method::GET, "scheduled_statuses")?;
+            let scheduled = send(request, "fetching scheduled statuses").await?;
+            let scheduled = scheduled
+                .as_array()
+                .ok_or_else(|| anyhow!("Scheduled statuses aren't a list in the response"))?;
+            scheduled
+                .iter()
+                .map(|scheduled| {
+                    Ok(ScheduledStatus {
+                        id: scheduled["id"]
+                            .as_str()
+                            .ok_or_else(|| anyhow!("Scheduled status without an id"))?
+                            .to_string(),
+                        scheduled_at: serde_json::from_value(scheduled["scheduled_at"].clone())?,
+                        text: scheduled["params"]["text"].as_str().map(String::from),
+                    })
+                })
+                .collect()
+        }))
+        .await
+    }
+
+    async fn cancel_scheduled(&self, id: &str) -> Result<()> {
+        publishing(timing::time_async(Phase::Post, async {
+            if !self.dry_run {
+                let request = self.request(
+                    reqwest::Method::DELETE,
+                    &format!("scheduled_statuses/{}", id),
+                )?;
+                send(request, &format!("cancelling scheduled status {}", id)).await?;
+            }
+            let cancelled = Posted {
+                id: id.to_string(),
+                url: None,
+                target: self.target(),
+                scheduled_at: None,
+            };
+            outbox::record(Sent::new(
+                Action::Cancel,
+                &cancelled,
+                &NewStatus::default(),
+                self.dry_run,
+            ));
+            Ok(())
+        }))
+        .await
+    }
+
+    async fn recent_statuses(&self, limit: usize) -> Result<Vec<RecentStatus>> {
+        publishing(timing::time_async(Phase::Post, async {
+            let request = self.request(reqwest::Method::GET, "accounts/verify_credentials")?;
+            let account = send(request, "fetching the account").await?;
+            let id = account["id"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Account without an id in the response"))?;
+            let request = self
+                .request(reqwest::Method::GET, &format!("accounts/{}/statuses", id))?
+                .query(&[
+                    ("limit", limit.to_string().as_str()),
+                    ("exclude_reblogs", "true"),
+                ]);
+            let statuses = send(request, "fetching the account's statuses").await?;
+            statuses
+                .as_array()
+                .ok_or_else(|| anyhow!("Statuses aren't a list in the response"))?
+                .iter()
+                .map(|status| {
+                    Ok(RecentStatus {
+                        posted: self.posted_from_json(status)?,
+                        text: plain_text(status["content"].as_str().unwrap_or_default()),
+                    })
+                })
+                .collect()
+        }))
+        .await
+    }
+
+    async fn set_pinned(&self, id: &str, pinned: bool) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        publishing(timing::time_async(Phase::Post, async {
+            let (action, description) = match pinned {
+                true => ("pin", "pinning"),
+                false => ("unpin", "unpinning"),
+            };
+            let request = self.request(
+                reqwest::Method::POST,
+                &format!("statuses/{}/{}", id, action),
+            )?;
+            send(request, &format!("{} status {}", description, id)).await?;
+            Ok(())
+        }))
+        .await
+    }
+
+    async fn mentions(&self, since_id: Option<&str>) -> Result<Vec<Mention>> {
+        const PAGE: usize = 40;
+        publishing(async {
+            let mut mentions = Vec::new();
+            let mut after = since_id.map(str::to_string);
+            loop {
+                let mut request = self
+                    .request(reqwest::Method::GET, "notifications")?
+                    .query(&[("types[]", "mention"), ("limit", &PAGE.to_string())]);
+                // min_id pages forward from it, where since_id would skip to the latest
+                if let Some(after) = &after {
+                    request = request.query(&[("min_id", after)]);
+                }
+                let notifications = send(request, "fetching the account's mentions").await?;
+                let notifications = notifications
+                    .as_array()
+                    .ok_or_else(|| anyhow!("Notifications aren't a list in the response"))?;
+                let mut page = notifications
+                    .iter()
+                    .filter(|notification| notification["status"].is_object())
+                    .map(|notification| {
+                        let status = &notification["status"];
+                        let text = |value: &serde_json::Value| value.as_str().map(str::to_string);
+                        Ok(Mention {
+                            id: text(&notification["id"]).ok_or_else(|| {
+                                anyhow!("Notification without an id in the response")
+                            })?,
+                            status_id: text(&status["id"])
+                                .ok_or_else(|| anyhow!("Status without an id in the response"))?,
+                            in_reply_to_id: text(&status["in_reply_to_id"]),
+                            account: text(&status["account"]["acct"]).unwrap_or_default(),
+                            text: plain_text(status["content"].as_str().unwrap_or_default()),
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                page.reverse();
+                let full = notifications.len() == PAGE;
+                after = notifications
+                    .first()
+                    .and_then(|newest| newest["id"].as_str())
+                    .map(str::to_string);
+                mentions.extend(page);
+                // The first time, only the latest ones
+                if !full || since_id.is_none() || after.is_none() {
+                    break;
+                }
+            }
+            Ok(mentions)
+        })
+        .await
+    }
+}
+
+/// Publishes to an account on whichever network it is.
+pub enum NetworkPublisher {
+    Mastodon(MastodonPublisher),
+    Matrix(MatrixPublisher),
+}
+
+impl NetworkPublisher {
+    /// Names where statuses went: the instance's host or the Matrix room.
+    pub fn target(&self) -> String {
+        match self {
+            NetworkPublisher::Mastodon(publisher) => publisher.target(),
+            NetworkPublisher::Matrix(publisher) => publisher.target(),
+        }
+    }
+}
+
+/// Hands a call to the publisher of the network.
+macro_rules! on_network {
+    ($self:ident, $publisher:ident => $call:expr) => {
+        match $self {
+            NetworkPublisher::Mastodon($publisher) => $call.await,
+            NetworkPublisher::Matrix($publisher) => $call.await,
+        }
+    };
+}
+
+impl Publisher for NetworkPublisher {
+    async fn publish(&self, status: NewStatus) -> Result<Posted> {
+        on_network!(self, publisher => publisher.publish(status))
+    }
+
+    async fn publish_with_media(&self, status: NewStatus, media: Media) -> Result<Posted> {
+        on_network!(self, publisher => publisher.publish_with_media(status, media))
+    }
+
+    async fn edit(&self, id: &str, status: NewStatus) -> Result<Posted> {
+        on_network!(self, publisher => publisher.edit(id, status))
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        on_network!(self, publisher => publisher.delete(id))
+    }
+
+    async fn publish_poll(&self, status: NewStatus, poll: NewPoll) -> Result<Posted> {
+        on_network!(self, publisher => publisher.publish_poll(status, poll))
+    }
+
+    async fn poll_votes(&self, id: &str) -> Result<Vec<u64>> {
+        on_network!(self, publisher => publisher.poll_votes(id))
+    }
+
+    async fn max_status_chars(&self) -> Result<Option<usize>> {
+        on_network!(self, publisher => publisher.max_status_chars())
+    }
+
+    async fn profile_fields(&self) -> Result<Vec<ProfileField>> {
+        on_network!(self, publisher => publisher.profile_fields())
+    }
+
+    async fn set_profile_fields(&self, fields: Vec<ProfileField>) -> Result<()> {
+        on_network!(self, publisher => publisher.set_profile_fields(fields))
+    }
+
+    async fn schedule(
+        &self,
+        status: NewStatus,
+        media: Option<Media>,
+        at: DateTime<Utc>,
+    ) -> Result<Posted> {
+        on_network!(self, publisher => publisher.schedule(status, media, at))
+    }
+
+    async fn scheduled_statuses(&self) -> Result<Vec<ScheduledStatus>> {
+        on_network!(self, publisher => publisher.scheduled_statuses())
+    }
+
+    async fn cancel_scheduled(&self, id: &str) -> Result<()> {
+        on_network!(self, publisher => publisher.cancel_scheduled(id))
+    }
+
+    async fn recent_statuses(&self, limit: usize) -> Result<Vec<RecentStatus>> {
+        on_network!(self, publisher => publisher.recent_statuses(limit))
+    }
+
+    async fn set_pinned(&self, id: &str, pinned: bool) -> Result<()> {
+        on_network!(self, publisher => publisher.set_pinned(id, pinned))
+    }
+
+    async fn mentions(&self, since_id: Option<&str>) -> Result<Vec<Mention>> {
+        on_network!(self, publisher => publisher.mentions(since_id))
+    }
+}
+
+/// A status that went to the backup account because the primary instance
+/// couldn't be reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failover {
+    pub text: String,
+    pub posted: Posted,
+}
+
+/// Publishes to a primary account, retrying and then falling back to a
+/// backup account when the primary's instance is unreachable.
+///
+/// Without a backup, statuses are tried once, like a plain publisher.
+pub struct FailoverPublisher<P, B> {
+    primary: P,
+    backup: Option<B>,
+    retries: u32,
+    retry_delay: Duration,
+    failovers: Mutex<Vec<Failover>>,
+}
+
+impl<P: Publisher, B: Publisher> FailoverPublisher<P, B> {
+    pub fn new(primary: P) -> Self {
+        FailoverPublisher {
+            primary,
+            backup: None,
+            retries: 1,
+            retry_delay: Duration::ZERO,
+            failovers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_backup(mut self, backup: B, retries: u32, retry_delay: Duration) -> Self {
+        self.backup = Some(backup);
+        self.retries = retries.max(1);
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// The statuses that went to the backup since the last call.
+    pub fn take_failovers(&self) -> Vec<Failover> {
+        std::mem::take(&mut *self.failovers.lock().unwrap())
+    }
+}
+
+impl<P: Publisher, B: Publisher> FailoverPublisher<P, B> {
+    async fn publish_to_either(&self, status: NewStatus, media: Option<Media>) -> Result<Posted> {
+        let mut attempt = 1;
+        let error = loop {
+            match publish_to(&self.primary, status.clone(), media.clone()).await {
+                Ok(posted) => return Ok(posted),
+                // It said when it's back, so the post waits for that instead
+                Err(e) if unavailable(&e).is_some() => return Err(e),
+                Err(e) if attempt >= self.retries => break e,
+                Err(e) => {
+                    eprintln!(
+                        "Warning: posting failed (attempt {} of {}): {}",
+                        attempt, self.retries, e
+                    );
+                    tokio::time::sleep(self.retry_delay).await;
+                    attempt += 1;
+                }
+            }
+        };
+
+        let Some(backup) = &self.backup else {
+            return Err(error);
+        };
+
+        eprintln!(
+            "Warning: primary instance unreachable ({}); posting to the backup instance",
+            error
+        );
+        let text = status.status.clone().unwrap_or_default();
+        let posted = publish_to(backup, status, media).await?;
+        self.failovers.lock().unwrap().push(Failover {
+            text,
+            posted: posted.clone(),
+        });
+        Ok(posted)
+    }
+}
+
+async fn publish_to(
+    publisher: &impl Publisher,
+    status: NewStatus,
+    media: Option<Media>,
+) -> Result<Posted> {
+    match media {
+        Some(media) => publisher.publish_with_media(status, media).await,
+        None => publisher.publish(status).await,
+    }
+}
+
+impl<P: Publisher, B: Publisher> Publisher for FailoverPublisher<P, B> {
+    async fn publish(&self, status: NewStatus) -> Result<Posted> {
+        self.publish_to_either(status, None).await
+    }
+
+    async fn publish_with_media(&self, status: NewStatus, media: Media) -> Result<Posted> {
+        self.publish_to_either(status, Some(media)).await
+    }
+
+    async fn edit(&self, id: &str, status: NewStatus) -> Result<Posted> {
+        // Statuses only ever get edited where the recorded id came from
+        self.primary.edit(id, status).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        self.primary.delete(id).await
+    }
+
+    async fn publish_poll(&self, status: NewStatus, poll: NewPoll) -> Result<Posted> {
+        // Votes are read back from the primary, so polls don't fail over
+        self.primary.publish_poll(status, poll).await
+    }
+
+    async fn poll_votes(&self, id: &str) -> Result<Vec<u64>> {
+        self.primary.poll_votes(id).await
+    }
+
+    async fn max_status_chars(&self) -> Result<Option<usize>> {
+        self.primary.max_status_chars().await
+    }
+
+    async fn profile_fields(&self) -> Result<Vec<ProfileField>> {
+        self.primary.profile_fields().await
+    }
+
+    async fn set_profile_fields(&self, fields: Vec<ProfileField>) -> Result<()> {
+        self.primary.set_profile_fields(fields).await
+    }
+
+    async fn schedule(
+        &self,
+        status: NewStatus,
+        media: Option<Media>,
+        at: DateTime<Utc>,
+    ) -> Result<Posted> {
+        // Scheduled statuses are managed on the instance they wait on
+        self.primary.schedule(status, media, at).await
+    }
+
+    async fn scheduled_statuses(&self) -> Result<Vec<ScheduledStatus>> {
+        self.primary.scheduled_statuses().await
+    }
+
+    async fn cancel_scheduled(&self, id: &str) -> Result<()> {
+        self.primary.cancel_scheduled(id).await
+    }
+
+    async fn recent_statuses(&self, limit: usize) -> Result<Vec<RecentStatus>> {
+        self.primary.recent_statuses(limit).await
+    }
+
+    async fn set_pinned(&self, id: &str, pinned: bool) -> Result<()> {
+        self.primary.set_pinned(id, pinned).await
+    }
+
+    async fn mentions(&self, since_id: Option<&str>) -> Result<Vec<Mention>> {
+        self.primary.mentions(since_id).await
+    }
+}
+
+/// What posts for an account: to its network, falling back to the backup
+/// account when one is configured.
+pub type AccountPublisher = FailoverPublisher<NetworkPublisher, MastodonPublisher>;
+
+/// The publisher for the account being posted to: the main one, falling back
+/// to the backup account, or a language account on its own.
+pub fn account_publisher(config: &Config) -> Result<AccountPublisher> {
+    if let Some(account) = config.language_account() {
+        let data = config::load_token_file(&account.token_file)?;
+        return Ok(FailoverPublisher::new(NetworkPublisher::Mastodon(
+            mastodon_publisher(config, data),
+        )));
+    }
+
+    let publisher = FailoverPublisher::new(match config.network {
+        Network::Mastodon => {
+            NetworkPublisher::Mastodon(mastodon_publisher(config, config::load_token(config)?))
+        }
+        Network::Matrix => {
+            let room = config.room.as_deref().ok_or_else(|| {
+                error::invalid("a Matrix account needs the room to send messages to")
+            })?;
+            let token = match &config.access_token {
+                Some(token) => token.clone(),
+                None => matrix::load_token_file(&config.token_file)
+                    .map_err(|e| error::invalid(e.to_string()))?,
+            };
+            NetworkPublisher::Matrix(
+                MatrixPublisher::new(&config.instance, &token, room)
+                    .with_dry_run(config.dry_run)
+                    .with_url_policy(Some(config.url_policy.clone())),
+            )
+        }
+    });
+
+    match &config.backup {
+        Some(backup) => {
+            let data = config::load_token_file(&backup.token_file)?;
+            Ok(publisher.with_backup(
+                mastodon_publisher(config, data),
+                backup.retries,
+                Duration::from_secs(backup.retry_delay_seconds),
+            ))
+        }
+        None => Ok(publisher),
+    }
+}
+
+fn mastodon_publisher(config: &Config, data: mastodon_async::Data) -> MastodonPublisher {
+    MastodonPublisher::new(Mastodon::from(data))
+        .with_dry_run(config.dry_run)
+        .with_url_policy(Some(config.url_policy.clone()))
+}
+
+/// Identifies a file by its contents and description.
+fn media_key(media: &Media) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (&media.data, &media.description).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A plain status with just the given text.
+pub fn text_status(text: String) -> NewStatus {
+    NewStatus {
+        status: Some(text),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_path() {
+        let first = upload_path("event.png");
+        let second = upload_path("event.png");
+        assert_ne!(first, second);
+        assert!(first.to_string_lossy().ends_with("event.png"));
+    }
+
+    /// An instance that fails the first `failed_uploads` uploads, and then
+    /// processes a file for `polls` checks.
+    #[derive(Default)]
+    struct SlowInstance {
+        failed_uploads: u32,
+        polls: u32,
+        uploads: Mutex<u32>,
+        checks: Mutex<u32>,
+    }
+
+    impl MediaSteps for SlowInstance {
+        type Uploaded = String;
+
+        async fn send_media(&self, media: &Media) -> anyhow::Result<String> {
+            let mut uploads = self.uploads.lock().unwrap();
+            *uploads += 1;
+            match *uploads <= self.failed_uploads {
+                true => Err(anyhow!("connection reset")),
+                false => Ok(format!("{}-{}", media.file_name, uploads)),
+            }
+        }
+
+        async fn processed(&self, uploaded: &String) -> anyhow::Result<Option<String>> {
+            let mut checks = self.checks.lock().unwrap();
+            *checks += 1;
+            Ok((*checks > self.polls).then(|| uploaded.clone()))
+        }
+    }
+
+    fn timing() -> MediaTiming {
+        MediaTiming {
+            attempts: 3,
+            retry_delay: Duration::from_millis(1),
+            poll_interval: Duration::from_millis(1),
+            processing_timeout: Duration::from_millis(200),
+        }
+    }
+
+    fn flyer() -> Media {
+        Media {
+            data: vec![0; 16],
+            file_name: "flyer.png".to_string(),
+            description: "Flyer".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_waits_for_processing() {
+        let instance = SlowInstance {
+            polls: 2,
+            ..Default::default()
+        };
+        let id = upload_with_retries(&instance, &flyer(), &timing()).await;
+        assert_eq!(id.unwrap(), "flyer.png-1");
+        assert_eq!(*instance.uploads.lock().unwrap(), 1);
+        assert_eq!(*instance.checks.lock().unwrap(), 3);
+
+        // A failed upload is tried again, until it has been tried too often
+        let instance = SlowInstance {
+            failed_uploads: 2,
+            ..Default::default()
+        };
+        let id = upload_with_retries(&instance, &flyer(), &timing()).await;
+        assert_eq!(id.unwrap(), "flyer.png-3");
+        let instance = SlowInstance {
+            failed_uploads: 3,
+            ..Default::default()
+        };
+        let error = upload_with_retries(&instance, &flyer(), &timing()).await;
+        assert_eq!(error.unwrap_err().to_string(), "connection reset");
+        assert_eq!(*instance.uploads.lock().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_upload_processing_too_long() {
+        let instance = SlowInstance {
+            polls: u32::MAX,
+            ..Default::default()
+        };
+        let timing = MediaTiming {
+            processing_timeout: Duration::from_millis(20),
+            ..timing()
+        };
+        let error = upload_with_retries(&instance, &flyer(), &timing).await;
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "The instance took too long to process flyer.png"
+        );
+        // Each retry waits for the file sent the first time
+        assert_eq!(*instance.uploads.lock().unwrap(), 1);
+    }
+}
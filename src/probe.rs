@@ -0,0 +1,131 @@
+use crate::config::{Network, ProbeConfig, UrlPolicyConfig};
+use crate::{scope, url_policy};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// What asking the instance, or its status page, found out.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Probe {
+    pub url: String,
+    pub healthy: bool,
+    /// The HTTP status it answered with, if it answered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Why it isn't taken to be healthy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub problem: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// The URL to ask: the configured status page, or the instance's own
+/// description of itself.
+pub fn url(config: &ProbeConfig, instance: &str, network: Network) -> String {
+    if let Some(url) = &config.status_url {
+        return url.clone();
+    }
+    let instance = instance.trim_end_matches('/');
+    match network {
+        Network::Mastodon => format!("{}/api/v1/instance", instance),
+        Network::Matrix => format!("{}/_matrix/client/versions", instance),
+    }
+}
+
+/// What is wrong with an answer of `status` and `body`: an error status, or
+/// a status page in the Statuspage format whose indicator isn't `none`.
+fn problem(status: u16, body: &str) -> Option<String> {
+    if !(200..300).contains(&status) {
+        return Some(format!("answered HTTP {}", status));
+    }
+    let page: serde_json::Value = serde_json::from_str(body).ok()?;
+    let indicator = page["status"]["indicator"].as_str()?;
+    (indicator != "none").then(|| match page["status"]["description"].as_str() {
+        Some(description) => format!("reports {} ({})", indicator, description),
+        None => format!("reports {}", indicator),
+    })
+}
+
+/// Asks whether the instance is up to a thread of posts. Each URL is only
+/// asked once per run; one that can't be reached in time, or that `policy`
+/// doesn't allow, is degraded.
+pub async fn probe(
+    config: &ProbeConfig,
+    instance: &str,
+    network: Network,
+    policy: Option<&UrlPolicyConfig>,
+) -> Probe {
+    let url = url(config, instance, network);
+    let records = scope::current();
+    if let Some(probe) = records.probed.lock().unwrap().get(&url) {
+        return probe.clone();
+    }
+    let started = Instant::now();
+    let (status, problem) = match url_policy::client(policy, &url) {
+        Ok(client) => {
+            let answer = client
+                .get(&url)
+                .timeout(Duration::from_secs(config.timeout_seconds))
+                .send()
+                .await;
+            match answer {
+                Ok(response) => {
+                    let status = response.status().as_u16();
+                    let body = response.text().await.unwrap_or_default();
+                    (Some(status), problem(status, &body))
+                }
+                Err(e) if e.is_timeout() => (None, Some("didn't answer in time".to_string())),
+                Err(e) => (None, Some(format!("couldn't be reached: {}", e))),
+            }
+        }
+        Err(e) => (None, Some(format!("may not be asked: {}", e))),
+    };
+    let probe = Probe {
+        url: url.clone(),
+        healthy: problem.is_none(),
+        status,
+        problem,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    };
+    records.probed.lock().unwrap().insert(url, probe.clone());
+    probe
+}
+
+/// The probes made so far in this run.
+pub fn summary() -> Vec<Probe> {
+    scope::current()
+        .probed
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem() {
+        assert_eq!(problem(200, r#"{"uri": "mastodon.example"}"#), None);
+        assert_eq!(problem(200, "<html>All good</html>"), None);
+        assert_eq!(problem(502, "").as_deref(), Some("answered HTTP 502"));
+        assert_eq!(problem(200, r#"{"status": {"indicator": "none"}}"#), None);
+        assert_eq!(
+            problem(
+                200,
+                r#"{"status": {"indicator": "minor", "description": "Degraded Performance"}}"#
+            )
+            .as_deref(),
+            Some("reports minor (Degraded Performance)")
+        );
+
+        let config = ProbeConfig {
+            status_url: None,
+            timeout_seconds: 10,
+        };
+        assert_eq!(
+            url(&config, "https://mastodon.example/", Network::Mastodon),
+            "https://mastodon.example/api/v1/instance"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::config::{Config, ProfileConfig};
+use crate::fetch;
+use crate::ical::CalendarEvent;
+use crate::poster::ProfileField;
+use anyhow::{Result, anyhow};
+
+/// How many fields a Mastodon profile has room for.
+pub const MAX_FIELDS: usize = 4;
+
+/// The fields `sync-profile` keeps up to date, with `next` the next meeting
+/// the account announces.
+pub fn fields(
+    config: &Config,
+    profile: &ProfileConfig,
+    next: Option<&CalendarEvent>,
+) -> Vec<ProfileField> {
+    let calendar_url = profile
+        .calendar_url
+        .as_deref()
+        .or_else(|| config.subscription_post.as_ref()?.url.as_deref())
+        .unwrap_or(&config.webcal);
+    let next_meeting = next
+        .and_then(CalendarEvent::start_time_formatted)
+        .unwrap_or_else(|| profile.no_meeting.clone());
+
+    [
+        (&profile.next_meeting_field, Some(next_meeting)),
+        // Profiles only link http(s) URLs, not webcal:// ones
+        (
+            &profile.calendar_field,
+            Some(fetch::normalize_url(calendar_url)),
+        ),
+        (&profile.website_field, profile.website.clone()),
+    ]
+    .into_iter()
+    .filter(|(name, _)| !name.is_empty())
+    .filter_map(|(name, value)| {
+        Some(ProfileField {
+            name: name.clone(),
+            value: value?,
+        })
+    })
+    .collect()
+}
+
+/// The profile's `current` fields with `ours` in place of those of the same
+/// name (ignoring case), and added after them when they are new.
+pub fn merge(current: &[ProfileField], ours: Vec<ProfileField>) -> Result<Vec<ProfileField>> {
+    let mut merged = current.to_vec();
+    for field in ours {
+        let existing = merged
+            .iter()
+            .position(|existing| existing.name.eq_ignore_ascii_case(&field.name));
+        match existing {
+            Some(index) => merged[index].value = field.value,
+            None if merged.len() < MAX_FIELDS => merged.push(field),
+            None => {
+                return Err(anyhow!(
+                    "The profile has no room for the '{}' field; remove one of its {} fields or leave it out of [profile]",
+                    field.name,
+                    MAX_FIELDS
+                ));
+            }
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, value: &str) -> ProfileField {
+        ProfileField {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let current = vec![
+            field("Pronouns", "it/its"),
+            field("next meeting", "Wed, Dec 03, 2025 at 07:00 PM"),
+        ];
+        let merged = merge(
+            &current,
+            vec![
+                field("Next meeting", "Wed, Jan 07, 2026 at 07:00 PM"),
+                field("Calendar", "https://example.com/calendar.ics"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            merged,
+            vec![
+                field("Pronouns", "it/its"),
+                field("next meeting", "Wed, Jan 07, 2026 at 07:00 PM"),
+                field("Calendar", "https://example.com/calendar.ics"),
+            ]
+        );
+
+        let full: Vec<_> = (1..=4).map(|n| field(&n.to_string(), "")).collect();
+        assert!(merge(&full, vec![field("Calendar", "")]).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::config::{Config, QuietHoursConfig};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+
+/// The configured timezone.
+pub fn timezone(config: &Config) -> Result<Tz> {
+    config
+        .timezone
+        .parse()
+        .map_err(|e| anyhow!("Invalid timezone {}: {}", config.timezone, e))
+}
+
+/// The configured timezone times are shown in, if any.
+pub fn display_timezone(config: &Config) -> Result<Option<Tz>> {
+    config
+        .display_timezone
+        .as_deref()
+        .map(|name| {
+            name.parse()
+                .map_err(|e| anyhow!("Invalid display_timezone {}: {}", name, e))
+        })
+        .transpose()
+}
+
+/// Whether `now` falls outside the posting windows of the quiet hours
+/// schedule, or in a maintenance window of the instance. Without either the
+/// bot is never quiet.
+pub fn is_quiet(config: &Config, now: DateTime<Utc>) -> Result<bool> {
+    if in_maintenance(config, now) {
+        return Ok(true);
+    }
+    let Some(quiet_hours) = &config.quiet_hours else {
+        return Ok(false);
+    };
+    let local = now.with_timezone(&timezone(config)?);
+    let Some(windows) = windows(quiet_hours, local.weekday()) else {
+        return Ok(false);
+    };
+
+    let time = local.time();
+    for window in windows {
+        let (start, end) = parse_window(window)?;
+        if start <= time && end.is_none_or(|end| time < end) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether `now` falls in one of the configured maintenance windows.
+pub fn in_maintenance(config: &Config, now: DateTime<Utc>) -> bool {
+    config
+        .maintenance
+        .iter()
+        .any(|window| window.start <= now && now < window.end)
+}
+
+fn windows(config: &QuietHoursConfig, weekday: Weekday) -> Option<&[String]> {
+    let windows = match weekday {
+        Weekday::Mon => &config.mon,
+        Weekday::Tue => &config.tue,
+        Weekday::Wed => &config.wed,
+        Weekday::Thu => &config.thu,
+        Weekday::Fri => &config.fri,
+        Weekday::Sat => &config.sat,
+        Weekday::Sun => &config.sun,
+    };
+    windows.as_deref()
+}
+
+/// Parses a window like `08:00-20:00` into its start and end, where an end of
+/// `24:00` (midnight at the end of the day) is returned as `None`.
+fn parse_window(window: &str) -> Result<(NaiveTime, Option<NaiveTime>)> {
+    let invalid = || {
+        anyhow!(
+            "Invalid quiet_hours window {:?}, expected e.g. \"08:00-20:00\"",
+            window
+        )
+    };
+    let (start, end) = window.split_once('-').ok_or_else(invalid)?;
+    let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|_| invalid())?;
+    let end = match end.trim() {
+        "24:00" => None,
+        end => Some(NaiveTime::parse_from_str(end, "%H:%M").map_err(|_| invalid())?),
+    };
+    if end.is_some_and(|end| end <= start) {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use indoc::indoc;
+
+    fn config() -> Config {
+        toml::from_str(indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+            timezone = "America/New_York"
+
+            [quiet_hours]
+            mon = ["08:00-12:00", "13:00-20:00"]
+            tue = ["08:00-20:00"]
+            sat = []
+            sun = ["18:00-24:00"]
+
+            [[maintenance]]
+            start = "2025-12-04T02:00:00Z"
+            end = "2025-12-04T04:00:00Z"
+        "#})
+        .unwrap()
+    }
+
+    #[test]
+    fn test_is_quiet() {
+        let config = config();
+        let quiet = |month, day, hour, min| {
+            let now = Utc
+                .with_ymd_and_hms(2025, month, day, hour, min, 0)
+                .unwrap();
+            is_quiet(&config, now).unwrap()
+        };
+
+        // Monday, December 1st: 08:00 in New York is 13:00 UTC
+        assert!(quiet(12, 1, 12, 59));
+        assert!(!quiet(12, 1, 13, 0));
+        assert!(quiet(12, 1, 17, 30));
+        assert!(quiet(12, 2, 1, 0));
+        // Wednesday isn't restricted
+        assert!(!quiet(12, 3, 15, 0));
+        // Saturday is quiet all day, Sunday until the evening
+        assert!(quiet(12, 6, 17, 0));
+        assert!(quiet(12, 7, 22, 59));
+        assert!(!quiet(12, 8, 4, 59));
+        // Thursday isn't restricted either, but for maintenance
+        assert!(!quiet(12, 4, 1, 59));
+        assert!(quiet(12, 4, 2, 0));
+        assert!(!quiet(12, 4, 4, 0));
+    }
+
+    #[test]
+    fn test_invalid_window() {
+        assert!(parse_window("20:00-08:00").is_err());
+        assert!(parse_window("8am-5pm").is_err());
+        assert!(parse_window("08:00-24:00").unwrap().1.is_none());
+    }
+}
@@ -0,0 +1,238 @@
+use crate::config::Config;
+use crate::formatter::{self, MAX_STATUS_CHARS};
+use crate::ical::CalendarEvent;
+use crate::template::{Context, Template};
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+/// The state key kind recaps are remembered under.
+pub const KIND: &str = "recap";
+
+/// Default template for the recap of a past period.
+pub const RECAP_TEMPLATE: &str = "🗓️ {#if events}Meetings {period} ({count}):\n\n\
+{#each events}{#unless first}\n\n{/unless}\
+📅 {#if summary}{summary}{else}Meeting{/if}\
+{#if start}\n🕒 {start}{/if}\
+{#if minutes_url}\n📝 Minutes: {minutes_url}{/if}\
+{/each}\
+{#if more}\n\n…and {more} more{/if}\
+{else}No meetings {period}{/if}";
+
+/// The days a recap is about: from the start of `from` to the start of `to`,
+/// in the configured timezone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Period {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// How the recap names the period, like `last week`.
+    pub label: String,
+}
+
+impl Period {
+    /// The week before the one `today` is in, for weeks starting on
+    /// `week_start`. With `numbered` it is named by its number.
+    pub fn last_week(today: NaiveDate, week_start: Weekday, numbered: bool) -> Self {
+        let since_start = today.weekday().days_since(week_start);
+        let this_week = today - Duration::days(since_start.into());
+        let from = this_week - Duration::weeks(1);
+        Period {
+            from,
+            to: this_week,
+            label: match numbered {
+                true => format!("in week {}", formatter::week_of(from, week_start).week()),
+                false => "last week".to_string(),
+            },
+        }
+    }
+
+    /// The `days` days before `today`.
+    pub fn last_days(today: NaiveDate, days: i64) -> Self {
+        Period {
+            from: today - Duration::days(days),
+            to: today,
+            label: match days {
+                1 => "yesterday".to_string(),
+                days => format!("in the last {} days", days),
+            },
+        }
+    }
+
+    /// The key the recap of this period is remembered under.
+    pub fn key(&self) -> String {
+        format!(
+            "{}:{}-{}",
+            KIND,
+            self.from.format("%Y%m%d"),
+            self.to.format("%Y%m%d")
+        )
+    }
+
+    /// When the period starts and ends in `timezone`.
+    pub fn bounds(&self, timezone: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+        let midnight = |date: NaiveDate| {
+            let naive = date.and_hms_opt(0, 0, 0).expect("midnight exists");
+            // Where DST skips midnight, the day starts at the end of the gap
+            timezone
+                .from_local_datetime(&naive)
+                .earliest()
+                .or_else(|| {
+                    timezone
+                        .from_local_datetime(&(naive + Duration::hours(1)))
+                        .earliest()
+                })
+                .map(|start| start.with_timezone(&Utc))
+                .unwrap_or_else(|| Utc.from_utc_datetime(&naive))
+        };
+        (midnight(self.from), midnight(self.to))
+    }
+}
+
+/// Where the minutes of an event are: listed in `[recap]` by its UID or
+/// summary, or else the `[minutes]` link.
+pub fn minutes_url(config: &Config, event: &CalendarEvent) -> Option<String> {
+    let listed = config.recap.as_ref().and_then(|recap| {
+        event
+            .uid
+            .as_ref()
+            .and_then(|uid| recap.minutes.get(uid))
+            .or_else(|| {
+                recap
+                    .minutes
+                    .iter()
+                    .find(|(summary, _)| event.summary_matches(std::slice::from_ref(summary)))
+                    .map(|(_, url)| url)
+            })
+    });
+    listed
+        .cloned()
+        .or_else(|| config.minutes.as_ref()?.url.clone())
+}
+
+/// The template the recap is rendered from: the configured one, or the default.
+pub fn template_source(config: &Config) -> &str {
+    config
+        .recap
+        .as_ref()
+        .and_then(|recap| recap.template.as_deref())
+        .unwrap_or(RECAP_TEMPLATE)
+}
+
+/// The variables of a recap of the `meetings` of a period, each with where
+/// its minutes are, and `more` of them left out.
+pub fn context(label: &str, meetings: &[(&CalendarEvent, Option<String>)], more: usize) -> Context {
+    let events = meetings
+        .iter()
+        .map(|(event, minutes_url)| {
+            let mut context = formatter::event_context(event);
+            context.set_opt_text("minutes_url", minutes_url.clone());
+            context
+        })
+        .collect();
+
+    let mut context = Context::new();
+    context
+        .set_text("period", label)
+        .set_text("count", (meetings.len() + more).to_string())
+        .set_list("events", events)
+        .set_opt_text("more", (more > 0).then(|| more.to_string()));
+    context
+}
+
+/// Renders the recap of the meetings of `period`, leaving the last ones out
+/// until it fits in a status.
+pub fn render(config: &Config, period: &Period, events: &[&CalendarEvent]) -> Result<String> {
+    let template = Template::parse(template_source(config))?;
+    let meetings: Vec<_> = events
+        .iter()
+        .map(|event| (*event, minutes_url(config, event)))
+        .collect();
+
+    let mut shown = meetings.len();
+    loop {
+        let context = context(&period.label, &meetings[..shown], meetings.len() - shown);
+        let status = template.render(&context);
+        if shown <= 1 || formatter::status_length(&status) <= MAX_STATUS_CHARS {
+            return Ok(status);
+        }
+        shown -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_periods() {
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2025, 12, 10).unwrap();
+        let week = Period::last_week(today, Weekday::Mon, false);
+        assert_eq!(week.from, NaiveDate::from_ymd_opt(2025, 12, 1).unwrap());
+        assert_eq!(week.to, NaiveDate::from_ymd_opt(2025, 12, 8).unwrap());
+        assert_eq!(week.key(), "recap:20251201-20251208");
+        assert_eq!(
+            Period::last_week(
+                NaiveDate::from_ymd_opt(2025, 12, 8).unwrap(),
+                Weekday::Mon,
+                false
+            ),
+            week
+        );
+
+        let (from, to) = week.bounds(chrono_tz::Europe::Berlin);
+        assert_eq!(from, Utc.with_ymd_and_hms(2025, 11, 30, 23, 0, 0).unwrap());
+        assert_eq!(to, Utc.with_ymd_and_hms(2025, 12, 7, 23, 0, 0).unwrap());
+
+        // Weeks starting on Sunday, named by their number
+        let week = Period::last_week(today, Weekday::Sun, true);
+        assert_eq!(week.from, NaiveDate::from_ymd_opt(2025, 11, 30).unwrap());
+        assert_eq!(week.to, NaiveDate::from_ymd_opt(2025, 12, 7).unwrap());
+        assert_eq!(week.label, "in week 49");
+
+        assert_eq!(Period::last_days(today, 1).label, "yesterday");
+        assert_eq!(
+            Period::last_days(today, 14).from,
+            NaiveDate::from_ymd_opt(2025, 11, 26).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let config: Config = toml::from_str(indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            [minutes]
+            url = "https://example.com/minutes"
+
+            [recap.minutes]
+            "install fest" = "https://example.com/install-fest"
+        "#})
+        .unwrap();
+        let event = |summary: &str, start: &str| CalendarEvent {
+            summary: Some(summary.to_string()),
+            start_time: Some(start.to_string()),
+            ..Default::default()
+        };
+        let meeting = event("Weekly Meeting", "20251203T190000Z");
+        let fest = event("Install Fest", "20251206T140000Z");
+        let period = Period::last_week(
+            NaiveDate::from_ymd_opt(2025, 12, 10).unwrap(),
+            Weekday::Mon,
+            false,
+        );
+
+        assert_eq!(
+            render(&config, &period, &[&meeting, &fest]).unwrap(),
+            "🗓️ Meetings last week (2):\n\n\
+             📅 Weekly Meeting\n🕒 Wed, Dec 03, 2025 at 07:00 PM UTC\n📝 Minutes: https://example.com/minutes\n\n\
+             📅 Install Fest\n🕒 Sat, Dec 06, 2025 at 02:00 PM UTC\n📝 Minutes: https://example.com/install-fest"
+        );
+        assert_eq!(
+            render(&config, &period, &[]).unwrap(),
+            "🗓️ No meetings last week"
+        );
+    }
+}
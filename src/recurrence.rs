@@ -0,0 +1,753 @@
+use crate::ical::{self, CalendarEvent, IcalCalendar, TimeForm};
+use crate::state::{CachedOccurrences, State, fingerprint};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use std::collections::HashSet;
+
+/// The most occurrences generated for one recurring event in one window.
+pub const MAX_OCCURRENCES: u16 = 1000;
+
+/// The span of time recurring events are expanded over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Window {
+    /// The window from `lookback` before `now` to `lookahead` after it.
+    pub fn around(now: DateTime<Utc>, lookback: Duration, lookahead: Duration) -> Self {
+        Window {
+            start: now - lookback,
+            end: now + lookahead,
+        }
+    }
+
+    /// This window widened to whole days, so that runs within the same day
+    /// can reuse the cached expansion.
+    fn widened(&self) -> Self {
+        let day_start = |t: DateTime<Utc>| {
+            Utc.from_utc_datetime(&t.date_naive().and_time(chrono::NaiveTime::MIN))
+        };
+        Window {
+            start: day_start(self.start),
+            end: day_start(self.end) + Duration::days(1),
+        }
+    }
+
+    fn contains(&self, other: &Window) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    /// Whether an iCal start time falls inside the window, reading floating
+    /// and all-day times as UTC like the expansion does.
+    fn contains_start(&self, start: &str) -> bool {
+        ical::parse_ical_time(start).is_some_and(|(time, _)| {
+            let time = Utc.from_utc_datetime(&time);
+            self.start <= time && time <= self.end
+        })
+    }
+}
+
+/// How expanding one recurring event went.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RuleStats {
+    /// Occurrences generated inside the window.
+    pub generated: usize,
+    /// Whether the rule has occurrences past the end of the window, which is
+    /// normal for rules without COUNT or UNTIL.
+    pub truncated_at_horizon: bool,
+    /// Whether expansion stopped at [`MAX_OCCURRENCES`] before reaching the
+    /// end of the window, so occurrences inside it were dropped.
+    pub hit_limit: bool,
+}
+
+/// The occurrences of a recurring event inside a window.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Occurrences {
+    /// Start times in the same form as the event's DTSTART.
+    pub starts: Vec<String>,
+    pub stats: RuleStats,
+}
+
+/// Expands a recurring event over the window, never past its end, however
+/// far the rule itself goes on. RDATEs are added to the RRULE occurrences;
+/// an event with RDATEs but no RRULE also occurs at its DTSTART. EXDATEs
+/// are then taken out.
+pub fn occurrence_starts(event: &CalendarEvent, window: &Window) -> Result<Occurrences> {
+    if !event.is_recurring() {
+        return Err(anyhow!("Event has no RRULE or RDATE"));
+    }
+    let (start, form) = event
+        .start_time
+        .as_deref()
+        .and_then(ical::parse_ical_time)
+        .ok_or_else(|| anyhow!("Recurring event has no valid DTSTART"))?;
+
+    let mut stats = RuleStats::default();
+    let mut dates = Vec::new();
+    if let Some(rule) = &event.rrule {
+        // Floating and all-day times are expanded as if they were UTC, which keeps
+        // them on the same wall-clock time and is how they are compared elsewhere.
+        // Times read with a TZID are expanded in that zone, so a weekly 7pm
+        // meeting stays at 7pm local time when the clocks change.
+        let dt_start = match (event.timezone, form) {
+            (Some(zone), TimeForm::Utc) => to_rrule_time(start).with_timezone(&rrule::Tz::Tz(zone)),
+            _ => to_rrule_time(start),
+        };
+        let (rule, _) = normalize_until(rule, form);
+        let rule: rrule::RRule<rrule::Unvalidated> = rule.parse()?;
+        let set = rule.build(dt_start)?;
+        let horizon = to_rrule_time(window.end.naive_utc());
+        let result = set
+            .clone()
+            .after(to_rrule_time(window.start.naive_utc()))
+            .before(horizon)
+            .all(MAX_OCCURRENCES);
+        let beyond = set.after(horizon + Duration::seconds(1)).all(1);
+
+        stats.truncated_at_horizon = !beyond.dates.is_empty();
+        stats.hit_limit = result.limited;
+        dates.extend(result.dates.iter().map(|date| date.naive_utc()));
+    } else {
+        dates.push(start);
+    }
+
+    // Dates that can't be read are skipped, validate-calendar reports them
+    let read = |property, values: &[String]| -> Vec<NaiveDateTime> {
+        values
+            .iter()
+            .filter_map(|value| normalize_date(property, value, start, form))
+            .map(|(date, _)| date)
+            .collect()
+    };
+    dates.extend(read("RDATE", &event.rdates));
+    let excluded = read("EXDATE", &event.exdates);
+    dates.retain(|date| {
+        if excluded.contains(date) {
+            return false;
+        }
+        let date = Utc.from_utc_datetime(date);
+        stats.truncated_at_horizon |= date > window.end;
+        window.start <= date && date <= window.end
+    });
+    dates.sort();
+    dates.dedup();
+
+    stats.generated = dates.len();
+    let starts = dates
+        .into_iter()
+        .map(|date| ical::format_ical_time(date, form))
+        .collect();
+    Ok(Occurrences { starts, stats })
+}
+
+/// Reads an RDATE or EXDATE (named by `property`) the way DTSTART is
+/// written, describing any mismatch like [`normalize_until`] does. A date on
+/// a date-time series takes the time of DTSTART, and a date-time on an
+/// all-day series only its date.
+pub fn normalize_date(
+    property: &str,
+    value: &str,
+    start: NaiveDateTime,
+    start_form: TimeForm,
+) -> Option<(NaiveDateTime, Option<String>)> {
+    let (date, rdate_form) = ical::parse_ical_time(value)?;
+    let date = match (rdate_form, start_form) {
+        (TimeForm::Date, TimeForm::Utc | TimeForm::Floating) => date.date().and_time(start.time()),
+        (TimeForm::Utc | TimeForm::Floating, TimeForm::Date) => {
+            date.date().and_time(chrono::NaiveTime::MIN)
+        }
+        _ => date,
+    };
+    let mismatch = (rdate_form != start_form).then(|| {
+        format!(
+            "{} {} is {} but DTSTART is {}; reading it as {}",
+            property,
+            value,
+            describe(rdate_form),
+            describe(start_form),
+            date.format("%Y-%m-%d %H:%M:%S"),
+        )
+    });
+    Some((date, mismatch))
+}
+
+/// Rewrites the UNTIL of a rule into the UTC date-time the expansion needs,
+/// since it expands every DTSTART as UTC.
+///
+/// RFC 5545 wants UNTIL written the same way as DTSTART: a date for all-day
+/// events, UTC for UTC starts and floating for floating ones. When a feed
+/// disagrees, a description of the mismatch is returned too. A date UNTIL on
+/// a date-time series is taken to include that whole day, which is what the
+/// feed meant more often than not.
+pub fn normalize_until(rule: &str, start_form: TimeForm) -> (String, Option<String>) {
+    let mut mismatch = None;
+    let parts: Vec<String> = rule
+        .split(';')
+        .map(|part| {
+            let Some((name, value)) = part.split_once('=') else {
+                return part.to_string();
+            };
+            if !name.eq_ignore_ascii_case("UNTIL") {
+                return part.to_string();
+            }
+            let Some((until, until_form)) = ical::parse_ical_time(value) else {
+                return part.to_string();
+            };
+
+            let until = match (until_form, start_form) {
+                (TimeForm::Date, TimeForm::Utc | TimeForm::Floating) => {
+                    until.date().and_hms_opt(23, 59, 59).unwrap_or(until)
+                }
+                _ => until,
+            };
+            if until_form != start_form {
+                mismatch = Some(format!(
+                    "UNTIL={} is {} but DTSTART is {}; reading it as {}",
+                    value,
+                    describe(until_form),
+                    describe(start_form),
+                    until.format("%Y-%m-%d %H:%M:%S"),
+                ));
+            }
+            format!("{}={}", name, ical::format_ical_time(until, TimeForm::Utc))
+        })
+        .collect();
+    (parts.join(";"), mismatch)
+}
+
+fn describe(form: TimeForm) -> &'static str {
+    match form {
+        TimeForm::Utc => "a UTC date-time",
+        TimeForm::Floating => "a date-time without a timezone",
+        TimeForm::Date => "a date",
+    }
+}
+
+fn warn_about_rule(event: &CalendarEvent, window: &Window, stats: &RuleStats) {
+    if stats.hit_limit {
+        eprintln!(
+            "Warning: recurring event {} ({}) generated {} occurrences before {}, the most allowed; \
+             later ones inside the lookahead were dropped{}",
+            event.summary.as_deref().unwrap_or("(no summary)"),
+            event.rrule.as_deref().unwrap_or(""),
+            stats.generated,
+            window.end.format("%Y-%m-%d"),
+            if stats.truncated_at_horizon {
+                " (the rule also continues past the lookahead)"
+            } else {
+                ""
+            }
+        );
+    }
+}
+
+fn to_rrule_time(time: NaiveDateTime) -> DateTime<rrule::Tz> {
+    Utc.from_utc_datetime(&time).with_timezone(&rrule::Tz::UTC)
+}
+
+/// A copy of `event` moved to start at `start`, keeping its duration.
+fn occurrence(event: &CalendarEvent, start: &str) -> CalendarEvent {
+    let end_time = match (
+        event.start_time.as_deref().and_then(ical::parse_ical_time),
+        event.end_time.as_deref().and_then(ical::parse_ical_time),
+        ical::parse_ical_time(start),
+    ) {
+        (Some((original_start, _)), Some((original_end, end_form)), Some((new_start, _))) => Some(
+            ical::format_ical_time(new_start + (original_end - original_start), end_form),
+        ),
+        _ => event.end_time.clone(),
+    };
+
+    CalendarEvent {
+        start_time: Some(start.to_string()),
+        end_time,
+        rrule: None,
+        rdates: Vec::new(),
+        exdates: Vec::new(),
+        ..event.clone()
+    }
+}
+
+fn cache_key(event: &CalendarEvent) -> String {
+    event
+        .uid
+        .clone()
+        .unwrap_or_else(|| event.summary.clone().unwrap_or_default())
+}
+
+fn rule_fingerprint(event: &CalendarEvent) -> String {
+    let mut fingerprinted = format!(
+        "{}\n{}",
+        event.start_time.as_deref().unwrap_or(""),
+        event.rrule.as_deref().unwrap_or("")
+    );
+    // Only added when present, so caches of RRULE-only series stay valid
+    if !event.rdates.is_empty() {
+        fingerprinted = format!("{}\n{}", fingerprinted, event.rdates.join(","));
+    }
+    if !event.exdates.is_empty() {
+        fingerprinted = format!("{}\nEXDATE:{}", fingerprinted, event.exdates.join(","));
+    }
+    if let Some(zone) = event.timezone {
+        fingerprinted = format!("{}\nTZID:{}", fingerprinted, zone.name());
+    }
+    fingerprint(&fingerprinted)
+}
+
+/// Replaces every recurring event in the calendar with its occurrences inside
+/// the window.
+///
+/// Expansions are cached in the state per UID and reused until the rule,
+/// RDATEs, EXDATEs, DTSTART, its zone or SEQUENCE changes, or the window moves past what was cached.
+/// Returns whether the cache in the state changed.
+pub fn expand_calendar(calendar: &mut IcalCalendar, window: &Window, state: &mut State) -> bool {
+    let mut changed = false;
+    let mut expanded = Vec::with_capacity(calendar.events.len());
+    let mut seen = Vec::new();
+    // Occurrences moved, changed or cancelled by events of their own
+    let overridden: HashSet<(String, String)> = calendar
+        .events
+        .iter()
+        .filter_map(|event| Some((cache_key(event), event.recurrence_id.clone()?)))
+        .collect();
+
+    for event in calendar.events.drain(..) {
+        if !event.is_recurring() {
+            expanded.push(event);
+            continue;
+        }
+
+        let key = cache_key(&event);
+        let rule_fingerprint = rule_fingerprint(&event);
+        let sequence = event.sequence.unwrap_or(0);
+        seen.push(key.clone());
+
+        let cached = state.occurrences.get(&key).filter(|cached| {
+            cached.fingerprint == rule_fingerprint
+                && cached.sequence == sequence
+                && cached.window().contains(window)
+        });
+
+        let starts = match cached {
+            Some(cached) => cached.starts.clone(),
+            None => {
+                let widened = window.widened();
+                match occurrence_starts(&event, &widened) {
+                    Ok(Occurrences { starts, stats }) => {
+                        warn_about_rule(&event, &widened, &stats);
+                        state.occurrences.insert(
+                            key,
+                            CachedOccurrences {
+                                fingerprint: rule_fingerprint,
+                                sequence,
+                                window_start: widened.start,
+                                window_end: widened.end,
+                                starts: starts.clone(),
+                            },
+                        );
+                        changed = true;
+                        starts
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "Warning: could not expand recurring event {}: {}",
+                            event.summary.as_deref().unwrap_or("(no summary)"),
+                            e
+                        );
+                        expanded.push(event);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        // The cache covers whole days; only what's inside the window is used
+        let series = cache_key(&event);
+        expanded.extend(
+            starts
+                .iter()
+                .filter(|start| window.contains_start(start))
+                .filter(|start| !overridden.contains(&(series.clone(), start.to_string())))
+                .map(|start| occurrence(&event, start)),
+        );
+    }
+
+    // Forget series that are no longer in the calendar
+    let before = state.occurrences.len();
+    state.occurrences.retain(|key, _| seen.contains(key));
+    changed |= state.occurrences.len() != before;
+
+    calendar.events = expanded;
+    changed
+}
+
+impl CachedOccurrences {
+    fn window(&self) -> Window {
+        Window {
+            start: self.window_start,
+            end: self.window_end,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const WEEKLY: &str = indoc! {"
+        BEGIN:VCALENDAR
+        VERSION:2.0
+        PRODID:-//Test//Test//EN
+        BEGIN:VEVENT
+        UID:weekly@example.com
+        DTSTART:20251203T190000Z
+        DTEND:20251203T210000Z
+        RRULE:FREQ=WEEKLY;BYDAY=WE
+        SUMMARY:Weekly Meeting
+        END:VEVENT
+        BEGIN:VEVENT
+        UID:once@example.com
+        DTSTART:20251205T190000Z
+        SUMMARY:One-off
+        END:VEVENT
+        END:VCALENDAR
+    "};
+
+    fn window() -> Window {
+        Window {
+            start: Utc.with_ymd_and_hms(2025, 12, 8, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2025, 12, 31, 0, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_expand_calendar() {
+        let mut calendar = IcalCalendar::parse_ical_content(WEEKLY).unwrap();
+        let mut state = State::default();
+
+        assert!(expand_calendar(&mut calendar, &window(), &mut state));
+
+        let upcoming = calendar.get_upcoming_events("20251208T000000Z");
+        let starts: Vec<_> = upcoming
+            .iter()
+            .map(|e| e.start_time.clone().unwrap())
+            .collect();
+        assert_eq!(
+            starts,
+            vec!["20251210T190000Z", "20251217T190000Z", "20251224T190000Z"]
+        );
+        assert_eq!(upcoming[0].end_time, Some("20251210T210000Z".to_string()));
+        assert!(upcoming[0].rrule.is_none());
+    }
+
+    #[test]
+    fn test_expansion_cache() {
+        let mut state = State::default();
+
+        let mut calendar = IcalCalendar::parse_ical_content(WEEKLY).unwrap();
+        assert!(expand_calendar(&mut calendar, &window(), &mut state));
+
+        // The same window again is served from the cache
+        let mut calendar = IcalCalendar::parse_ical_content(WEEKLY).unwrap();
+        assert!(!expand_calendar(&mut calendar, &window(), &mut state));
+
+        // A new SEQUENCE invalidates it
+        let changed = WEEKLY.replace("RRULE:", "SEQUENCE:2\nRRULE:");
+        let mut calendar = IcalCalendar::parse_ical_content(&changed).unwrap();
+        assert!(expand_calendar(&mut calendar, &window(), &mut state));
+        assert_eq!(state.occurrences["weekly@example.com"].sequence, 2);
+
+        // So does a changed rule
+        let changed = WEEKLY.replace("BYDAY=WE", "BYDAY=TH");
+        let mut calendar = IcalCalendar::parse_ical_content(&changed).unwrap();
+        assert!(expand_calendar(&mut calendar, &window(), &mut state));
+        assert_eq!(
+            calendar.get_upcoming_events("20251208T000000Z")[0].start_time,
+            Some("20251211T190000Z".to_string())
+        );
+    }
+
+    #[test]
+    fn test_floating_and_all_day_recurrences() {
+        let event = CalendarEvent {
+            start_time: Some("20251203T190000".to_string()),
+            rrule: Some("FREQ=DAILY;COUNT=3".to_string()),
+            ..Default::default()
+        };
+        let window = Window {
+            start: Utc.with_ymd_and_hms(2025, 12, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2025, 12, 31, 0, 0, 0).unwrap(),
+        };
+        assert_eq!(
+            occurrence_starts(&event, &window).unwrap().starts,
+            vec!["20251203T190000", "20251204T190000", "20251205T190000"]
+        );
+
+        let event = CalendarEvent {
+            start_time: Some("20251203".to_string()),
+            rrule: Some("FREQ=MONTHLY;COUNT=2".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            occurrence_starts(&event, &window).unwrap().starts,
+            vec!["20251203"]
+        );
+    }
+
+    #[test]
+    fn test_normalize_until() {
+        let (rule, mismatch) = normalize_until("FREQ=WEEKLY;UNTIL=20251217", TimeForm::Utc);
+        assert_eq!(rule, "FREQ=WEEKLY;UNTIL=20251217T235959Z");
+        assert!(
+            mismatch
+                .unwrap()
+                .starts_with("UNTIL=20251217 is a date but DTSTART is a UTC")
+        );
+
+        // Matching forms are only rewritten into UTC
+        let (rule, mismatch) =
+            normalize_until("FREQ=DAILY;UNTIL=20251217T190000", TimeForm::Floating);
+        assert_eq!(rule, "FREQ=DAILY;UNTIL=20251217T190000Z");
+        assert!(mismatch.is_none());
+        assert_eq!(
+            normalize_until("FREQ=DAILY;COUNT=3", TimeForm::Date),
+            ("FREQ=DAILY;COUNT=3".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn test_date_until_includes_last_day() {
+        let event = CalendarEvent {
+            start_time: Some("20251203T190000Z".to_string()),
+            rrule: Some("FREQ=WEEKLY;UNTIL=20251217".to_string()),
+            ..Default::default()
+        };
+        let occurrences = occurrence_starts(&event, &window()).unwrap();
+        assert_eq!(occurrences.starts, ["20251210T190000Z", "20251217T190000Z"]);
+    }
+
+    #[test]
+    fn test_rdates() {
+        // First Wednesday of the month, plus a special Saturday session
+        let content = WEEKLY
+            .replace("FREQ=WEEKLY;BYDAY=WE", "FREQ=MONTHLY;BYDAY=1WE")
+            .replace(
+                "SUMMARY:Weekly",
+                "RDATE;VALUE=DATE:20251213\nSUMMARY:Weekly",
+            );
+        let mut calendar = IcalCalendar::parse_ical_content(&content).unwrap();
+        let window = Window {
+            end: Utc.with_ymd_and_hms(2026, 1, 31, 0, 0, 0).unwrap(),
+            ..window()
+        };
+        let mut state = State::default();
+        expand_calendar(&mut calendar, &window, &mut state);
+
+        let upcoming = calendar.get_upcoming_events("20251208T000000Z");
+        let starts: Vec<_> = upcoming
+            .iter()
+            .map(|e| e.start_time.clone().unwrap())
+            .collect();
+        assert_eq!(starts, ["20251213T190000Z", "20260107T190000Z"]);
+        assert_eq!(upcoming[0].end_time, Some("20251213T210000Z".to_string()));
+
+        // An added RDATE invalidates the cache
+        let changed = content.replace("20251213", "20251213,20251220T170000Z");
+        let mut calendar = IcalCalendar::parse_ical_content(&changed).unwrap();
+        assert!(expand_calendar(&mut calendar, &window, &mut state));
+        assert_eq!(calendar.events.len(), 4);
+
+        // Without an RRULE, DTSTART and the RDATEs are the occurrences
+        let event = CalendarEvent {
+            start_time: Some("20251210T190000Z".to_string()),
+            rdates: vec![
+                "20251217T190000Z".to_string(),
+                "20260301T190000Z".to_string(),
+            ],
+            ..Default::default()
+        };
+        let occurrences = occurrence_starts(&event, &window).unwrap();
+        assert_eq!(occurrences.starts, ["20251210T190000Z", "20251217T190000Z"]);
+        assert!(occurrences.stats.truncated_at_horizon);
+    }
+
+    #[test]
+    fn test_exdates() {
+        // No meeting on Christmas Eve, but an extra one on the Saturday after
+        let content = WEEKLY.replace(
+            "SUMMARY:Weekly",
+            "RDATE:20251227T170000Z\nEXDATE:20251224T190000Z\nSUMMARY:Weekly",
+        );
+        let mut calendar = IcalCalendar::parse_ical_content(&content).unwrap();
+        let mut state = State::default();
+        expand_calendar(&mut calendar, &window(), &mut state);
+
+        let starts: Vec<_> = calendar
+            .get_upcoming_events("20251208T000000Z")
+            .iter()
+            .map(|e| e.start_time.clone().unwrap())
+            .collect();
+        assert_eq!(
+            starts,
+            ["20251210T190000Z", "20251217T190000Z", "20251227T170000Z"]
+        );
+
+        // A changed EXDATE invalidates the cache; a date takes DTSTART's time
+        let changed = content.replace("EXDATE:20251224T190000Z", "EXDATE;VALUE=DATE:20251217");
+        let mut calendar = IcalCalendar::parse_ical_content(&changed).unwrap();
+        assert!(expand_calendar(&mut calendar, &window(), &mut state));
+        let starts: Vec<_> = calendar
+            .get_upcoming_events("20251208T000000Z")
+            .iter()
+            .map(|e| e.start_time.clone().unwrap())
+            .collect();
+        assert_eq!(
+            starts,
+            ["20251210T190000Z", "20251224T190000Z", "20251227T170000Z"]
+        );
+    }
+
+    #[test]
+    fn test_overridden_occurrences() {
+        // The meeting of the 10th moves to the 11th, and that of the 17th is cancelled
+        let content = WEEKLY.replace(
+            "END:VCALENDAR",
+            indoc! {"
+                BEGIN:VEVENT
+                UID:weekly@example.com
+                RECURRENCE-ID:20251210T190000Z
+                DTSTART:20251211T190000Z
+                DTEND:20251211T210000Z
+                SUMMARY:Weekly Meeting (moved)
+                END:VEVENT
+                BEGIN:VEVENT
+                UID:weekly@example.com
+                RECURRENCE-ID;TZID=Europe/Berlin:20251217T200000
+                DTSTART;TZID=Europe/Berlin:20251217T200000
+                SUMMARY:Weekly Meeting
+                STATUS:CANCELLED
+                END:VEVENT
+                END:VCALENDAR
+            "},
+        );
+        let mut calendar = IcalCalendar::parse_ical_content(&content).unwrap();
+        let mut state = State::default();
+        expand_calendar(&mut calendar, &window(), &mut state);
+
+        let occurrences: Vec<_> = calendar
+            .get_upcoming_events("20251208T000000Z")
+            .iter()
+            .map(|e| {
+                (
+                    e.start_time.clone().unwrap(),
+                    e.summary.clone().unwrap(),
+                    e.status.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            occurrences,
+            [
+                (
+                    "20251211T190000Z".to_string(),
+                    "Weekly Meeting (moved)".to_string(),
+                    None
+                ),
+                (
+                    "20251217T190000Z".to_string(),
+                    "Weekly Meeting".to_string(),
+                    Some("CANCELLED".to_string())
+                ),
+                (
+                    "20251224T190000Z".to_string(),
+                    "Weekly Meeting".to_string(),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_local_time_across_dst() {
+        // New York moves its clocks forward on March 8th, 2026
+        let calendar = IcalCalendar::parse_ical_content(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:weekly@example.com
+            DTSTART;TZID=America/New_York:20260225T190000
+            RRULE:FREQ=WEEKLY;COUNT=3
+            SUMMARY:Weekly Meeting
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+        let window = Window {
+            start: Utc.with_ymd_and_hms(2026, 2, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2026, 3, 31, 0, 0, 0).unwrap(),
+        };
+        let occurrences = occurrence_starts(&calendar.events[0], &window).unwrap();
+        assert_eq!(
+            occurrences.starts,
+            ["20260226T000000Z", "20260305T000000Z", "20260311T230000Z"]
+        );
+    }
+
+    #[test]
+    fn test_unbounded_rules_stop_at_horizon() {
+        let window = Window {
+            start: Utc.with_ymd_and_hms(2025, 12, 1, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2025, 12, 24, 12, 0, 0).unwrap(),
+        };
+
+        let weekly = CalendarEvent {
+            start_time: Some("20251203T190000Z".to_string()),
+            rrule: Some("FREQ=WEEKLY".to_string()),
+            ..Default::default()
+        };
+        let occurrences = occurrence_starts(&weekly, &window).unwrap();
+        assert_eq!(occurrences.starts.len(), 3);
+        assert_eq!(
+            occurrences.stats,
+            RuleStats {
+                generated: 3,
+                truncated_at_horizon: true,
+                hit_limit: false,
+            }
+        );
+
+        let bounded = CalendarEvent {
+            rrule: Some("FREQ=WEEKLY;COUNT=2".to_string()),
+            ..weekly.clone()
+        };
+        assert!(
+            !occurrence_starts(&bounded, &window)
+                .unwrap()
+                .stats
+                .truncated_at_horizon
+        );
+
+        let runaway = CalendarEvent {
+            rrule: Some("FREQ=MINUTELY".to_string()),
+            ..weekly.clone()
+        };
+        let occurrences = occurrence_starts(&runaway, &window).unwrap();
+        assert_eq!(occurrences.starts.len(), usize::from(MAX_OCCURRENCES));
+        assert!(occurrences.stats.hit_limit);
+
+        // The cache is kept for the whole last day, but nothing after the
+        // window's end is handed out
+        let mut calendar = IcalCalendar {
+            events: vec![weekly],
+        };
+        expand_calendar(&mut calendar, &window, &mut State::default());
+        assert_eq!(
+            calendar.events.last().unwrap().start_time,
+            Some("20251217T190000Z".to_string())
+        );
+    }
+}
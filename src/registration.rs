@@ -0,0 +1,823 @@
+use crate::config::{self, Config, Network, UrlPolicyConfig};
+use crate::error::{self, Error, Result};
+use crate::state::State;
+use crate::{secrets, url_policy};
+use anyhow::anyhow;
+use mastodon_async::prelude::Scopes;
+use mastodon_async::registration::Registered;
+use mastodon_async::{Language, Registration};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The account an app registration is for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Account<'a> {
+    pub instance: &'a str,
+    pub token_file: &'a str,
+}
+
+/// The primary account, or the backup or a language one if asked for and
+/// configured.
+pub fn account(config: &Config, backup: bool, language: Option<Language>) -> Result<Account<'_>> {
+    if backup {
+        config
+            .backup
+            .as_ref()
+            .map(|backup| Account {
+                instance: &backup.instance,
+                token_file: &backup.token_file,
+            })
+            .ok_or_else(|| error::invalid("no [backup] section found in the configuration"))
+    } else if let Some(language) = language {
+        config
+            .language_accounts
+            .iter()
+            .find(|account| account.language == language)
+            .map(|account| Account {
+                instance: &account.instance,
+                token_file: &account.token_file,
+            })
+            .ok_or_else(|| {
+                error::invalid(format!(
+                    "no [[language_account]] for '{}' found in the configuration",
+                    language.to_639_3()
+                ))
+            })
+    } else if config.network == Network::Matrix {
+        Err(error::invalid(format!(
+            "Matrix accounts aren't registered; put the access token in {} instead",
+            config.token_file
+        )))
+    } else {
+        Ok(Account {
+            instance: &config.instance,
+            token_file: &config.token_file,
+        })
+    }
+}
+
+/// The scopes to request: the ones given on the command line, or else just
+/// the ones the configured features need.
+pub fn requested_scopes(config: &Config, scopes: Vec<String>) -> String {
+    let scopes = if scopes.is_empty() {
+        config
+            .required_scopes()
+            .into_iter()
+            .map(String::from)
+            .collect()
+    } else {
+        scopes
+    };
+    let scopes = scopes.join(" ");
+    println!("Requesting scopes: {}", scopes);
+    scopes
+}
+
+/// Registers the application on the account's instance, has the user
+/// authorize it and saves the token.
+#[allow(clippy::too_many_arguments)]
+pub async fn register(
+    account: Account<'_>,
+    client_name: &str,
+    redirect_uri: Option<&str>,
+    scopes: Option<&str>,
+    website: Option<&str>,
+    auth_code: Option<&str>,
+    timeout: Duration,
+) -> Result<()> {
+    let mut registration = Registration::new(account.instance);
+    registration.client_name(client_name);
+
+    if let Some(uri) = redirect_uri {
+        registration.redirect_uris(uri);
+    }
+
+    if let Some(scope_str) = scopes {
+        registration.scopes(Scopes::from_str(scope_str).map_err(error::invalid)?);
+    }
+
+    if let Some(website_url) = website {
+        registration.website(website_url);
+    }
+
+    let app = registration
+        .build()
+        .await
+        .map_err(|e| registration_error(account.instance, e))?;
+
+    println!("Application registered successfully!");
+
+    authorize(app, &account, auth_code, timeout).await
+}
+
+/// Signs in with an application created by hand in the instance's settings,
+/// for instances that don't let apps register themselves.
+pub async fn login(
+    account: Account<'_>,
+    client_id: &str,
+    client_secret: &str,
+    redirect_uri: &str,
+    scopes: &str,
+    auth_code: Option<&str>,
+    timeout: Duration,
+) -> Result<()> {
+    let app = Registered::from_parts(
+        account.instance.trim_end_matches('/'),
+        client_id,
+        client_secret,
+        redirect_uri,
+        Scopes::from_str(scopes).map_err(error::invalid)?,
+        false,
+    );
+
+    authorize(app, &account, auth_code, timeout).await
+}
+
+/// Saves the access token shown in the settings of an application created on
+/// the instance, once the instance accepts it.
+pub async fn save_access_token(account: Account<'_>, token: &str) -> Result<()> {
+    let data = mastodon_async::Data {
+        base: account.instance.trim_end_matches('/').to_string().into(),
+        token: token.trim().to_string().into(),
+        ..Default::default()
+    };
+    let me = mastodon_async::Mastodon::from(data.clone())
+        .verify_credentials()
+        .await
+        .map_err(|e| {
+            Error::Publish(anyhow!(
+                "the instance didn't accept the access token: {}",
+                e
+            ))
+        })?;
+    println!("Signed in as @{}", me.acct);
+    Ok(config::save_token_file(account.token_file, &data)?)
+}
+
+/// Revokes the account's access token at the instance, shreds its token file
+/// and forgets the instance being down and, for the `main` account, the runs
+/// that failed with the old token. The main account's token may come from
+/// the environment instead, which is then left to be removed there. With
+/// `force`, the file is shredded even when the instance can't be told; the
+/// token should then be revoked in the instance's settings.
+pub async fn unregister_account(
+    config: &Config,
+    account: Account<'_>,
+    main: bool,
+    force: bool,
+) -> Result<()> {
+    let from_environment = main && config.access_token.is_some();
+    let data = match main {
+        true => config::load_token(config)?,
+        false => config::load_token_file(account.token_file)?,
+    };
+    let token_file = (!from_environment).then_some(account.token_file);
+    let mut state = State::load(&config.state_file)?;
+    if main && !state.scheduled.is_empty() {
+        eprintln!(
+            "Warning: {} statuses scheduled on the instance still go out; cancel them first with cancel-scheduled if they shouldn't",
+            state.scheduled.len()
+        );
+    }
+    if config.dry_run {
+        match token_file {
+            Some(token_file) => println!(
+                "Would revoke the access token at {} and shred {}",
+                data.base, token_file
+            ),
+            None => println!("Would revoke the access token at {}", data.base),
+        }
+        return Ok(());
+    }
+
+    unregister(&data, token_file, force, Some(&config.url_policy)).await?;
+    if from_environment {
+        println!("The access token came from the environment; remove it there");
+    }
+
+    state.unavailable_until.remove(account.instance);
+    if main {
+        state.health.reset();
+    }
+    state.save()?;
+    Ok(())
+}
+
+/// Has the user authorize a registered application and saves the token.
+/// When that fails, the client credentials are printed to try again with.
+async fn authorize(
+    app: Registered,
+    account: &Account<'_>,
+    auth_code: Option<&str>,
+    timeout: Duration,
+) -> Result<()> {
+    let result = complete(&app, account, auth_code, timeout).await;
+    if result.is_err() {
+        print_client_credentials(&app);
+    }
+    result
+}
+
+async fn complete(
+    app: &Registered,
+    account: &Account<'_>,
+    auth_code: Option<&str>,
+    timeout: Duration,
+) -> Result<()> {
+    let authorize_url = app
+        .authorize_url()
+        .map_err(|e| error::invalid(format!("could not generate the authorization URL: {}", e)))?;
+    println!("\nPlease open this URL in your browser to authorize the application:");
+    println!("{}", authorize_url);
+
+    use std::io::IsTerminal;
+    let interactive = std::io::stdin().is_terminal();
+    let code = self::auth_code(auth_code, interactive, timeout, || {
+        let mut code = String::new();
+        std::io::stdin().read_line(&mut code).map(|_| code)
+    })
+    .await?;
+
+    let mastodon = app
+        .complete(code.as_str())
+        .await
+        .map_err(|e| completion_error(account.instance, e))?;
+    println!("Authentication successful!");
+    config::save_token_file(account.token_file, &mastodon.data)?;
+    Ok(())
+}
+
+fn print_client_credentials(app: &Registered) {
+    let (_, client_id, client_secret, _, _, _) = app.clone().into_parts();
+    eprintln!("You can try again with the 'login' command and these client credentials:");
+    eprintln!("Client ID: {}", client_id);
+    eprintln!("Client secret: {}", client_secret);
+}
+
+/// Explains an instance refusing to complete signing in, such as for a code
+/// that is wrong or an account that still awaits approval.
+fn completion_error(instance: &str, e: mastodon_async::Error) -> Error {
+    let (status, message) = http_status(&e);
+    match completion_refusal(instance, status, message.as_deref()) {
+        Some(refusal) => Error::Publish(anyhow!(refusal)),
+        None => Error::Publish(anyhow!("could not complete signing in: {}", e)),
+    }
+}
+
+/// Explains an instance refusing to register the application, which some
+/// instances do when app registration is disabled or needs admin approval.
+fn registration_error(instance: &str, e: mastodon_async::Error) -> Error {
+    let (status, message) = http_status(&e);
+    match refusal(instance, status, message.as_deref()) {
+        Some(refusal) => Error::Publish(anyhow!(refusal)),
+        None => Error::Publish(e.into()),
+    }
+}
+
+/// The HTTP status an instance answered with, and its error message.
+fn http_status(e: &mastodon_async::Error) -> (Option<u16>, Option<String>) {
+    match e {
+        mastodon_async::Error::Api { status, response } => {
+            (Some(status.as_u16()), Some(response.error.clone()))
+        }
+        mastodon_async::Error::Http(http) => (http.status().map(|status| status.as_u16()), None),
+        _ => (None, None),
+    }
+}
+
+/// The authorization code to complete signing in with: the one given on the
+/// command line, or else the line `read` returns once the user pasted it.
+/// Without a terminal to paste it in, nothing is read. `read` blocks, so it
+/// runs on a thread of its own, which is left behind after `timeout` rather
+/// than holding up the runtime's shutdown.
+pub async fn auth_code<R>(
+    given: Option<&str>,
+    interactive: bool,
+    timeout: Duration,
+    read: R,
+) -> Result<String>
+where
+    R: FnOnce() -> std::io::Result<String> + Send + 'static,
+{
+    if let Some(code) = given {
+        return Ok(code.trim().to_string());
+    }
+    if !interactive {
+        return Err(error::invalid(
+            "stdin is not a terminal; pass the authorization code with --auth-code instead",
+        ));
+    }
+
+    println!("\nAfter authorizing, paste the authorization code here:");
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || sender.send(read()));
+    match tokio::time::timeout(timeout, receiver).await {
+        Ok(Ok(Ok(code))) => Ok(code.trim().to_string()),
+        Ok(Ok(Err(e))) => Err(error::invalid(format!(
+            "could not read the authorization code: {}",
+            e
+        ))),
+        Ok(Err(_)) => Err(error::invalid("could not read the authorization code")),
+        Err(_) => Err(error::invalid(format!(
+            "timed out after {} seconds waiting for the authorization code",
+            timeout.as_secs()
+        ))),
+    }
+}
+
+/// What to tell the user about an instance answering the registration of
+/// the application with an HTTP error, such as when app registration is
+/// disabled or needs admin approval there. `None` without an HTTP status.
+pub fn refusal(instance: &str, status: Option<u16>, message: Option<&str>) -> Option<String> {
+    let detail = message.map(|m| format!(": {}", m)).unwrap_or_default();
+    match status? {
+        code @ (401 | 403 | 404 | 405) => Some(format!(
+            "{} refused to register the application (HTTP {}{}). App registration may be \
+             disabled or need admin approval there. Create an application in the instance's \
+             settings (Preferences > Development) and sign in with \
+             'login --client-id <ID> --client-secret <SECRET>' instead",
+            instance, code, detail
+        )),
+        code => Some(format!(
+            "{} rejected the application registration (HTTP {}{})",
+            instance, code, detail
+        )),
+    }
+}
+
+/// What to tell the user about an instance answering the authorization
+/// code with an HTTP error. `None` without an HTTP status.
+pub fn completion_refusal(
+    instance: &str,
+    status: Option<u16>,
+    message: Option<&str>,
+) -> Option<String> {
+    let detail = message.map(|m| format!(": {}", m)).unwrap_or_default();
+    match status? {
+        code @ 400 => Some(format!(
+            "{} didn't accept the authorization code (HTTP {}{}). It may be mistyped, \
+             already used or expired; authorize again for a new one",
+            instance, code, detail
+        )),
+        code @ 401 => Some(format!(
+            "{} didn't recognize the application (HTTP {}{}). Check the client ID and secret, \
+             or register again",
+            instance, code, detail
+        )),
+        code @ 403 => Some(format!(
+            "{} refused to sign in (HTTP {}{}). The account or application may still await \
+             approval by the instance's admins; try again once it is approved",
+            instance, code, detail
+        )),
+        code => Some(format!(
+            "{} could not complete signing in (HTTP {}{})",
+            instance, code, detail
+        )),
+    }
+}
+
+/// Asks the instance to revoke the access token, which needs the client
+/// credentials it was issued to.
+pub async fn revoke(data: &mastodon_async::Data, policy: Option<&UrlPolicyConfig>) -> Result<()> {
+    if data.client_id.is_empty() || data.client_secret.is_empty() {
+        return Err(error::invalid(
+            "there are no client credentials to revoke the access token with",
+        ));
+    }
+    let url = format!("{}/oauth/revoke", data.base.trim_end_matches('/'));
+    let response = url_policy::client(policy, &url)
+        .map_err(error::invalid)?
+        .post(&url)
+        .form(&[
+            ("client_id", &*data.client_id),
+            ("client_secret", &*data.client_secret),
+            ("token", &*data.token),
+        ])
+        .send()
+        .await
+        .map_err(|e| Error::Publish(e.into()))?;
+    if !response.status().is_success() {
+        return Err(Error::Publish(anyhow!("HTTP error {}", response.status())));
+    }
+    Ok(())
+}
+
+/// Revokes the access token and shreds `token_file`, the file it was read
+/// from, if it was. With `force`, the file is shredded even when the
+/// instance can't revoke the token.
+pub async fn unregister(
+    data: &mastodon_async::Data,
+    token_file: Option<&str>,
+    force: bool,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<()> {
+    match revoke(data, policy).await {
+        Ok(()) => println!("Revoked the access token at {}", data.base),
+        Err(e) if force => eprintln!(
+            "Warning: could not revoke the access token ({}); revoke it in the instance's settings",
+            e
+        ),
+        Err(e) => {
+            return Err(Error::Publish(anyhow!(
+                "could not revoke the access token: {}; revoke it in the instance's settings and run again with --force",
+                e
+            )));
+        }
+    }
+    if let Some(token_file) = token_file {
+        secrets::shred(Path::new(token_file)).map_err(config::io_error(token_file))?;
+        println!("Shredded {}", token_file);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// Answers every request with `status`, noting the body of each.
+    fn serve(status: &'static str, bodies: Arc<Mutex<Vec<String>>>) -> String {
+        answer(status, "", bodies)
+    }
+
+    /// Answers every request with `status` and `body`, noting the body of
+    /// each request.
+    fn answer(
+        status: &'static str,
+        response_body: &'static str,
+        bodies: Arc<Mutex<Vec<String>>>,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some((name, value)) = line.split_once(':')
+                        && name.eq_ignore_ascii_case("content-length")
+                    {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8(body).unwrap());
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    response_body.len(),
+                    response_body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_unregister() {
+        let token_file = std::env::temp_dir().join(format!(
+            "ical-to-masto-unregister-{}.json",
+            std::process::id()
+        ));
+        let path = token_file.to_str().unwrap();
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let data = |base: &str| mastodon_async::Data {
+            base: base.to_string().into(),
+            client_id: "app".to_string().into(),
+            client_secret: "secret".to_string().into(),
+            token: "abc".to_string().into(),
+            ..Default::default()
+        };
+
+        // Revoked, then shredded
+        std::fs::write(&token_file, "{}").unwrap();
+        let revoking = data(&serve("200 OK", bodies.clone()));
+        unregister(&revoking, Some(path), false, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            *bodies.lock().unwrap(),
+            ["client_id=app&client_secret=secret&token=abc"]
+        );
+        assert!(!token_file.exists());
+
+        // Kept when the instance turns the revocation down, unless forced
+        std::fs::write(&token_file, "{}").unwrap();
+        let refusing = data(&serve("401 Unauthorized", bodies.clone()));
+        let error = unregister(&refusing, Some(path), false, None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("HTTP error 401"), "{}", error);
+        assert!(token_file.exists());
+        unregister(&refusing, Some(path), true, None).await.unwrap();
+        assert!(!token_file.exists());
+
+        // A token from the environment has no client credentials, nor a file
+        let environment = mastodon_async::Data {
+            base: revoking.base.clone(),
+            token: "abc".to_string().into(),
+            ..Default::default()
+        };
+        let error = unregister(&environment, None, false, None)
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("no client credentials"),
+            "{}",
+            error
+        );
+        unregister(&environment, None, true, None).await.unwrap();
+        assert_eq!(bodies.lock().unwrap().len(), 3);
+    }
+
+    fn config(sections: &str) -> Config {
+        toml::from_str(&format!(
+            "instance = \"https://mastodon.example\"\nwebcal = \"https://example.com/calendar.ics\"\n{}",
+            sections
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_account() {
+        let main = config("");
+        assert_eq!(
+            account(&main, false, None).unwrap(),
+            Account {
+                instance: "https://mastodon.example",
+                token_file: &main.token_file,
+            }
+        );
+        let error = account(&main, true, None).unwrap_err();
+        assert!(matches!(error, Error::Config(_)));
+        assert_eq!(
+            error.to_string(),
+            "no [backup] section found in the configuration"
+        );
+        let error = account(&main, false, Some(Language::Fra)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no [[language_account]] for 'fra' found in the configuration"
+        );
+
+        let accounts = config(
+            r#"
+            [backup]
+            instance = "https://backup.example"
+            token_file = "token-backup.json"
+
+            [[language_account]]
+            language = "fr"
+            instance = "https://mastodon.example.fr"
+            token_file = "token-fr.json"
+            "#,
+        );
+        assert_eq!(
+            account(&accounts, true, None).unwrap(),
+            Account {
+                instance: "https://backup.example",
+                token_file: "token-backup.json",
+            }
+        );
+        assert_eq!(
+            account(&accounts, false, Some(Language::Fra)).unwrap(),
+            Account {
+                instance: "https://mastodon.example.fr",
+                token_file: "token-fr.json",
+            }
+        );
+
+        let matrix = config("network = \"matrix\"\ntoken_file = \"token-room.json\"");
+        let error = account(&matrix, false, None).unwrap_err();
+        assert!(error.to_string().contains("token-room.json"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_login() {
+        // Scopes are checked before the instance is contacted
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let instance = serve("200 OK", bodies.clone());
+        let account = Account {
+            instance: &instance,
+            token_file: "token-unused.json",
+        };
+        let second = Duration::from_secs(1);
+        let redirect = "urn:ietf:wg:oauth:2.0:oob";
+        let scopes = "write:nonsense";
+        let login = login(account, "app", "secret", redirect, scopes, None, second);
+        assert!(login.await.is_err());
+        let register = register(account, "bot", None, Some(scopes), None, None, second);
+        assert!(register.await.is_err());
+        assert!(bodies.lock().unwrap().is_empty());
+
+        // A code the instance turns down fails the command, without a token
+        let token_file =
+            std::env::temp_dir().join(format!("ical-to-masto-login-{}.json", std::process::id()));
+        let invalid = r#"{"error":"invalid_grant"}"#;
+        let refusing = answer("400 Bad Request", invalid, bodies.clone());
+        let account = Account {
+            instance: &refusing,
+            token_file: token_file.to_str().unwrap(),
+        };
+        let code = Some("wrong");
+        let refused = super::login(
+            account,
+            "app",
+            "secret",
+            redirect,
+            "read write",
+            code,
+            second,
+        );
+        let error = refused.await.unwrap_err();
+        assert!(matches!(error, Error::Publish(_)), "{}", error);
+        assert!(
+            error
+                .to_string()
+                .contains("didn't accept the authorization code (HTTP 400: invalid_grant)"),
+            "{}",
+            error
+        );
+        assert!(!token_file.exists());
+        assert_eq!(bodies.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_registration_error() {
+        let error = registration_error(
+            "https://closed.example",
+            mastodon_async::Error::ClientIdRequired,
+        );
+        assert!(matches!(
+            error.cause().and_then(|e| e.downcast_ref()),
+            Some(mastodon_async::Error::ClientIdRequired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unregister_account() {
+        let dir = std::env::temp_dir().join(format!(
+            "ical-to-masto-unregister-account-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let token_file = dir.join("token.json");
+        let state_file = dir.join("state.json");
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let base = serve("200 OK", bodies.clone());
+        let config = config(&format!(
+            "token_file = {:?}\nstate_file = {:?}\n[url_policy]\nallow = [\"127.0.0.1\"]",
+            token_file.to_str().unwrap(),
+            state_file.to_str().unwrap()
+        ));
+        let data = mastodon_async::Data {
+            base: base.clone().into(),
+            client_id: "app".to_string().into(),
+            client_secret: "secret".to_string().into(),
+            token: "abc".to_string().into(),
+            ..Default::default()
+        };
+        config::save_token_file(&config.token_file, &data).unwrap();
+        let mut state = State::load(&config.state_file).unwrap();
+        state
+            .unavailable_until
+            .insert(config.instance.clone(), chrono::Utc::now());
+        state.health.consecutive_failures = 3;
+        state.save().unwrap();
+        let main = account(&config, false, None).unwrap();
+
+        // A dry run neither revokes nor shreds
+        let dry_run = config.with_dry_run(true);
+        unregister_account(&dry_run, main, true, false)
+            .await
+            .unwrap();
+        assert!(token_file.exists());
+        assert!(bodies.lock().unwrap().is_empty());
+
+        unregister_account(&config, main, true, false)
+            .await
+            .unwrap();
+        assert!(!token_file.exists());
+        assert_eq!(bodies.lock().unwrap().len(), 1);
+        let state = State::load(&config.state_file).unwrap();
+        assert!(state.unavailable_until.is_empty());
+        assert_eq!(state.health.consecutive_failures, 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_refusal() {
+        let instance = "https://closed.example";
+        let closed = refusal(instance, Some(403), Some("Registrations are closed")).unwrap();
+        assert!(
+            closed.starts_with(
+                "https://closed.example refused to register the application \
+                 (HTTP 403: Registrations are closed). App registration may be disabled"
+            ),
+            "{}",
+            closed
+        );
+        assert!(closed.contains("'login --client-id <ID> --client-secret <SECRET>'"));
+        for code in [401, 404, 405] {
+            let refused = refusal(instance, Some(code), None).unwrap();
+            assert!(refused.contains(&format!("(HTTP {}). App registration", code)));
+        }
+
+        assert_eq!(
+            refusal(instance, Some(422), Some("Validation failed")).unwrap(),
+            "https://closed.example rejected the application registration \
+             (HTTP 422: Validation failed)"
+        );
+        assert_eq!(refusal(instance, None, None), None);
+    }
+
+    #[test]
+    fn test_completion_refusal() {
+        let instance = "https://pending.example";
+        let pending = completion_refusal(instance, Some(403), Some("pending approval")).unwrap();
+        assert!(
+            pending.starts_with(
+                "https://pending.example refused to sign in (HTTP 403: pending approval). \
+                 The account or application may still await approval"
+            ),
+            "{}",
+            pending
+        );
+        let wrong = completion_refusal(instance, Some(400), Some("invalid_grant")).unwrap();
+        assert!(
+            wrong.contains("didn't accept the authorization code"),
+            "{}",
+            wrong
+        );
+        let unknown = completion_refusal(instance, Some(401), None).unwrap();
+        assert!(
+            unknown.contains("Check the client ID and secret"),
+            "{}",
+            unknown
+        );
+        assert_eq!(
+            completion_refusal(instance, Some(500), None).unwrap(),
+            "https://pending.example could not complete signing in (HTTP 500)"
+        );
+        assert_eq!(completion_refusal(instance, None, None), None);
+    }
+
+    #[tokio::test]
+    async fn test_auth_code() {
+        let unread = || -> std::io::Result<String> { panic!("stdin was read") };
+        let second = Duration::from_secs(1);
+
+        let given = auth_code(Some(" abc123\n"), false, second, unread).await;
+        assert_eq!(given.unwrap(), "abc123");
+
+        let error = auth_code(None, false, second, unread).await.unwrap_err();
+        assert!(error.to_string().contains("--auth-code"), "{}", error);
+
+        let pasted = auth_code(None, true, second, || Ok("xyz789\n".to_string())).await;
+        assert_eq!(pasted.unwrap(), "xyz789");
+
+        let slow = || {
+            std::thread::sleep(Duration::from_millis(200));
+            Ok("late\n".to_string())
+        };
+        let error = auth_code(None, true, Duration::ZERO, slow)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "timed out after 0 seconds waiting for the authorization code"
+        );
+    }
+
+    #[test]
+    fn test_auth_code_timeout_returns() {
+        // Like stdin that never closes; the runtime shuts down all the same
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let stuck = || -> std::io::Result<String> {
+            loop {
+                std::thread::park();
+            }
+        };
+        let error = runtime
+            .block_on(auth_code(None, true, Duration::from_millis(50), stuck))
+            .unwrap_err();
+        assert!(error.to_string().starts_with("timed out"), "{}", error);
+        drop(runtime);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::config::RemindMeConfig;
+use crate::ical::CalendarEvent;
+use crate::reminders::{self, LeadTime};
+use crate::template::{Context, Template};
+use anyhow::Result;
+
+/// Default template of the direct message to a follower who asked to be
+/// reminded of a meeting.
+pub const REMIND_ME_TEMPLATE: &str = "⏰ You asked to be reminded: {#if summary}{summary}{else}Meeting{/if} starts in {lead}\
+{#if is_online}\n💻 {#if location}{location}{else}Online{/if}\
+{else}{#if location}\n📍 {location}{/if}{/if}\
+{#if conference_url}\n🎥 Join: {conference_url}{/if}\
+{#if start}\n🕒 {start}{/if}\
+{#if url}\n🔗 {url}{/if}\
+\n\nReply \"stop\" to get no more reminders.";
+
+/// The state key kind of reminders followers asked for.
+pub const KIND: &str = "remind-me";
+
+/// What a follower's mention of the account asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// A reminder of the meeting announced in the status it replies to.
+    Remind,
+    /// No more reminders of any meeting.
+    Stop,
+}
+
+/// The lowercase words of a text, without punctuation and mentions of
+/// accounts.
+fn words(text: &str) -> String {
+    text.split_whitespace()
+        .filter(|word| !word.starts_with('@'))
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What the text of a mention asks for, if anything: the keywords are
+/// matched as whole words, stopping before reminding.
+pub fn request(config: &RemindMeConfig, text: &str) -> Option<Request> {
+    let text = format!(" {} ", words(text));
+    let says = |keywords: &[String]| {
+        keywords
+            .iter()
+            .map(|keyword| words(keyword))
+            .any(|keyword| !keyword.is_empty() && text.contains(&format!(" {} ", keyword)))
+    };
+    if says(&config.stop_keywords) {
+        Some(Request::Stop)
+    } else if says(&config.keywords) {
+        Some(Request::Remind)
+    } else {
+        None
+    }
+}
+
+/// How long before the meeting followers are reminded.
+pub fn lead_time(config: &RemindMeConfig) -> Result<LeadTime> {
+    LeadTime::parse(&config.lead_time)
+}
+
+/// The state key kind of the reminder to one follower.
+pub fn kind(account: &str) -> String {
+    format!("{}-{}", KIND, account)
+}
+
+/// The template reminders are rendered from: the configured one, or the default.
+pub fn template_source(config: &RemindMeConfig) -> &str {
+    config.template.as_deref().unwrap_or(REMIND_ME_TEMPLATE)
+}
+
+/// The variables of the reminder `lead` before an event to `account`.
+pub fn context(event: &CalendarEvent, lead: LeadTime, account: &str) -> Context {
+    let mut context = reminders::context(event, lead);
+    context.set_text("account", account.to_string());
+    context
+}
+
+/// The text of the direct message reminding `account` of an event: the
+/// reminder, after a mention of them.
+pub fn render(
+    config: &RemindMeConfig,
+    event: &CalendarEvent,
+    lead: LeadTime,
+    account: &str,
+) -> Result<String> {
+    let template = Template::parse(template_source(config))?;
+    Ok(format!(
+        "@{}\n{}",
+        account,
+        template.render(&context(event, lead, account))
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request() {
+        let config = RemindMeConfig {
+            lead_time: "1h".to_string(),
+            keywords: vec!["Remind me".to_string()],
+            stop_keywords: vec!["stop".to_string()],
+            max_per_event: 10,
+            template: None,
+        };
+        let request = |text| super::request(&config, text);
+        assert_eq!(request("@bot remind me!"), Some(Request::Remind));
+        assert_eq!(
+            request("@bot@example.social Please REMIND ME"),
+            Some(Request::Remind)
+        );
+        assert_eq!(request("@bot Stop."), Some(Request::Stop));
+        assert_eq!(request("@bot nonstop fun, can't wait"), None);
+        assert_eq!(request("@bot remind meeting organizers"), None);
+        assert_eq!(request("@remind me"), None);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::config::RemindersConfig;
+use crate::formatter;
+use crate::ical::{self, CalendarEvent};
+use crate::state::State;
+use crate::template::{Context, Template};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
+
+/// Default template for a reminder that a meeting starts soon.
+pub const REMINDER_TEMPLATE: &str = "⏰ Starting in {lead}: {#if summary}{summary}{else}Meeting{/if}\
+{#if is_online}\n💻 {#if location}{location}{else}Online{/if}\
+{else}{#if location}\n📍 {location}{/if}{/if}\
+{#if conference_url}\n🎥 Join: {conference_url}{/if}\
+{#if start}\n🕒 {start}{/if}\
+{#if url}\n🔗 {url}{/if}";
+
+/// Default template for the post listing the meetings whose reminders were
+/// missed.
+pub const MISSED_TEMPLATE: &str = "⏰ Coming up soon ({count}):\n\n\
+{#each events}{#unless first}\n\n{/unless}\
+📅 {#if summary}{summary}{else}Meeting{/if}\
+{#if start}\n🕒 {start}{/if}\
+{#if url}\n🔗 {url}{/if}\
+{/each}";
+
+/// How long before a meeting a reminder goes out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LeadTime {
+    pub minutes: i64,
+}
+
+impl LeadTime {
+    /// Parses a lead time like `24h`, `30m`, `2d` or `1h30m`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid lead time {:?}, expected e.g. \"24h\" or \"30m\"",
+                text
+            )
+        };
+        let mut minutes = 0;
+        let mut rest = text.trim();
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(invalid)?;
+            let count: i64 = rest[..digits].parse().map_err(|_| invalid())?;
+            let mut units = rest[digits..].chars();
+            let unit = match units.next() {
+                Some('d') => 24 * 60,
+                Some('h') => 60,
+                Some('m') => 1,
+                _ => return Err(invalid()),
+            };
+            minutes += count * unit;
+            rest = units.as_str();
+        }
+        if minutes == 0 {
+            return Err(invalid());
+        }
+        Ok(LeadTime { minutes })
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::minutes(self.minutes)
+    }
+
+    /// The state key kind of reminders this long before meetings, the same
+    /// however the lead time is written.
+    pub fn kind(&self) -> String {
+        format!("reminder-{}m", self.minutes)
+    }
+
+    /// The lead time for people, like `1 hour` or `1 hour 30 minutes`.
+    pub fn text(&self) -> String {
+        let parts = [
+            (self.minutes / (24 * 60), "day"),
+            (self.minutes / 60 % 24, "hour"),
+            (self.minutes % 60, "minute"),
+        ];
+        parts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, unit)| match count {
+                1 => format!("1 {}", unit),
+                count => format!("{} {}s", count, unit),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// The configured lead times, longest first.
+pub fn lead_times(config: &RemindersConfig) -> Result<Vec<LeadTime>> {
+    let mut lead_times = config
+        .lead_times
+        .iter()
+        .map(|text| LeadTime::parse(text))
+        .collect::<Result<Vec<_>>>()?;
+    lead_times.sort_by(|a, b| b.cmp(a));
+    lead_times.dedup();
+    Ok(lead_times)
+}
+
+/// The selected meetings with a reminder due at `now` that hasn't been
+/// posted, each with its lead time. A reminder is due from its lead time
+/// before the meeting until the next shorter one is, so a first run shortly
+/// before a meeting only posts the last reminder.
+pub fn due_reminders<'a>(
+    events: impl IntoIterator<Item = &'a CalendarEvent>,
+    config: &RemindersConfig,
+    state: &State,
+    now: DateTime<Utc>,
+) -> Result<Vec<(&'a CalendarEvent, LeadTime)>> {
+    let lead_times = lead_times(config)?;
+    let at = |time: DateTime<Utc>| time.format("%Y%m%dT%H%M%SZ").to_string();
+    let current_time = at(now);
+
+    let mut due = Vec::new();
+    for event in events {
+        let Some(start) = event.start_time.as_deref() else {
+            continue;
+        };
+        if start <= current_time.as_str() || !event.summary_matches(&config.events) {
+            continue;
+        }
+        let due_lead = lead_times.iter().enumerate().find(|(index, lead)| {
+            let shorter = lead_times.get(index + 1);
+            at(now + lead.duration()).as_str() >= start
+                && shorter.is_none_or(|shorter| at(now + shorter.duration()).as_str() < start)
+        });
+        let sent = |kind: &str| state.is_posted(&State::key(kind, event));
+        if let Some((_, lead)) = due_lead
+            && !(sent(&lead.kind())
+                && (config.members.is_empty() || sent(&direct_kind(config, *lead))))
+        {
+            due.push((event, *lead));
+        }
+    }
+    Ok(due)
+}
+
+/// Whether the reminder `lead` before `event` is more than `late_minutes`
+/// late at `now`, as when the bot was down when it was due.
+pub fn is_missed(
+    config: &RemindersConfig,
+    event: &CalendarEvent,
+    lead: LeadTime,
+    now: DateTime<Utc>,
+) -> bool {
+    let Some((start, _)) = event.start_time.as_deref().and_then(ical::parse_ical_time) else {
+        return false;
+    };
+    let due_at = start.and_utc() - lead.duration();
+    now - due_at > Duration::minutes(config.late_minutes)
+}
+
+/// Renders the post listing the meetings whose reminders were missed.
+pub fn render_missed(config: &RemindersConfig, events: &[&CalendarEvent]) -> Result<String> {
+    let template = Template::parse(config.missed_template.as_deref().unwrap_or(MISSED_TEMPLATE))?;
+    Ok(template.render(&formatter::events_context(events)))
+}
+
+/// The template reminders are rendered from: the configured one, or the default.
+pub fn template_source(config: &RemindersConfig) -> &str {
+    config.template.as_deref().unwrap_or(REMINDER_TEMPLATE)
+}
+
+/// The variables of the reminder `lead` before an event.
+pub fn context(event: &CalendarEvent, lead: LeadTime) -> Context {
+    let mut context = formatter::event_context(event);
+    context.set_text("lead", lead.text());
+    context
+}
+
+/// Renders the reminder `lead` before an event.
+pub fn render(config: &RemindersConfig, event: &CalendarEvent, lead: LeadTime) -> Result<String> {
+    let template = Template::parse(template_source(config))?;
+    Ok(template.render(&context(event, lead)))
+}
+
+/// The state key kind of the direct message of reminders this long before
+/// meetings. Without a public reminder it stands in for it.
+pub fn direct_kind(config: &RemindersConfig, lead: LeadTime) -> String {
+    match config.public {
+        true => format!("{}-direct", lead.kind()),
+        false => lead.kind(),
+    }
+}
+
+/// The text of a direct message to the members: the reminder, after
+/// mentions of each of them.
+pub fn direct_message(config: &RemindersConfig, text: &str) -> String {
+    let mentions: Vec<_> = config
+        .members
+        .iter()
+        .map(|member| member.trim())
+        .filter(|member| !member.is_empty())
+        .map(|member| format!("@{}", member.trim_start_matches('@')))
+        .collect();
+    format!("{}\n{}", mentions.join(" "), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::PostRecord;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_lead_times() {
+        let lead = |text| LeadTime::parse(text).unwrap();
+        assert_eq!(lead("24h"), lead("1d"));
+        assert_eq!(lead("1h30m").minutes, 90);
+        assert_eq!(lead("1h30m").text(), "1 hour 30 minutes");
+        assert_eq!(lead("2d").text(), "2 days");
+        assert_eq!(lead("24h").kind(), "reminder-1440m");
+        for invalid in ["", "h", "24", "1w", "0m", "1h 30m"] {
+            assert!(LeadTime::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_due_reminders() {
+        let config = RemindersConfig {
+            lead_times: vec!["1h".to_string(), "24h".to_string()],
+            reply_to_announcement: false,
+            members: Vec::new(),
+            public: true,
+            events: Vec::new(),
+            template: None,
+            missed: Default::default(),
+            late_minutes: 15,
+            missed_template: None,
+        };
+        let meeting = CalendarEvent {
+            uid: Some("meeting-42@example.com".to_string()),
+            start_time: Some("20251203T190000Z".to_string()),
+            ..Default::default()
+        };
+        let mut state = State::default();
+        let due = |state: &State, day, hour, minute| {
+            let now = Utc
+                .with_ymd_and_hms(2025, 12, day, hour, minute, 0)
+                .unwrap();
+            due_reminders([&meeting], &config, state, now)
+                .unwrap()
+                .into_iter()
+                .map(|(_, lead)| lead.text())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(due(&state, 2, 18, 0).is_empty());
+        assert_eq!(due(&state, 2, 19, 0), ["1 day"]);
+        assert_eq!(due(&state, 3, 12, 0), ["1 day"]);
+        // Once the last reminder is due, the earlier one isn't any more
+        assert_eq!(due(&state, 3, 18, 15), ["1 hour"]);
+        assert!(due(&state, 3, 19, 0).is_empty());
+
+        state.record(
+            State::key("reminder-60m", &meeting),
+            PostRecord {
+                status_id: "1".to_string(),
+                url: None,
+                posted_at: Utc.with_ymd_and_hms(2025, 12, 3, 18, 0, 0).unwrap(),
+                template_hash: None,
+                event_hash: None,
+                text_hash: None,
+                links: Default::default(),
+                details: None,
+            },
+        );
+        assert!(due(&state, 3, 18, 15).is_empty());
+    }
+}
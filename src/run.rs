@@ -0,0 +1,440 @@
+use crate::cache::FeedCache;
+use crate::clock::Clock;
+use crate::commands;
+use crate::config::{Config, SafeModeConfig, UrlPolicyConfig};
+use crate::error::{Error, Result};
+use crate::gc;
+use crate::health;
+use crate::poster::{self, AccountPublisher, FailoverPublisher, Posted, Publisher};
+use crate::source::{CalendarSource, WebcalSource};
+use crate::state::State;
+use chrono::{DateTime, Utc};
+
+/// What a posting run did besides its command.
+#[derive(Debug)]
+pub struct Run<T> {
+    /// What went to the backup account while the primary was down, posted
+    /// to the primary now.
+    pub caught_up: Vec<Posted>,
+    /// What was held back during quiet hours, posted now they are over.
+    pub released: Vec<Posted>,
+    /// How many more statuses wait for quiet hours to end than before the
+    /// command ran.
+    pub held_back: usize,
+    /// Whether posts are held back after the command ran.
+    pub holding_back: bool,
+    /// What the command returned.
+    pub output: T,
+}
+
+/// How a run that counts towards [safe mode](SafeModeConfig) went.
+#[derive(Debug)]
+pub enum Outcome<T> {
+    /// The command ran.
+    Ran(T),
+    /// The bot is in safe mode, so the calendar was only fetched.
+    SafeMode {
+        since: Option<DateTime<Utc>>,
+        events: usize,
+    },
+}
+
+/// The state file of the account being posted to, which a dry run doesn't
+/// write back.
+pub fn load_state(config: &Config) -> Result<State> {
+    let state = State::load(&config.state_file)?;
+    Ok(match config.dry_run {
+        true => state.detached(),
+        false => state,
+    })
+}
+
+/// Runs a posting command for the account `config` posts to.
+///
+/// What went to the backup account and what was held back during quiet
+/// hours are posted first. The statuses the command posts to the backup are
+/// recorded for catching up, and where the calendar redirected to is
+/// remembered for the next run.
+pub async fn posting<T>(
+    clock: &impl Clock,
+    config: &Config,
+    command: impl AsyncFnOnce(&WebcalSource, &AccountPublisher, &mut State) -> Result<T>,
+) -> Result<Run<T>> {
+    let publisher = poster::account_publisher(config)?;
+    let mut state = load_state(config)?;
+    let source = WebcalSource::for_config(config, &state);
+    let run = run_command(&source, &publisher, clock, config, &mut state, command).await?;
+    remember_resolved_url(config, &mut state, &source)?;
+    Ok(run)
+}
+
+async fn run_command<S: CalendarSource, P: Publisher, B: Publisher, T>(
+    source: &S,
+    publisher: &FailoverPublisher<P, B>,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+    command: impl AsyncFnOnce(&S, &FailoverPublisher<P, B>, &mut State) -> Result<T>,
+) -> Result<Run<T>> {
+    let caught_up = catch_up(publisher.primary(), config, state).await;
+    let released = post_deferred(source, publisher, clock, config, state).await;
+    let deferred = state.deferred.len();
+
+    let result = command(source, publisher, state).await;
+    commands::record_failovers(publisher, clock, state)?;
+    let output = result?;
+
+    Ok(Run {
+        caught_up,
+        released,
+        held_back: state.deferred.len().saturating_sub(deferred),
+        holding_back: commands::holding_back(clock, config, state)?,
+        output,
+    })
+}
+
+/// Posts what went to the backup account while the primary was down. The
+/// primary still being unreachable isn't fatal; it is tried again next run.
+async fn catch_up(primary: &impl Publisher, config: &Config, state: &mut State) -> Vec<Posted> {
+    // Only the main account has a backup to catch up from
+    if state.catch_up.is_empty() || config.audience.is_some() {
+        return Vec::new();
+    }
+
+    commands::catch_up(primary, config, state)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!(
+                "Warning: primary instance still unreachable, {} status(es) left to catch up: {}",
+                state.catch_up.len(),
+                e
+            );
+            Vec::new()
+        })
+}
+
+/// Posts what was held back during quiet hours, once they are over. Failing
+/// isn't fatal; the rest is tried again next run.
+async fn post_deferred(
+    source: &impl CalendarSource,
+    publisher: &impl Publisher,
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Vec<Posted> {
+    commands::post_deferred(source, publisher, clock, config, state)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!(
+                "Warning: {} status(es) held back during quiet hours not posted yet: {}",
+                state.deferred.len(),
+                e
+            );
+            Vec::new()
+        })
+}
+
+/// Remembers where the calendar URL redirected to, so the next run can go
+/// straight there.
+pub fn remember_resolved_url(
+    config: &Config,
+    state: &mut State,
+    source: &WebcalSource,
+) -> Result<()> {
+    let previous = state.resolved_urls.get(&config.webcal).cloned();
+    if source.resolved_url() != previous {
+        match source.resolved_url() {
+            Some(url) => state.resolved_urls.insert(config.webcal.clone(), url),
+            None => state.resolved_urls.remove(&config.webcal),
+        };
+        state.save()?;
+    }
+    Ok(())
+}
+
+/// Runs a posting command as a run that counts towards [safe mode], or only
+/// fetches the calendar while the bot is in it. A run that succeeds collects
+/// garbage afterwards when `[gc]` is automatic.
+///
+/// [safe mode]: SafeModeConfig
+pub async fn tracked<T>(
+    clock: &impl Clock,
+    config: &Config,
+    post: impl Future<Output = Result<T>>,
+) -> Result<Outcome<T>> {
+    let outcome = health_tracked(clock, config, post).await?;
+    if !config.dry_run
+        && let Some(gc_config) = config.gc.as_ref().filter(|gc| gc.automatic)
+    {
+        let mut state = load_state(config)?;
+        let mut collected = gc::collect(gc_config, &mut state, clock.now());
+        if !collected.is_empty() {
+            state.save()?;
+        }
+        if let Some(cache) = config.calendar_cache() {
+            let cache = FeedCache::new(&cache);
+            gc::collect_cache(gc_config, &cache, clock.now(), false, &mut collected)
+                .map_err(Error::State)?;
+        }
+    }
+    Ok(outcome)
+}
+
+/// Runs a posting command, entering safe mode when runs keep failing.
+async fn health_tracked<T>(
+    clock: &impl Clock,
+    config: &Config,
+    post: impl Future<Output = Result<T>>,
+) -> Result<Outcome<T>> {
+    // A dry run says nothing about whether posting works
+    let Some(safe_mode) = config.safe_mode.as_ref().filter(|_| !config.dry_run) else {
+        return post.await.map(Outcome::Ran);
+    };
+
+    let mut state = load_state(config)?;
+    if state.health.in_safe_mode() {
+        send_safe_mode_alert(safe_mode, &config.url_policy, &mut state).await;
+        let result = fetch_only(clock, config, &mut state).await;
+        state.save()?;
+        return result;
+    }
+
+    let result = post.await;
+    // The command saved its own changes to the state
+    let mut state = load_state(config)?;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    if state.health.record_run(safe_mode, clock.now(), error) {
+        eprintln!(
+            "Error: {} failed runs in a row; entering safe mode",
+            state.health.consecutive_failures
+        );
+        send_safe_mode_alert(safe_mode, &config.url_policy, &mut state).await;
+    }
+    state.save()?;
+    result.map(Outcome::Ran)
+}
+
+/// Sends the alert about entering safe mode unless it already went out. A
+/// failure to send it is retried on the next run.
+async fn send_safe_mode_alert(
+    safe_mode: &SafeModeConfig,
+    policy: &UrlPolicyConfig,
+    state: &mut State,
+) {
+    if state.health.alert_sent {
+        return;
+    }
+    let text = state.health.alert_text();
+    match &safe_mode.alert_webhook {
+        Some(url) => match health::send_alert(url, &text, Some(policy)).await {
+            Ok(()) => state.health.alert_sent = true,
+            Err(e) => eprintln!("Warning: could not send the safe mode alert: {}", e),
+        },
+        None => {
+            eprintln!("Warning: {}", text);
+            state.health.alert_sent = true;
+        }
+    }
+}
+
+/// What a posting run does in safe mode: fetch the calendar, so a broken
+/// feed still shows up, without posting anything.
+async fn fetch_only<T>(
+    clock: &impl Clock,
+    config: &Config,
+    state: &mut State,
+) -> Result<Outcome<T>> {
+    let source = WebcalSource::for_config(config, state);
+    let calendar = commands::load_calendar(&source, clock, config, state).await?;
+    remember_resolved_url(config, state, &source)?;
+    Ok(Outcome::SafeMode {
+        since: state.health.safe_mode_since,
+        events: calendar.events.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::{ManualClock, MemorySource, RecordingPublisher};
+    use chrono::{Duration, TimeZone};
+    use indoc::indoc;
+
+    const MEETINGS: &str = indoc! {"
+        BEGIN:VCALENDAR
+        VERSION:2.0
+        BEGIN:VEVENT
+        UID:week-1@example.com
+        DTSTART:20251203T190000Z
+        SUMMARY:Weekly Meeting
+        END:VEVENT
+        BEGIN:VEVENT
+        UID:week-2@example.com
+        DTSTART:20251210T190000Z
+        SUMMARY:Weekly Meeting
+        END:VEVENT
+        END:VCALENDAR
+    "};
+
+    fn config(sections: &str) -> Config {
+        toml::from_str(&format!(
+            "instance = \"https://mastodon.example\"\nwebcal = \"https://example.com/calendar.ics\"\n{}",
+            sections
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_run_command() {
+        let source = MemorySource::new(MEETINGS);
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let config = config("");
+        let mut state = State::default();
+        let primary = RecordingPublisher::new();
+        primary.set_reachable(false);
+        let backup = RecordingPublisher::new();
+        let publisher =
+            FailoverPublisher::new(&primary).with_backup(&backup, 1, std::time::Duration::ZERO);
+
+        let run = run_command(
+            &source,
+            &publisher,
+            &clock,
+            &config,
+            &mut state,
+            async |source, publisher, state| {
+                commands::post_next_meeting(source, publisher, &clock, &config, state).await
+            },
+        )
+        .await
+        .unwrap();
+        assert!(run.output.is_some());
+        assert!(run.caught_up.is_empty());
+        assert_eq!(backup.texts().len(), 1);
+        assert_eq!(state.catch_up.len(), 1);
+
+        // Once the primary is back the next run catches up on it first
+        primary.set_reachable(true);
+        let run = run_command(
+            &source,
+            &publisher,
+            &clock,
+            &config,
+            &mut state,
+            async |source, publisher, state| {
+                commands::post_next_meeting(source, publisher, &clock, &config, state).await
+            },
+        )
+        .await
+        .unwrap();
+        assert!(run.output.is_none());
+        assert_eq!(run.caught_up.len(), 1);
+        assert_eq!(primary.texts(), backup.texts());
+        assert!(state.catch_up.is_empty());
+
+        let error = run_command(
+            &source,
+            &publisher,
+            &clock,
+            &config,
+            &mut state,
+            async |_, _, _| -> Result<()> { Err(Error::Publish(anyhow::anyhow!("refused"))) },
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, Error::Publish(_)));
+    }
+
+    #[tokio::test]
+    async fn test_run_command_quiet_hours() {
+        let source = MemorySource::new(MEETINGS);
+        let publisher = FailoverPublisher::<_, &RecordingPublisher>::new(RecordingPublisher::new());
+        // Saturday, November 29th
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 11, 29, 12, 0, 0).unwrap());
+        let config = config(indoc! {"
+            [quiet_hours]
+            sat = []
+            sun = []
+        "});
+        let mut state = State::default();
+
+        let run = run_command(
+            &source,
+            &publisher,
+            &clock,
+            &config,
+            &mut state,
+            async |_, publisher, state| {
+                commands::post_status(publisher, &clock, &config, state, "Hello".to_string()).await
+            },
+        )
+        .await
+        .unwrap();
+        assert!(run.output.is_none());
+        assert_eq!(run.held_back, 1);
+        assert!(run.holding_back);
+
+        // Monday: what was held back goes out before the command
+        clock.advance(Duration::days(2));
+        let run = run_command(
+            &source,
+            &publisher,
+            &clock,
+            &config,
+            &mut state,
+            async |_, _, _| Ok(()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(run.released.len(), 1);
+        assert_eq!(run.held_back, 0);
+        assert!(!run.holding_back);
+        assert_eq!(publisher.primary().texts(), ["Hello"]);
+    }
+
+    #[tokio::test]
+    async fn test_tracked() {
+        let dir = std::env::temp_dir().join(format!("ical-to-masto-run-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let calendar = dir.join("calendar.ics").display().to_string();
+        std::fs::write(&calendar, MEETINGS).unwrap();
+        let config: Config = toml::from_str(&format!(
+            "instance = \"https://mastodon.example\"\nwebcal = {:?}\nstate_file = {:?}\n[safe_mode]\nafter_failures = 2\n",
+            calendar,
+            dir.join("state.json").display().to_string()
+        ))
+        .unwrap();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap());
+        let fail = || async { Err::<(), _>(Error::Publish(anyhow::anyhow!("refused"))) };
+
+        assert!(matches!(
+            tracked(&clock, &config, async { Ok(1) }).await.unwrap(),
+            Outcome::Ran(1)
+        ));
+        assert!(tracked(&clock, &config, fail()).await.is_err());
+        assert!(!load_state(&config).unwrap().health.in_safe_mode());
+        assert!(tracked(&clock, &config, fail()).await.is_err());
+        let state = load_state(&config).unwrap();
+        assert!(state.health.in_safe_mode());
+        assert!(state.health.alert_sent);
+
+        // In safe mode the command doesn't run; the calendar is only fetched
+        let outcome = tracked(&clock, &config, async { unreachable!() as Result<()> })
+            .await
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            Outcome::SafeMode {
+                since: Some(_),
+                events: 2
+            }
+        ));
+
+        assert!(commands::resume(&mut load_state(&config).unwrap()).unwrap());
+        assert!(matches!(
+            tracked(&clock, &config, async { Ok(()) }).await.unwrap(),
+            Outcome::Ran(())
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
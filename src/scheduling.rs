@@ -0,0 +1,180 @@
+use crate::config::SchedulingPollConfig;
+use crate::formatter;
+use crate::ical::{CalendarEvent, IcalCalendar};
+use crate::state::PollRecord;
+use crate::template::{Context, Template};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+/// The state key kind used to remember which scheduling polls were posted.
+pub const KIND: &str = "scheduling-poll";
+
+/// The most options a poll can have on a default Mastodon instance.
+pub const MAX_OPTIONS: usize = 4;
+
+/// The longest a poll option can be on a default Mastodon instance.
+const MAX_OPTION_CHARS: usize = 50;
+
+/// Default template for the poll's question.
+pub const POLL_TEMPLATE: &str = "🗳️ When should we hold {#if summary}{summary}{else}the next meeting{/if}? \
+Vote for the time that works best for you!";
+
+/// Default template for announcing the poll's winner.
+pub const RESULT_TEMPLATE: &str = "🗳️ The votes are in: {#if summary}{summary}{else}the meeting{/if} \
+will be held {start} ({votes} of {total_votes} votes). Thanks for voting!";
+
+/// The candidate times for the next tentative meeting: the upcoming tentative
+/// events sharing the summary of the earliest selected one, at most
+/// [`MAX_OPTIONS`] of them.
+pub fn candidates<'a>(
+    calendar: &'a IcalCalendar,
+    config: &SchedulingPollConfig,
+    now: DateTime<Utc>,
+) -> Vec<&'a CalendarEvent> {
+    let current_time = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let tentative: Vec<_> = calendar
+        .get_upcoming_events(&current_time)
+        .into_iter()
+        .filter(|event| event.is_tentative() && event.summary_matches(&config.events))
+        .collect();
+
+    let Some(first) = tentative.first() else {
+        return Vec::new();
+    };
+    let summary = first.summary.clone();
+    tentative
+        .into_iter()
+        .filter(|event| event.summary == summary)
+        .take(MAX_OPTIONS)
+        .collect()
+}
+
+/// The poll option standing for a candidate.
+pub fn option_label(event: &CalendarEvent) -> String {
+    formatter::truncate(
+        &event.start_time_formatted().unwrap_or_default(),
+        MAX_OPTION_CHARS,
+    )
+}
+
+/// Renders the poll's question about the candidates.
+pub fn render_question(
+    config: &SchedulingPollConfig,
+    candidates: &[&CalendarEvent],
+) -> Result<String> {
+    let template = Template::parse(config.template.as_deref().unwrap_or(POLL_TEMPLATE))?;
+    Ok(template.render(&question_context(candidates)))
+}
+
+/// The variables of the poll's question: the candidates, and the summary
+/// they share.
+pub fn question_context(candidates: &[&CalendarEvent]) -> Context {
+    let mut context = formatter::events_context(candidates);
+    if let Some(first) = candidates.first() {
+        context.set_opt_text("summary", first.summary.as_deref().map(formatter::isolate));
+    }
+    context
+}
+
+/// The option with the most votes, the earliest one on a tie, or none when
+/// nobody voted.
+pub fn winner(votes: &[u64]) -> Option<usize> {
+    let most = *votes.iter().max()?;
+    if most == 0 {
+        return None;
+    }
+    votes.iter().position(|&count| count == most)
+}
+
+/// Renders the announcement of the winning option of a poll.
+pub fn render_result(
+    config: &SchedulingPollConfig,
+    poll: &PollRecord,
+    winner: usize,
+    votes: &[u64],
+) -> Result<String> {
+    let template = Template::parse(config.result_template.as_deref().unwrap_or(RESULT_TEMPLATE))?;
+    Ok(template.render(&result_context(poll, winner, votes)))
+}
+
+/// The variables of the announcement of a poll's winning option.
+pub fn result_context(poll: &PollRecord, winner: usize, votes: &[u64]) -> Context {
+    let event = CalendarEvent {
+        summary: poll.summary.clone(),
+        start_time: poll.starts.get(winner).cloned(),
+        ..Default::default()
+    };
+    let mut context = formatter::event_context(&event);
+    context
+        .set_text("votes", votes[winner].to_string())
+        .set_text("total_votes", votes.iter().sum::<u64>().to_string());
+    context
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use indoc::indoc;
+
+    fn config() -> SchedulingPollConfig {
+        SchedulingPollConfig {
+            expires_hours: 72,
+            events: Vec::new(),
+            template: None,
+            result_template: None,
+        }
+    }
+
+    #[test]
+    fn test_candidates() {
+        let calendar = IcalCalendar::parse_ical_content(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:confirmed@example.com
+            DTSTART:20251203T190000
+            SUMMARY:Monthly Meeting
+            STATUS:CONFIRMED
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:option-1@example.com
+            DTSTART:20251210T190000
+            SUMMARY:Install Fest
+            STATUS:TENTATIVE
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:social@example.com
+            DTSTART:20251211T190000
+            SUMMARY:Social Hour
+            STATUS:TENTATIVE
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:option-2@example.com
+            DTSTART:20251213T100000
+            SUMMARY:Install Fest
+            STATUS:TENTATIVE
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+        let now = Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap();
+
+        let candidates = candidates(&calendar, &config(), now);
+        let uids: Vec<_> = candidates.iter().map(|e| e.uid.clone().unwrap()).collect();
+        assert_eq!(uids, vec!["option-1@example.com", "option-2@example.com"]);
+        assert_eq!(option_label(candidates[0]), "Wed, Dec 10, 2025 at 07:00 PM");
+        assert_eq!(
+            render_question(&config(), &candidates).unwrap(),
+            "🗳️ When should we hold Install Fest? Vote for the time that works best for you!"
+        );
+    }
+
+    #[test]
+    fn test_winner() {
+        assert_eq!(winner(&[1, 3, 3]), Some(1));
+        assert_eq!(winner(&[0, 0]), None);
+        assert_eq!(winner(&[]), None);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::link_check::Health;
+use crate::outbox::{Selected, Sent};
+use crate::probe::Probe;
+use crate::skips::Skipped;
+use crate::timing::{Phase, PhaseTiming};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// What a run found out along the way. A command run on its own records
+/// into the process's; each job the daemon runs gets one of its own, so
+/// jobs and tenants don't see each other's.
+#[derive(Debug, Default)]
+pub struct Records {
+    /// The statuses created, changed or deleted, oldest first.
+    pub(crate) sent: Mutex<Vec<Sent>>,
+    /// The events picked to post about.
+    pub(crate) selected: Mutex<Vec<Selected>>,
+    /// The events left out, and why.
+    pub(crate) skipped: Mutex<Vec<Skipped>>,
+    /// How often each phase ran and how long it took.
+    pub(crate) timings: Mutex<BTreeMap<Phase, PhaseTiming>>,
+    /// What was found out about each probed URL.
+    pub(crate) probed: Mutex<BTreeMap<String, Probe>>,
+    /// What was found out about each link checked.
+    pub(crate) checked: Mutex<BTreeMap<String, Health>>,
+}
+
+static PROCESS: LazyLock<Arc<Records>> = LazyLock::new(Default::default);
+
+tokio::task_local! {
+    static CURRENT: Arc<Records>;
+}
+
+/// The records of the job running, or the process's outside of one.
+pub(crate) fn current() -> Arc<Records> {
+    CURRENT
+        .try_with(Arc::clone)
+        .unwrap_or_else(|_| PROCESS.clone())
+}
+
+/// Runs `future` as a job of its own, starting with nothing recorded.
+pub async fn job<F: Future>(future: F) -> F::Output {
+    CURRENT.scope(Arc::default(), future).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_job() {
+        let probe = |url: &str| Probe {
+            url: url.to_string(),
+            healthy: true,
+            status: Some(200),
+            problem: None,
+            elapsed_ms: 1,
+        };
+        let urls = || -> Vec<String> { current().probed.lock().unwrap().keys().cloned().collect() };
+
+        job(async {
+            let probed = probe("https://one.example");
+            current()
+                .probed
+                .lock()
+                .unwrap()
+                .insert(probed.url.clone(), probed);
+            assert_eq!(urls(), vec!["https://one.example"]);
+        })
+        .await;
+        job(async {
+            assert!(urls().is_empty());
+            let probed = probe("https://two.example");
+            current()
+                .probed
+                .lock()
+                .unwrap()
+                .insert(probed.url.clone(), probed);
+            assert_eq!(urls(), vec!["https://two.example"]);
+        })
+        .await;
+        // Other tests probe in the process's records, but not these URLs
+        assert!(!urls().iter().any(|url| url.ends_with("one.example")));
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::{Result, anyhow};
+use std::path::Path;
+
+/// Environment variable holding the main account's access token, used in
+/// place of its token file.
+pub const ACCESS_TOKEN_VAR: &str = "ICAL_TO_MASTO_ACCESS_TOKEN";
+
+/// Environment variable naming a file with the main account's access token,
+/// such as a container secret.
+pub const ACCESS_TOKEN_FILE_VAR: &str = "ICAL_TO_MASTO_ACCESS_TOKEN_FILE";
+
+/// Environment variable with the main account's instance, in place of the
+/// configured one.
+pub const INSTANCE_VAR: &str = "ICAL_TO_MASTO_INSTANCE";
+
+/// The main account's access token from the environment, if it is given
+/// there, directly or in a file.
+pub fn access_token() -> Result<Option<String>> {
+    if let Some(token) = var(ACCESS_TOKEN_VAR) {
+        return Ok(Some(token));
+    }
+    match var(ACCESS_TOKEN_FILE_VAR) {
+        Some(path) => read(&path).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The instance from the environment, if it is given there.
+pub fn instance() -> Option<String> {
+    var(INSTANCE_VAR)
+}
+
+/// A variable that is set to something other than blanks.
+fn var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Reads a secret from a file, without the line break editors and `echo`
+/// leave after it.
+pub fn read(path: &str) -> Result<String> {
+    let secret = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read the secret file {}: {}", path, e))?;
+    warn_if_exposed(Path::new(path));
+    Ok(secret.trim().to_string())
+}
+
+/// Warns when a file with secrets may be read by every user on the machine.
+pub fn warn_if_exposed(path: &Path) {
+    if is_world_readable(path) {
+        eprintln!(
+            "Warning: {} can be read by every user on this machine; restrict it with 'chmod 600 {}'",
+            path.display(),
+            path.display()
+        );
+    }
+}
+
+#[cfg(unix)]
+fn is_world_readable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o004 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_world_readable(_path: &Path) -> bool {
+    false
+}
+
+/// Writes a file with secrets that only its owner may read.
+pub fn write(path: &Path, content: &str) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        // An existing file keeps its mode when opened
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        file.write_all(content.as_bytes())
+    }
+    #[cfg(not(unix))]
+    std::fs::write(path, content)
+}
+
+/// Overwrites a file with secrets before deleting it, so the secret isn't
+/// left on the disk for anyone who can read the blocks to find.
+pub fn shred(path: &Path) -> std::io::Result<()> {
+    use std::io::Write;
+    let length = std::fs::metadata(path)?.len();
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    file.write_all(&vec![0; length as usize])?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::remove_file(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_written_secrets_are_private() {
+        let path =
+            std::env::temp_dir().join(format!("ical-to-masto-secret-{}", std::process::id()));
+        std::fs::write(&path, "old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(is_world_readable(&path));
+
+        write(&path, "token\n").unwrap();
+        assert!(!is_world_readable(&path));
+        assert_eq!(read(path.to_str().unwrap()).unwrap(), "token");
+
+        shred(&path).unwrap();
+        assert!(!path.exists());
+        assert!(shred(&path).is_err());
+    }
+}
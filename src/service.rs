@@ -0,0 +1,394 @@
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
+
+/// The service name used when none is given.
+pub const DEFAULT_NAME: &str = "ical-to-masto";
+
+/// The daemon as a service of the operating system: a launchd agent on
+/// macOS, a Windows service on Windows.
+pub struct Service {
+    /// Tells several bots on one machine apart.
+    pub name: String,
+    /// The `ical-to-masto` executable.
+    pub program: PathBuf,
+    /// The configuration file, as an absolute path.
+    pub config: PathBuf,
+    /// The `--feed` the daemon runs for.
+    pub feed: Option<String>,
+}
+
+impl Service {
+    /// The service for the running executable and the configuration at
+    /// `config`.
+    pub fn new(name: &str, config: &str, feed: Option<&str>) -> Result<Self> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        {
+            return Err(anyhow!(
+                "Service names may only have letters, digits, '-', '_' and '.': {:?}",
+                name
+            ));
+        }
+        Ok(Service {
+            name: name.to_string(),
+            program: std::env::current_exe()?,
+            config: std::path::absolute(config)?,
+            feed: feed.map(String::from),
+        })
+    }
+
+    /// The arguments the service is started with, before the command.
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["-c".to_string(), self.config.display().to_string()];
+        if let Some(feed) = &self.feed {
+            args.extend(["--feed".to_string(), feed.clone()]);
+        }
+        args
+    }
+
+    /// Where relative paths in the configuration are read from.
+    pub fn working_directory(&self) -> &Path {
+        self.config.parent().unwrap_or(Path::new("/"))
+    }
+
+    /// The launchd label of the agent.
+    pub fn label(&self) -> String {
+        format!("org.ical-to-masto.{}", self.name)
+    }
+
+    /// The launchd property list of an agent that starts the daemon at login
+    /// and restarts it when it exits, logging to `log`.
+    pub fn launchd_plist(&self, log: &Path) -> String {
+        let argument = |arg: &str| format!("\n        <string>{}</string>", xml_escape(arg));
+        let mut arguments = argument(&self.program.display().to_string());
+        for arg in self.args() {
+            arguments.push_str(&argument(&arg));
+        }
+        arguments.push_str(&argument("daemon"));
+        let log = xml_escape(&log.display().to_string());
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>{}
+    </array>
+    <key>WorkingDirectory</key>
+    <string>{}</string>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardOutPath</key>
+    <string>{}</string>
+    <key>StandardErrorPath</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#,
+            xml_escape(&self.label()),
+            arguments,
+            xml_escape(&self.working_directory().display().to_string()),
+            log,
+            log,
+        )
+    }
+
+    /// The command line Windows starts the service with.
+    pub fn windows_command_line(&self) -> String {
+        let mut words = vec![quote(&self.program.display().to_string())];
+        words.extend(self.args().iter().map(|arg| quote(arg)));
+        words.extend(["service", "run", "--name"].map(String::from));
+        words.push(quote(&self.name));
+        words.join(" ")
+    }
+
+    /// Installs and starts the service, returning where it is described.
+    pub fn install(&self) -> Result<String> {
+        if cfg!(target_os = "macos") {
+            let logs = home()?.join("Library/Logs/ical-to-masto");
+            std::fs::create_dir_all(&logs)?;
+            let plist = self.plist_path()?;
+            if let Some(agents) = plist.parent() {
+                std::fs::create_dir_all(agents)?;
+            }
+            let log = logs.join(format!("{}.log", self.name));
+            std::fs::write(&plist, self.launchd_plist(&log))?;
+            run("launchctl", &["load", "-w", &plist.display().to_string()])?;
+            Ok(plist.display().to_string())
+        } else if cfg!(windows) {
+            let display_name = format!("ical-to-masto ({})", self.name);
+            let command_line = self.windows_command_line();
+            run(
+                "sc.exe",
+                &[
+                    "create",
+                    &self.name,
+                    "binPath=",
+                    &command_line,
+                    "start=",
+                    "auto",
+                    "DisplayName=",
+                    &display_name,
+                ],
+            )?;
+            run(
+                "sc.exe",
+                &[
+                    "description",
+                    &self.name,
+                    "Announces meetings from an iCal calendar on Mastodon",
+                ],
+            )?;
+            run("sc.exe", &["start", &self.name])?;
+            Ok(format!("the Windows service {}", self.name))
+        } else {
+            Err(unsupported())
+        }
+    }
+
+    /// Stops and removes the service.
+    pub fn uninstall(&self) -> Result<()> {
+        if cfg!(target_os = "macos") {
+            let plist = self.plist_path()?;
+            if !plist.exists() {
+                return Err(anyhow!("No launchd agent at {}", plist.display()));
+            }
+            // An agent that isn't loaded is removed all the same
+            if let Err(e) = run("launchctl", &["unload", "-w", &plist.display().to_string()]) {
+                eprintln!("Warning: {}", e);
+            }
+            std::fs::remove_file(&plist)?;
+            Ok(())
+        } else if cfg!(windows) {
+            // A service that isn't running is deleted all the same
+            if let Err(e) = run("sc.exe", &["stop", &self.name]) {
+                eprintln!("Warning: {}", e);
+            }
+            run("sc.exe", &["delete", &self.name])
+        } else {
+            Err(unsupported())
+        }
+    }
+
+    fn plist_path(&self) -> Result<PathBuf> {
+        Ok(home()?
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", self.label())))
+    }
+}
+
+fn home() -> Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| anyhow!("Could not find the home directory"))
+}
+
+fn unsupported() -> anyhow::Error {
+    anyhow!(
+        "Services can be installed on macOS and Windows; elsewhere, run 'daemon' from a systemd unit or an init script"
+    )
+}
+
+/// Runs a service manager's command, failing with its output when it does.
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow!("Could not run {}: {}", program, e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let mut message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if message.is_empty() {
+        message = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    }
+    Err(anyhow!(
+        "{} {} failed: {}",
+        program,
+        args.first().unwrap_or(&""),
+        message
+    ))
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A word of a Windows command line, quoted when it has spaces.
+fn quote(word: &str) -> String {
+    if word.is_empty() || word.contains([' ', '\t', '"']) {
+        format!("\"{}\"", word.replace('"', "\\\""))
+    } else {
+        word.to_string()
+    }
+}
+
+/// Runs `daemon` as the Windows service `name` until it ends or the service
+/// is stopped, returning its outcome, or `None` when stopped. Fails when the
+/// process wasn't started by the Service Control Manager.
+#[cfg(windows)]
+pub async fn run_windows_service<T>(
+    name: &str,
+    daemon: impl Future<Output = T>,
+) -> Result<Option<T>> {
+    windows::run(name, daemon).await
+}
+
+#[cfg(windows)]
+mod windows {
+    use anyhow::{Result, anyhow};
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicPtr, Ordering};
+    use std::sync::mpsc;
+    use windows_sys::Win32::System::Services::{
+        RegisterServiceCtrlHandlerExW, SERVICE_ACCEPT_SHUTDOWN, SERVICE_ACCEPT_STOP,
+        SERVICE_CONTROL_INTERROGATE, SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP,
+        SERVICE_RUNNING, SERVICE_STATUS, SERVICE_STATUS_CURRENT_STATE, SERVICE_STOP_PENDING,
+        SERVICE_STOPPED, SERVICE_TABLE_ENTRYW, SERVICE_WIN32_OWN_PROCESS, SetServiceStatus,
+        StartServiceCtrlDispatcherW,
+    };
+
+    const NO_ERROR: u32 = 0;
+    const ERROR_CALL_NOT_IMPLEMENTED: u32 = 120;
+
+    /// The service's name, for the dispatcher to start it under.
+    static NAME: Mutex<Vec<u16>> = Mutex::new(Vec::new());
+    /// Tells the daemon to stop.
+    static STOP: Mutex<Option<tokio::sync::oneshot::Sender<()>>> = Mutex::new(None);
+    /// Tells the service's main function that the daemon has stopped.
+    static FINISHED: Mutex<Option<mpsc::Receiver<()>>> = Mutex::new(None);
+    static STATUS_HANDLE: AtomicPtr<core::ffi::c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+    pub async fn run<T>(name: &str, daemon: impl Future<Output = T>) -> Result<Option<T>> {
+        let (stop, stopped) = tokio::sync::oneshot::channel();
+        let (finish, finished) = mpsc::channel();
+        *NAME.lock().unwrap() = name.encode_utf16().chain([0]).collect();
+        *STOP.lock().unwrap() = Some(stop);
+        *FINISHED.lock().unwrap() = Some(finished);
+
+        // The dispatcher blocks until the service has stopped
+        let (started, dispatched) = tokio::sync::oneshot::channel();
+        let dispatcher = std::thread::spawn(move || {
+            let mut name = NAME.lock().unwrap().clone();
+            let table = [
+                SERVICE_TABLE_ENTRYW {
+                    lpServiceName: name.as_mut_ptr(),
+                    lpServiceProc: Some(service_main),
+                },
+                SERVICE_TABLE_ENTRYW::default(),
+            ];
+            // SAFETY: the table ends with an empty entry, and it and the name
+            // outlive the dispatcher
+            let ok = unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } != 0;
+            if !ok {
+                let _ = started.send(std::io::Error::last_os_error());
+            }
+        });
+
+        let outcome = tokio::select! {
+            outcome = daemon => Some(outcome),
+            _ = stopped => None,
+            Ok(e) = dispatched => {
+                return Err(anyhow!(
+                    "Could not run as a Windows service ({}); install it with 'service install'",
+                    e
+                ));
+            }
+        };
+        let _ = finish.send(());
+        tokio::task::spawn_blocking(move || dispatcher.join())
+            .await?
+            .map_err(|_| anyhow!("The service dispatcher panicked"))?;
+        Ok(outcome)
+    }
+
+    unsafe extern "system" fn service_main(_argc: u32, _argv: *mut windows_sys::core::PWSTR) {
+        let name = NAME.lock().unwrap().clone();
+        // SAFETY: the name is a NUL-terminated UTF-16 string
+        let handle = unsafe {
+            RegisterServiceCtrlHandlerExW(name.as_ptr(), Some(control_handler), std::ptr::null())
+        };
+        if handle.is_null() {
+            return;
+        }
+        STATUS_HANDLE.store(handle, Ordering::SeqCst);
+        set_status(SERVICE_RUNNING);
+
+        if let Some(finished) = FINISHED.lock().unwrap().take() {
+            let _ = finished.recv();
+        }
+        set_status(SERVICE_STOPPED);
+    }
+
+    unsafe extern "system" fn control_handler(
+        control: u32,
+        _event_type: u32,
+        _event_data: *mut core::ffi::c_void,
+        _context: *mut core::ffi::c_void,
+    ) -> u32 {
+        match control {
+            SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+                set_status(SERVICE_STOP_PENDING);
+                if let Some(stop) = STOP.lock().unwrap().take() {
+                    let _ = stop.send(());
+                }
+                NO_ERROR
+            }
+            SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+            _ => ERROR_CALL_NOT_IMPLEMENTED,
+        }
+    }
+
+    fn set_status(state: SERVICE_STATUS_CURRENT_STATE) {
+        let status = SERVICE_STATUS {
+            dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+            dwCurrentState: state,
+            dwControlsAccepted: match state {
+                SERVICE_RUNNING => SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN,
+                _ => 0,
+            },
+            dwWaitHint: 10_000,
+            ..Default::default()
+        };
+        // SAFETY: the handle came from RegisterServiceCtrlHandlerExW
+        unsafe { SetServiceStatus(STATUS_HANDLE.load(Ordering::SeqCst), &status) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_definitions() {
+        let service = Service {
+            name: "club".to_string(),
+            program: PathBuf::from("/usr/local/bin/ical-to-masto"),
+            config: PathBuf::from("/Users/sam/Bots & Co/bot.toml"),
+            feed: Some("board".to_string()),
+        };
+        let plist = service.launchd_plist(Path::new("/Users/sam/Library/Logs/club.log"));
+        assert!(plist.contains("<string>org.ical-to-masto.club</string>"));
+        assert!(plist.contains(indoc::indoc! {"
+            <array>
+                    <string>/usr/local/bin/ical-to-masto</string>
+                    <string>-c</string>
+                    <string>/Users/sam/Bots &amp; Co/bot.toml</string>
+                    <string>--feed</string>
+                    <string>board</string>
+                    <string>daemon</string>
+                </array>"}));
+        assert!(plist.contains("<string>/Users/sam/Bots &amp; Co</string>"));
+
+        assert_eq!(
+            service.windows_command_line(),
+            r#"/usr/local/bin/ical-to-masto -c "/Users/sam/Bots & Co/bot.toml" --feed board service run --name club"#
+        );
+    }
+}
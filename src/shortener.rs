@@ -0,0 +1,191 @@
+use crate::config::{Config, ShortenerConfig, UrlPolicyConfig};
+use crate::ical::CalendarEvent;
+use crate::state::State;
+use crate::{link_check, url_policy};
+use anyhow::{Result, anyhow};
+
+/// Tidies an event URL so the same link is always posted (and shortened)
+/// the same way: surrounding whitespace and iCal escapes are dropped, a
+/// missing scheme becomes `https://`, and the scheme and host are lowercased.
+/// A URL that can't be read is only trimmed.
+pub fn normalize(url: &str) -> String {
+    let url = url.trim().replace("\\,", ",").replace("\\;", ";");
+    let with_scheme = if url.contains("://") {
+        url.clone()
+    } else {
+        format!("https://{}", url)
+    };
+    match reqwest::Url::parse(&with_scheme) {
+        Ok(parsed) if parsed.has_host() => {
+            let mut normalized = parsed.to_string();
+            // Parsing adds a path to a bare domain; keep it as written
+            let bare =
+                parsed.path() == "/" && parsed.query().is_none() && parsed.fragment().is_none();
+            if bare && !url.ends_with('/') {
+                normalized.pop();
+            }
+            normalized
+        }
+        _ => url,
+    }
+}
+
+/// Asks the shortener for a short link, with a POST of `{"url": "..."}`.
+/// The answer is read from the configured field of a JSON object, or taken
+/// as is when it is a bare JSON string or plain-text link. The shortener is
+/// only asked if `policy` allows it.
+pub async fn shorten(
+    config: &ShortenerConfig,
+    url: &str,
+    policy: Option<&UrlPolicyConfig>,
+) -> Result<String> {
+    let mut request = url_policy::client(policy, &config.api_url)?
+        .post(&config.api_url)
+        .timeout(std::time::Duration::from_secs(config.timeout_seconds))
+        .json(&serde_json::json!({ "url": url }));
+    if let Some(token) = &config.token {
+        request = request.bearer_auth(token);
+    }
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("HTTP error {}", response.status()));
+    }
+    let body = response.text().await?;
+    short_link(&body, &config.response_field)
+        .ok_or_else(|| anyhow!("No short link in the shortener's answer: {}", body))
+}
+
+fn short_link(body: &str, field: &str) -> Option<String> {
+    let link = match serde_json::from_str(body) {
+        Ok(serde_json::Value::Object(object)) => object.get(field)?.as_str()?.to_string(),
+        Ok(serde_json::Value::String(link)) => link,
+        _ => body.trim().to_string(),
+    };
+    (link.starts_with("http://") || link.starts_with("https://")).then_some(link)
+}
+
+/// The URL to post for `url`: normalized and, when a shortener is configured
+/// and the URL is longer than `min_length`, shortened. Short links are kept
+/// in the state; when the shortener can't be reached the normalized URL is
+/// posted instead, and shortening is tried again on the next run. A dry run
+/// only uses short links made earlier.
+pub async fn posted_url(config: &Config, state: &mut State, url: &str) -> Result<String> {
+    let url = normalize(url);
+    let Some(shortener) = &config.shortener else {
+        return Ok(url);
+    };
+    if url.chars().count() <= shortener.min_length {
+        return Ok(url);
+    }
+    if let Some(short) = state.short_urls.get(&url) {
+        return Ok(short.clone());
+    }
+    if config.dry_run {
+        return Ok(url);
+    }
+
+    match shorten(shortener, &url, Some(&config.url_policy)).await {
+        Ok(short) => {
+            state.short_urls.insert(url, short.clone());
+            state.save()?;
+            Ok(short)
+        }
+        Err(e) => {
+            eprintln!("Warning: posting {} unshortened: {}", url, e);
+            Ok(url)
+        }
+    }
+}
+
+/// A copy of the event with the URL to post in place of its own, or none
+/// when the `link_check` found it dead and says to drop it.
+pub async fn with_posted_url(
+    config: &Config,
+    state: &mut State,
+    event: &CalendarEvent,
+) -> Result<CalendarEvent> {
+    let url = match (&event.url, &config.link_check) {
+        (Some(url), Some(link_check)) => {
+            link_check::checked(link_check, normalize(url), Some(&config.url_policy)).await
+        }
+        (url, _) => url.clone(),
+    };
+    let url = match url {
+        Some(url) => Some(posted_url(config, state, &url).await?),
+        None => None,
+    };
+    Ok(CalendarEvent {
+        url,
+        ..event.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(" HTTPS://Example.COM/register?a=1\\,2 "),
+            "https://example.com/register?a=1,2"
+        );
+        assert_eq!(normalize("example.com"), "https://example.com");
+        assert_eq!(normalize("https://example.com/"), "https://example.com/");
+        assert_eq!(normalize("not a url"), "not a url");
+    }
+
+    #[test]
+    fn test_short_link() {
+        assert_eq!(
+            short_link(r#"{"short_url": "https://s.example/abc"}"#, "short_url").as_deref(),
+            Some("https://s.example/abc")
+        );
+        assert_eq!(
+            short_link(r#"{"link": "https://s.example/abc"}"#, "short_url"),
+            None
+        );
+        assert_eq!(
+            short_link("https://s.example/abc\n", "short_url").as_deref(),
+            Some("https://s.example/abc")
+        );
+        assert_eq!(short_link("Internal error", "short_url"), None);
+    }
+
+    #[tokio::test]
+    async fn test_posted_url_cached_and_fallback() {
+        let mut config: Config = toml::from_str(indoc::indoc! {r#"
+            instance = "https://mastodon.example"
+            webcal = "https://example.com/calendar.ics"
+
+            [shortener]
+            api_url = "http://127.0.0.1:9/shorten"
+            min_length = 30
+        "#})
+        .unwrap();
+        let mut state = State::default();
+        let long = "https://example.com/events/2025/12/install-fest/register";
+
+        // The shortener isn't listening, so the URL is posted as it is
+        assert_eq!(posted_url(&config, &mut state, long).await.unwrap(), long);
+        assert!(state.short_urls.is_empty());
+
+        state
+            .short_urls
+            .insert(long.to_string(), "https://s.example/if".to_string());
+        assert_eq!(
+            posted_url(&config, &mut state, long).await.unwrap(),
+            "https://s.example/if"
+        );
+        assert_eq!(
+            posted_url(&config, &mut state, "https://example.com/short")
+                .await
+                .unwrap(),
+            "https://example.com/short"
+        );
+
+        config.shortener = None;
+        assert_eq!(posted_url(&config, &mut state, long).await.unwrap(), long);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::ical::CalendarEvent;
+use crate::scope;
+use serde::Serialize;
+
+/// Why an event wasn't announced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// It has no DTSTART.
+    NoStart,
+    /// It has already started.
+    Past,
+    /// STATUS:CANCELLED.
+    Cancelled,
+    /// CLASS:PRIVATE or CLASS:CONFIDENTIAL.
+    Private,
+    /// The `[filter]` rules leave it out.
+    Filtered,
+    /// An operator muted it.
+    Muted,
+    /// An override leaves the occurrence out.
+    Overridden,
+    /// STATUS:TENTATIVE, with `tentative_events = "exclude"`.
+    Tentative,
+    /// A language account announces it, or the main account does when
+    /// posting for a language account.
+    OtherAccount,
+    /// `post-next` already announced it and it hasn't changed since.
+    AlreadyAnnounced,
+    /// Its reminder is late, with `missed = "skip"`.
+    MissedReminder,
+}
+
+impl SkipReason {
+    pub fn name(self) -> &'static str {
+        match self {
+            SkipReason::NoStart => "no-start",
+            SkipReason::Past => "past",
+            SkipReason::Cancelled => "cancelled",
+            SkipReason::Private => "private",
+            SkipReason::Filtered => "filtered",
+            SkipReason::Muted => "muted",
+            SkipReason::Overridden => "overridden",
+            SkipReason::Tentative => "tentative",
+            SkipReason::OtherAccount => "other-account",
+            SkipReason::AlreadyAnnounced => "already-announced",
+            SkipReason::MissedReminder => "missed-reminder",
+        }
+    }
+}
+
+/// An event a run left out, and why.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Skipped {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub start: Option<String>,
+    pub reason: SkipReason,
+}
+
+/// Notes that the run left an event out.
+pub fn record(event: &CalendarEvent, reason: SkipReason) {
+    tracing::debug!(
+        summary = event.summary.as_deref(),
+        start = event.start_time.as_deref(),
+        reason = reason.name(),
+        "event skipped"
+    );
+    scope::current().skipped.lock().unwrap().push(Skipped {
+        uid: event.uid.clone(),
+        summary: event.summary.clone(),
+        start: event.start_time.clone(),
+        reason,
+    });
+}
+
+/// The events left out so far in this run, in the order they were.
+pub fn summary() -> Vec<Skipped> {
+    scope::current().skipped.lock().unwrap().clone()
+}
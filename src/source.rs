@@ -0,0 +1,419 @@
+use crate::cache::{CachedFeed, FeedCache};
+use crate::config::{
+    CacheConfig, CalendarLoginConfig, CalendarOAuthConfig, Config, UrlPolicyConfig,
+};
+use crate::error::{Error, Result};
+use crate::fetch::{self, Auth, Fetched, HttpError};
+use crate::ical::IcalCalendar;
+use crate::nextcloud;
+use crate::oauth;
+use crate::state::{State, fingerprint};
+use chrono::Utc;
+use reqwest::StatusCode;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// The calendars parsed so far in this process with the fingerprint of the
+/// text they were parsed from, by URL, so a daemon only parses a cached
+/// calendar again once it changed.
+static PARSED: Mutex<BTreeMap<String, (String, IcalCalendar)>> = Mutex::new(BTreeMap::new());
+
+/// Somewhere a calendar can be fetched from.
+pub trait CalendarSource {
+    fn fetch(&self) -> impl Future<Output = Result<IcalCalendar>>;
+}
+
+/// A calendar published at a webcal/HTTP(S) URL, or read from a local file
+/// or stdin.
+pub struct WebcalSource {
+    pub url: String,
+    pub max_redirects: usize,
+    /// Client credentials to get a bearer token with, for protected calendars.
+    pub oauth: Option<CalendarOAuthConfig>,
+    /// Basic auth credentials, for calendars on WebDAV servers.
+    pub login: Option<CalendarLoginConfig>,
+    /// Where the URL last ended up after redirects, if somewhere else.
+    resolved_url: Mutex<Option<String>>,
+    cache: Option<FeedCache>,
+    /// Whether a cached calendar is checked with the server however recent.
+    revalidate: bool,
+    /// Which URLs the calendar may be fetched from, redirects included.
+    pub url_policy: Option<UrlPolicyConfig>,
+}
+
+impl WebcalSource {
+    pub fn new(url: &str) -> Self {
+        WebcalSource {
+            url: url.to_string(),
+            max_redirects: fetch::DEFAULT_MAX_REDIRECTS,
+            oauth: None,
+            login: None,
+            resolved_url: Mutex::new(None),
+            cache: None,
+            revalidate: false,
+            url_policy: None,
+        }
+    }
+
+    /// The configured calendar, fetched the way the command line does:
+    /// with its credentials, redirect limit and cache, starting from where it
+    /// redirected to last time.
+    pub fn for_config(config: &Config, state: &State) -> Self {
+        WebcalSource::new(&config.webcal)
+            .with_max_redirects(config.max_redirects)
+            .with_oauth(config.calendar_oauth.clone())
+            .with_login(config.calendar_login.clone())
+            .with_cache(config.calendar_cache().as_ref())
+            .with_resolved_url(state.resolved_urls.get(&config.webcal).cloned())
+            .with_url_policy(Some(config.url_policy.clone()))
+    }
+
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn with_oauth(mut self, oauth: Option<CalendarOAuthConfig>) -> Self {
+        self.oauth = oauth;
+        self
+    }
+
+    pub fn with_login(mut self, login: Option<CalendarLoginConfig>) -> Self {
+        self.login = login;
+        self
+    }
+
+    pub fn with_url_policy(mut self, url_policy: Option<UrlPolicyConfig>) -> Self {
+        self.url_policy = url_policy;
+        self
+    }
+
+    /// Keeps the fetched calendar on disk, to only download it again once it
+    /// changed.
+    pub fn with_cache(mut self, cache: Option<&CacheConfig>) -> Self {
+        self.cache = cache.map(FeedCache::new);
+        self
+    }
+
+    /// Asks the server whether the calendar changed even while the cached
+    /// copy is fresh, for refreshing the cache.
+    pub fn revalidating(mut self) -> Self {
+        self.revalidate = true;
+        self
+    }
+
+    /// Starts from a previously resolved URL, skipping the redirect chain.
+    pub fn with_resolved_url(self, resolved_url: Option<String>) -> Self {
+        *self.resolved_url.lock().unwrap() = resolved_url;
+        self
+    }
+
+    /// The URL the calendar was actually served from, when it differs from `url`.
+    pub fn resolved_url(&self) -> Option<String> {
+        self.resolved_url.lock().unwrap().clone()
+    }
+
+    /// Where the calendar is fetched from: `url`, or for a Nextcloud share
+    /// link or CalDAV collection, its iCalendar export.
+    fn location(&self) -> anyhow::Result<String> {
+        Ok(nextcloud::export_url(&self.url)?.unwrap_or_else(|| self.url.clone()))
+    }
+
+    /// Fetches `url`, with the calendar's credentials if `url` is on the
+    /// calendar's own host. A bearer token the server turns down is replaced
+    /// once, in case it was revoked before it expired.
+    async fn fetch_url(&self, url: &str, cached: Option<&CachedFeed>) -> anyhow::Result<Fetched> {
+        let own_host = fetch::same_host(url, &self.url);
+        let oauth = match &self.oauth {
+            Some(oauth) if own_host => oauth,
+            _ => {
+                let login = self.login.as_ref().filter(|_| own_host);
+                let auth = login.map(|login| Auth::Basic {
+                    username: &login.username,
+                    password: login.password.as_deref(),
+                });
+                return fetch::fetch_text(
+                    url,
+                    self.max_redirects,
+                    auth,
+                    cached,
+                    self.url_policy.as_ref(),
+                )
+                .await;
+            }
+        };
+        let token = oauth::bearer_token(oauth, self.url_policy.as_ref()).await?;
+        let auth = Some(Auth::Bearer(&token));
+        match fetch::fetch_text(
+            url,
+            self.max_redirects,
+            auth,
+            cached,
+            self.url_policy.as_ref(),
+        )
+        .await
+        {
+            Err(e)
+                if e.downcast_ref::<HttpError>()
+                    .is_some_and(|e| e.status == StatusCode::UNAUTHORIZED) =>
+            {
+                oauth::forget(oauth);
+                let token = oauth::bearer_token(oauth, self.url_policy.as_ref()).await?;
+                let auth = Some(Auth::Bearer(&token));
+                fetch::fetch_text(
+                    url,
+                    self.max_redirects,
+                    auth,
+                    cached,
+                    self.url_policy.as_ref(),
+                )
+                .await
+            }
+            fetched => fetched,
+        }
+    }
+
+    /// Parses a fetched calendar, or with a cache, takes the calendar parsed
+    /// from the same text before.
+    fn parse(&self, body: &str) -> Result<IcalCalendar> {
+        if self.cache.is_none() {
+            return IcalCalendar::parse_ical_content(body).map_err(Error::Parse);
+        }
+        let hash = fingerprint(body);
+        if let Some((parsed_hash, calendar)) = PARSED.lock().unwrap().get(&self.url)
+            && *parsed_hash == hash
+        {
+            return Ok(calendar.clone());
+        }
+        let calendar = IcalCalendar::parse_ical_content(body).map_err(Error::Parse)?;
+        PARSED
+            .lock()
+            .unwrap()
+            .insert(self.url.clone(), (hash, calendar.clone()));
+        Ok(calendar)
+    }
+
+    /// Keeps what was fetched in the cache, if there is one. Failing to is
+    /// only worth a warning, as the calendar was fetched all the same.
+    fn store(&self, fetched: &Fetched) {
+        if let Some(cache) = &self.cache
+            && let Err(e) = cache.store(&self.url, fetched, Utc::now())
+        {
+            eprintln!("Warning: could not cache the calendar: {}", e);
+        }
+    }
+}
+
+/// A source that keeps the calendar it fetched in memory for `ttl`, so tools
+/// asking for it often don't fetch it each time. When fetching it again
+/// fails, the calendar fetched last is used, with a warning.
+pub struct CachedCalendarSource<S> {
+    inner: S,
+    ttl: std::time::Duration,
+    /// The calendar fetched last, and until when it is used without asking
+    /// the source again; `None` for ever.
+    fetched: Mutex<Option<(Option<Instant>, IcalCalendar)>>,
+}
+
+impl<S: CalendarSource> CachedCalendarSource<S> {
+    pub fn new(inner: S, ttl: std::time::Duration) -> Self {
+        CachedCalendarSource {
+            inner,
+            ttl,
+            fetched: Mutex::new(None),
+        }
+    }
+
+    /// The source the calendar is fetched from.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Has the next fetch ask the source again, however recent the kept
+    /// calendar is.
+    pub fn invalidate(&self) {
+        if let Some((until, _)) = self.fetched.lock().unwrap().as_mut() {
+            *until = Some(Instant::now());
+        }
+    }
+}
+
+impl<S: CalendarSource> CalendarSource for CachedCalendarSource<S> {
+    async fn fetch(&self) -> Result<IcalCalendar> {
+        if let Some((until, calendar)) = self.fetched.lock().unwrap().as_ref()
+            && until.is_none_or(|until| Instant::now() < until)
+        {
+            return Ok(calendar.clone());
+        }
+        match self.inner.fetch().await {
+            Ok(calendar) => {
+                let until = Instant::now().checked_add(self.ttl);
+                *self.fetched.lock().unwrap() = Some((until, calendar.clone()));
+                Ok(calendar)
+            }
+            Err(e) => match self.fetched.lock().unwrap().as_ref() {
+                Some((_, calendar)) => {
+                    eprintln!(
+                        "Warning: could not fetch the calendar again ({}); using the one fetched before",
+                        e
+                    );
+                    Ok(calendar.clone())
+                }
+                None => Err(e),
+            },
+        }
+    }
+}
+
+impl CalendarSource for WebcalSource {
+    async fn fetch(&self) -> Result<IcalCalendar> {
+        if fetch::is_local(&self.url) {
+            let content = fetch::read_local(&self.url).map_err(Error::Fetch)?;
+            return IcalCalendar::parse_ical_content(&content).map_err(Error::Parse);
+        }
+        let cached = self.cache.as_ref().and_then(|cache| cache.load(&self.url));
+        if let (Some(cache), Some(cached)) = (&self.cache, &cached)
+            && !self.revalidate
+            && cache.is_fresh(cached, Utc::now())
+        {
+            return self.parse(&cached.body);
+        }
+
+        if let Some(resolved_url) = self.resolved_url() {
+            match self.fetch_url(&resolved_url, cached.as_ref()).await {
+                Ok(fetched) => {
+                    self.store(&fetched);
+                    return self.parse(&fetched.body);
+                }
+                Err(e) => {
+                    // The short link may point somewhere new now
+                    eprintln!(
+                        "Warning: could not fetch {} ({}); retrying {}",
+                        resolved_url, e, self.url
+                    );
+                }
+            }
+        }
+
+        let location = self.location().map_err(Error::Fetch)?;
+        let fetched = self
+            .fetch_url(&location, cached.as_ref())
+            .await
+            .map_err(Error::Fetch)?;
+        let resolved_url = (fetched.final_url != fetch::normalize_url(&location))
+            .then(|| fetched.final_url.clone());
+        *self.resolved_url.lock().unwrap() = resolved_url;
+
+        self.store(&fetched);
+        self.parse(&fetched.body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:cached@example.com\r\nDTSTART:20251203T190000Z\r\nSUMMARY:Meeting\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+
+    /// Serves the calendar with an ETag, answering `304 Not Modified` to
+    /// requests that already have it, and notes the status of each answer.
+    fn serve(answers: Arc<Mutex<Vec<u16>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/calendar.ics", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut revalidating = false;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    revalidating |= line.eq_ignore_ascii_case("if-none-match: \"v1\"\r\n");
+                }
+                let response = match revalidating {
+                    true => {
+                        "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                    false => format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        CALENDAR.len(),
+                        CALENDAR
+                    ),
+                };
+                answers
+                    .lock()
+                    .unwrap()
+                    .push(if revalidating { 304 } else { 200 });
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    /// Serves `CALENDAR` until it is told to fail, counting fetches.
+    #[derive(Default)]
+    struct FlakySource {
+        fetches: Mutex<usize>,
+        failing: Mutex<bool>,
+    }
+
+    impl CalendarSource for FlakySource {
+        async fn fetch(&self) -> Result<IcalCalendar> {
+            *self.fetches.lock().unwrap() += 1;
+            match *self.failing.lock().unwrap() {
+                true => Err(Error::Fetch(anyhow::anyhow!("connection refused"))),
+                false => IcalCalendar::parse_ical_content(CALENDAR).map_err(Error::Parse),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_calendar_source() {
+        let source =
+            CachedCalendarSource::new(FlakySource::default(), std::time::Duration::from_secs(60));
+        source.fetch().await.unwrap();
+        source.fetch().await.unwrap();
+        assert_eq!(*source.inner().fetches.lock().unwrap(), 1);
+
+        // Once it is out of date, a failed fetch falls back to it
+        source.invalidate();
+        *source.inner().failing.lock().unwrap() = true;
+        assert_eq!(source.fetch().await.unwrap().events.len(), 1);
+        assert_eq!(*source.inner().fetches.lock().unwrap(), 2);
+
+        let uncached =
+            CachedCalendarSource::new(FlakySource::default(), std::time::Duration::from_secs(60));
+        *uncached.inner().failing.lock().unwrap() = true;
+        assert!(uncached.fetch().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_conditional_fetch() {
+        let answers = Arc::new(Mutex::new(Vec::new()));
+        let url = serve(answers.clone());
+        let dir = std::env::temp_dir().join(format!("ical-to-masto-cache-{}", std::process::id()));
+        let cache = |ttl_seconds| CacheConfig {
+            dir: dir.to_str().unwrap().to_string(),
+            ttl_seconds,
+        };
+
+        for ttl_seconds in [0, 0, 3600] {
+            let source = WebcalSource::new(&url).with_cache(Some(&cache(ttl_seconds)));
+            let calendar = source.fetch().await.unwrap();
+            assert_eq!(calendar.events.len(), 1);
+        }
+        // Downloaded once, then only asked whether it changed, and not at
+        // all while it is fresh
+        assert_eq!(*answers.lock().unwrap(), [200, 304]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,625 @@
+use crate::error::{Error, Result};
+use crate::health::Health;
+use crate::ical::CalendarEvent;
+use crate::uid_map::UidMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::path::Path;
+
+/// A status the bot has posted about an event occurrence.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostRecord {
+    pub status_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub posted_at: DateTime<Utc>,
+    /// Fingerprint of the template the status was rendered from, to tell
+    /// when it is out of date.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_hash: Option<String>,
+    /// Fingerprint of the event's details as they were announced, to tell
+    /// when they have changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_hash: Option<String>,
+    /// Fingerprint of the text the status shows, so an edit that wouldn't
+    /// change it is left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_hash: Option<String>,
+    /// Every copy of the status, keyed by the target it went to. Records
+    /// from before targets were tracked only have the fields above.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: BTreeMap<String, PostLink>,
+    /// The event's details as they were announced, to say what changed when
+    /// the announcement is edited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<AnnouncedDetails>,
+}
+
+/// What an announcement showed of its meeting, as written in the calendar.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnnouncedDetails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl AnnouncedDetails {
+    pub fn of(event: &CalendarEvent) -> Self {
+        AnnouncedDetails {
+            summary: event.summary.clone(),
+            start: event.start_time.clone(),
+            end: event.end_time.clone(),
+            location: event.location.clone(),
+            url: event.url.clone(),
+        }
+    }
+}
+
+/// Where one copy of a post ended up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostLink {
+    pub status_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// A scheduling poll and the candidate times its options stand for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PollRecord {
+    pub status_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub summary: Option<String>,
+    /// DTSTARTs of the candidates, in the order of the poll's options.
+    pub starts: Vec<String>,
+    pub expires_at: DateTime<Utc>,
+    /// Whether the outcome has been dealt with.
+    #[serde(default)]
+    pub reported: bool,
+}
+
+/// A status that was posted to the backup account and still has to be posted
+/// to the primary once its instance is back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingCatchUp {
+    pub text: String,
+    pub backup_status_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_url: Option<String>,
+    pub failed_over_at: DateTime<Utc>,
+    /// The post the status belongs to, so the primary's copy can be linked
+    /// to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_key: Option<String>,
+}
+
+/// A status held back during quiet hours, to be posted once they are over.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeferredPost {
+    pub text: String,
+    /// Statuses posted after it, each in reply to the one before.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replies: Vec<String>,
+    /// The post it is, recorded under this key once it's out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_hash: Option<String>,
+    /// Fingerprint of the details of the event it is about, as it was
+    /// rendered from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_hash: Option<String>,
+    pub deferred_at: DateTime<Utc>,
+    /// ISO 639-3 code of the language account it goes to; the main account
+    /// when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+/// A status the instance was asked to post later, remembered so the post
+/// isn't made again before then.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledPost {
+    /// Id of the scheduled status, to cancel it with.
+    pub id: String,
+    pub scheduled_at: DateTime<Utc>,
+    pub target: String,
+    /// The post it is.
+    pub post_key: String,
+}
+
+/// An announcement pinned to the profile until its meeting is over.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PinnedPost {
+    pub status_id: String,
+    /// When it is unpinned again.
+    pub until: DateTime<Utc>,
+}
+
+/// Events an operator silenced with `mute`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mute {
+    /// A UID, or a summary or UID with `*` for any text, in any case.
+    pub pattern: String,
+    pub muted_at: DateTime<Utc>,
+    /// When announcements resume by themselves; `None` for until `unmute`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Mute {
+    /// Whether it silences `event` at `now`.
+    pub fn silences(&self, event: &CalendarEvent, now: DateTime<Utc>) -> bool {
+        if self.until.is_some_and(|until| until <= now) {
+            return false;
+        }
+        let summary = event.summary.as_deref().map(crate::ical::unescape_text);
+        event.uid.as_deref() == Some(self.pattern.as_str())
+            || [event.uid.as_deref(), summary.as_deref()]
+                .into_iter()
+                .flatten()
+                .any(|text| glob_matches(&self.pattern.to_lowercase(), &text.to_lowercase()))
+    }
+}
+
+/// Whether `text` is `pattern` with each `*` standing for any text.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The occurrences of a recurring event computed for a window of time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedOccurrences {
+    /// Fingerprint of the DTSTART and RRULE the occurrences were expanded from.
+    pub fingerprint: String,
+    pub sequence: i64,
+    pub window_start: DateTime<Utc>,
+    pub window_end: DateTime<Utc>,
+    pub starts: Vec<String>,
+}
+
+/// A short fingerprint of some text (64-bit FNV-1a), stable across releases
+/// so that it can be stored in the state file.
+pub fn fingerprint(text: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn is_healthy(health: &Health) -> bool {
+    *health == Health::default()
+}
+
+/// A status rendered for an occurrence, kept so it isn't rendered again
+/// until what it was rendered from changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderedText {
+    /// Fingerprint of the event and configuration it was rendered from.
+    pub inputs: String,
+    pub text: String,
+}
+
+/// Persistent record of what the bot has already posted, so repeated runs
+/// don't announce the same thing twice.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub posts: BTreeMap<String, PostRecord>,
+    /// Calendar URLs that redirect, mapped to where they ended up.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub resolved_urls: BTreeMap<String, String>,
+    /// Event URLs mapped to the short links posted in their place.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub short_urls: BTreeMap<String, String>,
+    /// Expanded occurrences of recurring events, keyed by UID.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub occurrences: BTreeMap<String, CachedOccurrences>,
+    /// Scheduling polls, keyed like posts by their first candidate.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub polls: BTreeMap<String, PollRecord>,
+    /// Statuses posted to the backup account while the primary was down,
+    /// oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub catch_up: Vec<PendingCatchUp>,
+    /// Statuses held back during quiet hours, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deferred: Vec<DeferredPost>,
+    /// Announcements scheduled on the instance, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scheduled: Vec<ScheduledPost>,
+    /// Announcements of important meetings pinned to the profile.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned: Vec<PinnedPost>,
+    /// Events not to post about, whatever the calendar says.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub muted: Vec<Mute>,
+    /// Statuses rendered for occurrences, by post key.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rendered: BTreeMap<String, RenderedText>,
+    /// Instances that said they're down for maintenance, with when to try
+    /// them again.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub unavailable_until: BTreeMap<String, DateTime<Utc>>,
+    /// Followers who asked to be reminded of a meeting, by the post key of
+    /// its announcement.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reminder_requests: BTreeMap<String, Vec<String>>,
+    /// The latest mention of the account already answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_mention: Option<String>,
+    /// Failed runs in a row and whether they put the bot in safe mode.
+    #[serde(default, skip_serializing_if = "is_healthy")]
+    pub health: Health,
+    /// The file the state was loaded from; an in-memory state has none.
+    #[serde(skip)]
+    path: Option<String>,
+}
+
+impl State {
+    /// Loads the state file, starting with an empty state if it doesn't exist yet.
+    pub fn load(path: &str) -> Result<Self> {
+        let mut state = Self::read(path).map_err(Error::State)?;
+        state.path = Some(path.to_string());
+        Ok(state)
+    }
+
+    fn read(path: &str) -> anyhow::Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(State::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// This state without the file it came from, so changes to it aren't
+    /// saved.
+    pub fn detached(mut self) -> Self {
+        self.path = None;
+        self
+    }
+
+    /// Writes the state back to the file it was loaded from. An in-memory
+    /// state isn't persisted.
+    ///
+    /// The state is written next to the file and moved over it, so a crash
+    /// or a full disk midway leaves the earlier state rather than half of it.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        self.write(path).map_err(Error::State)
+    }
+
+    fn write(&self, path: &str) -> anyhow::Result<()> {
+        if let Some(parent) = Path::new(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        let temporary = temporary_path(path);
+        std::fs::write(&temporary, json)?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    /// The key identifying one kind of post (e.g. `agenda-call`) about one
+    /// occurrence of an event.
+    pub fn key(kind: &str, event: &CalendarEvent) -> String {
+        let start = event.start_time.as_deref().unwrap_or("");
+        format!("{}:{}@{}", kind, Self::event_id(event), start)
+    }
+
+    /// What identifies an event in keys: its UID or, without one, its summary.
+    pub fn event_id(event: &CalendarEvent) -> &str {
+        event
+            .uid
+            .as_deref()
+            .or(event.summary.as_deref())
+            .unwrap_or("")
+    }
+
+    /// The kind, event id and start of a key.
+    pub fn split_key(key: &str) -> Option<(&str, &str, &str)> {
+        // UIDs may contain both separators, so split from the outside
+        let (kind, rest) = key.split_once(':')?;
+        let (id, start) = rest.rsplit_once('@')?;
+        Some((kind, id, start))
+    }
+
+    /// Whether the post was made, is waiting for quiet hours to end, or is
+    /// scheduled.
+    pub fn is_posted(&self, key: &str) -> bool {
+        self.posts.contains_key(key)
+            || self
+                .deferred
+                .iter()
+                .any(|deferred| deferred.post_key.as_deref() == Some(key))
+            || self
+                .scheduled
+                .iter()
+                .any(|scheduled| scheduled.post_key == key)
+    }
+
+    /// Holds `post` back for a later run, in place of the same post already
+    /// held back for its account: the one under its key or, without a key,
+    /// one with the same text.
+    pub fn hold_back(&mut self, post: DeferredPost) {
+        let held = self.deferred.iter_mut().find(|held| {
+            held.account == post.account
+                && match (&held.post_key, &post.post_key) {
+                    (Some(held_key), Some(key)) => held_key == key,
+                    (None, None) => held.text == post.text && held.replies == post.replies,
+                    _ => false,
+                }
+        });
+        match held {
+            Some(held) => *held = post,
+            None => self.deferred.push(post),
+        }
+    }
+
+    pub fn record(&mut self, key: String, record: PostRecord) {
+        self.posts.insert(key, record);
+    }
+
+    /// The text rendered for the post under `key`, if it was rendered from
+    /// the same `inputs`.
+    pub fn rendered_text(&self, key: &str, inputs: &str) -> Option<&str> {
+        self.rendered
+            .get(key)
+            .filter(|rendered| rendered.inputs == inputs)
+            .map(|rendered| rendered.text.as_str())
+    }
+
+    /// Keeps the text rendered for the post under `key` from `inputs`.
+    pub fn remember_rendered(&mut self, key: String, inputs: String, text: String) {
+        self.rendered.insert(key, RenderedText { inputs, text });
+    }
+
+    /// The mute silencing `event` at `now`, if one does.
+    pub fn muting(&self, event: &CalendarEvent, now: DateTime<Utc>) -> Option<&Mute> {
+        self.muted.iter().find(|mute| mute.silences(event, now))
+    }
+
+    /// The posts about any occurrence of the event with the given UID, as
+    /// their kind, the occurrence's start and the record, in key order.
+    pub fn posts_about(&self, uid: &str) -> Vec<(&str, &str, &PostRecord)> {
+        self.posts
+            .iter()
+            .filter_map(|(key, record)| {
+                let (kind, id, start) = Self::split_key(key)?;
+                (id == uid).then_some((kind, start, record))
+            })
+            .collect()
+    }
+
+    /// Moves everything remembered about events whose UID changed over to
+    /// their new UID. Where something is already remembered under the new
+    /// UID, that is kept and the entry under the old one dropped.
+    pub fn remap_uids(&mut self, map: &UidMap) -> Remapped {
+        let remap_key = |key: &str| {
+            let (kind, id, start) = Self::split_key(key)?;
+            Some(format!("{}:{}@{}", kind, map.map(id)?, start))
+        };
+        let mut remapped = Remapped::default();
+        remap_keys(&mut self.posts, remap_key, &mut remapped);
+        remap_keys(&mut self.polls, remap_key, &mut remapped);
+        remap_keys(&mut self.occurrences, |uid| map.map(uid), &mut remapped);
+
+        let post_keys = self
+            .deferred
+            .iter_mut()
+            .map(|deferred| &mut deferred.post_key)
+            .chain(
+                self.catch_up
+                    .iter_mut()
+                    .map(|pending| &mut pending.post_key),
+            );
+        for post_key in post_keys {
+            if let Some(new_key) = post_key.as_deref().and_then(remap_key) {
+                *post_key = Some(new_key);
+                remapped.moved += 1;
+            }
+        }
+        for scheduled in &mut self.scheduled {
+            if let Some(new_key) = remap_key(&scheduled.post_key) {
+                scheduled.post_key = new_key;
+                remapped.moved += 1;
+            }
+        }
+        remapped
+    }
+}
+
+/// What [`State::remap_uids`] changed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Remapped {
+    /// How many entries moved to their new UID.
+    pub moved: usize,
+    /// The keys of entries dropped because their new UID already had one.
+    pub dropped: Vec<String>,
+}
+
+impl Remapped {
+    pub fn changed(&self) -> bool {
+        self.moved > 0 || !self.dropped.is_empty()
+    }
+}
+
+/// Renames the entries whose key `remap` gives a new one for, dropping those
+/// whose new key is taken.
+fn remap_keys<T>(
+    entries: &mut BTreeMap<String, T>,
+    remap: impl Fn(&str) -> Option<String>,
+    remapped: &mut Remapped,
+) {
+    let renamed: Vec<_> = entries
+        .keys()
+        .filter_map(|key| Some((key.clone(), remap(key)?)))
+        .collect();
+    for (old_key, new_key) in renamed {
+        let Some(entry) = entries.remove(&old_key) else {
+            continue;
+        };
+        match entries.entry(new_key) {
+            Entry::Occupied(_) => remapped.dropped.push(old_key),
+            Entry::Vacant(vacant) => {
+                vacant.insert(entry);
+                remapped.moved += 1;
+            }
+        }
+    }
+}
+
+/// Where the state is written before it replaces the file at `path`.
+fn temporary_path(path: &str) -> String {
+    format!("{}.tmp", path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("ical-to-masto-state-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut state = State::load(path).unwrap();
+        assert!(state.posts.is_empty());
+
+        let event = CalendarEvent {
+            uid: Some("meeting@example.com".to_string()),
+            summary: Some("Meeting".to_string()),
+            start_time: Some("20251203T100000Z".to_string()),
+            ..Default::default()
+        };
+        let key = State::key("agenda-call", &event);
+        assert_eq!(key, "agenda-call:meeting@example.com@20251203T100000Z");
+
+        state.record(
+            key.clone(),
+            PostRecord {
+                status_id: "1".to_string(),
+                url: None,
+                posted_at: Utc::now(),
+                template_hash: None,
+                event_hash: None,
+                text_hash: None,
+                links: BTreeMap::new(),
+                details: None,
+            },
+        );
+        state.save().unwrap();
+        assert!(!Path::new(&temporary_path(path)).exists());
+
+        let loaded = State::load(path).unwrap();
+        assert!(loaded.is_posted(&key));
+        let about = loaded.posts_about("meeting@example.com");
+        assert_eq!(about.len(), 1);
+        assert_eq!(
+            (about[0].0, about[0].1),
+            ("agenda-call", "20251203T100000Z")
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_remap_uids() {
+        let record = |status_id: &str| PostRecord {
+            status_id: status_id.to_string(),
+            url: None,
+            posted_at: Utc::now(),
+            template_hash: None,
+            event_hash: None,
+            text_hash: None,
+            links: BTreeMap::new(),
+            details: None,
+        };
+        let mut state = State::default();
+        state.record(
+            "next-meeting:a@old@20251203T190000Z".to_string(),
+            record("1"),
+        );
+        state.record(
+            "agenda-call:a@old@20251203T190000Z".to_string(),
+            record("2"),
+        );
+        state.record(
+            "next-meeting:b@old@20251210T190000Z".to_string(),
+            record("3"),
+        );
+        state.record(
+            "next-meeting:b@new@20251210T190000Z".to_string(),
+            record("4"),
+        );
+        state.record(
+            "next-meeting:c@other@20251217T190000Z".to_string(),
+            record("5"),
+        );
+        state.deferred.push(DeferredPost {
+            text: "Agenda?".to_string(),
+            replies: Vec::new(),
+            post_key: Some("agenda-call:b@old@20251210T190000Z".to_string()),
+            template_hash: None,
+            event_hash: None,
+            deferred_at: Utc::now(),
+            account: None,
+        });
+
+        let map = UidMap {
+            rules: vec![crate::uid_map::UidRule {
+                old: "*@old".to_string(),
+                new: "*@new".to_string(),
+            }],
+            ..Default::default()
+        };
+        // The record already under b@new is kept over b@old's
+        let remapped = state.remap_uids(&map);
+        assert_eq!(remapped.moved, 3);
+        assert_eq!(remapped.dropped, ["next-meeting:b@old@20251210T190000Z"]);
+        let ids: Vec<_> = state
+            .posts
+            .iter()
+            .map(|(key, record)| (key.as_str(), record.status_id.as_str()))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ("agenda-call:a@new@20251203T190000Z", "2"),
+                ("next-meeting:a@new@20251203T190000Z", "1"),
+                ("next-meeting:b@new@20251210T190000Z", "4"),
+                ("next-meeting:c@other@20251217T190000Z", "5"),
+            ]
+        );
+        assert!(state.is_posted("agenda-call:b@new@20251210T190000Z"));
+        assert!(!state.remap_uids(&map).changed());
+    }
+}
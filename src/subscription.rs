@@ -0,0 +1,64 @@
+use crate::config::{Config, SubscriptionPostConfig};
+use crate::fetch;
+use crate::nextcloud;
+use crate::state::State;
+use crate::template::{Context, Template};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+/// The state key the last reminder is recorded under.
+pub const KIND: &str = "subscription-info";
+
+/// Default template for the reminder of how to subscribe to the calendar.
+pub const SUBSCRIPTION_TEMPLATE: &str = "📆 Never miss a meeting: subscribe to our calendar!\n\
+{subscribe_url}\n\
+Most calendar apps can add it from a URL (\"Add calendar\" → \"From URL\" or \"Subscribe\"), \
+and it updates by itself when meetings change.";
+
+/// Whether a reminder is due: none was posted yet, or the last one is
+/// `interval_days` old.
+pub fn is_due(config: &SubscriptionPostConfig, state: &State, now: DateTime<Utc>) -> bool {
+    let held_back = state
+        .deferred
+        .iter()
+        .any(|deferred| deferred.post_key.as_deref() == Some(KIND));
+    if held_back {
+        return false;
+    }
+    match state.posts.get(KIND) {
+        Some(record) => record.posted_at + Duration::days(config.interval_days) <= now,
+        None => true,
+    }
+}
+
+/// The template the reminder is rendered from: the configured one, or the default.
+pub fn template_source(config: &SubscriptionPostConfig) -> &str {
+    config.template.as_deref().unwrap_or(SUBSCRIPTION_TEMPLATE)
+}
+
+/// Renders the reminder. The link is offered as `webcal://`, which calendar
+/// apps open as a subscription, with the plain `https://` form available as
+/// `https_url`. A Nextcloud share link is offered as its export, which
+/// calendar apps can subscribe to.
+pub fn render(config: &Config, subscription: &SubscriptionPostConfig) -> Result<String> {
+    let url = match &subscription.url {
+        Some(url) => url.clone(),
+        None => nextcloud::export_url(&config.webcal)?.unwrap_or_else(|| config.webcal.clone()),
+    };
+    Ok(Template::parse(template_source(subscription))?.render(&context(&url)))
+}
+
+/// The variables of the reminder about subscribing to `url`.
+pub fn context(url: &str) -> Context {
+    let https_url = fetch::normalize_url(url);
+    let subscribe_url = match https_url.split_once("://") {
+        Some(("https" | "http", rest)) => format!("webcal://{}", rest),
+        _ => https_url.clone(),
+    };
+
+    let mut context = Context::new();
+    context
+        .set_text("subscribe_url", subscribe_url)
+        .set_text("https_url", https_url);
+    context
+}
@@ -0,0 +1,447 @@
+use crate::timing::{self, Phase};
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+
+/// A value that can be referenced from a template.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Bool(bool),
+    List(Vec<Context>),
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Text(text) => !text.is_empty(),
+            Value::Bool(flag) => *flag,
+            Value::List(items) => !items.is_empty(),
+        }
+    }
+}
+
+/// The variables available while rendering a template.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Context {
+    values: HashMap<String, Value>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) -> &mut Self {
+        self.values.insert(name.to_string(), value);
+        self
+    }
+
+    pub fn set_text(&mut self, name: &str, text: impl Into<String>) -> &mut Self {
+        self.set(name, Value::Text(text.into()))
+    }
+
+    /// Sets `name` to the text if present; absent values are simply left unset.
+    pub fn set_opt_text(&mut self, name: &str, text: Option<impl Into<String>>) -> &mut Self {
+        if let Some(text) = text {
+            self.set_text(name, text);
+        }
+        self
+    }
+
+    pub fn set_bool(&mut self, name: &str, flag: bool) -> &mut Self {
+        self.set(name, Value::Bool(flag))
+    }
+
+    pub fn set_list(&mut self, name: &str, items: Vec<Context>) -> &mut Self {
+        self.set(name, Value::List(items))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    /// The names of the variables set, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Var(String),
+    If {
+        name: String,
+        negate: bool,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Each {
+        name: String,
+        body: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Text(String),
+    Var(String),
+    If(String),
+    Unless(String),
+    Else,
+    EndIf,
+    EndUnless,
+    Each(String),
+    EndEach,
+}
+
+/// A parsed post template.
+///
+/// Templates are plain text with placeholders in braces:
+///
+/// - `{summary}` inserts a variable (missing variables render as nothing)
+/// - `{#if location}...{else}...{/if}` renders a section only when the
+///   variable is present and non-empty (or `true` for flags)
+/// - `{#unless url}...{/unless}` is the inverse of `#if`
+/// - `{#each events}...{/each}` repeats a section for every item of a list,
+///   with the item's variables plus `index` (starting at 1), `first` and
+///   `last` available inside it
+///
+/// Literal braces are written as `{{` and `}}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let mut tokens = tokens.into_iter();
+        let (nodes, end) = parse_nodes(&mut tokens)?;
+        match end {
+            None => Ok(Template { nodes }),
+            Some(token) => Err(anyhow!("Unexpected {} in template", describe(&token))),
+        }
+    }
+
+    /// The variables the template uses that `context` doesn't provide, in the
+    /// order they first appear. Inside `{#each}`, the list's first item
+    /// stands for all of them.
+    pub fn unknown_variables(&self, context: &Context) -> Vec<String> {
+        let mut unknown = Vec::new();
+        check_nodes(&self.nodes, &[context], &mut unknown);
+        unknown
+    }
+
+    pub fn render(&self, context: &Context) -> String {
+        timing::time(Phase::Render, || {
+            let mut output = String::new();
+            render_nodes(&self.nodes, &[context], &mut output);
+            output
+        })
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut tag = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => tag.push(c),
+                        None => return Err(anyhow!("Unclosed tag '{{{}' in template", tag)),
+                    }
+                }
+                if !text.is_empty() {
+                    tokens.push(Token::Text(std::mem::take(&mut text)));
+                }
+                tokens.push(parse_tag(tag.trim())?);
+            }
+            c => text.push(c),
+        }
+    }
+
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+
+    Ok(tokens)
+}
+
+fn parse_tag(tag: &str) -> Result<Token> {
+    let (keyword, argument) = match tag.split_once(char::is_whitespace) {
+        Some((keyword, argument)) => (keyword, argument.trim()),
+        None => (tag, ""),
+    };
+
+    let token = match keyword {
+        "#if" => Token::If(variable_name(argument)?),
+        "#unless" => Token::Unless(variable_name(argument)?),
+        "#each" => Token::Each(variable_name(argument)?),
+        "else" if argument.is_empty() => Token::Else,
+        "/if" if argument.is_empty() => Token::EndIf,
+        "/unless" if argument.is_empty() => Token::EndUnless,
+        "/each" if argument.is_empty() => Token::EndEach,
+        _ if argument.is_empty() => Token::Var(variable_name(keyword)?),
+        _ => return Err(anyhow!("Unknown template tag '{{{}}}'", tag)),
+    };
+
+    Ok(token)
+}
+
+fn variable_name(name: &str) -> Result<String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(anyhow!("Invalid variable name '{}' in template", name))
+    }
+}
+
+fn describe(token: &Token) -> &'static str {
+    match token {
+        Token::Else => "{else}",
+        Token::EndIf => "{/if}",
+        Token::EndUnless => "{/unless}",
+        Token::EndEach => "{/each}",
+        _ => "tag",
+    }
+}
+
+/// Parses nodes until the input runs out or a closing token is reached,
+/// returning that token so the caller can check it matches its block.
+fn parse_nodes(tokens: &mut impl Iterator<Item = Token>) -> Result<(Vec<Node>, Option<Token>)> {
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Var(name) => nodes.push(Node::Var(name)),
+            Token::If(name) => nodes.push(parse_conditional(tokens, name, false)?),
+            Token::Unless(name) => nodes.push(parse_conditional(tokens, name, true)?),
+            Token::Each(name) => {
+                let (body, end) = parse_nodes(tokens)?;
+                match end {
+                    Some(Token::EndEach) => nodes.push(Node::Each { name, body }),
+                    Some(other) => {
+                        return Err(anyhow!(
+                            "Unexpected {} inside {{#each {}}}",
+                            describe(&other),
+                            name
+                        ));
+                    }
+                    None => return Err(anyhow!("Missing {{/each}} for {{#each {}}}", name)),
+                }
+            }
+            end => return Ok((nodes, Some(end))),
+        }
+    }
+
+    Ok((nodes, None))
+}
+
+fn parse_conditional(
+    tokens: &mut impl Iterator<Item = Token>,
+    name: String,
+    negate: bool,
+) -> Result<Node> {
+    let (keyword, closing) = if negate {
+        ("#unless", Token::EndUnless)
+    } else {
+        ("#if", Token::EndIf)
+    };
+
+    let (then, end) = parse_nodes(tokens)?;
+    let (otherwise, end) = match end {
+        Some(Token::Else) => parse_nodes(tokens)?,
+        end => (Vec::new(), end),
+    };
+
+    match end {
+        Some(token) if token == closing => Ok(Node::If {
+            name,
+            negate,
+            then,
+            otherwise,
+        }),
+        Some(other) => Err(anyhow!(
+            "Unexpected {} inside {{{} {}}}",
+            describe(&other),
+            keyword,
+            name
+        )),
+        None => Err(anyhow!(
+            "Missing {} for {{{} {}}}",
+            describe(&closing),
+            keyword,
+            name
+        )),
+    }
+}
+
+fn lookup<'a>(scopes: &[&'a Context], name: &str) -> Option<&'a Value> {
+    scopes.iter().rev().find_map(|scope| scope.get(name))
+}
+
+/// What `{#each}` adds to each item of the list it repeats over.
+fn each_item(item: &Context, index: usize, count: usize) -> Context {
+    let mut item = item.clone();
+    item.set_text("index", (index + 1).to_string())
+        .set_bool("first", index == 0)
+        .set_bool("last", index + 1 == count);
+    item
+}
+
+fn check_name(scopes: &[&Context], name: &String, unknown: &mut Vec<String>) {
+    if lookup(scopes, name).is_none() && !unknown.contains(name) {
+        unknown.push(name.clone());
+    }
+}
+
+fn check_nodes(nodes: &[Node], scopes: &[&Context], unknown: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Var(name) => check_name(scopes, name, unknown),
+            Node::If {
+                name,
+                then,
+                otherwise,
+                ..
+            } => {
+                check_name(scopes, name, unknown);
+                check_nodes(then, scopes, unknown);
+                check_nodes(otherwise, scopes, unknown);
+            }
+            Node::Each { name, body } => match lookup(scopes, name) {
+                Some(Value::List(items)) if !items.is_empty() => {
+                    let item = each_item(&items[0], 0, items.len());
+                    let mut inner = scopes.to_vec();
+                    inner.push(&item);
+                    check_nodes(body, &inner, unknown);
+                }
+                _ => {
+                    check_name(scopes, name, unknown);
+                    check_nodes(body, scopes, unknown);
+                }
+            },
+        }
+    }
+}
+
+fn render_nodes(nodes: &[Node], scopes: &[&Context], output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Var(name) => match lookup(scopes, name) {
+                Some(Value::Text(text)) => output.push_str(text),
+                Some(Value::Bool(flag)) => output.push_str(&flag.to_string()),
+                Some(Value::List(items)) => output.push_str(&items.len().to_string()),
+                None => {}
+            },
+            Node::If {
+                name,
+                negate,
+                then,
+                otherwise,
+            } => {
+                let truthy = lookup(scopes, name).is_some_and(Value::is_truthy);
+                if truthy != *negate {
+                    render_nodes(then, scopes, output);
+                } else {
+                    render_nodes(otherwise, scopes, output);
+                }
+            }
+            Node::Each { name, body } => {
+                if let Some(Value::List(items)) = lookup(scopes, name) {
+                    for (i, item) in items.iter().enumerate() {
+                        let item = each_item(item, i, items.len());
+
+                        let mut inner = scopes.to_vec();
+                        inner.push(&item);
+                        render_nodes(body, &inner, output);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_variables() {
+        let template = Template::parse("Hello {name}, {{literal}} {missing}!").unwrap();
+        let mut context = Context::new();
+        context.set_text("name", "NoVaLUG");
+        assert_eq!(template.render(&context), "Hello NoVaLUG, {literal} !");
+    }
+
+    #[test]
+    fn test_render_conditionals() {
+        let template = Template::parse(
+            "{#if location}📍 {location}{else}Online{/if}{#unless url} (no link){/unless}",
+        )
+        .unwrap();
+
+        let mut context = Context::new();
+        context.set_text("location", "Room 1");
+        assert_eq!(template.render(&context), "📍 Room 1 (no link)");
+
+        let mut context = Context::new();
+        context
+            .set_text("location", "")
+            .set_text("url", "https://example.com");
+        assert_eq!(template.render(&context), "Online");
+    }
+
+    #[test]
+    fn test_render_each() {
+        let template = Template::parse(
+            "{#each events}{#unless first}, {/unless}{index}. {summary} ({group}){/each}",
+        )
+        .unwrap();
+
+        let mut first = Context::new();
+        first.set_text("summary", "Install Fest");
+        let mut second = Context::new();
+        second.set_text("summary", "Meetup");
+
+        let mut context = Context::new();
+        context
+            .set_text("group", "NoVaLUG")
+            .set_list("events", vec![first, second]);
+        assert_eq!(
+            template.render(&context),
+            "1. Install Fest (NoVaLUG), 2. Meetup (NoVaLUG)"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Template::parse("{#if location}no end").is_err());
+        assert!(Template::parse("{/if}").is_err());
+        assert!(Template::parse("{#each events}{/if}").is_err());
+        assert!(Template::parse("{unclosed").is_err());
+        assert!(Template::parse("{bad name}").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use crate::agenda;
+use crate::config::MinutesConfig;
+use crate::formatter;
+use crate::ical::CalendarEvent;
+use crate::minutes;
+use crate::recap;
+use crate::remind_me;
+use crate::reminders::{self, LeadTime};
+use crate::scheduling;
+use crate::state::PollRecord;
+use crate::subscription;
+use crate::template::{Context, Template, Value};
+use anyhow::{Result, anyhow};
+use chrono::{TimeZone, Utc};
+
+/// What a template can be for, as `template check --kind` takes it, with
+/// the built-in template of each.
+pub const KINDS: &[(&str, &str)] = &[
+    ("next", formatter::NEXT_MEETING_TEMPLATE),
+    ("next-meetings", formatter::NEXT_MEETINGS_TEMPLATE),
+    ("upcoming", formatter::UPCOMING_MEETINGS_TEMPLATE),
+    ("no-meetings", formatter::NO_MEETINGS_TEMPLATE),
+    ("agenda", agenda::AGENDA_CALL_TEMPLATE),
+    ("minutes", minutes::MINUTES_TEMPLATE),
+    ("subscription", subscription::SUBSCRIPTION_TEMPLATE),
+    ("poll", scheduling::POLL_TEMPLATE),
+    ("poll-result", scheduling::RESULT_TEMPLATE),
+    ("recap", recap::RECAP_TEMPLATE),
+    ("reminder", reminders::REMINDER_TEMPLATE),
+    ("missed-reminders", reminders::MISSED_TEMPLATE),
+    ("remind-me", remind_me::REMIND_ME_TEMPLATE),
+];
+
+/// An event with every detail filled in, so each variable has a value.
+pub fn sample_event(summary: &str, start: &str) -> CalendarEvent {
+    CalendarEvent {
+        uid: Some("sample@ical-to-masto".to_string()),
+        summary: Some(summary.to_string()),
+        description: Some("Agenda: introductions, then a talk on dotfiles".to_string()),
+        start_time: Some(start.to_string()),
+        end_time: Some(start.replace("T19", "T21")),
+        location: Some("Room 101, Central Library".to_string()),
+        url: Some("https://example.com/events/monthly-meeting".to_string()),
+        // A hybrid meeting: held in the room, with a link to join from home
+        conference: Some("https://meet.example.com/monthly".to_string()),
+        online: Some(false),
+        hashtags: vec!["Linux".to_string(), "Meetup".to_string()],
+        ..Default::default()
+    }
+}
+
+/// The variables a template of the given kind is rendered with, taken from
+/// sample events; `None` for an unknown kind.
+pub fn sample_context(kind: &str) -> Option<Context> {
+    let first = sample_event("Monthly Meeting", "20251203T190000Z");
+    let second = sample_event("Install Fest", "20251213T190000Z");
+    let context = match kind {
+        "next" => formatter::next_meeting_context(
+            &first,
+            Some("https://example.com/ics/monthly-meeting.ics"),
+        ),
+        "next-meetings" | "upcoming" => formatter::digest_context(&[&first, &second], 1),
+        "no-meetings" => Context::new(),
+        "agenda" => formatter::event_context(&first),
+        "minutes" => {
+            let config = MinutesConfig {
+                delay_hours: 24,
+                window_hours: 72,
+                url: Some("https://example.com/minutes".to_string()),
+                events: Vec::new(),
+                template: None,
+            };
+            minutes::context(&config, &first)
+        }
+        "subscription" => subscription::context("https://example.com/calendar.ics"),
+        "poll" => scheduling::question_context(&[&first, &second]),
+        "poll-result" => {
+            let poll = PollRecord {
+                status_id: "1".to_string(),
+                url: None,
+                summary: first.summary.clone(),
+                starts: vec![
+                    "20251203T190000Z".to_string(),
+                    "20251204T190000Z".to_string(),
+                ],
+                expires_at: Utc.with_ymd_and_hms(2025, 12, 1, 12, 0, 0).unwrap(),
+                reported: false,
+            };
+            scheduling::result_context(&poll, 0, &[5, 3])
+        }
+        "recap" => recap::context(
+            "last week",
+            &[
+                (
+                    &first,
+                    Some("https://example.com/minutes/2025-12-03".to_string()),
+                ),
+                (&second, None),
+            ],
+            1,
+        ),
+        "reminder" => reminders::context(&first, LeadTime { minutes: 60 }),
+        "missed-reminders" => formatter::events_context(&[&first, &second]),
+        "remind-me" => remind_me::context(&first, LeadTime { minutes: 60 }, "alice@example.social"),
+        _ => return None,
+    };
+    Some(context)
+}
+
+/// How a template fared against the sample of its kind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// Variables the template uses that its kind doesn't provide, each with
+    /// the closest one that it does, if any is close.
+    pub unknown: Vec<(String, Option<String>)>,
+    /// The template rendered with the sample.
+    pub rendered: String,
+}
+
+/// Parses a template and checks its variables against those of `kind`.
+/// Syntax errors are returned as errors.
+pub fn check(source: &str, kind: &str) -> Result<Report> {
+    let context = sample_context(kind).ok_or_else(|| {
+        let kinds: Vec<_> = KINDS.iter().map(|(kind, _)| *kind).collect();
+        anyhow!(
+            "Unknown template kind '{}', expected one of: {}",
+            kind,
+            kinds.join(", ")
+        )
+    })?;
+    let template = Template::parse(source)?;
+
+    let known = known_names(&context);
+    let unknown = template
+        .unknown_variables(&context)
+        .into_iter()
+        .map(|name| {
+            let suggestion = closest(&name, &known);
+            (name, suggestion)
+        })
+        .collect();
+    Ok(Report {
+        unknown,
+        rendered: template.render(&context),
+    })
+}
+
+/// Every variable name a context provides, including those of list items.
+fn known_names(context: &Context) -> Vec<String> {
+    let mut names: Vec<String> = context.names().map(str::to_string).collect();
+    for name in context.names() {
+        if let Some(Value::List(items)) = context.get(name)
+            && let Some(item) = items.first()
+        {
+            names.extend(item.names().map(str::to_string));
+            names.extend(["index", "first", "last"].map(str::to_string));
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The known name within two edits of `name`, if there is one.
+fn closest(name: &str, known: &[String]) -> Option<String> {
+    known
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, candidate)| candidate.clone())
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_templates_pass() {
+        for (kind, source) in KINDS {
+            let report = check(source, kind).unwrap();
+            assert!(report.unknown.is_empty(), "{}: {:?}", kind, report.unknown);
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let report = check(
+            "📅 {sumary} at {location}{#each events}{titel}{/each}{#if ics_url}{ics_url}{/if}",
+            "next",
+        )
+        .unwrap();
+        assert_eq!(
+            report.unknown,
+            [
+                ("sumary".to_string(), Some("summary".to_string())),
+                ("events".to_string(), None),
+                ("titel".to_string(), None),
+            ]
+        );
+        assert!(report.rendered.starts_with("📅  at Room 101"));
+
+        let report = check("{#each events}{index}. {summary}\n{/each}", "upcoming").unwrap();
+        assert!(report.unknown.is_empty());
+        assert_eq!(report.rendered, "1. Monthly Meeting\n2. Install Fest\n");
+
+        assert!(check("{#if summary}unclosed", "next").is_err());
+        assert!(check("{summary}", "tweet").is_err());
+    }
+}
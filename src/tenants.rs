@@ -0,0 +1,222 @@
+use crate::config::{self, Config};
+use crate::fetch;
+use anyhow::{Result, anyhow};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The file in a tenant's directory holding its configuration.
+pub const CONFIG_FILE: &str = "bot.toml";
+
+/// A community the daemon runs for, with its own directory of files.
+pub struct Tenant {
+    /// The name of its directory, which labels everything done for it.
+    pub name: String,
+    pub config_path: String,
+    /// With every file it names kept in its directory.
+    pub config: Config,
+}
+
+/// `config` with every relative path it names, and those it leaves to
+/// their defaults, in `home`.
+pub fn isolated(config: Config, home: &Path) -> Config {
+    let mut config = Config {
+        home: Some(home.display().to_string()),
+        ..config
+    };
+    let place = |path: &mut String| {
+        if Path::new(path.as_str()).is_relative() {
+            *path = home.join(&*path).display().to_string();
+        }
+    };
+    place(&mut config.token_file);
+    place(&mut config.state_file);
+    if fetch::is_local(&config.webcal) && config.webcal != fetch::STDIN {
+        place(&mut config.webcal);
+    }
+    for path in [&mut config.uid_map, &mut config.overrides]
+        .into_iter()
+        .flatten()
+    {
+        place(path);
+    }
+    if let Some(cache) = &mut config.cache {
+        place(&mut cache.dir);
+    }
+    if let Some(backup) = &mut config.backup {
+        place(&mut backup.token_file);
+    }
+    for account in &mut config.language_accounts {
+        place(&mut account.token_file);
+    }
+    for feed in &mut config.feeds {
+        place(&mut feed.token_file);
+        if let Some(state_file) = &mut feed.state_file {
+            place(state_file);
+        }
+        if fetch::is_local(&feed.webcal) && feed.webcal != fetch::STDIN {
+            place(&mut feed.webcal);
+        }
+    }
+    config
+}
+
+/// Every file a tenant's bot writes, which no other tenant may share.
+pub fn files(config: &Config) -> Vec<String> {
+    let configs = std::iter::once(config.clone()).chain(
+        config
+            .feeds
+            .iter()
+            .map(|feed| config.for_feed(feed))
+            .collect::<Vec<_>>(),
+    );
+    let mut files = Vec::new();
+    for config in configs {
+        files.push(config.state_file.clone());
+        files.push(config.token_file.clone());
+        if let Some(cache) = config.calendar_cache() {
+            files.push(cache.dir);
+        }
+        files.extend(config.backup.iter().map(|backup| backup.token_file.clone()));
+        files.extend(
+            config
+                .language_accounts
+                .iter()
+                .map(|account| account.token_file.clone()),
+        );
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// The tenants in `dir`: each subdirectory with a [`CONFIG_FILE`], by name.
+/// A tenant whose configuration can't be loaded is left out with a warning,
+/// so the others keep running; two sharing a file are refused.
+pub fn load(dir: &str) -> Result<Vec<Tenant>> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| anyhow!("Could not read the tenants {}: {}", dir, e))?;
+    let mut homes = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && entry.path().join(CONFIG_FILE).is_file() {
+            homes.push(entry.path());
+        }
+    }
+    homes.sort();
+
+    let mut tenants = Vec::new();
+    let mut owners: BTreeMap<String, String> = BTreeMap::new();
+    for home in homes {
+        let name = home
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let config_path = home.join(CONFIG_FILE).display().to_string();
+        let config = match config::load_tenant_config(&config_path) {
+            Ok(config) => isolated(config, &home),
+            Err(e) => {
+                eprintln!("Warning: leaving out the tenant {}: {}", name, e);
+                continue;
+            }
+        };
+        for file in files(&config) {
+            let key = std::path::absolute(&file)
+                .map(|path| path.display().to_string())
+                .unwrap_or(file);
+            if let Some(owner) = owners.insert(key.clone(), name.clone()) {
+                return Err(anyhow!(
+                    "The tenants {} and {} both use {}; each needs files of its own",
+                    owner,
+                    name,
+                    key
+                ));
+            }
+        }
+        tenants.push(Tenant {
+            name,
+            config_path,
+            config,
+        });
+    }
+    if tenants.is_empty() {
+        return Err(anyhow!(
+            "No tenants in {}; each needs a directory with a {}",
+            dir,
+            CONFIG_FILE
+        ));
+    }
+    Ok(tenants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_load() {
+        let dir =
+            std::env::temp_dir().join(format!("ical-to-masto-tenants-{}", std::process::id()));
+        let write = |name: &str, config: &str| {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+            std::fs::write(dir.join(name).join(CONFIG_FILE), config).unwrap();
+        };
+        write(
+            "choir",
+            indoc! {r#"
+                instance = "https://choir.example"
+                webcal = "calendar.ics"
+                refresh_minutes = 30
+
+                [[feed]]
+                name = "youth"
+                webcal = "https://example.com/youth.ics"
+                instance = "https://choir.example"
+                token_file = "youth-token.json"
+            "#},
+        );
+        write(
+            "garden",
+            indoc! {r#"
+                instance = "https://garden.example"
+                webcal = "https://example.com/garden.ics"
+                state_file = "/var/lib/garden/state.json"
+            "#},
+        );
+        std::fs::create_dir_all(dir.join("not-a-tenant")).unwrap();
+
+        let tenants = load(dir.to_str().unwrap()).unwrap();
+        let names: Vec<_> = tenants.iter().map(|tenant| tenant.name.as_str()).collect();
+        assert_eq!(names, ["choir", "garden"]);
+        let [choir, garden] = &tenants[..] else {
+            unreachable!()
+        };
+        let home = |name: &str, file: &str| dir.join(name).join(file).display().to_string();
+        assert_eq!(choir.config.state_file, home("choir", "state.json"));
+        assert_eq!(choir.config.token_file, home("choir", "token.json"));
+        assert_eq!(choir.config.webcal, home("choir", "calendar.ics"));
+        assert_eq!(
+            choir.config.calendar_cache().unwrap().dir,
+            home("choir", "cache")
+        );
+        let youth = choir.config.for_feed(&choir.config.feeds[0]);
+        assert_eq!(youth.state_file, home("choir", "state-youth.json"));
+        assert_eq!(youth.token_file, home("choir", "youth-token.json"));
+        assert_eq!(garden.config.state_file, "/var/lib/garden/state.json");
+        assert_eq!(garden.config.token_file, home("garden", "token.json"));
+
+        // Naming another tenant's file is refused
+        write(
+            "orchestra",
+            &format!(
+                "instance = \"https://orchestra.example\"\n\
+                 webcal = \"https://example.com/orchestra.ics\"\n\
+                 token_file = \"{}\"\n",
+                home("choir", "token.json")
+            ),
+        );
+        let error = load(dir.to_str().unwrap()).err().unwrap().to_string();
+        assert!(error.contains("choir and orchestra"), "{}", error);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
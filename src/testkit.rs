@@ -0,0 +1,374 @@
+//! Deterministic stand-ins for the calendar, the clock and the Mastodon
+//! account, so schedules spanning weeks can be simulated in tests.
+//!
+//! Enable with the `testkit` feature.
+
+use crate::clock::Clock;
+use crate::error::{Error, Result};
+use crate::ical::IcalCalendar;
+use crate::media::Media;
+use crate::poster::{
+    Mention, NewPoll, Posted, ProfileField, Publisher, RecentStatus, ScheduledStatus, Unavailable,
+};
+use crate::source::CalendarSource;
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Utc};
+use mastodon_async::NewStatus;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// A calendar held in memory as iCal text, which can be replaced between runs.
+pub struct MemorySource {
+    content: Mutex<String>,
+}
+
+impl MemorySource {
+    pub fn new(content: &str) -> Self {
+        MemorySource {
+            content: Mutex::new(content.to_string()),
+        }
+    }
+
+    /// Replaces the calendar returned by later fetches.
+    pub fn set(&self, content: &str) {
+        *self.content.lock().unwrap() = content.to_string();
+    }
+}
+
+impl CalendarSource for MemorySource {
+    async fn fetch(&self) -> Result<IcalCalendar> {
+        let content = self.content.lock().unwrap().clone();
+        IcalCalendar::parse_ical_content(&content).map_err(Error::Parse)
+    }
+}
+
+/// A publisher that records statuses instead of posting them.
+#[derive(Default)]
+pub struct RecordingPublisher {
+    target: String,
+    posts: Mutex<Vec<NewStatus>>,
+    edits: Mutex<Vec<(String, NewStatus)>>,
+    deletions: Mutex<Vec<String>>,
+    pins: Mutex<Vec<String>>,
+    polls: Mutex<BTreeMap<String, (NewPoll, Vec<u64>)>>,
+    media: Mutex<BTreeMap<String, Media>>,
+    max_status_chars: Option<usize>,
+    profile_fields: Mutex<Vec<ProfileField>>,
+    profile_updates: Mutex<usize>,
+    scheduled: Mutex<Vec<(NewStatus, DateTime<Utc>)>>,
+    cancelled: Mutex<Vec<String>>,
+    unreachable: Mutex<bool>,
+    maintenance_until: Mutex<Option<DateTime<Utc>>>,
+    mentions: Mutex<Vec<Mention>>,
+}
+
+impl RecordingPublisher {
+    pub fn new() -> Self {
+        Self::for_target("example.test")
+    }
+
+    /// A publisher whose statuses go to the named target, for telling several
+    /// apart.
+    pub fn for_target(target: &str) -> Self {
+        RecordingPublisher {
+            target: target.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn posted(&self, id: &str) -> Posted {
+        Posted {
+            id: id.to_string(),
+            url: Some(format!("https://{}/@bot/{}", self.target, id)),
+            target: self.target.clone(),
+            scheduled_at: None,
+        }
+    }
+
+    /// A publisher for an instance that accepts statuses of up to
+    /// `max_status_chars` characters.
+    pub fn with_max_status_chars(mut self, max_status_chars: usize) -> Self {
+        self.max_status_chars = Some(max_status_chars);
+        self
+    }
+
+    /// A publisher for an account whose profile has these fields.
+    pub fn with_profile_fields(self, fields: Vec<ProfileField>) -> Self {
+        *self.profile_fields.lock().unwrap() = fields;
+        self
+    }
+
+    /// How many times the profile's fields were set so far.
+    pub fn profile_updates(&self) -> usize {
+        *self.profile_updates.lock().unwrap()
+    }
+
+    /// Every status scheduled so far with when it goes out, oldest first,
+    /// including those cancelled since.
+    pub fn scheduled(&self) -> Vec<(NewStatus, DateTime<Utc>)> {
+        self.scheduled.lock().unwrap().clone()
+    }
+
+    /// Makes later publishes fail as if the instance were down, or succeed again.
+    pub fn set_reachable(&self, reachable: bool) {
+        *self.unreachable.lock().unwrap() = !reachable;
+    }
+
+    /// Makes later publishes fail as if the instance were down for
+    /// maintenance until `until`, or succeed again with `None`.
+    pub fn set_maintenance(&self, until: Option<DateTime<Utc>>) {
+        *self.maintenance_until.lock().unwrap() = until;
+    }
+
+    /// Has `account` mention the account, replying to the status with id
+    /// `in_reply_to_id` if given.
+    pub fn add_mention(&self, account: &str, in_reply_to_id: Option<&str>, text: &str) {
+        let mut mentions = self.mentions.lock().unwrap();
+        let id = (mentions.len() + 1).to_string();
+        mentions.push(Mention {
+            id: id.clone(),
+            status_id: format!("mention-{}", id),
+            in_reply_to_id: in_reply_to_id.map(str::to_string),
+            account: account.to_string(),
+            text: text.to_string(),
+        });
+    }
+
+    /// Every status published so far, oldest first.
+    pub fn posts(&self) -> Vec<NewStatus> {
+        self.posts.lock().unwrap().clone()
+    }
+
+    /// Every edit made so far as the id of the edited status and its new
+    /// content, oldest first.
+    pub fn edits(&self) -> Vec<(String, NewStatus)> {
+        self.edits.lock().unwrap().clone()
+    }
+
+    /// The ids of the statuses pinned now, in the order they were pinned.
+    pub fn pins(&self) -> Vec<String> {
+        self.pins.lock().unwrap().clone()
+    }
+
+    /// The ids of every status deleted so far, oldest first.
+    pub fn deletions(&self) -> Vec<String> {
+        self.deletions.lock().unwrap().clone()
+    }
+
+    /// The poll attached to the status with the given id, if it had one.
+    pub fn poll(&self, id: &str) -> Option<NewPoll> {
+        self.polls
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|(poll, _)| poll.clone())
+    }
+
+    /// The file attached to the status with the given id, if it had one.
+    pub fn media(&self, id: &str) -> Option<Media> {
+        self.media.lock().unwrap().get(id).cloned()
+    }
+
+    /// Sets the vote counts of a published poll.
+    pub fn set_votes(&self, id: &str, votes: Vec<u64>) {
+        if let Some((_, counts)) = self.polls.lock().unwrap().get_mut(id) {
+            *counts = votes;
+        }
+    }
+
+    /// The text of every status published so far, oldest first.
+    pub fn texts(&self) -> Vec<String> {
+        self.posts()
+            .into_iter()
+            .map(|post| post.status.unwrap_or_default())
+            .collect()
+    }
+}
+
+impl Publisher for RecordingPublisher {
+    async fn publish(&self, status: NewStatus) -> Result<Posted> {
+        if *self.unreachable.lock().unwrap() {
+            return Err(Error::Publish(anyhow!("instance unreachable")));
+        }
+        if let Some(until) = *self.maintenance_until.lock().unwrap() {
+            return Err(Error::Publish(
+                Unavailable {
+                    retry_after: Some(until),
+                }
+                .into(),
+            ));
+        }
+        let mut posts = self.posts.lock().unwrap();
+        posts.push(status);
+        Ok(self.posted(&posts.len().to_string()))
+    }
+
+    async fn publish_with_media(&self, status: NewStatus, media: Media) -> Result<Posted> {
+        let posted = self.publish(status).await?;
+        self.media.lock().unwrap().insert(posted.id.clone(), media);
+        Ok(posted)
+    }
+
+    async fn edit(&self, id: &str, status: NewStatus) -> Result<Posted> {
+        if *self.unreachable.lock().unwrap() {
+            return Err(Error::Publish(anyhow!("instance unreachable")));
+        }
+        self.edits.lock().unwrap().push((id.to_string(), status));
+        Ok(self.posted(id))
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        if *self.unreachable.lock().unwrap() {
+            return Err(Error::Publish(anyhow!("instance unreachable")));
+        }
+        self.deletions.lock().unwrap().push(id.to_string());
+        Ok(())
+    }
+
+    async fn publish_poll(&self, status: NewStatus, poll: NewPoll) -> Result<Posted> {
+        let posted = self.publish(status).await?;
+        let votes = vec![0; poll.options.len()];
+        self.polls
+            .lock()
+            .unwrap()
+            .insert(posted.id.clone(), (poll, votes));
+        Ok(posted)
+    }
+
+    async fn poll_votes(&self, id: &str) -> Result<Vec<u64>> {
+        if *self.unreachable.lock().unwrap() {
+            return Err(Error::Publish(anyhow!("instance unreachable")));
+        }
+        self.polls
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|(_, votes)| votes.clone())
+            .ok_or_else(|| Error::Publish(anyhow!("status {} has no poll", id)))
+    }
+
+    async fn max_status_chars(&self) -> Result<Option<usize>> {
+        Ok(self.max_status_chars)
+    }
+
+    async fn profile_fields(&self) -> Result<Vec<ProfileField>> {
+        Ok(self.profile_fields.lock().unwrap().clone())
+    }
+
+    async fn set_profile_fields(&self, fields: Vec<ProfileField>) -> Result<()> {
+        if *self.unreachable.lock().unwrap() {
+            return Err(Error::Publish(anyhow!("instance unreachable")));
+        }
+        *self.profile_fields.lock().unwrap() = fields;
+        *self.profile_updates.lock().unwrap() += 1;
+        Ok(())
+    }
+
+    async fn schedule(
+        &self,
+        status: NewStatus,
+        _media: Option<Media>,
+        at: DateTime<Utc>,
+    ) -> Result<Posted> {
+        if *self.unreachable.lock().unwrap() {
+            return Err(Error::Publish(anyhow!("instance unreachable")));
+        }
+        let mut scheduled = self.scheduled.lock().unwrap();
+        scheduled.push((status, at));
+        Ok(Posted {
+            url: None,
+            scheduled_at: Some(at),
+            ..self.posted(&format!("scheduled-{}", scheduled.len()))
+        })
+    }
+
+    async fn scheduled_statuses(&self) -> Result<Vec<ScheduledStatus>> {
+        let cancelled = self.cancelled.lock().unwrap();
+        Ok(self
+            .scheduled
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(index, (status, at))| ScheduledStatus {
+                id: format!("scheduled-{}", index + 1),
+                scheduled_at: *at,
+                text: status.status.clone(),
+            })
+            .filter(|scheduled| !cancelled.contains(&scheduled.id))
+            .collect())
+    }
+
+    async fn cancel_scheduled(&self, id: &str) -> Result<()> {
+        if *self.unreachable.lock().unwrap() {
+            return Err(Error::Publish(anyhow!("instance unreachable")));
+        }
+        self.cancelled.lock().unwrap().push(id.to_string());
+        Ok(())
+    }
+
+    async fn recent_statuses(&self, limit: usize) -> Result<Vec<RecentStatus>> {
+        if *self.unreachable.lock().unwrap() {
+            return Err(Error::Publish(anyhow!("instance unreachable")));
+        }
+        Ok(self
+            .texts()
+            .into_iter()
+            .enumerate()
+            .rev()
+            .take(limit)
+            .map(|(index, text)| RecentStatus {
+                posted: self.posted(&(index + 1).to_string()),
+                text,
+            })
+            .collect())
+    }
+
+    async fn set_pinned(&self, id: &str, pinned: bool) -> Result<()> {
+        if *self.unreachable.lock().unwrap() {
+            return Err(Error::Publish(anyhow!("instance unreachable")));
+        }
+        let mut pins = self.pins.lock().unwrap();
+        pins.retain(|pin| pin != id);
+        if pinned {
+            pins.push(id.to_string());
+        }
+        Ok(())
+    }
+
+    async fn mentions(&self, since_id: Option<&str>) -> Result<Vec<Mention>> {
+        if *self.unreachable.lock().unwrap() {
+            return Err(Error::Publish(anyhow!("instance unreachable")));
+        }
+        let mentions = self.mentions.lock().unwrap();
+        let after = since_id.map_or(0, |id| id.parse().unwrap_or(0));
+        Ok(mentions.iter().skip(after).cloned().collect())
+    }
+}
+
+/// A clock that only moves when told to.
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        ManualClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
@@ -0,0 +1,109 @@
+use crate::scope;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+/// The phases of a run whose durations are tracked, to tell a slow calendar
+/// host from a slow instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Downloading the calendar.
+    Fetch,
+    /// Parsing the iCal text.
+    Parse,
+    /// Expanding recurring events into occurrences.
+    Expand,
+    /// Rendering statuses from templates.
+    Render,
+    /// Talking to the Mastodon API.
+    Post,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Fetch => "fetch",
+            Phase::Parse => "parse",
+            Phase::Expand => "expand",
+            Phase::Render => "render",
+            Phase::Post => "post",
+        }
+    }
+}
+
+/// How often a phase ran and how long it took altogether.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct PhaseTiming {
+    pub count: u32,
+    pub total_ms: f64,
+}
+
+/// Runs `f` in a debug span for the phase and records how long it took.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let span = tracing::debug_span!("phase", phase = phase.name());
+    let _entered = span.enter();
+    let start = Instant::now();
+    let result = f();
+    record(phase, start.elapsed());
+    result
+}
+
+/// Awaits `future` in a debug span for the phase and records how long it took.
+pub async fn time_async<T>(phase: Phase, future: impl Future<Output = T>) -> T {
+    let start = Instant::now();
+    let result = future
+        .instrument(tracing::debug_span!("phase", phase = phase.name()))
+        .await;
+    record(phase, start.elapsed());
+    result
+}
+
+fn record(phase: Phase, elapsed: Duration) {
+    let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+    tracing::debug!(phase = phase.name(), elapsed_ms, "phase finished");
+
+    let records = scope::current();
+    let mut timings = records.timings.lock().unwrap();
+    let timing = timings.entry(phase).or_default();
+    timing.count += 1;
+    timing.total_ms += elapsed_ms;
+}
+
+/// The phases that ran so far in this run, by name.
+pub fn summary() -> BTreeMap<&'static str, PhaseTiming> {
+    scope::current()
+        .timings
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(phase, timing)| {
+            let total_ms = (timing.total_ms * 1000.0).round() / 1000.0;
+            (
+                phase.name(),
+                PhaseTiming {
+                    total_ms,
+                    ..*timing
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_time() {
+        let timings = scope::job(async {
+            assert_eq!(time(Phase::Expand, || 42), 42);
+            assert_eq!(time_async(Phase::Expand, async { 7 }).await, 7);
+            summary()
+        })
+        .await;
+        assert_eq!(timings.keys().copied().collect::<Vec<_>>(), ["expand"]);
+        assert_eq!(timings["expand"].count, 2);
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// The UIDs events had on a calendar platform the calendar moved away from,
+/// mapped to the UIDs they have now, so what was posted about them carries
+/// over.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct UidMap {
+    /// Old UIDs and the new UID of each.
+    #[serde(default)]
+    pub uids: BTreeMap<String, String>,
+    /// Patterns for UIDs that changed in the same way, tried in order for
+    /// those not listed in `uids`; written as `[[rule]]`.
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<UidRule>,
+}
+
+/// Maps the old UIDs matching `old` to `new`. A `*` in `old` matches any
+/// text, which takes the place of the `*` in `new`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct UidRule {
+    pub old: String,
+    pub new: String,
+}
+
+impl UidMap {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read the UID map {}: {}", path, e))?;
+        toml::from_str(&content).map_err(|e| anyhow!("Invalid UID map {}: {}", path, e))
+    }
+
+    /// The UID an event with the old UID `uid` has now, if it changed.
+    pub fn map(&self, uid: &str) -> Option<String> {
+        self.uids
+            .get(uid)
+            .cloned()
+            .or_else(|| self.rules.iter().find_map(|rule| rule.apply(uid)))
+            .filter(|new| new != uid)
+    }
+}
+
+impl UidRule {
+    fn apply(&self, uid: &str) -> Option<String> {
+        match self.old.split_once('*') {
+            Some((prefix, suffix)) => {
+                let middle = uid.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                Some(self.new.replacen('*', middle, 1))
+            }
+            None => (uid == self.old).then(|| self.new.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_map() {
+        let map: UidMap = toml::from_str(indoc! {r#"
+            [uids]
+            "monthly@old.example.com" = "3f2a9c@new.example.com"
+
+            [[rule]]
+            old = "*@old.example.com"
+            new = "imported-*@new.example.com"
+
+            [[rule]]
+            old = "install-fest"
+            new = "install-fest@new.example.com"
+        "#})
+        .unwrap();
+
+        assert_eq!(
+            map.map("monthly@old.example.com").as_deref(),
+            Some("3f2a9c@new.example.com")
+        );
+        assert_eq!(
+            map.map("weekly@old.example.com").as_deref(),
+            Some("imported-weekly@new.example.com")
+        );
+        assert_eq!(
+            map.map("install-fest").as_deref(),
+            Some("install-fest@new.example.com")
+        );
+        assert_eq!(map.map("weekly@other.example.com"), None);
+        assert_eq!(map.map("3f2a9c@new.example.com"), None);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::config::UrlPolicyConfig;
+use crate::fetch::DEFAULT_MAX_REDIRECTS;
+use anyhow::{Result, anyhow};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{ClientBuilder, Url};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+/// Whether `ip` is on this machine or a private, link-local or otherwise
+/// non-public network, which a calendar shouldn't have the bot ask.
+pub fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+                // Benchmarking, 198.18.0.0/15
+                || (a == 198 && (18..20).contains(&b))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private(IpAddr::V4(ip)),
+            None => {
+                let segments = ip.segments();
+                let first = segments[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local, fc00::/7, and link-local, fe80::/10
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80
+                    // NAT64, 64:ff9b::/96, which reaches IPv4 addresses
+                    || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+            }
+        },
+    }
+}
+
+/// Whether `ip` is in the range `cidr`, like `192.168.1.0/24`, or is the
+/// address `cidr` when it has no prefix length.
+fn in_range(cidr: &str, ip: IpAddr) -> bool {
+    let (base, bits) = match cidr.split_once('/') {
+        Some((base, bits)) => (base, bits.parse::<u32>().ok()),
+        None => (cidr, None),
+    };
+    let Ok(base) = base.parse::<IpAddr>() else {
+        return false;
+    };
+    let (base, ip, width) = match (base, ip) {
+        (IpAddr::V4(base), IpAddr::V4(ip)) => (u32::from(base).into(), u32::from(ip).into(), 32),
+        (IpAddr::V6(base), IpAddr::V6(ip)) => (u128::from(base), u128::from(ip), 128),
+        _ => return false,
+    };
+    let bits = bits.unwrap_or(width).min(width);
+    let mask = u128::MAX.checked_shl(width - bits).unwrap_or(0) & (u128::MAX >> (128 - width));
+    base & mask == ip & mask
+}
+
+/// Whether the policy's `allow` list lets the bot ask `host` at `ip` even
+/// though it is private.
+fn allowed(policy: &UrlPolicyConfig, host: &str, ip: IpAddr) -> bool {
+    policy.allow.iter().any(|allowed| {
+        allowed.eq_ignore_ascii_case(host.trim_start_matches('[').trim_end_matches(']'))
+            || in_range(allowed, ip)
+    })
+}
+
+/// Checks what can be told of `url` without looking its host up: its scheme,
+/// and that a host given as an address isn't a private one.
+pub fn check(policy: &UrlPolicyConfig, url: &Url) -> Result<()> {
+    if !policy
+        .schemes
+        .iter()
+        .any(|scheme| scheme.eq_ignore_ascii_case(url.scheme()))
+    {
+        return Err(anyhow!(
+            "Not fetching {}: {} URLs aren't allowed",
+            url,
+            url.scheme()
+        ));
+    }
+    let host = url.host_str().unwrap_or_default();
+    if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse()
+        && is_private(ip)
+        && !allowed(policy, host, ip)
+    {
+        return Err(anyhow!("Not fetching {}: it is on a private network", url));
+    }
+    Ok(())
+}
+
+/// Looks host names up leaving out private addresses, so the bot only
+/// connects to the public addresses it checked, redirects included.
+struct PublicResolver {
+    policy: UrlPolicyConfig,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy.clone();
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let lookup = host.clone();
+            let found: Vec<SocketAddr> =
+                tokio::task::spawn_blocking(move || (lookup.as_str(), 0).to_socket_addrs())
+                    .await??
+                    .collect();
+            let public: Vec<SocketAddr> = found
+                .into_iter()
+                .filter(|addr| !is_private(addr.ip()) || allowed(&policy, &host, addr.ip()))
+                .collect();
+            if public.is_empty() {
+                return Err(format!("{} is on a private network", host).into());
+            }
+            Ok(Box::new(public.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// A client builder holding requests to `policy`: host names are only
+/// connected to at public addresses, and redirects are followed at most
+/// [`DEFAULT_MAX_REDIRECTS`] times and only to allowed URLs. Without a
+/// policy, a plain one.
+pub fn client_builder(policy: Option<&UrlPolicyConfig>) -> ClientBuilder {
+    let Some(policy) = policy else {
+        return reqwest::Client::builder();
+    };
+    let redirects = policy.clone();
+    reqwest::Client::builder()
+        .dns_resolver(Arc::new(PublicResolver {
+            policy: policy.clone(),
+        }))
+        .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > DEFAULT_MAX_REDIRECTS {
+                return attempt.error(format!("more than {} redirects", DEFAULT_MAX_REDIRECTS));
+            }
+            match check(&redirects, attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e.to_string()),
+            }
+        }))
+}
+
+/// A client for requests starting at `url`, once the policy allows it, held
+/// to the policy like [`client_builder`]'s.
+pub fn client(policy: Option<&UrlPolicyConfig>, url: &str) -> Result<reqwest::Client> {
+    if let Some(policy) = policy {
+        check(policy, &Url::parse(url)?)?;
+    }
+    Ok(client_builder(policy).build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let policy = UrlPolicyConfig {
+            schemes: vec!["https".to_string()],
+            allow: vec!["192.168.1.0/24".to_string(), "fd00::5".to_string()],
+        };
+        let check = |url: &str| check(&policy, &Url::parse(url).unwrap()).is_ok();
+
+        assert!(check("https://calendar.example.com/feed.ics"));
+        assert!(check("https://93.184.215.14/feed.ics"));
+        assert!(check("https://198.20.0.1/feed.ics"));
+        assert!(!check("http://calendar.example.com/feed.ics"));
+        assert!(!check("file:///etc/passwd"));
+        for private in [
+            "https://127.0.0.1/",
+            "https://10.1.2.3/",
+            "https://169.254.169.254/latest/meta-data/",
+            "https://100.100.1.1/",
+            "https://[::1]/",
+            "https://[fe80::1]/",
+            "https://[::ffff:10.0.0.1]/",
+            "https://198.18.0.1/",
+            "https://198.19.255.254/",
+            "https://[64:ff9b::a9fe:a9fe]/",
+        ] {
+            assert!(!check(private), "{}", private);
+        }
+        // Unless allowed
+        assert!(check("https://192.168.1.20/caldav"));
+        assert!(!check("https://192.168.2.20/caldav"));
+        assert!(check("https://[fd00::5]/"));
+    }
+
+    #[test]
+    fn test_client() {
+        let policy = UrlPolicyConfig::default();
+        assert!(client(Some(&policy), "https://mastodon.example/api/v1/instance").is_ok());
+        let refused = client(Some(&policy), "http://10.0.0.1/oauth/revoke").unwrap_err();
+        assert_eq!(
+            refused.to_string(),
+            "Not fetching http://10.0.0.1/oauth/revoke: it is on a private network"
+        );
+        assert!(client(Some(&policy), "ftp://mastodon.example/").is_err());
+        assert!(client(None, "http://10.0.0.1/oauth/revoke").is_ok());
+    }
+}
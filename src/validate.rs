@@ -0,0 +1,155 @@
+use crate::ical::{self, CalendarEvent, IcalCalendar};
+use crate::recurrence::{self, Window};
+use chrono::{Duration, Utc};
+
+/// Something in a calendar feed the bot has to work around or can't use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// The event's summary and UID.
+    pub event: String,
+    pub message: String,
+}
+
+/// Checks a calendar as fetched, before recurring events are expanded.
+pub fn check(calendar: &IcalCalendar) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for event in &calendar.events {
+        let mut problem = |message: String| {
+            problems.push(Problem {
+                event: describe(event),
+                message,
+            })
+        };
+
+        let Some((start, form)) = event.start_time.as_deref().and_then(ical::parse_ical_time)
+        else {
+            problem(match &event.start_time {
+                Some(start) => format!("DTSTART {} isn't a valid date or date-time", start),
+                None => "no DTSTART, so it is never announced".to_string(),
+            });
+            continue;
+        };
+
+        if let (Some(tzid), None) = (&event.tzid, event.timezone) {
+            problem(format!(
+                "TZID {} isn't a known timezone; reading its times as local to each reader",
+                tzid
+            ));
+        }
+
+        let dates = [("RDATE", &event.rdates), ("EXDATE", &event.exdates)];
+        for (property, values) in dates {
+            for value in values {
+                match recurrence::normalize_date(property, value, start, form) {
+                    Some((_, Some(mismatch))) => problem(mismatch),
+                    Some((_, None)) => {}
+                    None => problem(format!(
+                        "{} {} isn't a valid date or date-time",
+                        property, value
+                    )),
+                }
+            }
+        }
+
+        let Some(rule) = &event.rrule else {
+            continue;
+        };
+        if let (_, Some(mismatch)) = recurrence::normalize_until(rule, form) {
+            problem(mismatch);
+        }
+        // Any window will do to find out whether the rule can be expanded
+        let window = Window::around(Utc::now(), Duration::zero(), Duration::days(1));
+        if let Err(e) = recurrence::occurrence_starts(event, &window) {
+            problem(format!("RRULE {} can't be expanded: {}", rule, e));
+        }
+    }
+    problems
+}
+
+fn describe(event: &CalendarEvent) -> String {
+    let summary = event.summary.as_deref().unwrap_or("(no summary)");
+    match &event.uid {
+        Some(uid) => format!("{} ({})", summary, uid),
+        None => summary.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_check() {
+        let calendar = IcalCalendar::parse_ical_content(indoc! {"
+            BEGIN:VCALENDAR
+            VERSION:2.0
+            PRODID:-//Test//Test//EN
+            BEGIN:VEVENT
+            UID:fine@example.com
+            DTSTART:20251203T190000Z
+            RRULE:FREQ=WEEKLY;UNTIL=20260107T190000Z
+            SUMMARY:Weekly Meeting
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:until@example.com
+            DTSTART:20251203T190000Z
+            RRULE:FREQ=WEEKLY;UNTIL=20260107
+            SUMMARY:Book Club
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:broken@example.com
+            DTSTART:20251203T190000Z
+            RRULE:FREQ=SOMETIMES
+            SUMMARY:Social
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:rdate@example.com
+            DTSTART:20251203T190000Z
+            RRULE:FREQ=MONTHLY;BYDAY=1WE
+            RDATE:20251213,2025-12-20
+            EXDATE:20260107T190000
+            SUMMARY:Workshop
+            END:VEVENT
+            BEGIN:VEVENT
+            SUMMARY:Someday
+            END:VEVENT
+            END:VCALENDAR
+        "})
+        .unwrap();
+
+        let problems = check(&calendar);
+        let events: Vec<_> = problems.iter().map(|p| p.event.as_str()).collect();
+        assert_eq!(
+            events,
+            [
+                "Book Club (until@example.com)",
+                "Social (broken@example.com)",
+                "Workshop (rdate@example.com)",
+                "Workshop (rdate@example.com)",
+                "Workshop (rdate@example.com)",
+                "Someday"
+            ]
+        );
+        assert!(
+            problems[0]
+                .message
+                .contains("reading it as 2026-01-07 23:59:59")
+        );
+        assert!(
+            problems[2]
+                .message
+                .contains("reading it as 2025-12-13 19:00:00")
+        );
+        assert!(
+            problems[3]
+                .message
+                .contains("RDATE 2025-12-20 isn't a valid")
+        );
+        assert!(
+            problems[4]
+                .message
+                .starts_with("EXDATE 20260107T190000 is a date-time without a timezone")
+        );
+    }
+}
@@ -0,0 +1,519 @@
+use crate::config::Config;
+use crate::ical::{self, CalendarEvent, IcalCalendar, TimeForm};
+use crate::{fetch, url_policy};
+use anyhow::{Result, anyhow};
+
+/// The property holding the link of an event's announcement.
+pub const PROPERTY: &str = "X-MASTO-POSTED-URL";
+
+/// A property of a VEVENT as written, with the lines it is folded onto.
+struct Property<'a> {
+    lines: Vec<&'a str>,
+    /// Whether it is the event's own, not one of a VALARM inside it.
+    own: bool,
+}
+
+impl Property<'_> {
+    fn unfolded(&self) -> String {
+        let mut unfolded = self.lines[0].trim_end().to_string();
+        for line in &self.lines[1..] {
+            unfolded.push_str(&line.trim_end()[1..]);
+        }
+        unfolded
+    }
+
+    /// The value of the event's own property `name`, if this is it.
+    fn value(&self, name: &str) -> Option<String> {
+        let unfolded = self.unfolded();
+        let rest = unfolded
+            .get(..name.len())
+            .filter(|start| self.own && start.eq_ignore_ascii_case(name))
+            .map(|_| &unfolded[name.len()..])?;
+        if !rest.starts_with([':', ';']) {
+            return None;
+        }
+        Some(rest.split_once(':')?.1.to_string())
+    }
+}
+
+/// The properties of a VEVENT, from the lines between its BEGIN and END.
+fn properties<'a>(lines: &[&'a str]) -> Vec<Property<'a>> {
+    let mut properties: Vec<Property> = Vec::new();
+    let mut depth = 0;
+    for line in lines {
+        if line.starts_with([' ', '\t'])
+            && let Some(property) = properties.last_mut()
+        {
+            property.lines.push(line);
+            continue;
+        }
+        let upper = line.trim_end().to_ascii_uppercase();
+        if upper.starts_with("END:") {
+            depth -= 1;
+        }
+        properties.push(Property {
+            lines: vec![line],
+            own: depth == 0 && !upper.starts_with("BEGIN:") && !upper.starts_with("END:"),
+        });
+        if upper.starts_with("BEGIN:") {
+            depth += 1;
+        }
+    }
+    properties
+}
+
+/// The properties an override of an occurrence doesn't take from its series.
+const SERIES_ONLY: [&str; 6] = ["DTSTART", "DTEND", "RRULE", "RDATE", "EXDATE", PROPERTY];
+
+/// A DTSTART, DTEND or RECURRENCE-ID line of `value`.
+fn time_line(name: &str, value: &str, newline: &str) -> String {
+    let date_only = matches!(ical::parse_ical_time(value), Some((_, TimeForm::Date)));
+    let parameter = if date_only { ";VALUE=DATE" } else { "" };
+    format!("{}{}:{}{}", name, parameter, value, newline)
+}
+
+/// `ics` with [`PROPERTY`] set to `url` on the VEVENT `event` was read from,
+/// keeping everything else as written; `None` when there is no such event.
+/// An occurrence of a recurring event gets an override of its own, with a
+/// RECURRENCE-ID, so the other occurrences don't take its link.
+pub fn with_posted_url(ics: &str, event: &CalendarEvent, url: &str) -> Option<String> {
+    let uid = event.uid.as_deref()?;
+    // The same VEVENTs, in the same order, as the bot reads them
+    let read = IcalCalendar::parse_ical_content(ics).ok()?.events;
+    let newline = if ics.contains("\r\n") { "\r\n" } else { "\n" };
+    let posted = format!("{}:{}{}", PROPERTY, url, newline);
+    let mut written = String::with_capacity(ics.len());
+    let mut lines: Option<Vec<&str>> = None;
+    let mut index = 0;
+    let mut found = false;
+
+    for line in ics.split_inclusive('\n') {
+        let upper = line.trim_end().to_ascii_uppercase();
+        let Some(vevent) = &mut lines else {
+            match upper == "BEGIN:VEVENT" {
+                true => lines = Some(vec![line]),
+                false => written.push_str(line),
+            }
+            continue;
+        };
+        if upper != "END:VEVENT" {
+            vevent.push(line);
+            continue;
+        }
+        let properties = properties(&vevent[1..]);
+        let this = read
+            .get(index)
+            .filter(|this| this.uid.as_deref() == Some(uid));
+        index += 1;
+        let same = this.is_some_and(|this| this.recurrence_id == event.recurrence_id);
+        // An occurrence gets an override rather than its series the link
+        let occurrence =
+            same && this.is_some_and(CalendarEvent::is_recurring) && !event.is_recurring();
+        let stamped = same && !occurrence;
+
+        written.push_str(vevent[0]);
+        for property in &properties {
+            if !(stamped && property.value(PROPERTY).is_some()) {
+                written.extend(property.lines.iter().copied());
+            }
+        }
+        if stamped {
+            written.push_str(&posted);
+        }
+        written.push_str(line);
+        found |= stamped;
+
+        if occurrence && let Some(start) = event.start_time.as_deref() {
+            written.push_str(vevent[0]);
+            for property in &properties {
+                if !SERIES_ONLY
+                    .iter()
+                    .any(|name| property.value(name).is_some())
+                {
+                    written.extend(property.lines.iter().copied());
+                }
+            }
+            written.push_str(&time_line("DTSTART", start, newline));
+            if let Some(end) = event.end_time.as_deref()
+                && properties
+                    .iter()
+                    .any(|property| property.value("DTEND").is_some())
+            {
+                written.push_str(&time_line("DTEND", end, newline));
+            }
+            written.push_str(&time_line("RECURRENCE-ID", start, newline));
+            written.push_str(&posted);
+            written.push_str(line);
+            found = true;
+        }
+        lines = None;
+    }
+    found.then_some(written)
+}
+
+/// The first element named `name`, in any namespace, in `xml` and what
+/// follows it.
+fn element<'a>(xml: &'a str, name: &str) -> Option<(&'a str, &'a str)> {
+    let mut rest = xml;
+    loop {
+        let start = rest.find('<')?;
+        let tag_end = start + rest[start..].find('>')?;
+        let tag = &rest[start + 1..tag_end];
+        let tag_name = tag
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches('/');
+        let local = tag_name.rsplit(':').next().unwrap_or_default();
+        if !tag.starts_with('/') && local.eq_ignore_ascii_case(name) {
+            let after = &rest[tag_end + 1..];
+            if tag.ends_with('/') {
+                return Some(("", after));
+            }
+            let close = format!("</{}>", tag_name);
+            let end = after.find(&close)?;
+            return Some((&after[..end], &after[end + close.len()..]));
+        }
+        rest = &rest[tag_end + 1..];
+    }
+}
+
+/// Text of an XML element, with its entities and CDATA undone.
+fn text(xml: &str) -> String {
+    let xml = xml.trim();
+    if let Some(data) = xml
+        .strip_prefix("<![CDATA[")
+        .and_then(|xml| xml.strip_suffix("]]>"))
+    {
+        return data.to_string();
+    }
+    xml.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#13;", "\r")
+        .replace("&amp;", "&")
+}
+
+/// A calendar object found on the CalDAV server.
+struct Resource {
+    href: String,
+    etag: Option<String>,
+    data: String,
+}
+
+/// The resources of a CalDAV `multistatus` answer.
+fn resources(xml: &str) -> Vec<Resource> {
+    let mut resources = Vec::new();
+    let mut rest = xml;
+    while let Some((response, after)) = element(rest, "response") {
+        if let (Some((href, _)), Some((data, _))) = (
+            element(response, "href"),
+            element(response, "calendar-data"),
+        ) {
+            resources.push(Resource {
+                href: text(href),
+                etag: element(response, "getetag").map(|(etag, _)| text(etag)),
+                data: text(data),
+            });
+        }
+        rest = after;
+    }
+    resources
+}
+
+fn calendar_query(uid: &str) -> String {
+    let uid = uid
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:prop><d:getetag/><c:calendar-data/></d:prop>
+  <c:filter>
+    <c:comp-filter name="VCALENDAR">
+      <c:comp-filter name="VEVENT">
+        <c:prop-filter name="UID"><c:text-match collation="i;octet">{}</c:text-match></c:prop-filter>
+      </c:comp-filter>
+    </c:comp-filter>
+  </c:filter>
+</c:calendar-query>"#,
+        uid
+    )
+}
+
+/// Sets the link of the event's announcement in the CalDAV collection:
+/// finds the event's resource by UID and puts it back changed, unless it
+/// changed on the server in between. Only URLs the policy allows are asked.
+async fn write_caldav(
+    config: &Config,
+    collection: &str,
+    event: &CalendarEvent,
+    uid: &str,
+    url: &str,
+) -> Result<()> {
+    let policy = &config.url_policy;
+    let client = url_policy::client(Some(policy), collection)?;
+    let login = config.calendar_login.as_ref();
+    let authorized = |request: reqwest::RequestBuilder| match login {
+        Some(login) => request.basic_auth(&login.username, login.password.as_ref()),
+        None => request,
+    };
+
+    let report = authorized(client.request(reqwest::Method::from_bytes(b"REPORT")?, collection))
+        .header("Depth", "1")
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "application/xml; charset=utf-8",
+        )
+        .body(calendar_query(uid))
+        .send()
+        .await?;
+    if !report.status().is_success() {
+        return Err(anyhow!(
+            "HTTP error looking the event up: {}",
+            report.status()
+        ));
+    }
+    let resources = resources(&report.text().await?);
+    let base = reqwest::Url::parse(collection)?;
+    let mut found = false;
+    for resource in resources {
+        let Some(data) = with_posted_url(&resource.data, event, url) else {
+            continue;
+        };
+        found = true;
+        let resource_url = base.join(&resource.href)?;
+        url_policy::check(policy, &resource_url)?;
+        let mut request = authorized(client.put(resource_url))
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "text/calendar; charset=utf-8",
+            )
+            .body(data);
+        if let Some(etag) = &resource.etag {
+            request = request.header(reqwest::header::IF_MATCH, etag);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "HTTP error writing {}: {}",
+                resource.href,
+                response.status()
+            ));
+        }
+    }
+    match found {
+        true => Ok(()),
+        false => Err(anyhow!("No event with the UID {} in {}", uid, collection)),
+    }
+}
+
+/// Sets the link of the event's announcement in the local calendar file.
+fn write_file(location: &str, event: &CalendarEvent, uid: &str, url: &str) -> Result<()> {
+    let path = match location.strip_prefix("file://") {
+        Some(_) => reqwest::Url::parse(location)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+            .ok_or_else(|| anyhow!("Not a local file URL: {}", location))?,
+        None => location.into(),
+    };
+    let ics = std::fs::read_to_string(&path)?;
+    let written = with_posted_url(&ics, event, url)
+        .ok_or_else(|| anyhow!("No event with the UID {} in {}", uid, path.display()))?;
+    // Written next to it and moved over it, so readers never see half a file
+    let temporary = path.with_extension("ics.tmp");
+    std::fs::write(&temporary, written)?;
+    std::fs::rename(&temporary, &path)?;
+    Ok(())
+}
+
+/// Writes the link of the event's announcement `url` back into the
+/// calendar, unless it is there already. Returns whether it was written.
+pub async fn write(config: &Config, event: &CalendarEvent, url: &str) -> Result<bool> {
+    let Some(write_back) = &config.write_back else {
+        return Ok(false);
+    };
+    let Some(uid) = event.uid.as_deref() else {
+        return Ok(false);
+    };
+    if event.posted_url.as_deref() == Some(url) {
+        return Ok(false);
+    }
+    match &write_back.caldav_url {
+        Some(collection) => write_caldav(config, collection, event, uid, url).await?,
+        None if fetch::is_local(&config.webcal) && config.webcal != fetch::STDIN => {
+            write_file(&config.webcal, event, uid, url)?
+        }
+        None => {
+            return Err(anyhow!(
+                "{} can't be written to; set the caldav_url of [write_back]",
+                config.webcal
+            ));
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_with_posted_url() {
+        let ics = indoc! {"
+            BEGIN:VCALENDAR
+            BEGIN:VEVENT
+            UID:weekly@example.com
+            SUMMARY:Weekly Meeting
+            X-MASTO-POSTED-URL:https://mastodon.example/@bot/1
+            BEGIN:VALARM
+            ACTION:DISPLAY
+            END:VALARM
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:other@example.com
+            DESCRIPTION:A long description folded
+              onto two lines
+            END:VEVENT
+            END:VCALENDAR
+        "}
+        .replace('\n', "\r\n");
+        let event = |uid: &str| CalendarEvent {
+            uid: Some(uid.to_string()),
+            ..Default::default()
+        };
+
+        let written = with_posted_url(
+            &ics,
+            &event("weekly@example.com"),
+            "https://mastodon.example/@bot/2",
+        )
+        .unwrap();
+        assert_eq!(
+            written,
+            indoc! {"
+                BEGIN:VCALENDAR
+                BEGIN:VEVENT
+                UID:weekly@example.com
+                SUMMARY:Weekly Meeting
+                BEGIN:VALARM
+                ACTION:DISPLAY
+                END:VALARM
+                X-MASTO-POSTED-URL:https://mastodon.example/@bot/2
+                END:VEVENT
+                BEGIN:VEVENT
+                UID:other@example.com
+                DESCRIPTION:A long description folded
+                  onto two lines
+                END:VEVENT
+                END:VCALENDAR
+            "}
+            .replace('\n', "\r\n")
+        );
+        let calendar = IcalCalendar::parse_ical_content(&written).unwrap();
+        assert_eq!(
+            calendar.events[0].posted_url.as_deref(),
+            Some("https://mastodon.example/@bot/2")
+        );
+        let missing = event("missing@example.com");
+        assert!(with_posted_url(&ics, &missing, "https://x.example").is_none());
+    }
+
+    #[test]
+    fn test_posted_url_of_occurrence() {
+        let ics = indoc! {"
+            BEGIN:VCALENDAR
+            BEGIN:VEVENT
+            UID:weekly@example.com
+            DTSTART:20251203T190000Z
+            DTEND:20251203T210000Z
+            RRULE:FREQ=WEEKLY
+            SUMMARY:Weekly Meeting
+            END:VEVENT
+            BEGIN:VEVENT
+            UID:weekly@example.com
+            RECURRENCE-ID:20251210T190000Z
+            DTSTART:20251211T190000Z
+            DTEND:20251211T210000Z
+            SUMMARY:Weekly Meeting
+            END:VEVENT
+            END:VCALENDAR
+        "};
+        let series = IcalCalendar::parse_ical_content(ics).unwrap().events;
+
+        // An occurrence of the series gets an override of its own
+        let occurrence = CalendarEvent {
+            start_time: Some("20251217T190000Z".to_string()),
+            end_time: Some("20251217T210000Z".to_string()),
+            rrule: None,
+            ..series[0].clone()
+        };
+        let written = with_posted_url(ics, &occurrence, "https://mastodon.example/@bot/3").unwrap();
+        assert_eq!(
+            written,
+            ics.replacen(
+                "END:VEVENT\n",
+                indoc! {"
+                    END:VEVENT
+                    BEGIN:VEVENT
+                    UID:weekly@example.com
+                    SUMMARY:Weekly Meeting
+                    DTSTART:20251217T190000Z
+                    DTEND:20251217T210000Z
+                    RECURRENCE-ID:20251217T190000Z
+                    X-MASTO-POSTED-URL:https://mastodon.example/@bot/3
+                    END:VEVENT
+                "},
+                1
+            )
+        );
+        let read = IcalCalendar::parse_ical_content(&written).unwrap().events;
+        assert_eq!(read[0].posted_url, None);
+        assert_eq!(read[1].recurrence_id.as_deref(), Some("20251217T190000Z"));
+        assert_eq!(read[2].posted_url, None);
+
+        // A moved occurrence has its override stamped
+        let written = with_posted_url(ics, &series[1], "https://mastodon.example/@bot/2").unwrap();
+        let read = IcalCalendar::parse_ical_content(&written).unwrap().events;
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].posted_url, None);
+        assert_eq!(
+            read[1].posted_url.as_deref(),
+            Some("https://mastodon.example/@bot/2")
+        );
+    }
+
+    #[test]
+    fn test_resources() {
+        let xml = indoc! {r#"
+            <?xml version="1.0"?>
+            <d:multistatus xmlns:d="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">
+              <d:response>
+                <d:href>/remote.php/dav/calendars/bot/community/weekly.ics</d:href>
+                <d:propstat>
+                  <d:prop>
+                    <d:getetag>&quot;4f2a&quot;</d:getetag>
+                    <cal:calendar-data>BEGIN:VCALENDAR&#13;
+            SUMMARY:Q&amp;A&#13;
+            END:VCALENDAR</cal:calendar-data>
+                  </d:prop>
+                </d:propstat>
+              </d:response>
+            </d:multistatus>
+        "#};
+        let found = resources(xml);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].href,
+            "/remote.php/dav/calendars/bot/community/weekly.ics"
+        );
+        assert_eq!(found[0].etag.as_deref(), Some("\"4f2a\""));
+        assert_eq!(
+            found[0].data,
+            "BEGIN:VCALENDAR\r\nSUMMARY:Q&A\r\nEND:VCALENDAR"
+        );
+    }
+}